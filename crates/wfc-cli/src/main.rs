@@ -1,8 +1,18 @@
+use std::io::Write;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::process;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 
 use clap::{Args, Parser, Subcommand, ValueEnum};
-use wfc_core::{Boundary, Color, Config, RunOutcome, Sample, Wfc, default_pipe_sample};
+use wfc_core::{
+    BiasField, Boundary, Color, ColorMap, Config, EdgeConstraint, Edges, Guide, Heightmap,
+    Heuristic, LayerPalette, LayeredSample, OutputSymmetry, RowProfile, RunOutcome, Sample, Solved,
+    Symmetry, WeightMultiplier, Wfc, default_cache_dir, default_pipe_sample, dominant_colors, dual,
+    load_palette, montage, parse_hex, smooth,
+};
 
 #[derive(Clone, ValueEnum)]
 enum BoundaryArg {
@@ -14,6 +24,10 @@ enum BoundaryArg {
     PeriodicY,
     /// Wrap both axes (toroidal)
     Periodic,
+    /// Reflect the output past its right and bottom edges (output only)
+    Mirror,
+    /// Repeat the last column and row past the edges (output only)
+    Clamp,
 }
 
 impl From<BoundaryArg> for Boundary {
@@ -23,10 +37,141 @@ impl From<BoundaryArg> for Boundary {
             BoundaryArg::PeriodicX => Boundary::PeriodicX,
             BoundaryArg::PeriodicY => Boundary::PeriodicY,
             BoundaryArg::Periodic => Boundary::Periodic,
+            BoundaryArg::Mirror => Boundary::Mirror,
+            BoundaryArg::Clamp => Boundary::Clamp,
         }
     }
 }
 
+#[derive(Clone, ValueEnum)]
+enum OutputSymmetryArg {
+    /// No forced symmetry
+    None,
+    /// Right half mirrors the left
+    MirrorX,
+    /// Bottom half mirrors the top
+    MirrorY,
+    /// Mirrored both ways
+    FourFold,
+}
+
+impl From<OutputSymmetryArg> for OutputSymmetry {
+    fn from(s: OutputSymmetryArg) -> Self {
+        match s {
+            OutputSymmetryArg::None => OutputSymmetry::None,
+            OutputSymmetryArg::MirrorX => OutputSymmetry::MirrorX,
+            OutputSymmetryArg::MirrorY => OutputSymmetry::MirrorY,
+            OutputSymmetryArg::FourFold => OutputSymmetry::FourFold,
+        }
+    }
+}
+
+#[derive(Clone, ValueEnum)]
+enum HeuristicArg {
+    /// Lowest-entropy cell first; grows from the most constrained areas
+    MinEntropy,
+    /// Row by row from the top-left
+    Scanline,
+    /// Any undecided cell
+    Random,
+}
+
+impl From<HeuristicArg> for Heuristic {
+    fn from(h: HeuristicArg) -> Self {
+        match h {
+            HeuristicArg::MinEntropy => Heuristic::MinEntropy,
+            HeuristicArg::Scanline => Heuristic::Scanline,
+            HeuristicArg::Random => Heuristic::Random,
+        }
+    }
+}
+
+/// Process exit codes, so scripts can branch on the outcome.
+const EXIT_ERROR: i32 = 1;
+/// Same code clap uses for unparseable arguments.
+const EXIT_INVALID_CONFIG: i32 = 2;
+const EXIT_CONTRADICTION: i32 = 3;
+/// `verify` regenerated an output whose hash differs from the expected one.
+const EXIT_MISMATCH: i32 = 4;
+
+/// Estimated pattern count above which a run warns that it will be slow.
+const LARGE_MODEL_PATTERNS: usize = 2000;
+
+/// Final machine-readable summary of a run, printed as one JSON line on
+/// stdout (stderr when stdout carries the image).
+struct Report {
+    started: Instant,
+    to_stderr: bool,
+    seed: Option<u64>,
+    attempts: usize,
+    steps: usize,
+    output: Option<PathBuf>,
+    /// `Sample::content_hash` of the generated output, before recoloring.
+    hash: Option<u64>,
+}
+
+impl Report {
+    fn new(to_stderr: bool) -> Self {
+        Self {
+            started: Instant::now(),
+            to_stderr,
+            seed: None,
+            attempts: 0,
+            steps: 0,
+            output: None,
+            hash: None,
+        }
+    }
+
+    fn print(&self, status: &str) {
+        let line = format!(
+            r#"{{"status":"{}","seed":{},"attempts":{},"steps":{},"duration_ms":{:.1},"output":{},"hash":{}}}"#,
+            status,
+            self.seed.map_or("null".to_string(), |s| s.to_string()),
+            self.attempts,
+            self.steps,
+            self.started.elapsed().as_secs_f64() * 1000.0,
+            self.output.as_ref().map_or("null".to_string(), |p| {
+                json_string(&p.display().to_string())
+            }),
+            self.hash
+                .map_or("null".to_string(), |h| format!("\"{:016x}\"", h)),
+        );
+        if self.to_stderr {
+            eprintln!("{}", line);
+        } else {
+            println!("{}", line);
+        }
+    }
+
+    fn exit(&self, status: &str, code: i32) -> ! {
+        self.print(status);
+        process::exit(code);
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum StdoutFormat {
+    Png,
+    /// Binary PPM (16-bit PGM with --heightmap)
+    Ppm,
+}
+
 /// Wave Function Collapse image generator
 #[derive(Parser)]
 #[command(version)]
@@ -41,10 +186,35 @@ struct RunArgs {
     #[arg(short, long)]
     input: Option<PathBuf>,
 
+    /// Fetch the input sample from an http(s) URL instead of a file
+    #[arg(long, value_name = "URL", conflicts_with_all = ["input", "heightmap"])]
+    url: Option<String>,
+
     /// Output image path
     #[arg(short, long, default_value = "output.png")]
     output: PathBuf,
 
+    /// Write the image to stdout instead of --output (logs stay on stderr)
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        default_missing_value = "png",
+        conflicts_with_all = ["layers", "smooth"]
+    )]
+    stdout: Option<StdoutFormat>,
+
+    /// Extra pixel-aligned layer solved jointly with the input (repeatable);
+    /// layer N is written next to the output as `<name>.layerN.<ext>`
+    #[arg(long = "layer", requires = "input", conflicts_with = "heightmap")]
+    layers: Vec<PathBuf>,
+
+    /// Treat the input as a 16-bit grayscale heightmap; writes a 16-bit PNG,
+    /// or headerless little-endian u16 if the output ends in `.raw`
+    #[arg(long, requires = "input")]
+    heightmap: bool,
+
     /// Output width in cells
     #[arg(short = 'W', long, default_value_t = 32)]
     width: usize,
@@ -53,10 +223,16 @@ struct RunArgs {
     #[arg(short = 'H', long, default_value_t = 32)]
     height: usize,
 
-    /// Pattern size NxN
+    /// Pattern size NxN, up to 8. Above 4, structured samples (text, large
+    /// motifs) keep more of their shapes, but models grow quickly
     #[arg(short, long, default_value_t = 3)]
     pattern_size: usize,
 
+    /// Pattern height M for NxM patterns (N from --pattern-size), e.g. wide
+    /// patterns for brick walls; limits symmetry to mirrors and 180 degrees
+    #[arg(long, value_name = "M")]
+    pattern_height: Option<usize>,
+
     /// RNG seed for deterministic output
     #[arg(short, long)]
     seed: Option<u64>,
@@ -65,19 +241,262 @@ struct RunArgs {
     #[arg(long)]
     no_symmetry: bool,
 
+    /// Restrict symmetry to these transforms, comma-separated: rot90, rot180,
+    /// rot270, flip-x, flip-y, transpose, anti-transpose, or the groups
+    /// rotations, reflections, all (e.g. `flip-x` for samples with gravity)
+    #[arg(long, value_name = "LIST", conflicts_with = "no_symmetry")]
+    symmetry: Option<Symmetry>,
+
+    /// Force the output itself to be mirror-symmetric (emblems, creatures);
+    /// works best with a --symmetry that includes the matching flips
+    #[arg(long, value_enum, default_value_t = OutputSymmetryArg::None)]
+    output_symmetry: OutputSymmetryArg,
+
+    /// Weight of generated symmetry variants relative to patterns that occur
+    /// in the sample
+    #[arg(long, default_value_t = 1.0)]
+    variant_weight: f64,
+
+    /// Keep sample top/bottom edge patterns at the output's top/bottom
+    /// (shorthand for `--edge top=sample-top --edge bottom=sample-bottom`)
+    #[arg(long)]
+    ground: bool,
+
+    /// Restrict one output edge (repeatable): `SIDE=SPEC` with SIDE top,
+    /// bottom, left or right and SPEC sample-top, sample-bottom,
+    /// sample-left, sample-right, a RRGGBB color or pattern indices
+    /// `pINDEX,...` (e.g. `left=sample-right`, `bottom=1a1c2c`)
+    #[arg(long = "edge", value_name = "SIDE=SPEC", value_parser = parse_edge)]
+    edges: Vec<(&'static str, EdgeConstraint)>,
+
+    /// Weight multiplier for patterns allowed on a constrained top or
+    /// bottom edge near that edge
+    #[arg(long, default_value_t = 1.0)]
+    ground_boost: f64,
+
+    /// Vertical weight profile for one color (repeatable): `RRGGBB:CURVE` with
+    /// comma-separated multipliers from top to bottom (e.g. `87ceeb:4,1,0.2`),
+    /// or `RRGGBB:MASK.png` with a 1-D mask image (mid-gray = 1x)
+    #[arg(long = "row-profile", value_name = "COLOR:SPEC", value_parser = parse_row_profile)]
+    row_profiles: Vec<RowProfile>,
+
+    /// Grayscale image the size of the input weighting where patterns are
+    /// learned from: white counts fully, black only supplies adjacency
+    #[arg(long, value_name = "MASK", requires = "input")]
+    importance: Option<PathBuf>,
+
+    /// Generate a layout at 1/FACTOR of the output size first, from the
+    /// input shrunk by FACTOR (or --macro-sample), and make the full-size
+    /// pass follow its regions
+    #[arg(long, value_name = "FACTOR", value_parser = clap::value_parser!(u16).range(2..))]
+    macro_scale: Option<u16>,
+
+    /// Region map for the --macro-scale pass, in its own colors and
+    /// stretched over the input, instead of the shrunk input
+    #[arg(long, value_name = "IMAGE", requires = "macro_scale")]
+    macro_sample: Option<PathBuf>,
+
+    /// Weight factor for patterns of the region the layout shows at a cell
+    #[arg(
+        long,
+        value_name = "FACTOR",
+        default_value_t = 8.0,
+        requires = "macro_scale"
+    )]
+    macro_strength: f64,
+
+    /// Target image stretched over the output: cells favor patterns whose
+    /// color is close to the target's there (e.g. a sky-to-ground gradient)
+    #[arg(long, value_name = "IMAGE")]
+    bias: Option<PathBuf>,
+
+    /// How strongly --bias pulls: a pattern of the opposite color is
+    /// weighted by e^-STRENGTH
+    #[arg(
+        long,
+        value_name = "STRENGTH",
+        default_value_t = 4.0,
+        requires = "bias"
+    )]
+    bias_strength: f64,
+
+    /// Scale pattern weights: `RRGGBB=FACTOR` for patterns with that
+    /// top-left color, `pINDEX=FACTOR` for one pattern (repeatable)
+    #[arg(long = "weight", value_name = "KEY=FACTOR")]
+    weight_multipliers: Vec<WeightMultiplier>,
+
+    /// Weight factors `RIGHT,DOWN,LEFT,UP` for cells continuing the color
+    /// of the collapsed neighbor on that side (e.g. `1,3,1,3` for columns)
+    #[arg(long, value_name = "R,D,L,U", value_parser = parse_direction_weights)]
+    direction_weights: Option<[f64; 4]>,
+
+    /// Keep each top-left color within this share (e.g. 0.05) of its share
+    /// of the sample's patterns, so large outputs don't drift toward the
+    /// most common background
+    #[arg(long, value_name = "SHARE")]
+    frequency_tolerance: Option<f64>,
+
+    /// Never place the extracted pattern with this index, nor patterns
+    /// that only fit next to it (repeatable)
+    #[arg(long = "ban-pattern", value_name = "INDEX")]
+    banned_patterns: Vec<usize>,
+
+    /// Image setting the output shape (and size, overriding --width and
+    /// --height): light pixels are generated, dark ones left transparent
+    #[arg(long, value_name = "IMAGE")]
+    mask: Option<PathBuf>,
+
+    /// Image pixel-aligned with the input (normal map, emissive, ...) to
+    /// generate alongside as `<name>.<companion>.png` from the same
+    /// patterns; repeatable
+    #[arg(
+        long,
+        value_name = "IMAGE",
+        requires = "input",
+        conflicts_with = "stdout"
+    )]
+    companion: Vec<PathBuf>,
+
+    /// Also constrain diagonal neighbors
+    #[arg(long)]
+    diagonal: bool,
+
+    /// Constrain all overlapping offsets, not just direct neighbors
+    #[arg(long)]
+    strict_overlap: bool,
+
+    /// Treat overlapping pixels as matching when each channel differs by at
+    /// most this much; helps noisy samples (slower propagator build)
+    #[arg(long, value_name = "LEVELS", default_value_t = 0)]
+    color_tolerance: u8,
+
+    /// Drop patterns seen in fewer than K windows of the sample before
+    /// building the propagator; prunes the one-offs of noisy samples
+    #[arg(long, value_name = "K", default_value_t = 1)]
+    min_pattern_count: usize,
+
+    /// Reduce the sample to at most N colors (median cut) before extracting
+    /// patterns; merges anti-aliasing and noise
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..), conflicts_with_all = ["heightmap", "layers"])]
+    quantize: Option<u16>,
+
+    /// Compute compatibility lists on first use instead of all up front;
+    /// saves memory when lists are long (large samples, high tolerance)
+    #[arg(long)]
+    lazy_propagator: bool,
+
+    /// Which cell to collapse next
+    #[arg(long, value_enum, default_value_t = HeuristicArg::MinEntropy)]
+    heuristic: HeuristicArg,
+
+    /// Random offset added to cell entropies to break ties by seed; 0
+    /// breaks them by position
+    #[arg(long, default_value_t = 1e-6)]
+    entropy_noise: f64,
+
+    /// Keep cells of this color (RRGGBB) one connected region, e.g. maze
+    /// corridors; disconnected attempts backtrack
+    #[arg(long, value_name = "COLOR", value_parser = parse_color)]
+    connected: Option<Color>,
+
     /// Output boundary mode
     #[arg(long, value_enum, default_value_t = BoundaryArg::Fixed)]
     boundary: BoundaryArg,
 
-    /// Max retries on contradiction
+    /// Sample edges that pattern extraction wraps around, e.g. periodic-x
+    /// for a strip that only tiles horizontally
+    #[arg(long, value_enum, default_value_t = BoundaryArg::Periodic)]
+    input_boundary: BoundaryArg,
+
+    /// Also write marching-squares smoothed `<name>.smooth.png` (upscaled by
+    /// this factor) and `<name>.svg` contours, splitting cells into dark/light
+    #[arg(long, value_name = "SCALE")]
+    smooth: Option<usize>,
+
+    /// Corner-based model: input pixels are terrain at tile corners, output is
+    /// drawn as transition tiles at this many pixels per tile. Implies
+    /// `--pattern-size 2`
+    #[arg(
+        long,
+        value_name = "SCALE",
+        conflicts_with_all = ["pattern_size", "pattern_height", "heightmap", "layers", "surprise"]
+    )]
+    dual_grid: Option<usize>,
+
+    /// Don't read or write the extracted-model cache
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Write the settings, with the winning seed, for `wfc verify`. Not
+    /// allowed with options the file can't hold (row profiles, macro
+    /// layouts, masks, bias targets, and samples that `verify --sample`
+    /// wouldn't load the same way), since verify couldn't reproduce the run
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = [
+            "seeds", "row_profiles", "importance", "macro_scale", "bias", "mask",
+            "layers", "heightmap", "url", "quantize",
+        ]
+    )]
+    save_config: Option<PathBuf>,
+
+    /// Max attempts with fresh seeds before giving up on contradictions
     #[arg(short, long, default_value_t = 10)]
     retries: usize,
+
+    /// Recolor the output with a palette file (GIMP .gpl or one hex color per
+    /// line), assigned to sample colors from most to least frequent
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["heightmap", "layers"])]
+    palette: Option<PathBuf>,
+
+    /// Recolor the output with the nearest of this image's dominant colors
+    #[arg(long, value_name = "IMAGE", conflicts_with_all = ["palette", "heightmap", "layers"])]
+    palette_from: Option<PathBuf>,
+
+    /// Replace one sample color in the output (repeatable), applied after
+    /// --palette, e.g. `203040=101010`
+    #[arg(long, value_name = "FROM=TO", value_parser = parse_recolor, conflicts_with_all = ["heightmap", "layers"])]
+    recolor: Vec<(Color, Color)>,
+
+    /// Randomize pattern size, symmetry, periodicity, boundary, ground and
+    /// heuristic; --seed makes the pick reproducible
+    #[arg(long, conflicts_with_all = ["pattern_size", "pattern_height", "no_symmetry", "symmetry", "boundary", "input_boundary", "ground", "edges"])]
+    surprise: bool,
+
+    /// Generate one output per seed in `START..END` (or `START..=END`),
+    /// written as `<name>-<seed>.<ext>`; contradicted seeds are skipped
+    #[arg(long, value_name = "RANGE", value_parser = parse_seed_range, conflicts_with_all = ["seed", "stdout"])]
+    seeds: Option<Range<u64>>,
+
+    /// Also composite the --seeds outputs into one grid image, in seed order;
+    /// contradicted seeds leave an empty cell
+    #[arg(long, value_name = "FILE", requires = "seeds")]
+    montage: Option<PathBuf>,
+
+    /// Columns of the --montage grid (default: as square as possible)
+    #[arg(long, value_name = "N", requires = "montage")]
+    montage_columns: Option<usize>,
+
+    /// Enlarge each --montage cell by this factor
+    #[arg(long, value_name = "N", default_value_t = 2, requires = "montage")]
+    montage_scale: usize,
+
+    /// Caption each --montage cell with its seed
+    #[arg(long, requires = "montage")]
+    montage_labels: bool,
+
+    /// Worker threads for --seeds
+    #[arg(short, long, default_value_t = std::thread::available_parallelism().map_or(1, |n| n.get()))]
+    jobs: usize,
 }
 
 #[derive(Subcommand)]
 enum Command {
     /// Run WFC to generate an output image
-    Run(RunArgs),
+    Run(Box<RunArgs>),
+    /// Regenerate an output from saved settings and check its hash
+    Verify(VerifyArgs),
     /// Generate built-in sample pattern images into a directory
     GenerateSamples {
         /// Output directory
@@ -86,77 +505,895 @@ enum Command {
     },
 }
 
+#[derive(Args)]
+struct VerifyArgs {
+    /// Sample image the output was generated from (default: built-in pipes)
+    #[arg(long)]
+    sample: Option<PathBuf>,
+
+    /// Settings file, as written by `run --save-config`
+    #[arg(long)]
+    config: PathBuf,
+
+    /// Seed to use instead of the one in the settings file
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Expected output hash (the `hash` of `run`'s JSON summary)
+    #[arg(long, value_name = "HASH", value_parser = parse_hash)]
+    expect_hash: u64,
+}
+
 fn main() {
     let cli = Cli::parse();
 
     match cli.command {
-        Command::Run(args) => cmd_run(args),
+        Command::Run(args) => cmd_run(*args),
+        Command::Verify(args) => cmd_verify(args),
         Command::GenerateSamples { dir } => cmd_generate_samples(&dir),
     }
 }
 
+#[cfg(feature = "url")]
+fn fetch_sample(url: &str) -> Result<Sample, wfc_core::Error> {
+    Sample::from_url(url)
+}
+
+#[cfg(not(feature = "url"))]
+fn fetch_sample(_url: &str) -> Result<Sample, wfc_core::Error> {
+    Err(wfc_core::Error::Fetch(
+        "built without the `url` feature".to_string(),
+    ))
+}
+
 fn cmd_run(args: RunArgs) {
     let RunArgs {
         input,
+        url,
         output,
+        stdout,
+        layers,
+        heightmap,
         width,
         height,
         pattern_size,
+        pattern_height,
         seed,
         no_symmetry,
+        symmetry,
+        output_symmetry,
+        variant_weight,
+        ground,
+        edges,
+        ground_boost,
+        row_profiles,
+        importance,
+        macro_scale,
+        macro_sample,
+        macro_strength,
+        bias,
+        bias_strength,
+        weight_multipliers,
+        direction_weights,
+        frequency_tolerance,
+        banned_patterns,
+        mask,
+        companion,
+        diagonal,
+        strict_overlap,
+        color_tolerance,
+        min_pattern_count,
+        quantize,
+        lazy_propagator,
+        heuristic,
+        entropy_noise,
+        connected,
         boundary,
+        input_boundary,
+        smooth,
+        dual_grid,
+        no_cache,
+        save_config,
         retries,
+        palette: palette_file,
+        palette_from,
+        recolor,
+        surprise,
+        seeds,
+        montage,
+        montage_columns,
+        montage_scale,
+        montage_labels,
+        jobs,
     } = args;
+    let mut report = Report::new(stdout.is_some());
     let sample = match &input {
+        Some(path) if heightmap => Heightmap::from_image(path)
+            .map(|hm| hm.to_sample())
+            .unwrap_or_else(|e| {
+                eprintln!("Error loading heightmap '{}': {}", path.display(), e);
+                report.exit("error", EXIT_ERROR);
+            }),
         Some(path) => Sample::from_image(path).unwrap_or_else(|e| {
             eprintln!("Error loading sample '{}': {}", path.display(), e);
-            process::exit(1);
+            report.exit("error", EXIT_ERROR);
         }),
-        None => default_pipe_sample(),
+        None => match &url {
+            Some(url) => fetch_sample(url).unwrap_or_else(|e| {
+                eprintln!("Error fetching sample '{}': {}", url, e);
+                report.exit("error", EXIT_ERROR);
+            }),
+            None => default_pipe_sample(),
+        },
+    };
+    let sample = match quantize {
+        Some(n) => sample.quantize(n as usize),
+        None => sample,
     };
 
-    let config = Config {
-        pattern_size,
+    let (sample, palette) = if layers.is_empty() {
+        (sample, None)
+    } else {
+        let mut all = vec![sample];
+        for path in &layers {
+            all.push(Sample::from_image(path).unwrap_or_else(|e| {
+                eprintln!("Error loading layer '{}': {}", path.display(), e);
+                report.exit("error", EXIT_ERROR);
+            }));
+        }
+        let layered = LayeredSample::new(all).unwrap_or_else(|e| {
+            eprintln!("Error combining layers: {}", e);
+            report.exit("invalid_config", EXIT_INVALID_CONFIG);
+        });
+        let (joint, palette) = layered.joint();
+        (joint, Some(palette))
+    };
+
+    let importance = match &importance {
+        Some(path) => sample.load_importance(path).unwrap_or_else(|e| {
+            eprintln!("Error loading importance mask '{}': {}", path.display(), e);
+            report.exit("invalid_config", EXIT_INVALID_CONFIG);
+        }),
+        None => Vec::new(),
+    };
+
+    let companions = companion
+        .into_iter()
+        .map(|path| {
+            let map = Sample::from_image(&path).unwrap_or_else(|e| {
+                eprintln!("Error loading companion '{}': {}", path.display(), e);
+                report.exit("error", EXIT_ERROR);
+            });
+            if (map.width, map.height) != (sample.width, sample.height) {
+                eprintln!(
+                    "Error: companion '{}' is {}x{} but the input is {}x{}",
+                    path.display(),
+                    map.width,
+                    map.height,
+                    sample.width,
+                    sample.height
+                );
+                report.exit("invalid_config", EXIT_INVALID_CONFIG);
+            }
+            (path, map)
+        })
+        .collect();
+
+    let (width, height, mask) = match &mask {
+        Some(path) => {
+            let image = Sample::from_image(path).unwrap_or_else(|e| {
+                eprintln!("Error loading mask '{}': {}", path.display(), e);
+                report.exit("error", EXIT_ERROR);
+            });
+            let keep = image
+                .pixels
+                .iter()
+                .map(|&[r, g, b]| r as u32 + g as u32 + b as u32 >= 384)
+                .collect();
+            (image.width, image.height, keep)
+        }
+        None => (width, height, Vec::new()),
+    };
+
+    let bias = bias.map(|path| {
+        let image = Sample::from_image(&path).unwrap_or_else(|e| {
+            eprintln!("Error loading bias target '{}': {}", path.display(), e);
+            report.exit("error", EXIT_ERROR);
+        });
+        BiasField::Target {
+            image,
+            strength: bias_strength,
+        }
+    });
+
+    let mut config = Config {
+        pattern_size: if dual_grid.is_some() { 2 } else { pattern_size },
+        pattern_height,
         output_width: width,
         output_height: height,
-        periodic_input: true,
+        input_boundary: input_boundary.into(),
         boundary: boundary.into(),
-        symmetry: !no_symmetry,
-        ground: false,
-        sides: false,
+        symmetry: if no_symmetry {
+            Symmetry::NONE
+        } else {
+            symmetry.unwrap_or_default()
+        },
+        output_symmetry: output_symmetry.into(),
+        variant_weight,
+        edges: {
+            let mut all = if ground {
+                Edges::ground()
+            } else {
+                Edges::default()
+            };
+            for (side, constraint) in edges {
+                match side {
+                    "top" => all.top = constraint,
+                    "bottom" => all.bottom = constraint,
+                    "left" => all.left = constraint,
+                    _ => all.right = constraint,
+                }
+            }
+            all
+        },
+        ground_boost,
+        row_profiles,
+        bias,
+        importance,
+        weight_multipliers,
+        direction_weights: direction_weights.unwrap_or([1.0; 4]),
+        frequency_tolerance,
+        banned_patterns,
+        mask: mask.clone(),
         seed,
+        diagonal,
+        strict_overlap,
+        color_tolerance,
+        min_pattern_count,
+        lazy_propagator,
+        dual_grid: dual_grid.is_some(),
+        heuristic: heuristic.into(),
+        entropy_noise,
+        connected,
+        model_cache: (!no_cache).then(default_cache_dir),
         ..Default::default()
     };
+    if surprise {
+        let surprise_seed = config.randomize(&sample, seed);
+        eprintln!(
+            "Surprise: pattern size {}, symmetry {}, {:?} input, {:?} boundary, ground {}, \
+             flexibility heuristic {} (repeat with --surprise --seed {})",
+            config.pattern_size,
+            config.symmetry,
+            config.input_boundary,
+            config.boundary,
+            if config.edges.is_ground() {
+                "on"
+            } else {
+                "off"
+            },
+            if config.use_flexibility { "on" } else { "off" },
+            surprise_seed
+        );
+    }
 
-    for attempt in 1..=retries {
-        let mut wfc = Wfc::new(&sample, config.clone());
+    if let Err(e) = config.validate(&sample) {
+        eprintln!("Error: {}", e);
+        report.exit("invalid_config", EXIT_INVALID_CONFIG);
+    }
+    let stats = sample.analyze(&config);
+    if stats.unique_windows > Config::MAX_PATTERNS {
+        eprintln!(
+            "Error: {} distinct {}x{} windows, more than the {} patterns supported; \
+             try a smaller pattern size or sample",
+            stats.unique_windows,
+            config.pattern_dims().0,
+            config.pattern_dims().1,
+            Config::MAX_PATTERNS
+        );
+        report.exit("invalid_config", EXIT_INVALID_CONFIG);
+    }
+    if stats.estimated_patterns > LARGE_MODEL_PATTERNS {
+        eprintln!(
+            "Warning: about {} patterns and {} MB of wave state; building and solving \
+             will be slow. Try a smaller pattern size, less symmetry or a smaller sample",
+            stats.estimated_patterns,
+            stats.estimated_wave_bytes.div_ceil(1 << 20)
+        );
+    }
 
-        if wfc.run() == RunOutcome::Complete {
-            let colors = wfc.render();
-            let out_sample = Sample::new(width, height, colors);
-            match out_sample.save(Path::new(&output)) {
-                Ok(()) => {
-                    eprintln!("Saved to {} (attempt {})", output.display(), attempt);
-                    return;
-                }
+    if let Some(scale) = macro_scale {
+        let scale = scale as usize;
+        let regions = match &macro_sample {
+            Some(path) => Sample::from_image(path).unwrap_or_else(|e| {
+                eprintln!("Error loading macro sample '{}': {}", path.display(), e);
+                report.exit("error", EXIT_ERROR);
+            }),
+            None => sample.downscale(scale),
+        };
+        let Some(guide) = Guide::generate(regions, &config, scale, macro_strength, retries) else {
+            eprintln!(
+                "Macro pass failed after {} retries - all attempts hit contradictions",
+                retries
+            );
+            report.attempts = retries;
+            report.exit("contradiction", EXIT_CONTRADICTION);
+        };
+        config.guide = Some(guide);
+        if let Err(e) = config.validate(&sample) {
+            eprintln!("Error: {}", e);
+            report.exit("invalid_config", EXIT_INVALID_CONFIG);
+        }
+    }
+
+    let recolored = palette_file.is_some() || palette_from.is_some() || !recolor.is_empty();
+    let color_map = recolored.then(|| {
+        let mut map = ColorMap::from_sample(&sample);
+        if let Some(path) = &palette_file {
+            match load_palette(path) {
+                Ok(colors) => map.assign(&colors),
                 Err(e) => {
-                    eprintln!("Error saving '{}': {}", output.display(), e);
-                    process::exit(1);
+                    eprintln!("Error loading palette '{}': {}", path.display(), e);
+                    report.exit("invalid_config", EXIT_INVALID_CONFIG);
                 }
             }
         }
+        if let Some(path) = &palette_from {
+            let reference = Sample::from_image(path).unwrap_or_else(|e| {
+                eprintln!("Error loading palette image '{}': {}", path.display(), e);
+                report.exit("error", EXIT_ERROR);
+            });
+            map.match_nearest(&dominant_colors(&reference.pixels, map.entries.len()));
+        }
+        for &(from, to) in &recolor {
+            map.set(from, to);
+        }
+        map
+    });
+
+    let outputs = OutputOptions {
+        width,
+        height,
+        heightmap,
+        palette,
+        color_map,
+        smooth,
+        dual_grid: dual_grid.map(|scale| (config.boundary, scale.max(1))),
+        companions,
+        mask,
+        transparent: sample.transparent(),
+        montage: montage.map(|path| MontageOptions {
+            path,
+            columns: montage_columns,
+            scale: montage_scale,
+            labels: montage_labels,
+        }),
+    };
+    // Also catches models the estimates let through: too many patterns
+    // once symmetry variants count, or none left by --min-pattern-count
+    let mut wfc = build_solver(&sample, config.clone(), &report);
+    if let Some(seeds) = seeds {
+        run_seeds(&sample, wfc, seeds, jobs, &output, &outputs, &mut report);
+        return;
+    }
+
+    let retried = wfc.run_with_retries(retries, Duration::MAX);
+    if retried.outcome != RunOutcome::Complete {
+        eprintln!(
+            "Failed after {} retries - all attempts hit contradictions",
+            retries
+        );
+        report.attempts = retries;
+        report.exit("contradiction", EXIT_CONTRADICTION);
+    }
+    let solved = Solved {
+        output: wfc.render(),
+        seed: wfc.seed(),
+        attempts: retried.attempts,
+        steps: wfc.steps(),
+        propagation_time: wfc.propagation_time(),
+    };
+    report.seed = Some(solved.seed);
+    report.attempts = solved.attempts;
+    report.steps = solved.steps;
+    report.hash = Some(Sample::new(width, height, solved.output.clone()).content_hash());
+    if let Some(path) = &save_config {
+        let winning = Config {
+            seed: Some(solved.seed),
+            ..config.clone()
+        };
+        if let Err(e) = winning.save(path) {
+            eprintln!("Error saving config '{}': {}", path.display(), e);
+            report.exit("error", EXIT_ERROR);
+        }
+    }
+
+    if let Some(format) = stdout {
+        let colors = outputs.recolor(solved.output);
+        let bytes = match (format, heightmap) {
+            (StdoutFormat::Png, true) => {
+                Heightmap::from_colors(width, height, &colors).encode_png()
+            }
+            (StdoutFormat::Ppm, true) => {
+                Ok(Heightmap::from_colors(width, height, &colors).encode_pgm())
+            }
+            (StdoutFormat::Png, false) => outputs.image(colors).encode_png(),
+            (StdoutFormat::Ppm, false) => Ok(Sample::new(width, height, colors).encode_ppm()),
+        };
+        let written = bytes.map_err(|e| e.to_string()).and_then(|bytes| {
+            let mut out = std::io::stdout().lock();
+            out.write_all(&bytes)
+                .and_then(|_| out.flush())
+                .map_err(|e| e.to_string())
+        });
+        if let Err(e) = written {
+            eprintln!("Error writing to stdout: {}", e);
+            report.exit("error", EXIT_ERROR);
+        }
+        eprintln!(
+            "Wrote to stdout (seed {}, attempt {}, propagation {:.1?})",
+            solved.seed, solved.attempts, solved.propagation_time
+        );
+        report.print("complete");
+        return;
+    }
+
+    if let Err(e) = outputs.save(&output, solved.output) {
+        eprintln!("{}", e);
+        report.exit("error", EXIT_ERROR);
+    }
+    if let Err(e) = outputs.save_companions(&output, &wfc) {
+        eprintln!("{}", e);
+        report.exit("error", EXIT_ERROR);
+    }
+    eprintln!(
+        "Saved to {} (seed {}, attempt {}, propagation {:.1?})",
+        output.display(),
+        solved.seed,
+        solved.attempts,
+        solved.propagation_time
+    );
+    report.output = Some(output);
+    report.print("complete");
+}
+
+fn cmd_verify(args: VerifyArgs) {
+    let mut report = Report::new(false);
+    let sample = match &args.sample {
+        Some(path) => Sample::from_image(path).unwrap_or_else(|e| {
+            eprintln!("Error loading sample '{}': {}", path.display(), e);
+            report.exit("error", EXIT_ERROR);
+        }),
+        None => default_pipe_sample(),
+    };
+    let mut config = Config::load(&args.config).unwrap_or_else(|e| {
+        eprintln!("Error loading config '{}': {}", args.config.display(), e);
+        report.exit("invalid_config", EXIT_INVALID_CONFIG);
+    });
+    if args.seed.is_some() {
+        config.seed = args.seed;
+    }
+    let Some(seed) = config.seed else {
+        eprintln!("Error: no seed in the config; pass --seed");
+        report.exit("invalid_config", EXIT_INVALID_CONFIG);
+    };
+    if let Err(e) = config.validate(&sample) {
+        eprintln!("Error: {}", e);
+        report.exit("invalid_config", EXIT_INVALID_CONFIG);
+    }
+    config.model_cache = Some(default_cache_dir());
+
+    let (width, height) = (config.output_width, config.output_height);
+    let mut wfc = build_solver(&sample, config, &report);
+    let outcome = wfc.run();
+    report.seed = Some(seed);
+    report.attempts = 1;
+    report.steps = wfc.steps();
+    if outcome != RunOutcome::Complete {
+        eprintln!(
+            "Seed {} contradicted; the settings cannot reproduce an output",
+            seed
+        );
+        if let Some(info) = wfc.contradiction_info() {
+            eprintln!("Last contradiction: {}", info);
+        }
+        report.exit("contradiction", EXIT_CONTRADICTION);
+    }
 
+    let hash = Sample::new(width, height, wfc.render()).content_hash();
+    report.hash = Some(hash);
+    if hash != args.expect_hash {
         eprintln!(
-            "Attempt {}/{}: contradiction, retrying...",
-            attempt, retries
+            "Mismatch: output hash {:016x}, expected {:016x}",
+            hash, args.expect_hash
         );
+        report.exit("mismatch", EXIT_MISMATCH);
+    }
+    eprintln!("Verified: output hash {:016x}", hash);
+    report.print("verified");
+}
+
+/// 16 hex digits, as printed in the JSON summary.
+fn parse_hash(arg: &str) -> Result<u64, String> {
+    u64::from_str_radix(arg.trim_start_matches("0x"), 16).map_err(|e| e.to_string())
+}
+
+/// How a solved output is written to disk.
+struct OutputOptions {
+    width: usize,
+    height: usize,
+    heightmap: bool,
+    palette: Option<LayerPalette>,
+    color_map: Option<ColorMap>,
+    smooth: Option<usize>,
+    /// Draw tiles between the output's corners at this scale.
+    dual_grid: Option<(Boundary, usize)>,
+    /// Maps aligned with the input, rendered from the same patterns.
+    companions: Vec<(PathBuf, Sample)>,
+    /// Generated cells (`Config::mask`); the rest are saved transparent.
+    mask: Vec<bool>,
+    /// Output color of the sample's transparent pixels, saved transparent.
+    transparent: Option<Color>,
+    /// Grid of all `--seeds` outputs.
+    montage: Option<MontageOptions>,
+}
+
+impl OutputOptions {
+    fn recolor(&self, colors: Vec<Color>) -> Vec<Color> {
+        match &self.color_map {
+            Some(map) => map.apply(&colors),
+            None => colors,
+        }
     }
 
+    fn save(&self, path: &Path, colors: Vec<Color>) -> Result<(), String> {
+        let (width, height) = (self.width, self.height);
+        let colors = self.recolor(colors);
+        if let Some(scale) = self.smooth {
+            save_smoothed(path, width, height, &colors, scale)
+                .map_err(|e| format!("Error saving smoothed output: {}", e))?;
+        }
+
+        let saved = if let Some((boundary, scale)) = self.dual_grid {
+            let (tiles_w, tiles_h) = dual::tile_dims(width, height, boundary);
+            let pixels = dual::render_tiles(width, height, &colors, boundary, scale);
+            Sample::new(tiles_w * scale, tiles_h * scale, pixels).save(path)
+        } else if self.heightmap {
+            let hm = Heightmap::from_colors(width, height, &colors);
+            if path.extension().is_some_and(|ext| ext == "raw") {
+                hm.save_raw(path)
+            } else {
+                hm.save_png(path)
+            }
+        } else if let Some(palette) = &self.palette {
+            save_layers(path, width, height, palette.split(&colors))
+        } else if !self.mask.is_empty() {
+            self.image(colors).save_masked(path, &self.mask)
+        } else {
+            self.image(colors).save(path)
+        };
+        saved.map_err(|e| format!("Error saving '{}': {}", path.display(), e))
+    }
+
+    /// The output as a plain image: recolored, and drawn as tiles for the
+    /// dual grid. Smoothing and layer splitting are left out.
+    fn preview(&self, colors: Vec<Color>) -> Sample {
+        let colors = self.recolor(colors);
+        match self.dual_grid {
+            Some((boundary, scale)) => {
+                let (tiles_w, tiles_h) = dual::tile_dims(self.width, self.height, boundary);
+                let pixels = dual::render_tiles(self.width, self.height, &colors, boundary, scale);
+                Sample::new(tiles_w * scale, tiles_h * scale, pixels)
+            }
+            None => self.image(colors),
+        }
+    }
+
+    fn image(&self, colors: Vec<Color>) -> Sample {
+        Sample::new(self.width, self.height, colors).with_transparent(self.transparent)
+    }
+
+    /// Write each companion as `<stem>.<companion stem>.<ext>` beside `path`.
+    fn save_companions(&self, path: &Path, wfc: &Wfc) -> Result<(), String> {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let ext = path.extension().unwrap_or_default().to_string_lossy();
+        for (source, map) in &self.companions {
+            let name = source.file_stem().unwrap_or_default().to_string_lossy();
+            let target = path.with_file_name(format!("{}.{}.{}", stem, name, ext));
+            wfc.render_companion(map)
+                .and_then(|colors| Sample::new(self.width, self.height, colors).save(&target))
+                .map_err(|e| format!("Error saving '{}': {}", target.display(), e))?;
+        }
+        Ok(())
+    }
+}
+
+/// Grid image of every `--seeds` output.
+struct MontageOptions {
+    path: PathBuf,
+    /// Square-ish when unset.
+    columns: Option<usize>,
+    scale: usize,
+    /// Caption cells with their seed.
+    labels: bool,
+}
+
+/// Build the solver, exiting with `EXIT_INVALID_CONFIG` if the model can't
+/// be built.
+fn build_solver(sample: &Sample, config: Config, report: &Report) -> Wfc {
+    Wfc::with_progress(sample, config, |_| true).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        report.exit("invalid_config", EXIT_INVALID_CONFIG);
+    })
+}
+
+/// Solve every seed in `seeds` on `jobs` threads, writing `<stem>-<seed>.<ext>`.
+/// Each seed gets a single attempt, so the file name alone reproduces it.
+fn run_seeds(
+    sample: &Sample,
+    built: Wfc,
+    seeds: Range<u64>,
+    jobs: usize,
+    output: &Path,
+    outputs: &OutputOptions,
+    report: &mut Report,
+) {
+    let total = seeds.end - seeds.start;
+    let next = AtomicU64::new(seeds.start);
+    let done = AtomicU64::new(0);
+    let steps = AtomicUsize::new(0);
+    let failed = Mutex::new(Vec::new());
+    let errors = AtomicUsize::new(0);
+    let previews = Mutex::new(Vec::new());
+    // The first thread takes the already built solver
+    let config = built.config().clone();
+    let built = Mutex::new(Some(built));
+
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = output.extension().unwrap_or_default().to_string_lossy();
+    let seeded_path = |seed: u64| output.with_file_name(format!("{}-{}.{}", stem, seed, ext));
+
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, total.max(1) as usize) {
+            scope.spawn(|| {
+                // One solver per thread; reseeding reuses its extracted rules
+                let mut solver: Option<Wfc> = None;
+                loop {
+                    let seed = next.fetch_add(1, Ordering::Relaxed);
+                    if seed >= seeds.end {
+                        break;
+                    }
+                    let wfc = solver.get_or_insert_with(|| {
+                        built
+                            .lock()
+                            .unwrap()
+                            .take()
+                            .unwrap_or_else(|| Wfc::new(sample, config.clone()))
+                    });
+                    wfc.reseed(seed);
+
+                    if wfc.run() == RunOutcome::Complete {
+                        let path = seeded_path(seed);
+                        if outputs.montage.is_some() {
+                            let preview = outputs.preview(wfc.render());
+                            previews.lock().unwrap().push((seed, preview));
+                        }
+                        let saved = outputs
+                            .save(&path, wfc.render())
+                            .and_then(|_| outputs.save_companions(&path, wfc));
+                        if let Err(e) = saved {
+                            eprintln!("\r{}", e);
+                            errors.fetch_add(1, Ordering::Relaxed);
+                        }
+                    } else {
+                        failed.lock().unwrap().push(seed);
+                    }
+                    steps.fetch_add(wfc.steps(), Ordering::Relaxed);
+
+                    let finished = done.fetch_add(1, Ordering::Relaxed) + 1;
+                    eprint!(
+                        "\r{}/{} seeds, {} contradicted",
+                        finished,
+                        total,
+                        failed.lock().unwrap().len()
+                    );
+                }
+            });
+        }
+    });
+    eprintln!();
+
+    let mut failed = failed.into_inner().unwrap();
+    failed.sort_unstable();
+    if !failed.is_empty() {
+        eprintln!("Contradicted seeds: {:?}", failed);
+    }
     eprintln!(
-        "Failed after {} retries - all attempts hit contradictions",
-        retries
+        "Saved {} outputs to {}",
+        total as usize - failed.len() - errors.load(Ordering::Relaxed),
+        output
+            .with_file_name(format!("{}-<seed>.{}", stem, ext))
+            .display()
     );
-    process::exit(1);
+
+    if let Some(grid) = &outputs.montage {
+        let previews = previews.into_inner().unwrap();
+        let labels: Vec<String> = seeds
+            .clone()
+            .map(|seed| {
+                if grid.labels {
+                    seed.to_string()
+                } else {
+                    String::new()
+                }
+            })
+            .collect();
+        let cells: Vec<_> = seeds
+            .clone()
+            .zip(&labels)
+            .map(|(seed, label)| {
+                let preview = previews.iter().find(|(s, _)| *s == seed);
+                (preview.map(|(_, p)| p), label.as_str())
+            })
+            .collect();
+        let columns = grid
+            .columns
+            .unwrap_or_else(|| (cells.len() as f64).sqrt().ceil() as usize)
+            .clamp(1, cells.len());
+        let image = montage::compose(&cells, columns, grid.scale);
+        match image.save(&grid.path) {
+            Ok(()) => eprintln!(
+                "Saved {}x{} montage to {}",
+                columns,
+                cells.len().div_ceil(columns),
+                grid.path.display()
+            ),
+            Err(e) => {
+                eprintln!("Error saving montage '{}': {}", grid.path.display(), e);
+                errors.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    report.attempts = total as usize;
+    report.steps = steps.into_inner();
+    if errors.into_inner() > 0 {
+        report.exit("error", EXIT_ERROR);
+    }
+    if !failed.is_empty() {
+        report.exit("contradiction", EXIT_CONTRADICTION);
+    }
+    report.print("complete");
+}
+
+/// `START..END` (exclusive) or `START..=END`.
+fn parse_seed_range(arg: &str) -> Result<Range<u64>, String> {
+    let (start, end) = arg
+        .split_once("..")
+        .ok_or_else(|| "expected START..END".to_string())?;
+    let start: u64 = start
+        .parse()
+        .map_err(|_| format!("invalid start seed '{}'", start))?;
+    let end = match end.strip_prefix('=') {
+        Some(inclusive) => inclusive.parse::<u64>().map(|e| e.saturating_add(1)),
+        None => end.parse(),
+    }
+    .map_err(|_| format!("invalid end seed '{}'", end))?;
+    if start >= end {
+        return Err("seed range is empty".to_string());
+    }
+    Ok(start..end)
+}
+
+fn parse_color(hex: &str) -> Result<Color, String> {
+    parse_hex(hex).ok_or_else(|| format!("invalid color '{}', expected RRGGBB", hex))
+}
+
+/// `FROM=TO` hex colors.
+fn parse_recolor(arg: &str) -> Result<(Color, Color), String> {
+    let (from, to) = arg
+        .split_once('=')
+        .ok_or_else(|| "expected FROM=TO".to_string())?;
+    Ok((parse_color(from)?, parse_color(to)?))
+}
+
+/// `SIDE=SPEC`; see `--edge`.
+fn parse_edge(arg: &str) -> Result<(&'static str, EdgeConstraint), String> {
+    let (side, spec) = arg
+        .split_once('=')
+        .ok_or_else(|| "expected SIDE=SPEC".to_string())?;
+    let side = ["top", "bottom", "left", "right"]
+        .into_iter()
+        .find(|&s| s == side.trim())
+        .ok_or_else(|| {
+            format!(
+                "invalid side '{}', expected top, bottom, left or right",
+                side
+            )
+        })?;
+    Ok((
+        side,
+        spec.parse().map_err(|e: wfc_core::Error| e.to_string())?,
+    ))
+}
+
+fn parse_direction_weights(arg: &str) -> Result<[f64; 4], String> {
+    let weights: Vec<f64> = arg
+        .split(',')
+        .map(|w| {
+            w.trim()
+                .parse()
+                .map_err(|_| format!("invalid weight '{}'", w))
+        })
+        .collect::<Result<_, _>>()?;
+    weights
+        .try_into()
+        .map_err(|_| "expected four weights: RIGHT,DOWN,LEFT,UP".to_string())
+}
+
+fn parse_row_profile(arg: &str) -> Result<RowProfile, String> {
+    let (hex, spec) = arg
+        .split_once(':')
+        .ok_or_else(|| "expected COLOR:SPEC".to_string())?;
+    let color = parse_color(hex)?;
+
+    let curve: Result<Vec<f64>, _> = spec.split(',').map(str::parse).collect();
+    match curve {
+        Ok(curve) => Ok(RowProfile::new(color, curve)),
+        Err(_) => RowProfile::from_mask_image(color, Path::new(spec)).map_err(|e| e.to_string()),
+    }
+}
+
+/// Write `<stem>.smooth.png` and `<stem>.svg` next to `output`.
+fn save_smoothed(
+    output: &Path,
+    width: usize,
+    height: usize,
+    colors: &[Color],
+    scale: usize,
+) -> Result<(), wfc_core::Error> {
+    let dark = smooth::dark_mask(colors);
+    let pick = |want: bool| {
+        dark.iter()
+            .position(|&d| d == want)
+            .map_or([0, 0, 0], |i| colors[i])
+    };
+    let (dark_color, light_color) = (pick(true), pick(false));
+
+    let scale = scale.max(1);
+    let mask = smooth::smooth_mask(width, height, &dark, scale);
+    let pixels = mask
+        .iter()
+        .map(|&d| if d { dark_color } else { light_color })
+        .collect();
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    Sample::new(width * scale, height * scale, pixels)
+        .save(&output.with_file_name(format!("{}.smooth.png", stem)))?;
+
+    let segments = smooth::marching_squares(width, height, &dark);
+    let svg = smooth::contour_svg(width, height, colors, &segments, scale as f32);
+    std::fs::write(output.with_file_name(format!("{}.svg", stem)), svg)
+        .map_err(|e| wfc_core::Error::ImageSave(e.to_string()))
+}
+
+/// Layer 0 goes to `output`, layer N to `<stem>.layerN.<ext>`.
+fn save_layers(
+    output: &Path,
+    width: usize,
+    height: usize,
+    layers: Vec<Vec<Color>>,
+) -> Result<(), wfc_core::Error> {
+    for (i, colors) in layers.into_iter().enumerate() {
+        let path = if i == 0 {
+            output.to_path_buf()
+        } else {
+            let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+            let ext = output.extension().unwrap_or_default().to_string_lossy();
+            output.with_file_name(format!("{}.layer{}.{}", stem, i, ext))
+        };
+        Sample::new(width, height, colors).save(&path)?;
+    }
+    Ok(())
 }
 
 #[allow(clippy::type_complexity)]
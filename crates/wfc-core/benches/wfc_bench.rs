@@ -47,6 +47,20 @@ fn bench_run(c: &mut Criterion) {
         });
     });
 
+    // Wave scans and bans dominate here; tracks the bitset wave at scale
+    c.bench_function("run_128x128_pipes", |b| {
+        let config = Config {
+            seed: Some(42),
+            output_width: 128,
+            output_height: 128,
+            ..Default::default()
+        };
+        b.iter(|| {
+            let mut wfc = Wfc::new(&pipes, config.clone());
+            wfc.run();
+        });
+    });
+
     c.bench_function("run_32x32_large_sample", |b| {
         let config = Config {
             seed: Some(42),
@@ -85,6 +99,30 @@ fn bench_init(c: &mut Criterion) {
         };
         b.iter(|| Wfc::new(&large, config.clone()));
     });
+
+    // Every overlapping offset: the largest propagator to build
+    c.bench_function("init_32x32_large_sample_strict", |b| {
+        let config = Config {
+            seed: Some(42),
+            output_width: 32,
+            output_height: 32,
+            strict_overlap: true,
+            ..Default::default()
+        };
+        b.iter(|| Wfc::new(&large, config.clone()));
+    });
+
+    // Tolerance compares every pattern pair instead of grouping by overlap
+    c.bench_function("init_32x32_large_sample_tolerance", |b| {
+        let config = Config {
+            seed: Some(42),
+            output_width: 32,
+            output_height: 32,
+            color_tolerance: 8,
+            ..Default::default()
+        };
+        b.iter(|| Wfc::new(&large, config.clone()));
+    });
 }
 
 fn bench_step(c: &mut Criterion) {
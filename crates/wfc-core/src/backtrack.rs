@@ -1,6 +1,6 @@
-use rand::rngs::SmallRng;
-
-use crate::bitset::Bitset;
+use crate::bitset::{Bitset, SmallSet};
+use crate::memory::vec_bytes;
+use crate::rng::SplitMix64;
 use crate::rules::Rules;
 use crate::state::State;
 
@@ -8,9 +8,12 @@ struct Snapshot {
     wave: Bitset,
     compat: Vec<u16>,
     num_possible: Vec<usize>,
+    small: Vec<SmallSet>,
+    placed: Vec<u32>,
+    decided: u32,
     weight_sum: Vec<f64>,
     wlog_sum: Vec<f64>,
-    rng: SmallRng,
+    rng: SplitMix64,
     /// Cell collapsed after this snapshot was taken.
     cell: usize,
     /// Pattern chosen (banned on backtrack).
@@ -18,11 +21,24 @@ struct Snapshot {
 }
 
 impl Snapshot {
+    fn heap_bytes(&self) -> usize {
+        self.wave.heap_bytes()
+            + vec_bytes(&self.compat)
+            + vec_bytes(&self.num_possible)
+            + vec_bytes(&self.small)
+            + vec_bytes(&self.placed)
+            + vec_bytes(&self.weight_sum)
+            + vec_bytes(&self.wlog_sum)
+    }
+
     fn capture(state: &State) -> Self {
         Self {
             wave: state.wave.clone(),
             compat: state.compat.clone(),
             num_possible: state.num_possible.clone(),
+            small: state.small.clone(),
+            placed: state.placed.clone(),
+            decided: state.decided,
             weight_sum: state.weight_sum.clone(),
             wlog_sum: state.wlog_sum.clone(),
             rng: state.rng.clone(),
@@ -35,6 +51,9 @@ impl Snapshot {
         state.wave = self.wave;
         state.compat = self.compat;
         state.num_possible = self.num_possible;
+        state.small = self.small;
+        state.placed = self.placed;
+        state.decided = self.decided;
         state.weight_sum = self.weight_sum;
         state.wlog_sum = self.wlog_sum;
         state.rng = self.rng;
@@ -42,6 +61,8 @@ impl Snapshot {
         state.contradiction = false;
         state.done = false;
         state.last_collapsed = None;
+        state.last_observation = None;
+        state.restart_observation();
     }
 }
 
@@ -66,11 +87,26 @@ impl BacktrackState {
         }
     }
 
-    /// Capture pre-collapse state if due for a snapshot.
-    pub(crate) fn before_collapse(&mut self, state: &State) {
+    /// Heap bytes held by stored snapshots.
+    pub(crate) fn heap_bytes(&self) -> usize {
+        self.snapshots
+            .iter()
+            .chain(&self.pending_snapshot)
+            .map(Snapshot::heap_bytes)
+            .sum::<usize>()
+            + vec_bytes(&self.snapshots)
+    }
+
+    /// Capture pre-collapse state if due for a snapshot. `rng` is the
+    /// generator from before the cell was observed, so a replayed decision,
+    /// which skips the observation, snapshots the same stream.
+    pub(crate) fn before_collapse(&mut self, state: &State, rng: &SplitMix64) {
         self.collapse_count += 1;
         if self.collapse_count.is_multiple_of(self.snapshot_interval) {
-            self.pending_snapshot = Some(Snapshot::capture(state));
+            self.pending_snapshot = Some(Snapshot {
+                rng: rng.clone(),
+                ..Snapshot::capture(state)
+            });
         } else {
             self.pending_snapshot = None;
         }
@@ -85,6 +121,11 @@ impl BacktrackState {
         }
     }
 
+    /// Whether [`try_backtrack`](Self::try_backtrack) would replace the wave.
+    pub(crate) fn can_restore(&self) -> bool {
+        !self.snapshots.is_empty() && self.backtrack_count < self.max_backtracks
+    }
+
     /// Restore to an earlier snapshot and ban the pattern that caused the contradiction.
     pub(crate) fn try_backtrack(&mut self, state: &mut State, rules: &Rules) -> bool {
         while let Some(snapshot) = self.snapshots.pop() {
@@ -0,0 +1,110 @@
+//! Bevy integration: steps the solver each frame and mirrors the wave into
+//! an `Image`, so the collapse can be watched live in-engine.
+//!
+//! ```ignore
+//! let wfc = Wfc::new(&sample, config);
+//! let generator = WfcGenerator::new(wfc, &mut images);
+//! commands.spawn(Sprite::from_image(generator.image.clone()));
+//! commands.insert_resource(generator);
+//! ```
+
+use bevy::asset::RenderAssetUsages;
+use bevy::image::ImageSampler;
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+
+use crate::{RunOutcome, StepOutcome, Wfc};
+
+/// Adds the per-frame stepping system for a [`WfcGenerator`] resource.
+pub struct WfcPlugin;
+
+impl Plugin for WfcPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<WfcFinished>()
+            .add_systems(Update, step_generator);
+    }
+}
+
+/// Solver driven by [`WfcPlugin`]; insert it as a resource to start.
+#[derive(Resource)]
+pub struct WfcGenerator {
+    pub wfc: Wfc,
+    pub steps_per_frame: usize,
+    pub running: bool,
+    /// One texel per output cell, updated after every stepped frame.
+    pub image: Handle<Image>,
+}
+
+impl WfcGenerator {
+    pub fn new(wfc: Wfc, images: &mut Assets<Image>) -> Self {
+        let config = wfc.config();
+        let size = Extent3d {
+            width: config.output_width as u32,
+            height: config.output_height as u32,
+            depth_or_array_layers: 1,
+        };
+        let mut image = Image::new_fill(
+            size,
+            TextureDimension::D2,
+            &[0, 0, 0, 255],
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::default(),
+        );
+        image.sampler = ImageSampler::nearest();
+        write_wave(&wfc, &mut image);
+
+        Self {
+            wfc,
+            steps_per_frame: 1,
+            running: true,
+            image: images.add(image),
+        }
+    }
+}
+
+/// Sent once when the generator completes or gets stuck on a contradiction.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct WfcFinished(pub RunOutcome);
+
+fn step_generator(
+    generator: Option<ResMut<WfcGenerator>>,
+    mut images: ResMut<Assets<Image>>,
+    mut finished: EventWriter<WfcFinished>,
+) {
+    let Some(mut generator) = generator else {
+        return;
+    };
+    if !generator.running {
+        return;
+    }
+
+    for _ in 0..generator.steps_per_frame {
+        match generator.wfc.step() {
+            StepOutcome::Progressed => continue,
+            StepOutcome::Complete => {
+                generator.running = false;
+                finished.write(WfcFinished(RunOutcome::Complete));
+                break;
+            }
+            StepOutcome::Contradiction => {
+                generator.running = false;
+                finished.write(WfcFinished(RunOutcome::Contradiction));
+                break;
+            }
+        }
+    }
+
+    if let Some(image) = images.get_mut(&generator.image) {
+        write_wave(&generator.wfc, image);
+    }
+}
+
+fn write_wave(wfc: &Wfc, image: &mut Image) {
+    let Some(data) = image.data.as_mut() else {
+        return;
+    };
+    for (texel, color) in data.chunks_exact_mut(4).zip(wfc.render()) {
+        texel[..3].copy_from_slice(&color);
+        texel[3] = 255;
+    }
+}
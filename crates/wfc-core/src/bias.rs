@@ -0,0 +1,69 @@
+use std::fmt;
+use std::sync::Arc;
+
+use crate::{Color, Sample};
+
+/// Soft per-cell preference applied when a cell collapses, set as
+/// `Config::bias`: each candidate's weight is scaled by the field's factor
+/// at that cell, so large-scale gradients like sky above and ground below
+/// emerge without banning anything. Entropy ignores it.
+#[derive(Clone)]
+pub enum BiasField {
+    /// Favor patterns whose top-left color is close to the target's color
+    /// under the cell, the target stretched over the output. A pattern
+    /// `d` away (0 to 1 across the RGB cube) is scaled by `exp(-strength * d)`.
+    Target { image: Sample, strength: f64 },
+    /// Factor for `(x, y, pattern)`, with `pattern` indexing
+    /// `Wfc::patterns`. Clamped to `1e-9..=1e9` so weights stay positive
+    /// and finite; NaN counts as 1.
+    Custom(Arc<dyn Fn(usize, usize, usize) -> f64 + Send + Sync>),
+}
+
+impl BiasField {
+    /// Field from a closure over `(x, y, pattern)`.
+    pub fn custom(f: impl Fn(usize, usize, usize) -> f64 + Send + Sync + 'static) -> Self {
+        Self::Custom(Arc::new(f))
+    }
+
+    /// Weight factor for `pattern`, of top-left `color`, at `(x, y)` of a
+    /// `width` x `height` output.
+    pub(crate) fn factor(
+        &self,
+        (x, y): (usize, usize),
+        (width, height): (usize, usize),
+        pattern: usize,
+        color: Color,
+    ) -> f64 {
+        match self {
+            Self::Target { image, strength } => {
+                let target = image.get(
+                    x * image.width / width.max(1),
+                    y * image.height / height.max(1),
+                );
+                let squared: f64 = target
+                    .iter()
+                    .zip(color)
+                    .map(|(&a, b)| (f64::from(a) - f64::from(b)).powi(2))
+                    .sum();
+                (-strength * squared.sqrt() / (255.0 * 3f64.sqrt())).exp()
+            }
+            Self::Custom(f) => match f(x, y, pattern) {
+                factor if factor.is_nan() => 1.0,
+                factor => factor.clamp(1e-9, 1e9),
+            },
+        }
+    }
+}
+
+impl fmt::Debug for BiasField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Target { image, strength } => f
+                .debug_struct("Target")
+                .field("image", &(image.width, image.height))
+                .field("strength", strength)
+                .finish(),
+            Self::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
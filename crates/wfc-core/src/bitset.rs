@@ -28,6 +28,10 @@ impl Bitset {
         }
     }
 
+    pub(crate) fn heap_bytes(&self) -> usize {
+        crate::memory::vec_bytes(&self.bits)
+    }
+
     #[inline(always)]
     pub(crate) fn is_set(&self, cell: usize, pattern: usize) -> bool {
         let word_idx = cell * self.words_per_cell + pattern / 64;
@@ -110,6 +114,63 @@ impl Iterator for BitsetIter<'_> {
     }
 }
 
+/// Cells at or below this many candidates also keep them in a [`SmallSet`].
+pub(crate) const SMALL_MAX: usize = 8;
+
+/// A nearly collapsed cell's remaining patterns, inline and ascending, so
+/// reading them costs the candidates rather than every word of the cell.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct SmallSet {
+    len: u8,
+    items: [u16; SMALL_MAX],
+}
+
+impl SmallSet {
+    /// The first `SMALL_MAX` of `patterns`, which must be ascending.
+    pub(crate) fn collect(patterns: impl Iterator<Item = usize>) -> Self {
+        let mut set = Self::default();
+        for (slot, p) in set.items.iter_mut().zip(patterns) {
+            *slot = p as u16;
+            set.len += 1;
+        }
+        set
+    }
+
+    pub(crate) fn remove(&mut self, pattern: usize) {
+        let len = self.len as usize;
+        if let Some(i) = self.items[..len]
+            .iter()
+            .position(|&p| p as usize == pattern)
+        {
+            self.items.copy_within(i + 1..len, i);
+            self.len -= 1;
+        }
+    }
+
+    #[inline]
+    pub(crate) fn as_slice(&self) -> &[u16] {
+        &self.items[..self.len as usize]
+    }
+}
+
+/// A cell's remaining patterns, ascending, from whichever form is cheaper.
+pub(crate) enum Candidates<'a> {
+    Small(std::slice::Iter<'a, u16>),
+    Dense(BitsetIter<'a>),
+}
+
+impl Iterator for Candidates<'_> {
+    type Item = usize;
+
+    #[inline]
+    fn next(&mut self) -> Option<usize> {
+        match self {
+            Self::Small(iter) => iter.next().map(|&p| p as usize),
+            Self::Dense(iter) => iter.next(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -153,6 +214,14 @@ mod tests {
         assert!(set.contains(&66));
     }
 
+    #[test]
+    fn small_set_keeps_order_on_remove() {
+        let mut set = SmallSet::collect([1, 4, 6, 9].into_iter());
+        set.remove(4);
+        set.remove(5);
+        assert_eq!(set.as_slice(), &[1, 6, 9]);
+    }
+
     #[test]
     fn iter_does_not_yield_beyond_num_patterns() {
         // 10 patterns -> 1 word, bits 10..63 are cleared by new()
@@ -1,4 +1,5 @@
-/// Output grid edge behavior.
+/// Grid edge behavior: of the output as `Config::boundary`, of the sample
+/// during extraction as `Config::input_boundary`.
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub enum Boundary {
     /// No wrapping.
@@ -10,6 +11,13 @@ pub enum Boundary {
     PeriodicY,
     /// Wrap both axes (toroidal topology).
     Periodic,
+    /// Output only: past the right and bottom edges the output continues
+    /// reflected, so patterns reaching over an edge must match the cells
+    /// mirrored across it, as if the output were reflected there.
+    Mirror,
+    /// Output only: past the right and bottom edges the last column and
+    /// row repeat, so things crossing an edge run straight out of it.
+    Clamp,
 }
 
 impl Boundary {
@@ -22,4 +30,34 @@ impl Boundary {
     pub fn wraps_y(self) -> bool {
         matches!(self, Boundary::PeriodicY | Boundary::Periodic)
     }
+
+    /// Whether the output continues past its right and bottom edges,
+    /// mirrored or clamped, rather than stopping or wrapping.
+    #[inline]
+    pub fn extends(self) -> bool {
+        matches!(self, Boundary::Mirror | Boundary::Clamp)
+    }
+
+    /// How many `w`x`h` windows start along each axis of a `width`x`height`
+    /// image: every position on a wrapping axis, else only those that fit.
+    pub(crate) fn window_starts(
+        self,
+        width: usize,
+        height: usize,
+        w: usize,
+        h: usize,
+    ) -> (usize, usize) {
+        (
+            if self.wraps_x() {
+                width
+            } else {
+                width.saturating_sub(w - 1)
+            },
+            if self.wraps_y() {
+                height
+            } else {
+                height.saturating_sub(h - 1)
+            },
+        )
+    }
 }
@@ -0,0 +1,315 @@
+//! On-disk cache of extracted models, keyed by the sample and the settings
+//! that affect extraction. Best-effort: any I/O or format problem is a miss.
+//! Models saved explicitly by `Wfc::save_model` use the same format.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::config::Config;
+use crate::pattern::{Pattern, PatternOrigin};
+use crate::rules::{FlatPropagator, Model, Propagator};
+use crate::{Color, Error, Sample, Symmetry};
+
+const MAGIC: &[u8; 4] = b"WFCM";
+const VERSION: u32 = 5;
+/// Key of saved model files, which aren't tied to a sample.
+const MODEL_FILE_KEY: u64 = 0;
+
+/// `$XDG_CACHE_HOME/wfc`, falling back to `~/.cache/wfc`, then the temp dir.
+#[must_use]
+pub fn default_cache_dir() -> PathBuf {
+    std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .unwrap_or_else(std::env::temp_dir)
+        .join("wfc")
+}
+
+/// FNV-1a over everything that changes the extracted model.
+pub(crate) fn cache_key(samples: &[Sample], config: &Config) -> u64 {
+    let mut h: u64 = 0xcbf29ce484222325;
+    let mut feed = |bytes: &[u8]| {
+        for &b in bytes {
+            h ^= b as u64;
+            h = h.wrapping_mul(0x100000001b3);
+        }
+    };
+    feed(&VERSION.to_le_bytes());
+    for sample in samples {
+        feed(&(sample.width as u64).to_le_bytes());
+        feed(&(sample.height as u64).to_le_bytes());
+        for c in &sample.pixels {
+            feed(c);
+        }
+        match sample.transparent() {
+            Some(key) => feed(&[1, key[0], key[1], key[2]]),
+            None => feed(&[0]),
+        }
+    }
+    for w in &config.sample_weights {
+        feed(&w.to_le_bytes());
+    }
+    let (width, height) = config.pattern_dims();
+    feed(&(width as u64).to_le_bytes());
+    feed(&(height as u64).to_le_bytes());
+    feed(&config.variant_weight.to_le_bytes());
+    feed(&(config.min_pattern_count as u64).to_le_bytes());
+    for w in &config.importance {
+        feed(&w.to_le_bytes());
+    }
+    feed(&[
+        config.input_boundary.wraps_x() as u8 | (config.input_boundary.wraps_y() as u8) << 1,
+        config.symmetry.bits(),
+        config.edges.uses_sample_edge() as u8,
+        config.diagonal as u8,
+        config.strict_overlap as u8,
+        config.color_tolerance,
+    ]);
+    h
+}
+
+fn cache_path(dir: &Path, key: u64) -> PathBuf {
+    dir.join(format!("{:016x}.wfcmodel", key))
+}
+
+pub(crate) fn load(dir: &Path, key: u64, pattern_dims: (usize, usize)) -> Option<Model> {
+    let bytes = std::fs::read(cache_path(dir, key)).ok()?;
+    decode(&bytes, key, Some(pattern_dims))
+}
+
+/// Write `model` to `path`, completing a lazy propagator first.
+pub(crate) fn save_model(path: &Path, model: &Model) -> Result<(), Error> {
+    let propagator = model.propagator.to_flat(model.patterns.len());
+    std::fs::write(path, encode(model, &propagator, MODEL_FILE_KEY))
+        .map_err(|e| Error::ModelFile(e.to_string()))
+}
+
+pub(crate) fn load_model(path: &Path) -> Result<Model, Error> {
+    let bytes = std::fs::read(path).map_err(|e| Error::ModelFile(e.to_string()))?;
+    decode(&bytes, MODEL_FILE_KEY, None).ok_or_else(|| {
+        Error::ModelFile(format!(
+            "'{}' is not a model saved by this version",
+            path.display()
+        ))
+    })
+}
+
+pub(crate) fn store(dir: &Path, key: u64, model: &Model) -> std::io::Result<()> {
+    // A lazy propagator has no lists to save
+    let Propagator::Flat(propagator) = &*model.propagator else {
+        return Ok(());
+    };
+    std::fs::create_dir_all(dir)?;
+    // Write then rename so a concurrent reader never sees a partial file
+    let path = cache_path(dir, key);
+    let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+    std::fs::write(&tmp, encode(model, propagator, key))?;
+    std::fs::rename(tmp, path)
+}
+
+fn encode(model: &Model, propagator: &FlatPropagator, key: u64) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    out.extend_from_slice(&key.to_le_bytes());
+
+    let (w, h) = model
+        .patterns
+        .first()
+        .map_or((0, 0), |p| (p.width(), p.height()));
+    out.extend_from_slice(&(w as u32).to_le_bytes());
+    out.extend_from_slice(&(h as u32).to_le_bytes());
+    out.extend_from_slice(&(model.patterns.len() as u32).to_le_bytes());
+    out.extend_from_slice(&(model.dir_offsets.len() as u32).to_le_bytes());
+    for &(dx, dy) in &model.dir_offsets {
+        out.extend_from_slice(&dx.to_le_bytes());
+        out.extend_from_slice(&dy.to_le_bytes());
+    }
+    out.extend_from_slice(&(model.source_size.0 as u32).to_le_bytes());
+    out.extend_from_slice(&(model.source_size.1 as u32).to_le_bytes());
+    out.push(model.transparent.is_some() as u8);
+    out.extend_from_slice(&model.transparent.unwrap_or_default());
+
+    for (((pattern, weight), edges), origin) in model
+        .patterns
+        .iter()
+        .zip(&model.weights)
+        .zip(&model.edge_mask)
+        .zip(&model.origins)
+    {
+        for y in 0..h {
+            for x in 0..w {
+                out.extend_from_slice(&pattern.get(x, y));
+            }
+        }
+        out.extend_from_slice(&weight.to_le_bytes());
+        out.extend(edges.iter().map(|&e| e as u8));
+        out.extend_from_slice(&(origin.sample as u32).to_le_bytes());
+        out.extend_from_slice(&(origin.x as u32).to_le_bytes());
+        out.extend_from_slice(&(origin.y as u32).to_le_bytes());
+        out.push(origin.transform.bits());
+    }
+
+    for &(start, end) in &propagator.offsets {
+        out.extend_from_slice(&start.to_le_bytes());
+        out.extend_from_slice(&end.to_le_bytes());
+    }
+    out.extend_from_slice(&(propagator.data.len() as u32).to_le_bytes());
+    for &t in &propagator.data {
+        out.extend_from_slice(&t.to_le_bytes());
+    }
+    out
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let (head, rest) = self.bytes.split_at_checked(N)?;
+        self.bytes = rest;
+        head.try_into().ok()
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take().map(u32::from_le_bytes)
+    }
+
+    /// Whether `count` items of `size` bytes can still be read, checked
+    /// before allocating for them.
+    fn holds(&self, count: usize, size: usize) -> bool {
+        count
+            .checked_mul(size)
+            .is_some_and(|bytes| bytes <= self.bytes.len())
+    }
+}
+
+/// `pattern_dims`, if given, must match the stored patterns. Anything a
+/// corrupt file could make the solver trip on later (sizes, indices,
+/// asymmetric offsets, bad weights) is rejected here.
+fn decode(bytes: &[u8], key: u64, pattern_dims: Option<(usize, usize)>) -> Option<Model> {
+    let mut r = Reader { bytes };
+    if &r.take::<4>()? != MAGIC || r.u32()? != VERSION || u64::from_le_bytes(r.take()?) != key {
+        return None;
+    }
+
+    let (w, h) = (r.u32()? as usize, r.u32()? as usize);
+    let side = 1..=Config::MAX_PATTERN_SIZE;
+    if !side.contains(&w) || !side.contains(&h) || pattern_dims.is_some_and(|dims| dims != (w, h)) {
+        return None;
+    }
+    let num_patterns = r.u32()? as usize;
+    let num_dirs = r.u32()? as usize;
+    if !(1..=Config::MAX_PATTERNS).contains(&num_patterns) || !r.holds(num_dirs, 8) {
+        return None;
+    }
+    let mut dir_offsets = Vec::with_capacity(num_dirs);
+    for _ in 0..num_dirs {
+        let dx = i32::from_le_bytes(r.take()?);
+        let dy = i32::from_le_bytes(r.take()?);
+        dir_offsets.push((dx, dy));
+    }
+    // Overlapping windows are less than a pattern apart
+    if dir_offsets.iter().any(|&(dx, dy)| {
+        (dx, dy) == (0, 0) || dx.unsigned_abs() as usize >= w || dy.unsigned_abs() as usize >= h
+    }) {
+        return None;
+    }
+    let source_size = (r.u32()? as usize, r.u32()? as usize);
+    let has_transparent = r.take::<1>()?[0] != 0;
+    let transparent = Some(r.take::<3>()?).filter(|_| has_transparent);
+
+    // Pixels, weight, edges and origin
+    if !r.holds(num_patterns, w * h * 3 + 8 + 4 + 13) {
+        return None;
+    }
+    let mut patterns = Vec::with_capacity(num_patterns);
+    let mut weights = Vec::with_capacity(num_patterns);
+    let mut edge_mask = Vec::with_capacity(num_patterns);
+    let mut origins = Vec::with_capacity(num_patterns);
+    for _ in 0..num_patterns {
+        let mut pixels: Vec<Color> = Vec::with_capacity(w * h);
+        for _ in 0..w * h {
+            pixels.push(r.take()?);
+        }
+        patterns.push(Pattern::new_rect(w, h, pixels));
+        let weight = f64::from_le_bytes(r.take()?);
+        if !(weight >= 0.0 && weight.is_finite()) {
+            return None;
+        }
+        weights.push(weight);
+        edge_mask.push(r.take::<4>()?.map(|b| b != 0));
+        let origin = PatternOrigin {
+            sample: r.u32()? as usize,
+            x: r.u32()? as usize,
+            y: r.u32()? as usize,
+            transform: Symmetry::from_bits(r.take::<1>()?[0]),
+        };
+        if origin.sample == 0 && (origin.x >= source_size.0 || origin.y >= source_size.1) {
+            return None;
+        }
+        origins.push(origin);
+    }
+
+    let num_lists = num_patterns * num_dirs;
+    if !r.holds(num_lists, 8) {
+        return None;
+    }
+    let mut offsets = Vec::with_capacity(num_lists);
+    for _ in 0..num_lists {
+        offsets.push((r.u32()?, r.u32()?));
+    }
+    let data_len = r.u32()? as usize;
+    if !r.holds(data_len, 2) {
+        return None;
+    }
+    let mut data = Vec::with_capacity(data_len);
+    for _ in 0..data_len {
+        let t = u16::from_le_bytes(r.take()?);
+        if t as usize >= num_patterns {
+            return None;
+        }
+        data.push(t);
+    }
+    if offsets
+        .iter()
+        .any(|&(s, e)| s > e || e as usize > data_len || (e - s) as usize > Config::MAX_PATTERNS)
+    {
+        return None;
+    }
+    // Support must be mutual: the solver counts `t`'s supporters in `d` as
+    // the length of its own list the opposite way
+    let opposite: Vec<usize> = dir_offsets
+        .iter()
+        .map(|&(dx, dy)| dir_offsets.iter().position(|&o| o == (-dx, -dy)))
+        .collect::<Option<_>>()?;
+    let mut supporters = vec![0usize; num_lists];
+    for (i, &(start, end)) in offsets.iter().enumerate() {
+        for &t in &data[start as usize..end as usize] {
+            supporters[t as usize * num_dirs + opposite[i % num_dirs]] += 1;
+        }
+    }
+    if offsets
+        .iter()
+        .zip(&supporters)
+        .any(|(&(s, e), &n)| (e - s) as usize != n)
+    {
+        return None;
+    }
+
+    Some(Model {
+        patterns: patterns.into(),
+        weights,
+        edge_mask,
+        origins,
+        source_size,
+        transparent,
+        dir_offsets,
+        propagator: Arc::new(Propagator::Flat(FlatPropagator {
+            data,
+            num_dirs,
+            offsets,
+        })),
+    })
+}
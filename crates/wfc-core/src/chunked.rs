@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+
+use crate::boundary::Boundary;
+use crate::config::Config;
+use crate::edge::Edges;
+use crate::error::RunOutcome;
+use crate::rng::SplitMix64;
+use crate::rules::{Model, Rules};
+use crate::solver::Wfc;
+use crate::{Color, Sample};
+
+/// Unbounded output generated one fixed-size chunk at a time, on demand.
+///
+/// Each chunk is solved on its own small wave with a ring of cells around
+/// it; ring cells that fall in already generated chunks are pinned to the
+/// patterns there, so seams follow the same adjacency rules as the inside
+/// of a chunk. Only the collapsed patterns of loaded chunks are kept.
+///
+/// Chunk seeds derive from `Config::seed` and the chunk coordinates, but a
+/// chunk also depends on which neighbors existed when it was generated:
+/// the same seed and generation order reproduce the same world.
+pub struct ChunkedWfc {
+    model: Model,
+    /// Settings for each chunk's solver; the output size is the chunk size.
+    config: Config,
+    /// Cells of context around a chunk: the longest propagator offset.
+    ring: usize,
+    base_seed: u64,
+    max_attempts: usize,
+    chunks: HashMap<(i64, i64), Chunk>,
+}
+
+struct Chunk {
+    /// Collapsed pattern per cell, row-major.
+    patterns: Vec<u16>,
+    colors: Vec<Color>,
+}
+
+impl ChunkedWfc {
+    /// Chunks are `config.output_width` x `config.output_height` cells.
+    /// Boundary, edge constraints and the output mask don't apply to an
+    /// unbounded output and are ignored.
+    #[must_use]
+    pub fn new(sample: &Sample, config: Config) -> Self {
+        let model = Model::from_sample(sample, &config);
+        let ring = model
+            .dir_offsets
+            .iter()
+            .map(|&(dx, dy)| dx.unsigned_abs().max(dy.unsigned_abs()) as usize)
+            .max()
+            .unwrap_or(1);
+        let base_seed = config.seed.unwrap_or_else(rand::random);
+        Self {
+            model,
+            config: Config {
+                boundary: Boundary::Fixed,
+                edges: Edges::default(),
+                mask: Vec::new(),
+                ..config
+            },
+            ring,
+            base_seed,
+            max_attempts: 10,
+            chunks: HashMap::new(),
+        }
+    }
+
+    /// Seeds tried per chunk before [`generate`](Self::generate) gives up.
+    pub fn set_max_attempts(&mut self, attempts: usize) {
+        self.max_attempts = attempts.max(1);
+    }
+
+    #[must_use]
+    pub fn chunk_size(&self) -> (usize, usize) {
+        (self.config.output_width, self.config.output_height)
+    }
+
+    /// Colors of chunk `(cx, cy)`, row-major, generating it first if it
+    /// isn't loaded. `None` if every attempt contradicted.
+    pub fn generate(&mut self, cx: i64, cy: i64) -> Option<&[Color]> {
+        if !self.chunks.contains_key(&(cx, cy)) {
+            let chunk = self.solve_chunk(cx, cy)?;
+            self.chunks.insert((cx, cy), chunk);
+        }
+        self.chunk(cx, cy)
+    }
+
+    /// Colors of a loaded chunk.
+    #[must_use]
+    pub fn chunk(&self, cx: i64, cy: i64) -> Option<&[Color]> {
+        self.chunks.get(&(cx, cy)).map(|c| c.colors.as_slice())
+    }
+
+    /// Drop a chunk to free memory; returns whether it was loaded. Chunks
+    /// generated next to it later won't see it, so regenerating it may not
+    /// match.
+    pub fn unload(&mut self, cx: i64, cy: i64) -> bool {
+        self.chunks.remove(&(cx, cy)).is_some()
+    }
+
+    #[must_use]
+    pub fn loaded_chunks(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// `width` x `height` cells from world position `(x, y)`, generating
+    /// the chunks it covers in row-major order. `None` if one contradicted.
+    pub fn region(&mut self, x: i64, y: i64, width: usize, height: usize) -> Option<Vec<Color>> {
+        let (cw, ch) = self.chunk_size();
+        let (cw, ch) = (cw as i64, ch as i64);
+        let (x1, y1) = (x + width as i64 - 1, y + height as i64 - 1);
+        for cy in y.div_euclid(ch)..=y1.div_euclid(ch) {
+            for cx in x.div_euclid(cw)..=x1.div_euclid(cw) {
+                self.generate(cx, cy)?;
+            }
+        }
+
+        let mut output = Vec::with_capacity(width * height);
+        for gy in y..=y1 {
+            for gx in x..=x1 {
+                let (chunk, index) = self.locate(gx, gy);
+                output.push(self.chunks[&chunk].colors[index]);
+            }
+        }
+        Some(output)
+    }
+
+    /// Chunk holding world cell `(x, y)` and the cell's index in it.
+    fn locate(&self, x: i64, y: i64) -> ((i64, i64), usize) {
+        let (cw, ch) = self.chunk_size();
+        let (cw, ch) = (cw as i64, ch as i64);
+        let index = y.rem_euclid(ch) * cw + x.rem_euclid(cw);
+        ((x.div_euclid(cw), y.div_euclid(ch)), index as usize)
+    }
+
+    /// Collapsed pattern at world cell `(x, y)`, if its chunk is loaded.
+    fn pattern_at(&self, x: i64, y: i64) -> Option<usize> {
+        let (chunk, index) = self.locate(x, y);
+        self.chunks.get(&chunk).map(|c| c.patterns[index] as usize)
+    }
+
+    fn chunk_seed(&self, cx: i64, cy: i64) -> u64 {
+        let mut rng = SplitMix64::new(self.base_seed ^ (cx as u64).rotate_left(32) ^ cy as u64);
+        rng.next_u64()
+    }
+
+    fn solve_chunk(&self, cx: i64, cy: i64) -> Option<Chunk> {
+        let (w, h) = self.chunk_size();
+        let r = self.ring;
+        let seed = self.chunk_seed(cx, cy);
+        let config = Config {
+            output_width: w + 2 * r,
+            output_height: h + 2 * r,
+            seed: Some(seed),
+            ..self.config.clone()
+        };
+        let mut wfc = Wfc::from_rules(Rules::from_model(self.model.clone(), config));
+
+        let (ox, oy) = (cx * w as i64 - r as i64, cy * h as i64 - r as i64);
+        for y in 0..h + 2 * r {
+            for x in 0..w + 2 * r {
+                let inside = (r..r + w).contains(&x) && (r..r + h).contains(&y);
+                if let (false, Some(p)) = (inside, self.pattern_at(ox + x as i64, oy + y as i64)) {
+                    wfc.pin_patterns(x, y, vec![p]);
+                }
+            }
+        }
+
+        for attempt in 0..self.max_attempts {
+            if attempt > 0 {
+                wfc.reseed(seed.wrapping_add(attempt as u64));
+            }
+            if wfc.run() != RunOutcome::Complete {
+                continue;
+            }
+            let mut patterns = Vec::with_capacity(w * h);
+            let mut colors = Vec::with_capacity(w * h);
+            for y in r..r + h {
+                for x in r..r + w {
+                    let p = wfc.state.wave.first_set(wfc.rules.grid.cell(x, y));
+                    patterns.push(p as u16);
+                    colors.push(wfc.rules.colors[p]);
+                }
+            }
+            return Some(Chunk { patterns, colors });
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Direction, default_pipe_sample};
+
+    #[test]
+    fn seams_follow_adjacency_rules() {
+        let sample = default_pipe_sample();
+        let config = Config {
+            output_width: 8,
+            output_height: 6,
+            seed: Some(11),
+            ..Default::default()
+        };
+        let mut world = ChunkedWfc::new(&sample, config.clone());
+        for (cx, cy) in [(0, 0), (-1, 0), (0, -1), (-1, -1)] {
+            assert!(world.generate(cx, cy).is_some());
+        }
+        assert_eq!(world.loaded_chunks(), 4);
+
+        let rules = Wfc::new(&sample, config);
+        for y in -6..6 {
+            for x in -8..8 {
+                let here = world.pattern_at(x, y).unwrap();
+                if x < 7 {
+                    let right = world.pattern_at(x + 1, y).unwrap() as u16;
+                    assert!(
+                        rules
+                            .compatible_patterns(here, Direction::Right)
+                            .contains(&right)
+                    );
+                }
+                if y < 5 {
+                    let down = world.pattern_at(x, y + 1).unwrap() as u16;
+                    assert!(
+                        rules
+                            .compatible_patterns(here, Direction::Down)
+                            .contains(&down)
+                    );
+                }
+            }
+        }
+
+        let region = world.region(-3, -2, 6, 4).unwrap();
+        assert_eq!(region[0], world.chunk(-1, -1).unwrap()[4 * 8 + 5]);
+        assert!(world.unload(0, 0));
+        assert!(world.chunk(0, 0).is_none());
+    }
+}
@@ -1,46 +1,712 @@
+use std::path::{Path, PathBuf};
+
+use crate::bias::BiasField;
 use crate::boundary::Boundary;
+use crate::edge::{EdgeConstraint, Edges};
+use crate::graph::Graph;
+use crate::guide::Guide;
+use crate::heuristic::Heuristic;
+use crate::mirror::OutputSymmetry;
+use crate::profile::RowProfile;
+use crate::rng::SplitMix64;
+use crate::symmetry::Symmetry;
+use crate::weight::WeightMultiplier;
+use crate::{Color, Error, Sample};
 
 #[derive(Clone, Debug)]
 pub struct Config {
-    /// N in NxN pattern extraction.
+    /// N in NxN pattern extraction (the width with `pattern_height`).
     pub pattern_size: usize,
+    /// M for NxM patterns, e.g. wide ones for brick walls and horizons;
+    /// `None` keeps them square. Non-square patterns only take the
+    /// shape-preserving symmetries (mirrors and 180 degree rotation).
+    pub pattern_height: Option<usize>,
     pub output_width: usize,
     pub output_height: usize,
-    /// Wrap sample scanning around edges.
-    pub periodic_input: bool,
+    /// Generate over these nodes and links instead of a rectangle (see
+    /// [`Graph`]). The output becomes one row with a cell per node, which
+    /// replaces `output_width` and `output_height`.
+    pub graph: Option<Graph>,
+    /// Which sample edges pattern extraction wraps around, e.g.
+    /// `PeriodicX` for a strip that only tiles horizontally.
+    pub input_boundary: Boundary,
     pub boundary: Boundary,
-    /// Include rotation/reflection variants.
-    pub symmetry: bool,
-    /// Constrain top/bottom patterns to match sample edge positions.
-    pub ground: bool,
-    /// Constrain left/right patterns to match sample edge positions.
-    pub sides: bool,
-    /// RNG seed for deterministic output.
+    /// Rotation/reflection variants to add for each sample pattern. With
+    /// `ground` or `sides`, only `FLIP_X` is applied.
+    pub symmetry: Symmetry,
+    /// Mirror symmetry forced on the output; see [`OutputSymmetry`].
+    pub output_symmetry: OutputSymmetry,
+    /// Weight of each generated variant occurrence relative to one literal
+    /// occurrence in the sample (e.g. `0.5` keeps the sample's orientation dominant).
+    pub variant_weight: f64,
+    /// Patterns allowed on each output edge, e.g. [`Edges::ground`] to
+    /// keep the sample's top and bottom rows at the top and bottom.
+    pub edges: Edges,
+    /// Weight multiplier for patterns allowed on a constrained top or
+    /// bottom edge near that edge, fading out at mid-height. `1.0` leaves
+    /// only the hard bans at the extreme rows.
+    pub ground_boost: f64,
+    /// Per-row weight multipliers by pattern color; applied to both collapse
+    /// and entropy.
+    pub row_profiles: Vec<RowProfile>,
+    /// Coarse layout from a first pass that cells follow at the scale of
+    /// regions rather than patterns (see [`Guide`]).
+    pub guide: Option<Guide>,
+    /// Per-cell weight factors applied when a cell collapses (see
+    /// [`BiasField`]), for soft gradients across the output.
+    pub bias: Option<BiasField>,
+    /// Per-pixel weight in `sample` order (see `Sample::load_importance`):
+    /// each extracted occurrence counts by the value at its top-left pixel.
+    /// Empty weights every occurrence equally.
+    pub importance: Vec<f64>,
+    /// Weight of each occurrence per sample given to `Wfc::from_samples`,
+    /// in order; missing entries count 1.
+    pub sample_weights: Vec<f64>,
+    /// Factors on extracted pattern weights, by top-left color or pattern
+    /// index; a pattern matched by several gets their product. Applied to
+    /// both collapse and entropy, without re-extraction.
+    pub weight_multipliers: Vec<WeightMultiplier>,
+    /// Collapse-time factor per direction (Right, Down, Left, Up): a
+    /// candidate whose color matches the collapsed neighbor on that side
+    /// gets its weight scaled, once per such neighbor. E.g. `[1, 3, 1, 3]`
+    /// favors vertical runs like columns or waterfalls. Entropy ignores it.
+    pub direction_weights: [f64; 4],
+    /// Hold each top-left color to its share of the sample's pattern
+    /// weight plus this much (e.g. `0.05`), counted over the cells decided
+    /// so far: collapse passes over colors above that while another
+    /// candidate is within it. Keeps large outputs from drifting toward
+    /// the most common background. `None` leaves shares to chance.
+    pub frequency_tolerance: Option<f64>,
+    /// Extracted patterns (indices into `Wfc::patterns`) never placed;
+    /// patterns that only fit next to them are dropped too. Indices past
+    /// the pattern count are ignored.
+    pub banned_patterns: Vec<usize>,
+    /// Output cells to generate, row-major over the output; the rest are
+    /// left out of observation and propagation (their neighbors see an
+    /// edge there) and render black, or transparent with
+    /// [`Sample::save_masked`]. Empty generates every cell.
+    pub mask: Vec<bool>,
+    /// RNG seed for deterministic output: the same build on the same
+    /// platform reproduces a run exactly (see [`rng`](crate::rng)). `None`
+    /// draws one from the OS.
     pub seed: Option<u64>,
     /// Bias collapse toward patterns with more viable neighbors.
     pub use_flexibility: bool,
+    /// Also constrain the four diagonal neighbors.
+    pub diagonal: bool,
+    /// Constrain every overlapping offset (|dx|, |dy| < N), not just the four
+    /// neighbors. Fewer contradictions, but a propagator up to (2N-1)^2/4 times larger.
+    pub strict_overlap: bool,
+    /// Largest per-channel difference at which overlapping pixels still
+    /// agree when building adjacency; `0` requires exact matches. Lets noisy
+    /// samples keep rich adjacency, at O(P^2) propagator construction.
+    pub color_tolerance: u8,
+    /// Drop patterns seen in fewer than this many windows of the samples
+    /// (counting each symmetry variant on its own) before building the
+    /// propagator: the one-off patterns of noisy samples slow every step
+    /// and mostly place noise. `1` keeps them all.
+    pub min_pattern_count: usize,
+    /// Skip building the full propagator: each compatibility list is written
+    /// on first use. Startup still compares every pair of distinct overlap
+    /// strips once, since the support counts need every list's length, so
+    /// it saves writing and holding the lists, not the comparisons. Pays off
+    /// when lists are long (large samples with `color_tolerance`): memory
+    /// holds only the lists propagation reached. Lazy models are not written
+    /// to `model_cache`.
+    pub lazy_propagator: bool,
+    /// Corner-based model: sample pixels are terrain at tile corners and
+    /// output is drawn with `dual::render_tiles`. Requires `pattern_size` 2,
+    /// under which the solver's 2x2 patterns already are the tiles, so this
+    /// only checks that and tells front ends to draw tiles.
+    pub dual_grid: bool,
+    /// Which undecided cell each step collapses next.
+    pub heuristic: Heuristic,
+    /// Amplitude of the random offset added to each cell's entropy under
+    /// `Heuristic::MinEntropy`, so equal entropies break ties by seed. 0
+    /// breaks them by cell index instead.
+    pub entropy_noise: f64,
+    /// Keep cells of this color a single connected region (see
+    /// [`Connectivity`](crate::Connectivity)), e.g. a maze's corridors.
+    pub connected: Option<Color>,
+    /// Render color for cells with no remaining pattern.
+    pub contradiction_color: Color,
+    /// Directory for caching extracted models across runs (see `default_cache_dir`).
+    pub model_cache: Option<PathBuf>,
     pub backtracking: bool,
     pub max_backtracks: usize,
     /// Snapshot interval (in collapses) for backtracking.
     pub snapshot_interval: usize,
+    /// Extra cells reopened around the rectangle passed to
+    /// `Wfc::uncollapse_region`, so its border can adapt to the new content.
+    pub region_halo: usize,
+    /// Observations `Wfc::undo` can rewind at least; 0 disables the
+    /// history. Costs a copy of the solver state per `snapshot_interval`
+    /// observations kept.
+    pub undo_depth: usize,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             pattern_size: 3,
+            pattern_height: None,
             output_width: 32,
             output_height: 32,
-            periodic_input: true,
+            graph: None,
+            input_boundary: Boundary::Periodic,
             boundary: Boundary::Fixed,
-            symmetry: true,
-            ground: false,
-            sides: false,
+            symmetry: Symmetry::ALL,
+            output_symmetry: OutputSymmetry::None,
+            variant_weight: 1.0,
+            edges: Edges::default(),
+            ground_boost: 1.0,
+            row_profiles: Vec::new(),
+            guide: None,
+            bias: None,
+            importance: Vec::new(),
+            sample_weights: Vec::new(),
+            weight_multipliers: Vec::new(),
+            direction_weights: [1.0; 4],
+            frequency_tolerance: None,
+            banned_patterns: Vec::new(),
+            mask: Vec::new(),
             seed: None,
             use_flexibility: true,
+            diagonal: false,
+            strict_overlap: false,
+            color_tolerance: 0,
+            min_pattern_count: 1,
+            lazy_propagator: false,
+            dual_grid: false,
+            heuristic: Heuristic::MinEntropy,
+            entropy_noise: 1e-6,
+            connected: None,
+            contradiction_color: [128, 0, 128],
+            model_cache: None,
             backtracking: true,
             max_backtracks: 100,
             snapshot_interval: 10,
+            region_halo: 1,
+            undo_depth: 0,
+        }
+    }
+}
+
+impl Config {
+    /// Largest supported `pattern_size`. Sides above 4 are stored on the
+    /// heap, and their pattern counts grow quickly with the sample.
+    pub const MAX_PATTERN_SIZE: usize = 8;
+
+    /// Most distinct patterns a model can hold; pattern indices are `u16`.
+    pub const MAX_PATTERNS: usize = u16::MAX as usize;
+
+    /// Pattern `(width, height)`.
+    #[must_use]
+    pub fn pattern_dims(&self) -> (usize, usize) {
+        (
+            self.pattern_size,
+            self.pattern_height.unwrap_or(self.pattern_size),
+        )
+    }
+
+    /// Symmetry actually applied during extraction: edge constraints only
+    /// survive a left-right mirror, and NxM patterns can't turn sideways.
+    #[must_use]
+    pub fn effective_symmetry(&self) -> Symmetry {
+        let (w, h) = self.pattern_dims();
+        let symmetry = if w == h {
+            self.symmetry
+        } else {
+            self.symmetry & (Symmetry::FLIP_X | Symmetry::FLIP_Y | Symmetry::ROTATE_180)
+        };
+        if self.edges.uses_sample_edge() {
+            symmetry & Symmetry::FLIP_X
+        } else {
+            symmetry
+        }
+    }
+
+    /// Reject settings the solver would panic on or silently misbehave with.
+    pub fn validate(&self, sample: &Sample) -> Result<(), Error> {
+        self.validate_samples(std::slice::from_ref(sample))
+    }
+
+    /// [`validate`](Self::validate) for the samples given to
+    /// `Wfc::from_samples`: every one is checked, and the importance mask
+    /// against the first.
+    pub fn validate_samples(&self, samples: &[Sample]) -> Result<(), Error> {
+        let invalid = |msg: String| Err(Error::InvalidConfig(msg));
+        let Some(first) = samples.first() else {
+            return invalid("at least one sample is required".to_string());
+        };
+        let name = |i: usize| {
+            if samples.len() == 1 {
+                "sample".to_string()
+            } else {
+                format!("sample {}", i + 1)
+            }
+        };
+        let (n, m) = self.pattern_dims();
+        for side in [n, m] {
+            if !(1..=Self::MAX_PATTERN_SIZE).contains(&side) {
+                return invalid(format!(
+                    "pattern size {} is outside 1..={}",
+                    side,
+                    Self::MAX_PATTERN_SIZE
+                ));
+            }
+        }
+        if self.dual_grid && (n, m) != (2, 2) {
+            return invalid(
+                "dual grid needs pattern size 2 (one tile per 2x2 corners)".to_string(),
+            );
+        }
+        if let Some(graph) = &self.graph {
+            if graph.is_empty() {
+                return invalid("graph has no nodes".to_string());
+            }
+            if self.strict_overlap
+                || self.connected.is_some()
+                || self.output_symmetry != OutputSymmetry::None
+            {
+                return invalid(
+                    "graphs don't support strict overlap, connectivity or output symmetry"
+                        .to_string(),
+                );
+            }
+            // All of these read rows and columns, which a graph doesn't have
+            if self.edges != Edges::default()
+                || !self.row_profiles.is_empty()
+                || self.guide.is_some()
+                || self.dual_grid
+            {
+                return invalid(
+                    "graphs don't support edge constraints, row profiles, a guide or the dual grid"
+                        .to_string(),
+                );
+            }
+        } else if self.output_width == 0 || self.output_height == 0 {
+            return invalid("output size must be non-zero".to_string());
+        }
+        if self.input_boundary.extends() {
+            return invalid("the input boundary can't mirror or clamp".to_string());
+        }
+        if self.boundary.extends() && self.graph.is_some() {
+            return invalid("graphs can't mirror or clamp".to_string());
+        }
+        let wrap = self.input_boundary;
+        for (i, sample) in samples.iter().enumerate() {
+            if sample.width == 0 || sample.height == 0 {
+                return invalid(format!("{} is empty", name(i)));
+            }
+            if (!wrap.wraps_x() && sample.width < n) || (!wrap.wraps_y() && sample.height < m) {
+                return invalid(format!(
+                    "{} {}x{} is smaller than the {}x{} pattern size",
+                    name(i),
+                    sample.width,
+                    sample.height,
+                    n,
+                    m
+                ));
+            }
+        }
+        if self.min_pattern_count == 0 {
+            return invalid("min pattern count must be at least 1".to_string());
+        }
+        // With symmetry a pattern also collects its variants' windows, so
+        // only bound it by every window counting toward one pattern
+        let symmetry = self.effective_symmetry();
+        let reachable = if symmetry == Symmetry::NONE {
+            Sample::analyze_all(samples, self).unique_windows > 0
+        } else {
+            let windows: usize = samples
+                .iter()
+                .map(|s| {
+                    let (x_max, y_max) = wrap.window_starts(s.width, s.height, n, m);
+                    x_max * y_max
+                })
+                .sum();
+            windows * (1 + symmetry.len()) >= self.min_pattern_count
+        };
+        if !reachable {
+            return invalid(format!(
+                "no pattern occurs the {} times min pattern count asks for",
+                self.min_pattern_count
+            ));
+        }
+        if !self.importance.is_empty() && self.importance.len() != first.width * first.height {
+            return invalid(format!(
+                "importance mask has {} values, {} has {} pixels",
+                self.importance.len(),
+                name(0),
+                first.width * first.height
+            ));
+        }
+        if self
+            .importance
+            .iter()
+            .any(|w| !(*w >= 0.0 && w.is_finite()))
+        {
+            return invalid("importance values must be non-negative".to_string());
+        }
+        if self
+            .sample_weights
+            .iter()
+            .any(|w| !(*w >= 0.0 && w.is_finite()))
+        {
+            return invalid("sample weights must be non-negative".to_string());
+        }
+        if self
+            .weight_multipliers
+            .iter()
+            .any(|m| !(m.factor > 0.0 && m.factor.is_finite()))
+        {
+            return invalid("weight multipliers must be positive".to_string());
+        }
+        if self
+            .direction_weights
+            .iter()
+            .any(|w| !(*w > 0.0 && w.is_finite()))
+        {
+            return invalid("direction weights must be positive".to_string());
+        }
+        if self
+            .frequency_tolerance
+            .is_some_and(|t| !(t >= 0.0 && t.is_finite()))
+        {
+            return invalid("frequency tolerance must be non-negative".to_string());
+        }
+        let cells = self
+            .graph
+            .as_ref()
+            .map_or(self.output_width * self.output_height, Graph::len);
+        if !self.mask.is_empty() && self.mask.len() != cells {
+            return invalid(format!(
+                "output mask has {} cells, output has {}",
+                self.mask.len(),
+                cells
+            ));
+        }
+        let mirror = self.output_symmetry;
+        if (mirror.mirrors_x() && !self.boundary.wraps_x() && self.output_width < n)
+            || (mirror.mirrors_y() && !self.boundary.wraps_y() && self.output_height < m)
+        {
+            return invalid(format!(
+                "a mirrored output must be at least the {}x{} pattern size",
+                n, m
+            ));
+        }
+        if !(self.variant_weight > 0.0 && self.variant_weight.is_finite()) {
+            return invalid("variant weight must be positive".to_string());
+        }
+        if !(self.ground_boost > 0.0 && self.ground_boost.is_finite()) {
+            return invalid("ground boost must be positive".to_string());
         }
+        if !(self.entropy_noise >= 0.0 && self.entropy_noise.is_finite()) {
+            return invalid("entropy noise must be non-negative".to_string());
+        }
+        if let Some(guide) = &self.guide {
+            if [&guide.layout, &guide.regions]
+                .iter()
+                .any(|s| s.width == 0 || s.height == 0)
+            {
+                return invalid("guide layout and regions must be non-empty".to_string());
+            }
+            if !(guide.strength > 0.0 && guide.strength.is_finite()) {
+                return invalid("guide strength must be positive".to_string());
+            }
+        }
+        if let Some(BiasField::Target { image, strength }) = &self.bias {
+            if image.width == 0 || image.height == 0 {
+                return invalid("bias target must be non-empty".to_string());
+            }
+            if !(*strength >= 0.0 && strength.is_finite()) {
+                return invalid("bias strength must be non-negative".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Pick random pattern size, symmetry, periodicity, boundary, ground and
+    /// collapse heuristic within ranges that build quickly, plus a new output
+    /// seed. The same `seed` always gives the same settings; `None` draws one.
+    /// Returns the seed used.
+    pub fn randomize(&mut self, sample: &Sample, seed: Option<u64>) -> u64 {
+        let symmetries = [
+            Symmetry::NONE,
+            Symmetry::FLIP_X,
+            Symmetry::FLIP_X | Symmetry::FLIP_Y,
+            Symmetry::ROTATIONS,
+            Symmetry::ALL,
+        ];
+        const BOUNDARIES: [Boundary; 4] = [
+            Boundary::Fixed,
+            Boundary::PeriodicX,
+            Boundary::PeriodicY,
+            Boundary::Periodic,
+        ];
+
+        let seed = seed.unwrap_or_else(rand::random);
+        let mut rng = SplitMix64::new(seed);
+        // 3 is the sweet spot for most samples; 4 only occasionally
+        self.pattern_size = [2, 3, 3, 3, 4][rng.below(5)];
+        self.input_boundary = if rng.chance(0.7) {
+            Boundary::Periodic
+        } else {
+            Boundary::Fixed
+        };
+        if self.input_boundary == Boundary::Fixed {
+            let fits = sample.width.min(sample.height).max(1);
+            self.pattern_size = self.pattern_size.min(fits);
+        }
+        self.symmetry = symmetries[rng.below(symmetries.len())];
+        self.boundary = BOUNDARIES[rng.below(BOUNDARIES.len())];
+        self.edges = if rng.chance(0.3) {
+            Edges::ground()
+        } else {
+            Edges::default()
+        };
+        self.use_flexibility = rng.chance(0.5);
+        self.seed = Some(rng.next_u64());
+        seed
+    }
+
+    /// Settings as `key = value` lines, readable by [`from_text`](Self::from_text).
+    /// Row profiles, the guide, the bias field, the graph, the importance and output masks
+    /// and the cache directory come from files of their own and are left out.
+    #[must_use]
+    pub fn to_text(&self) -> String {
+        let [r, g, b] = self.contradiction_color;
+        let mut lines = vec![
+            format!("pattern_size = {}", self.pattern_size),
+            format!(
+                "pattern_height = {}",
+                self.pattern_height
+                    .map_or("square".to_string(), |m| m.to_string())
+            ),
+            format!("output_width = {}", self.output_width),
+            format!("output_height = {}", self.output_height),
+            format!("input_boundary = {}", boundary_name(self.input_boundary)),
+            format!("boundary = {}", boundary_name(self.boundary)),
+            format!("symmetry = {}", self.symmetry),
+            format!(
+                "output_symmetry = {}",
+                output_symmetry_name(self.output_symmetry)
+            ),
+            format!("variant_weight = {}", self.variant_weight),
+            format!("edge_top = {}", self.edges.top),
+            format!("edge_bottom = {}", self.edges.bottom),
+            format!("edge_left = {}", self.edges.left),
+            format!("edge_right = {}", self.edges.right),
+            format!("ground_boost = {}", self.ground_boost),
+            format!("use_flexibility = {}", self.use_flexibility),
+            format!("diagonal = {}", self.diagonal),
+            format!("strict_overlap = {}", self.strict_overlap),
+            format!("color_tolerance = {}", self.color_tolerance),
+            format!("min_pattern_count = {}", self.min_pattern_count),
+            format!("lazy_propagator = {}", self.lazy_propagator),
+            format!("dual_grid = {}", self.dual_grid),
+            format!(
+                "heuristic = {}",
+                match self.heuristic {
+                    Heuristic::MinEntropy => "min-entropy",
+                    Heuristic::Scanline => "scanline",
+                    Heuristic::Random => "random",
+                }
+            ),
+            format!("entropy_noise = {}", self.entropy_noise),
+            format!("contradiction_color = {:02x}{:02x}{:02x}", r, g, b),
+            format!("backtracking = {}", self.backtracking),
+            format!("max_backtracks = {}", self.max_backtracks),
+            format!("snapshot_interval = {}", self.snapshot_interval),
+            format!("region_halo = {}", self.region_halo),
+            format!("undo_depth = {}", self.undo_depth),
+        ];
+        if !self.sample_weights.is_empty() {
+            let weights: Vec<String> = self.sample_weights.iter().map(f64::to_string).collect();
+            lines.push(format!("sample_weights = {}", weights.join(",")));
+        }
+        for multiplier in &self.weight_multipliers {
+            lines.push(format!("weight = {}", multiplier));
+        }
+        if self.direction_weights != [1.0; 4] {
+            let weights: Vec<String> = self.direction_weights.iter().map(f64::to_string).collect();
+            lines.push(format!("direction_weights = {}", weights.join(",")));
+        }
+        if let Some(tolerance) = self.frequency_tolerance {
+            lines.push(format!("frequency_tolerance = {}", tolerance));
+        }
+        if !self.banned_patterns.is_empty() {
+            let banned: Vec<String> = self.banned_patterns.iter().map(usize::to_string).collect();
+            lines.push(format!("banned_patterns = {}", banned.join(",")));
+        }
+        if let Some([r, g, b]) = self.connected {
+            lines.push(format!("connected = {:02x}{:02x}{:02x}", r, g, b));
+        }
+        if let Some(seed) = self.seed {
+            lines.push(format!("seed = {}", seed));
+        }
+        lines.join("\n") + "\n"
     }
+
+    /// Parse `key = value` lines over the defaults; `#` starts a comment and
+    /// unknown keys are errors.
+    pub fn from_text(text: &str) -> Result<Self, Error> {
+        fn parse<T: std::str::FromStr>(value: &str) -> Option<T> {
+            value.parse().ok()
+        }
+
+        let mut config = Self::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = |msg: &str| Error::InvalidConfig(format!("line {}: {}", i + 1, msg));
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| invalid("expected key = value"))?;
+            let (key, value) = (key.trim(), value.trim());
+            let parsed = match key {
+                "pattern_size" => parse(value).map(|v| config.pattern_size = v),
+                "pattern_height" => match value {
+                    "square" => Some(None),
+                    _ => parse(value).map(Some),
+                }
+                .map(|v| config.pattern_height = v),
+                "output_width" => parse(value).map(|v| config.output_width = v),
+                "output_height" => parse(value).map(|v| config.output_height = v),
+                "input_boundary" => BOUNDARY_NAMES
+                    .iter()
+                    .find(|(name, _)| *name == value)
+                    .map(|&(_, b)| config.input_boundary = b),
+                // Written before input wrapping was set per axis
+                "periodic_input" => parse(value).map(|v| {
+                    config.input_boundary = if v {
+                        Boundary::Periodic
+                    } else {
+                        Boundary::Fixed
+                    }
+                }),
+                "boundary" => BOUNDARY_NAMES
+                    .iter()
+                    .find(|(name, _)| *name == value)
+                    .map(|&(_, b)| config.boundary = b),
+                "symmetry" => parse(value).map(|v| config.symmetry = v),
+                "output_symmetry" => OUTPUT_SYMMETRY_NAMES
+                    .iter()
+                    .find(|(name, _)| *name == value)
+                    .map(|&(_, s)| config.output_symmetry = s),
+                "variant_weight" => parse(value).map(|v| config.variant_weight = v),
+                "edge_top" => parse(value).map(|v| config.edges.top = v),
+                "edge_bottom" => parse(value).map(|v| config.edges.bottom = v),
+                "edge_left" => parse(value).map(|v| config.edges.left = v),
+                "edge_right" => parse(value).map(|v| config.edges.right = v),
+                // Shorthands older files were written with
+                "ground" => parse(value).map(|v: bool| {
+                    let sample = |edge| if v { edge } else { EdgeConstraint::None };
+                    config.edges.top = sample(EdgeConstraint::SampleTop);
+                    config.edges.bottom = sample(EdgeConstraint::SampleBottom);
+                }),
+                "sides" => parse(value).map(|v: bool| {
+                    let sample = |edge| if v { edge } else { EdgeConstraint::None };
+                    config.edges.left = sample(EdgeConstraint::SampleLeft);
+                    config.edges.right = sample(EdgeConstraint::SampleRight);
+                }),
+                "ground_boost" => parse(value).map(|v| config.ground_boost = v),
+                "seed" => parse(value).map(|v| config.seed = Some(v)),
+                "use_flexibility" => parse(value).map(|v| config.use_flexibility = v),
+                "diagonal" => parse(value).map(|v| config.diagonal = v),
+                "strict_overlap" => parse(value).map(|v| config.strict_overlap = v),
+                "color_tolerance" => parse(value).map(|v| config.color_tolerance = v),
+                "min_pattern_count" => parse(value).map(|v| config.min_pattern_count = v),
+                "lazy_propagator" => parse(value).map(|v| config.lazy_propagator = v),
+                "dual_grid" => parse(value).map(|v| config.dual_grid = v),
+                // Written while an exhaustive propagation mode existed
+                "propagation" => matches!(value, "fast" | "exhaustive").then_some(()),
+                "heuristic" => match value {
+                    "min-entropy" => Some(Heuristic::MinEntropy),
+                    "scanline" => Some(Heuristic::Scanline),
+                    "random" => Some(Heuristic::Random),
+                    _ => None,
+                }
+                .map(|v| config.heuristic = v),
+                "entropy_noise" => parse(value).map(|v| config.entropy_noise = v),
+                "sample_weights" => value
+                    .split(',')
+                    .map(|v| v.trim().parse().ok())
+                    .collect::<Option<Vec<f64>>>()
+                    .map(|v| config.sample_weights = v),
+                "direction_weights" => value
+                    .split(',')
+                    .map(|v| v.trim().parse().ok())
+                    .collect::<Option<Vec<f64>>>()
+                    .and_then(|v| v.try_into().ok())
+                    .map(|v| config.direction_weights = v),
+                "frequency_tolerance" => parse(value).map(|v| config.frequency_tolerance = Some(v)),
+                "banned_patterns" => value
+                    .split(',')
+                    .map(|v| v.trim().parse().ok())
+                    .collect::<Option<Vec<usize>>>()
+                    .map(|v| config.banned_patterns = v),
+                "weight" => parse(value).map(|v| config.weight_multipliers.push(v)),
+                "connected" => crate::parse_hex(value).map(|v| config.connected = Some(v)),
+                "contradiction_color" => {
+                    crate::parse_hex(value).map(|v| config.contradiction_color = v)
+                }
+                "backtracking" => parse(value).map(|v| config.backtracking = v),
+                "max_backtracks" => parse(value).map(|v| config.max_backtracks = v),
+                "snapshot_interval" => parse(value).map(|v| config.snapshot_interval = v),
+                "region_halo" => parse(value).map(|v| config.region_halo = v),
+                "undo_depth" => parse(value).map(|v| config.undo_depth = v),
+                _ => return Err(invalid(&format!("unknown key '{}'", key))),
+            };
+            parsed.ok_or_else(|| invalid(&format!("bad value '{}' for {}", value, key)))?;
+        }
+        Ok(config)
+    }
+
+    /// Read a file written by [`save`](Self::save) (or by hand).
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| Error::InvalidConfig(format!("{}: {}", path.display(), e)))?;
+        Self::from_text(&text)
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::write(path, self.to_text())
+    }
+}
+
+/// Config file names for `Boundary`, matching the CLI's `--boundary` values.
+const BOUNDARY_NAMES: [(&str, Boundary); 6] = [
+    ("fixed", Boundary::Fixed),
+    ("periodic-x", Boundary::PeriodicX),
+    ("periodic-y", Boundary::PeriodicY),
+    ("periodic", Boundary::Periodic),
+    ("mirror", Boundary::Mirror),
+    ("clamp", Boundary::Clamp),
+];
+
+fn boundary_name(boundary: Boundary) -> &'static str {
+    BOUNDARY_NAMES
+        .iter()
+        .find(|&&(_, b)| b == boundary)
+        .map_or("fixed", |&(name, _)| name)
+}
+
+/// Config file names for `OutputSymmetry`, matching the CLI's
+/// `--output-symmetry` values.
+const OUTPUT_SYMMETRY_NAMES: [(&str, OutputSymmetry); 4] = [
+    ("none", OutputSymmetry::None),
+    ("mirror-x", OutputSymmetry::MirrorX),
+    ("mirror-y", OutputSymmetry::MirrorY),
+    ("four-fold", OutputSymmetry::FourFold),
+];
+
+fn output_symmetry_name(symmetry: OutputSymmetry) -> &'static str {
+    OUTPUT_SYMMETRY_NAMES
+        .iter()
+        .find(|&&(_, s)| s == symmetry)
+        .map_or("none", |&(name, _)| name)
 }
@@ -0,0 +1,170 @@
+use crate::constraint::GlobalConstraint;
+use crate::solver::Wfc;
+use crate::{Boundary, Color};
+
+/// Keeps the cells of one color (e.g. maze corridors) a single
+/// 4-connected region. After each propagation, cells that can no longer
+/// reach the already-decided region lose their patterns of that color,
+/// and decided cells split into separate regions are a contradiction, so
+/// backtracking retries instead of finishing with an unreachable area.
+/// A cell's color is its pattern's top-left pixel, as rendered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Connectivity {
+    pub color: Color,
+}
+
+impl Connectivity {
+    pub fn new(color: Color) -> Self {
+        Self { color }
+    }
+}
+
+impl GlobalConstraint for Connectivity {
+    fn check(&self, wfc: &Wfc) -> Vec<(usize, usize)> {
+        let config = wfc.config();
+        let (width, height) = (config.output_width, config.output_height);
+        let colored: Vec<usize> = (0..wfc.num_patterns())
+            .filter(|&p| wfc.patterns()[p].get(0, 0) == self.color)
+            .collect();
+
+        // Per cell: can it still take the color, and must it?
+        let mut may = vec![false; width * height];
+        let mut must = vec![false; width * height];
+        for y in 0..height {
+            for x in 0..width {
+                let cell = y * width + x;
+                if !config.mask.is_empty() && !config.mask[cell] {
+                    continue;
+                }
+                let live = colored
+                    .iter()
+                    .filter(|&&p| wfc.is_possible(x, y, p))
+                    .count();
+                may[cell] = live > 0;
+                must[cell] = live > 0 && live == wfc.remaining_patterns(x, y);
+            }
+        }
+
+        let Some(start) = must.iter().position(|&m| m) else {
+            return Vec::new();
+        };
+        let reached = flood(&may, start, width, height, config.boundary);
+
+        if must.iter().zip(&reached).any(|(&m, &r)| m && !r) {
+            // Two decided regions with no colorable path between them
+            let (x, y) = (start % width, start / width);
+            return (0..wfc.num_patterns())
+                .filter(|&p| wfc.is_possible(x, y, p))
+                .map(|p| (start, p))
+                .collect();
+        }
+        let mut bans = Vec::new();
+        for cell in (0..width * height).filter(|&c| may[c] && !reached[c]) {
+            let (x, y) = (cell % width, cell / width);
+            bans.extend(
+                colored
+                    .iter()
+                    .filter(|&&p| wfc.is_possible(x, y, p))
+                    .map(|&p| (cell, p)),
+            );
+        }
+        bans
+    }
+}
+
+/// Cells reachable from `start` through `open` cells, wrapping per `boundary`.
+fn flood(
+    open: &[bool],
+    start: usize,
+    width: usize,
+    height: usize,
+    boundary: Boundary,
+) -> Vec<bool> {
+    let mut reached = vec![false; open.len()];
+    let mut stack = vec![start];
+    reached[start] = true;
+    while let Some(cell) = stack.pop() {
+        let (x, y) = (cell % width, cell / width);
+        let step = |v: usize, dv: isize, size: usize, wraps: bool| {
+            let next = v as isize + dv;
+            if (0..size as isize).contains(&next) {
+                Some(next as usize)
+            } else if wraps {
+                Some(next.rem_euclid(size as isize) as usize)
+            } else {
+                None
+            }
+        };
+        let neighbors = [
+            step(x, -1, width, boundary.wraps_x()).map(|nx| (nx, y)),
+            step(x, 1, width, boundary.wraps_x()).map(|nx| (nx, y)),
+            step(y, -1, height, boundary.wraps_y()).map(|ny| (x, ny)),
+            step(y, 1, height, boundary.wraps_y()).map(|ny| (x, ny)),
+        ];
+        for (nx, ny) in neighbors.into_iter().flatten() {
+            let next = ny * width + nx;
+            if open[next] && !reached[next] {
+                reached[next] = true;
+                stack.push(next);
+            }
+        }
+    }
+    reached
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, RunOutcome, Sample};
+
+    /// Corridors (white) and walls (black) with disconnected pockets.
+    fn rooms() -> Sample {
+        const W: Color = [255, 255, 255];
+        const B: Color = [0, 0, 0];
+        let rows = [
+            [W, W, B, W, B, B],
+            [B, W, B, W, B, W],
+            [B, B, B, B, B, B],
+            [W, B, W, W, W, B],
+            [W, B, B, B, W, B],
+            [B, B, W, B, B, B],
+        ];
+        Sample::new(6, 6, rows.concat())
+    }
+
+    #[test]
+    fn flood_wraps_only_periodic_axes() {
+        // Row of three open cells split by the left/right edge
+        let open = [true, false, true];
+        assert!(!flood(&open, 0, 3, 1, Boundary::Fixed)[2]);
+        assert!(flood(&open, 0, 3, 1, Boundary::PeriodicX)[2]);
+    }
+
+    #[test]
+    fn completed_outputs_have_one_corridor_region() {
+        let white = [255, 255, 255];
+        let mut completed = 0;
+        for seed in 0..6 {
+            let config = Config {
+                pattern_size: 2,
+                output_width: 12,
+                output_height: 12,
+                connected: Some(white),
+                seed: Some(seed),
+                ..Default::default()
+            };
+            let mut wfc = Wfc::new(&rooms(), config);
+            if wfc.run() != RunOutcome::Complete {
+                continue;
+            }
+            completed += 1;
+            let open: Vec<bool> = wfc.render().iter().map(|&c| c == white).collect();
+            let Some(start) = open.iter().position(|&o| o) else {
+                continue;
+            };
+            let reached = flood(&open, start, 12, 12, Boundary::Fixed);
+            assert_eq!(open, reached, "seed {seed} has a disconnected corridor");
+        }
+        assert!(completed > 0);
+    }
+}
@@ -1,5 +1,6 @@
 use crate::Color;
 use crate::rules::Rules;
+use crate::solver::Wfc;
 use crate::state::State;
 
 /// User-defined constraint applied to the wave before solving.
@@ -7,6 +8,16 @@ pub trait CellConstraint {
     fn apply(&self, ctx: &mut ConstraintContext);
 }
 
+/// Domain rule checked against the whole wave after every propagation
+/// (see [`Wfc::add_constraint`]), e.g. a maximum count for a pattern or
+/// patterns that may not appear within some distance of each other.
+pub trait GlobalConstraint: Send + Sync {
+    /// `(cell, pattern)` pairs to ban, with `cell` the row-major index
+    /// `y * output_width + x`. Pairs already banned are ignored; returning
+    /// nothing ends the check for this propagation.
+    fn check(&self, wfc: &Wfc) -> Vec<(usize, usize)>;
+}
+
 /// Wave state handle for applying constraints.
 pub struct ConstraintContext<'a> {
     pub(crate) state: &'a mut State,
@@ -0,0 +1,123 @@
+use std::fmt;
+
+use crate::rules::Rules;
+use crate::state::State;
+
+/// Bans kept by [`BanLog`]; enough to show how the last cell emptied.
+const LOG_LEN: usize = 64;
+
+/// One pattern removed from one cell during propagation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Ban {
+    /// `(x, y)` of the cell that lost the pattern.
+    pub cell: (usize, usize),
+    pub pattern: usize,
+    /// `(x, y)` of the neighbor whose own ban left `pattern` without
+    /// support; `None` for bans by a global constraint.
+    pub cause: Option<(usize, usize)>,
+}
+
+/// Where and why the wave last contradicted; see
+/// [`Wfc::contradiction_info`](crate::Wfc::contradiction_info).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContradictionInfo {
+    /// `(x, y)` of the cell left without patterns.
+    pub cell: (usize, usize),
+    /// The latest bans in `cell`, oldest first; the last one emptied it.
+    /// Earlier bans may have scrolled out of the log.
+    pub bans: Vec<Ban>,
+    /// Cell observed and pattern chosen at the step that started the
+    /// chain; `None` before the first observation, e.g. for pins or edge
+    /// constraints that can't be met.
+    pub observation: Option<((usize, usize), usize)>,
+}
+
+impl ContradictionInfo {
+    pub(crate) fn from_state(state: &State, rules: &Rules) -> Self {
+        let coords = |cell: usize| rules.grid.coords(cell);
+        // Prefer the cell the latest bans emptied, then any empty cell;
+        // a collapse with no weight left contradicts in the observed cell
+        let cell = state
+            .ban_log
+            .newest_first()
+            .map(|(cell, _, _)| cell)
+            .find(|&cell| state.num_possible[cell] == 0)
+            .or_else(|| state.num_possible.iter().position(|&n| n == 0))
+            .map(coords)
+            .or(state.last_collapsed)
+            .unwrap_or((0, 0));
+        let mut bans: Vec<Ban> = state
+            .ban_log
+            .newest_first()
+            .filter(|&(c, _, _)| coords(c) == cell)
+            .map(|(c, pattern, cause)| Ban {
+                cell: coords(c),
+                pattern,
+                cause: cause.map(coords),
+            })
+            .collect();
+        bans.reverse();
+        Self {
+            cell,
+            bans,
+            observation: state.last_observation.map(|(c, p)| (coords(c), p)),
+        }
+    }
+}
+
+impl fmt::Display for ContradictionInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (x, y) = self.cell;
+        write!(f, "cell ({}, {}) has no pattern left", x, y)?;
+        if let Some(((ox, oy), p)) = self.observation {
+            write!(f, " after placing pattern {} at ({}, {})", p, ox, oy)?;
+        }
+        for ban in &self.bans {
+            write!(f, "\n  lost pattern {}", ban.pattern)?;
+            match ban.cause {
+                Some((cx, cy)) => write!(f, " when ({}, {}) changed", cx, cy)?,
+                None => write!(f, " to a global constraint")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Ring of the latest propagation bans as `(cell, pattern, cause)`.
+#[derive(Clone)]
+pub(crate) struct BanLog {
+    entries: [(u32, u32, u32); LOG_LEN],
+    next: usize,
+    len: usize,
+}
+
+impl Default for BanLog {
+    fn default() -> Self {
+        Self {
+            entries: [(0, 0, 0); LOG_LEN],
+            next: 0,
+            len: 0,
+        }
+    }
+}
+
+impl BanLog {
+    /// `u32::MAX` marks a ban without a neighbor cause.
+    const NO_CAUSE: u32 = u32::MAX;
+
+    #[inline]
+    pub(crate) fn push(&mut self, cell: usize, pattern: usize, cause: Option<usize>) {
+        let cause = cause.map_or(Self::NO_CAUSE, |c| c as u32);
+        self.entries[self.next] = (cell as u32, pattern as u32, cause);
+        self.next = (self.next + 1) % LOG_LEN;
+        self.len = (self.len + 1).min(LOG_LEN);
+    }
+
+    fn newest_first(&self) -> impl Iterator<Item = (usize, usize, Option<usize>)> + '_ {
+        (1..=self.len).map(move |back| {
+            let (cell, pattern, cause) = self.entries[(self.next + LOG_LEN - back) % LOG_LEN];
+            let cause = (cause != Self::NO_CAUSE).then_some(cause as usize);
+            (cell as usize, pattern as usize, cause)
+        })
+    }
+}
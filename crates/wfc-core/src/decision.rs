@@ -0,0 +1,124 @@
+use std::path::Path;
+
+use crate::error::Error;
+
+/// One observation: the cell, the pattern it kept and how many random
+/// numbers choosing took.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Decision {
+    pub x: usize,
+    pub y: usize,
+    pub pattern: usize,
+    pub draws: u32,
+}
+
+/// Every observation of a run since its last reset, in order, including
+/// those later backtracked over; see [`Wfc::decision_log`] and
+/// [`Wfc::replay`].
+///
+/// [`Wfc::decision_log`]: crate::Wfc::decision_log
+/// [`Wfc::replay`]: crate::Wfc::replay
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DecisionLog {
+    pub seed: u64,
+    /// `(width, height, patterns)` of the rules it was recorded under.
+    pub shape: (usize, usize, usize),
+    pub decisions: Vec<Decision>,
+}
+
+impl DecisionLog {
+    /// A `seed = ` and a `shape = ` line, then one `x y pattern draws` line
+    /// per decision.
+    #[must_use]
+    pub fn to_text(&self) -> String {
+        let (w, h, n) = self.shape;
+        let mut text = format!("seed = {}\nshape = {}x{}x{}\n", self.seed, w, h, n);
+        for d in &self.decisions {
+            text.push_str(&format!("{} {} {} {}\n", d.x, d.y, d.pattern, d.draws));
+        }
+        text
+    }
+
+    /// Parse what [`to_text`](Self::to_text) wrote; `#` starts a comment.
+    pub fn from_text(text: &str) -> Result<Self, Error> {
+        let mut log = Self::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let invalid =
+                || Error::InvalidConfig(format!("line {}: bad decision '{}'", i + 1, line));
+            if let Some((key, value)) = line.split_once('=') {
+                let value = value.trim();
+                match key.trim() {
+                    "seed" => log.seed = value.parse().map_err(|_| invalid())?,
+                    "shape" => {
+                        let dims: Vec<usize> = value
+                            .split('x')
+                            .map(|v| v.trim().parse().ok())
+                            .collect::<Option<_>>()
+                            .ok_or_else(invalid)?;
+                        let [w, h, n] = dims[..] else {
+                            return Err(invalid());
+                        };
+                        log.shape = (w, h, n);
+                    }
+                    _ => return Err(invalid()),
+                }
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [x, y, pattern, draws] = fields[..] else {
+                return Err(invalid());
+            };
+            log.decisions.push(Decision {
+                x: x.parse().map_err(|_| invalid())?,
+                y: y.parse().map_err(|_| invalid())?,
+                pattern: pattern.parse().map_err(|_| invalid())?,
+                draws: draws.parse().map_err(|_| invalid())?,
+            });
+        }
+        Ok(log)
+    }
+
+    /// Read a file written by [`save`](Self::save).
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| Error::InvalidConfig(format!("{}: {}", path.display(), e)))?;
+        Self::from_text(&text)
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::write(path, self.to_text())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_round_trips() {
+        let log = DecisionLog {
+            seed: 7,
+            shape: (16, 8, 30),
+            decisions: vec![
+                Decision {
+                    x: 3,
+                    y: 4,
+                    pattern: 12,
+                    draws: 1,
+                },
+                Decision {
+                    x: 15,
+                    y: 0,
+                    pattern: 0,
+                    draws: 2,
+                },
+            ],
+        };
+        assert_eq!(DecisionLog::from_text(&log.to_text()), Ok(log));
+        assert!(DecisionLog::from_text("1 2 3").is_err());
+    }
+}
@@ -0,0 +1,154 @@
+//! Corner-based (dual grid) model. Sample pixels are terrain values at tile
+//! corners and every 2x2 window is a tile described by its four corners, so
+//! tiles constrain each other through shared corners. This reuses the
+//! overlapping wave with `pattern_size` 2: each output cell's top-left pixel
+//! (what [`Wfc::render`](crate::Wfc::render) returns) is the terrain at that
+//! corner, and [`render_tiles`] draws the tiles between them.
+
+use crate::{Boundary, Color};
+
+/// Tiles spanned by a `width` x `height` corner grid: one fewer per axis
+/// unless it wraps.
+#[must_use]
+pub fn tile_dims(width: usize, height: usize, boundary: Boundary) -> (usize, usize) {
+    let tiles = |corners: usize, wraps: bool| {
+        if wraps {
+            corners
+        } else {
+            corners.saturating_sub(1)
+        }
+    };
+    (
+        tiles(width, boundary.wraps_x()),
+        tiles(height, boundary.wraps_y()),
+    )
+}
+
+/// Draw every tile of the corner grid at `scale` pixels per tile. Each pixel
+/// takes the terrain with the largest bilinear weight among its tile's four
+/// corners, so transitions (grass-to-water corners) come out rounded rather
+/// than blocky. Output is `tile_dims` times `scale`, row-major.
+#[must_use]
+pub fn render_tiles(
+    width: usize,
+    height: usize,
+    corners: &[Color],
+    boundary: Boundary,
+    scale: usize,
+) -> Vec<Color> {
+    assert_eq!(corners.len(), width * height);
+    let (tiles_w, tiles_h) = tile_dims(width, height, boundary);
+    let (out_w, out_h) = (tiles_w * scale, tiles_h * scale);
+    let corner = |x: usize, y: usize| corners[(y % height) * width + x % width];
+
+    let mut out = Vec::with_capacity(out_w * out_h);
+    for py in 0..out_h {
+        let (ty, fy) = (py / scale, (py % scale) as f32 + 0.5);
+        let v = fy / scale as f32;
+        for px in 0..out_w {
+            let (tx, fx) = (px / scale, (px % scale) as f32 + 0.5);
+            let u = fx / scale as f32;
+            let quad = [
+                (corner(tx, ty), (1.0 - u) * (1.0 - v)),
+                (corner(tx + 1, ty), u * (1.0 - v)),
+                (corner(tx, ty + 1), (1.0 - u) * v),
+                (corner(tx + 1, ty + 1), u * v),
+            ];
+            // Sum weights per terrain; ties go to the earlier corner
+            let mut best = (quad[0].0, f32::MIN);
+            for &(color, _) in &quad {
+                let weight: f32 = quad
+                    .iter()
+                    .filter(|(c, _)| *c == color)
+                    .map(|(_, w)| w)
+                    .sum();
+                if weight > best.1 {
+                    best = (color, weight);
+                }
+            }
+            out.push(best.0);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_water_corner_rounds_into_tile() {
+        let (grass, water) = ([0, 200, 0], [0, 0, 200]);
+        // 2x2 corners = one tile with water at the bottom-right corner
+        let corners = [grass, grass, grass, water];
+        let tile = render_tiles(2, 2, &corners, Boundary::Fixed, 4);
+        assert_eq!(tile.len(), 16);
+        assert_eq!(tile[0], grass);
+        assert_eq!(tile[15], water);
+        // Water covers less than a quarter: the corner is cut diagonally
+        let wet = tile.iter().filter(|&&c| c == water).count();
+        assert!(wet > 0 && wet < 4);
+
+        assert_eq!(tile_dims(5, 4, Boundary::Fixed), (4, 3));
+        assert_eq!(tile_dims(5, 4, Boundary::PeriodicX), (5, 3));
+    }
+
+    #[test]
+    fn solved_corners_form_sample_tiles() {
+        let (grass, water) = ([0, 200, 0], [0, 0, 200]);
+        let (w, h) = (8, 8);
+        let pixels = (0..w * h)
+            .map(|i| {
+                let (x, y) = (i % w, i / w);
+                if (2..5).contains(&x) && (3..7).contains(&y) {
+                    water
+                } else {
+                    grass
+                }
+            })
+            .collect();
+        let sample = crate::Sample::new(w, h, pixels);
+        let config = crate::Config {
+            dual_grid: true,
+            pattern_size: 2,
+            output_width: 12,
+            output_height: 10,
+            seed: Some(4),
+            ..Default::default()
+        };
+        assert!(config.validate(&sample).is_ok());
+        let mut wfc = crate::Wfc::new(&sample, config);
+        assert_eq!(wfc.run(), crate::RunOutcome::Complete);
+
+        let corners = wfc.render();
+        let (tiles_w, tiles_h) = tile_dims(12, 10, Boundary::Fixed);
+        let corner = |x: usize, y: usize| corners[y * 12 + x];
+        let scale = 3;
+        let drawn = render_tiles(12, 10, &corners, Boundary::Fixed, scale);
+        assert_eq!(drawn.len(), tiles_w * tiles_h * scale * scale);
+        for ty in 0..tiles_h {
+            for tx in 0..tiles_w {
+                let quad = [
+                    corner(tx, ty),
+                    corner(tx + 1, ty),
+                    corner(tx, ty + 1),
+                    corner(tx + 1, ty + 1),
+                ];
+                // Every tile is one the sample (or a symmetry of it) has
+                let tile = crate::Pattern::new(2, quad.to_vec());
+                assert!(wfc.patterns().contains(&tile));
+                for py in ty * scale..(ty + 1) * scale {
+                    for px in tx * scale..(tx + 1) * scale {
+                        assert!(quad.contains(&drawn[py * tiles_w * scale + px]));
+                    }
+                }
+            }
+        }
+
+        let odd = crate::Config {
+            pattern_size: 3,
+            ..wfc.config().clone()
+        };
+        assert!(odd.validate(&sample).is_err());
+    }
+}
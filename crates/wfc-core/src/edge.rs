@@ -0,0 +1,168 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{Color, Error, parse_hex};
+
+/// Which patterns may sit on one edge of the output.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum EdgeConstraint {
+    /// Anything that fits.
+    #[default]
+    None,
+    /// Patterns seen along the sample's top edge.
+    SampleTop,
+    /// Patterns seen along the sample's bottom edge.
+    SampleBottom,
+    /// Patterns seen along the sample's left edge.
+    SampleLeft,
+    /// Patterns seen along the sample's right edge.
+    SampleRight,
+    /// Patterns whose top-left pixel, the color a cell renders as, is this.
+    Color(Color),
+    /// These patterns, by index into `Wfc::patterns`; indices past the
+    /// pattern count are ignored.
+    Patterns(Vec<usize>),
+}
+
+impl EdgeConstraint {
+    /// Restricts the output edge to patterns from a sample edge, which
+    /// only makes sense for the sample's own orientation.
+    #[must_use]
+    pub fn uses_sample_edge(&self) -> bool {
+        matches!(
+            self,
+            Self::SampleTop | Self::SampleBottom | Self::SampleLeft | Self::SampleRight
+        )
+    }
+}
+
+/// `none`, `sample-top`, `sample-bottom`, `sample-left`, `sample-right`,
+/// `RRGGBB`, or comma-separated `pINDEX`s.
+impl FromStr for EdgeConstraint {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let s = s.trim();
+        Ok(match s {
+            "none" => Self::None,
+            "sample-top" => Self::SampleTop,
+            "sample-bottom" => Self::SampleBottom,
+            "sample-left" => Self::SampleLeft,
+            "sample-right" => Self::SampleRight,
+            _ if s.starts_with('p') => Self::Patterns(
+                s.split(',')
+                    .map(|p| {
+                        p.trim()
+                            .strip_prefix('p')
+                            .and_then(|i| i.parse().ok())
+                            .ok_or_else(|| {
+                                Error::InvalidConfig(format!(
+                                    "invalid pattern '{}', expected pINDEX",
+                                    p
+                                ))
+                            })
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            _ => Self::Color(parse_hex(s).ok_or_else(|| {
+                Error::InvalidConfig(format!(
+                    "invalid edge '{}', expected none, sample-top, sample-bottom, \
+                     sample-left, sample-right, RRGGBB or pINDEX,...",
+                    s
+                ))
+            })?),
+        })
+    }
+}
+
+impl fmt::Display for EdgeConstraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::None => write!(f, "none"),
+            Self::SampleTop => write!(f, "sample-top"),
+            Self::SampleBottom => write!(f, "sample-bottom"),
+            Self::SampleLeft => write!(f, "sample-left"),
+            Self::SampleRight => write!(f, "sample-right"),
+            Self::Color([r, g, b]) => write!(f, "{:02x}{:02x}{:02x}", r, g, b),
+            Self::Patterns(patterns) => {
+                let list: Vec<String> = patterns.iter().map(|p| format!("p{}", p)).collect();
+                write!(f, "{}", list.join(","))
+            }
+        }
+    }
+}
+
+/// One [`EdgeConstraint`] per output edge.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Edges {
+    pub top: EdgeConstraint,
+    pub bottom: EdgeConstraint,
+    pub left: EdgeConstraint,
+    pub right: EdgeConstraint,
+}
+
+impl Edges {
+    /// Top and bottom rows keep the sample's top and bottom patterns, so
+    /// skies stay up and floors down.
+    #[must_use]
+    pub fn ground() -> Self {
+        Self {
+            top: EdgeConstraint::SampleTop,
+            bottom: EdgeConstraint::SampleBottom,
+            ..Self::default()
+        }
+    }
+
+    /// Left and right columns keep the sample's left and right patterns.
+    #[must_use]
+    pub fn sides() -> Self {
+        Self {
+            left: EdgeConstraint::SampleLeft,
+            right: EdgeConstraint::SampleRight,
+            ..Self::default()
+        }
+    }
+
+    /// Top and bottom set like [`Edges::ground`].
+    #[must_use]
+    pub fn is_ground(&self) -> bool {
+        self.top == EdgeConstraint::SampleTop && self.bottom == EdgeConstraint::SampleBottom
+    }
+
+    #[must_use]
+    pub fn uses_sample_edge(&self) -> bool {
+        self.all().iter().any(|edge| edge.uses_sample_edge())
+    }
+
+    /// In `rules::TOP`, `BOTTOM`, `LEFT`, `RIGHT` order.
+    pub(crate) fn all(&self) -> [&EdgeConstraint; 4] {
+        [&self.top, &self.bottom, &self.left, &self.right]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_every_kind_of_edge() {
+        for text in [
+            "none",
+            "sample-top",
+            "sample-bottom",
+            "sample-left",
+            "sample-right",
+            "ff8000",
+            "p3,p12",
+        ] {
+            let edge: EdgeConstraint = text.parse().unwrap();
+            assert_eq!(edge.to_string(), text);
+        }
+        assert_eq!(
+            "p3, p12".parse::<EdgeConstraint>(),
+            Ok(EdgeConstraint::Patterns(vec![3, 12]))
+        );
+        assert!("p3,12".parse::<EdgeConstraint>().is_err());
+        assert!("sideways".parse::<EdgeConstraint>().is_err());
+    }
+}
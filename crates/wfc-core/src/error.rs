@@ -1,9 +1,23 @@
 use std::fmt;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Error {
     ImageLoad(String),
     ImageSave(String),
+    NoLayers,
+    LayerSizeMismatch {
+        expected: (usize, usize),
+        found: (usize, usize),
+    },
+    InvalidSymmetry(String),
+    InvalidConfig(String),
+    InvalidPalette(String),
+    /// Downloading a sample failed or the response was too large.
+    Fetch(String),
+    /// A progress callback asked to stop building the model.
+    Cancelled,
+    /// Reading or writing a saved model failed, or the file isn't one.
+    ModelFile(String),
 }
 
 impl fmt::Display for Error {
@@ -11,6 +25,18 @@ impl fmt::Display for Error {
         match self {
             Error::ImageLoad(msg) => write!(f, "image load error: {}", msg),
             Error::ImageSave(msg) => write!(f, "image save error: {}", msg),
+            Error::NoLayers => write!(f, "layered sample needs at least one layer"),
+            Error::LayerSizeMismatch { expected, found } => write!(
+                f,
+                "layer is {}x{}, expected {}x{}",
+                found.0, found.1, expected.0, expected.1
+            ),
+            Error::InvalidSymmetry(name) => write!(f, "unknown symmetry transform '{}'", name),
+            Error::InvalidConfig(msg) => write!(f, "invalid config: {}", msg),
+            Error::InvalidPalette(msg) => write!(f, "invalid palette: {}", msg),
+            Error::Fetch(msg) => write!(f, "fetch error: {}", msg),
+            Error::Cancelled => write!(f, "model build cancelled"),
+            Error::ModelFile(msg) => write!(f, "model file error: {}", msg),
         }
     }
 }
@@ -0,0 +1,74 @@
+use crate::Color;
+
+/// Color shares the output is held to under `Config::frequency_tolerance`:
+/// each top-left color may cover at most its share of the sample's pattern
+/// weight plus the tolerance of the cells decided so far.
+pub(crate) struct FrequencyTarget {
+    /// `class[p]`: index of pattern `p`'s color in `limit`.
+    pub(crate) class: Vec<u32>,
+    /// Largest share of decided cells each color may hold.
+    pub(crate) limit: Vec<f64>,
+}
+
+impl FrequencyTarget {
+    /// Shares from `weights` (one per pattern, `(w, ln w)`), counting only
+    /// `viable` patterns.
+    pub(crate) fn new(
+        colors: &[Color],
+        weights: &[(f64, f64)],
+        viable: &[bool],
+        tolerance: f64,
+    ) -> Self {
+        let mut palette: Vec<Color> = Vec::new();
+        let mut share: Vec<f64> = Vec::new();
+        let mut class = Vec::with_capacity(colors.len());
+        for (p, &color) in colors.iter().enumerate() {
+            let i = palette.iter().position(|&c| c == color).unwrap_or_else(|| {
+                palette.push(color);
+                share.push(0.0);
+                palette.len() - 1
+            });
+            if viable[p] {
+                share[i] += weights[p].0;
+            }
+            class.push(i as u32);
+        }
+        let total: f64 = share.iter().sum();
+        let limit = share
+            .iter()
+            .map(|&s| if total > 0.0 { s / total } else { 0.0 } + tolerance)
+            .collect();
+        Self { class, limit }
+    }
+
+    /// Whether `pattern`'s color already covers more than its limit of the
+    /// `decided` cells, `placed` counting them per color.
+    #[inline]
+    pub(crate) fn over(&self, placed: &[u32], decided: u32, pattern: usize) -> bool {
+        let class = self.class[pattern] as usize;
+        f64::from(placed[class]) > self.limit[class] * f64::from(decided)
+    }
+
+    pub(crate) fn num_colors(&self) -> usize {
+        self.limit.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_follow_color_weights() {
+        let colors = [[0, 0, 0], [255, 255, 255], [0, 0, 0], [9, 9, 9]];
+        let weights = [(3.0, 0.0), (1.0, 0.0), (1.0, 0.0), (5.0, 0.0)];
+        let target = FrequencyTarget::new(&colors, &weights, &[true, true, true, false], 0.1);
+        assert_eq!(target.class, vec![0, 1, 0, 2]);
+        assert!((target.limit[0] - 0.9).abs() < 1e-9);
+        assert!((target.limit[1] - 0.3).abs() < 1e-9);
+        assert!((target.limit[2] - 0.1).abs() < 1e-9);
+        // 3 of 10 white is at the limit, 4 of 10 over it
+        assert!(!target.over(&[7, 3, 0], 10, 1));
+        assert!(target.over(&[6, 4, 0], 10, 1));
+    }
+}
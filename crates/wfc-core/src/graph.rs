@@ -0,0 +1,96 @@
+use crate::Direction;
+use crate::boundary::Boundary;
+
+/// Planar directions a link can take, in `Direction` order.
+const PLANAR: usize = 8;
+
+/// Cells and their neighbors for generating on something other than a
+/// rectangle: quad-sphere faces, irregular meshes, region graphs. Each
+/// link is labeled with the direction it leaves its node in, and patterns
+/// overlap across it as they would with a grid neighbor there. Set as
+/// `Config::graph`; the output is then a single row, one cell per node.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Graph {
+    /// `links[node * 8 + dir]`, `dir` indexing `Direction`.
+    links: Vec<Option<usize>>,
+}
+
+impl Graph {
+    /// `nodes` nodes with no links yet.
+    #[must_use]
+    pub fn new(nodes: usize) -> Self {
+        Self {
+            links: vec![None; nodes * PLANAR],
+        }
+    }
+
+    /// Rectangular grid, row-major, wrapping as `boundary` says; a start
+    /// for stitching grids together, e.g. the faces of a cube. Diagonals
+    /// are linked too, for `Config::diagonal`.
+    #[must_use]
+    pub fn grid(width: usize, height: usize, boundary: Boundary) -> Self {
+        let mut graph = Self::new(width * height);
+        let step = |i: usize, d: i32, len: usize, wraps: bool| {
+            let j = i as isize + d as isize;
+            if (0..len as isize).contains(&j) {
+                Some(j as usize)
+            } else {
+                (wraps && len > 0).then(|| j.rem_euclid(len as isize) as usize)
+            }
+        };
+        for y in 0..height {
+            for x in 0..width {
+                let node = y * width + x;
+                // The other four directions come from `connect`'s back links
+                for dir in [
+                    Direction::Right,
+                    Direction::Down,
+                    Direction::DownRight,
+                    Direction::DownLeft,
+                ] {
+                    let nx = step(x, dir.dx(), width, boundary.wraps_x());
+                    let ny = step(y, dir.dy(), height, boundary.wraps_y());
+                    if let (Some(nx), Some(ny)) = (nx, ny) {
+                        graph.connect(node, dir, ny * width + nx);
+                    }
+                }
+            }
+        }
+        graph
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.links.len() / PLANAR
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.links.is_empty()
+    }
+
+    /// Link `b` as `a`'s neighbor in `dir` and `a` as `b`'s in the
+    /// opposite direction, replacing their earlier links that way.
+    ///
+    /// # Panics
+    /// If a node is out of range or `dir` leaves the plane.
+    pub fn connect(&mut self, a: usize, dir: Direction, b: usize) {
+        assert!(
+            (dir as usize) < PLANAR,
+            "graph links are planar, got {:?}",
+            dir
+        );
+        assert!(a.max(b) < self.len(), "node out of range");
+        self.links[a * PLANAR + dir as usize] = Some(b);
+        self.links[b * PLANAR + dir.opposite() as usize] = Some(a);
+    }
+
+    /// Neighbor of `node` in `dir`, if linked.
+    #[must_use]
+    pub fn neighbor(&self, node: usize, dir: Direction) -> Option<usize> {
+        if dir as usize >= PLANAR {
+            return None;
+        }
+        self.links[node * PLANAR + dir as usize]
+    }
+}
@@ -1,14 +1,27 @@
 use crate::boundary::Boundary;
+use crate::graph::Graph;
 
+/// A neighbor of an output cell. The discriminant is the propagator's
+/// direction index. Marked non-exhaustive because the diagonals were
+/// added after the four sides, so matches need a wildcard arm.
 #[derive(Clone, Copy, Debug)]
+#[non_exhaustive]
 pub enum Direction {
     Right = 0,
     Down = 1,
     Left = 2,
     Up = 3,
+    /// Diagonals are only propagated with `Config::diagonal` (or `strict_overlap`).
+    DownRight = 4,
+    DownLeft = 5,
+    UpLeft = 6,
+    UpRight = 7,
 }
 
 impl Direction {
+    /// The four side neighbors, which every model propagates. Despite the
+    /// name, this excludes the diagonals; chain it with
+    /// [`DIAGONALS`](Self::DIAGONALS) for all eight.
     pub const ALL: [Direction; 4] = [
         Direction::Right,
         Direction::Down,
@@ -16,6 +29,15 @@ impl Direction {
         Direction::Up,
     ];
 
+    /// The four corner neighbors, propagated only with `Config::diagonal`
+    /// or `strict_overlap`.
+    pub const DIAGONALS: [Direction; 4] = [
+        Direction::DownRight,
+        Direction::DownLeft,
+        Direction::UpLeft,
+        Direction::UpRight,
+    ];
+
     #[inline]
     pub fn opposite(self) -> Self {
         match self {
@@ -23,14 +45,18 @@ impl Direction {
             Direction::Down => Direction::Up,
             Direction::Left => Direction::Right,
             Direction::Up => Direction::Down,
+            Direction::DownRight => Direction::UpLeft,
+            Direction::DownLeft => Direction::UpRight,
+            Direction::UpLeft => Direction::DownRight,
+            Direction::UpRight => Direction::DownLeft,
         }
     }
 
     #[inline]
     pub(crate) fn dx(self) -> i32 {
         match self {
-            Direction::Right => 1,
-            Direction::Left => -1,
+            Direction::Right | Direction::DownRight | Direction::UpRight => 1,
+            Direction::Left | Direction::DownLeft | Direction::UpLeft => -1,
             _ => 0,
         }
     }
@@ -38,8 +64,8 @@ impl Direction {
     #[inline]
     pub(crate) fn dy(self) -> i32 {
         match self {
-            Direction::Down => 1,
-            Direction::Up => -1,
+            Direction::Down | Direction::DownRight | Direction::DownLeft => 1,
+            Direction::Up | Direction::UpLeft | Direction::UpRight => -1,
             _ => 0,
         }
     }
@@ -51,14 +77,22 @@ const NO_NEIGHBOR: u32 = u32::MAX;
 pub(crate) struct Grid {
     pub(crate) width: usize,
     pub(crate) height: usize,
-    /// `neighbors[cell * 4 + dir]`: neighbor cell index, or sentinel if out of bounds.
+    num_dirs: usize,
+    /// `neighbors[cell * num_dirs + dir]`: neighbor cell index, or sentinel if out of bounds.
     neighbors: Vec<u32>,
 }
 
 impl Grid {
-    pub(crate) fn new(width: usize, height: usize, boundary: Boundary) -> Self {
+    /// `offsets[dir]` is the (dx, dy) step to the neighbor in direction `dir`.
+    pub(crate) fn new(
+        width: usize,
+        height: usize,
+        boundary: Boundary,
+        offsets: &[(i32, i32)],
+    ) -> Self {
         let size = width * height;
-        let mut neighbors = vec![NO_NEIGHBOR; size * 4];
+        let num_dirs = offsets.len();
+        let mut neighbors = vec![NO_NEIGHBOR; size * num_dirs];
         let wrap_x = boundary.wraps_x();
         let wrap_y = boundary.wraps_y();
 
@@ -66,9 +100,9 @@ impl Grid {
             let x = cell % width;
             let y = cell / width;
 
-            for dir in Direction::ALL {
-                let raw_x = x as i32 + dir.dx();
-                let raw_y = y as i32 + dir.dy();
+            for (dir, &(dx, dy)) in offsets.iter().enumerate() {
+                let raw_x = x as i32 + dx;
+                let raw_y = y as i32 + dy;
 
                 let resolved_x = if raw_x >= 0 && raw_x < width as i32 {
                     Some(raw_x as usize)
@@ -87,7 +121,7 @@ impl Grid {
                 };
 
                 if let (Some(nx), Some(ny)) = (resolved_x, resolved_y) {
-                    neighbors[cell * 4 + dir as usize] = (ny * width + nx) as u32;
+                    neighbors[cell * num_dirs + dir] = (ny * width + nx) as u32;
                 }
             }
         }
@@ -95,13 +129,52 @@ impl Grid {
         Self {
             width,
             height,
+            num_dirs,
+            neighbors,
+        }
+    }
+
+    /// One row with a cell per node of `graph`, linked as it says in the
+    /// first `num_dirs` directions (`Direction` order).
+    pub(crate) fn from_graph(graph: &Graph, num_dirs: usize) -> Self {
+        let planar = Direction::ALL.iter().chain(&Direction::DIAGONALS);
+        let dirs: Vec<Direction> = planar.copied().take(num_dirs).collect();
+        let mut neighbors = Vec::with_capacity(graph.len() * num_dirs);
+        for node in 0..graph.len() {
+            for &dir in &dirs {
+                neighbors.push(graph.neighbor(node, dir).map_or(NO_NEIGHBOR, |n| n as u32));
+            }
+        }
+        Self {
+            width: graph.len(),
+            height: 1,
+            num_dirs,
             neighbors,
         }
     }
 
+    /// Cut every link to or from cells where `keep` is false, so they act
+    /// like the space past a fixed edge.
+    pub(crate) fn mask_out(&mut self, keep: &[bool]) {
+        for cell in 0..self.size() {
+            for dir in 0..self.num_dirs {
+                let slot = cell * self.num_dirs + dir;
+                let n = self.neighbors[slot];
+                if !keep[cell] || (n != NO_NEIGHBOR && !keep[n as usize]) {
+                    self.neighbors[slot] = NO_NEIGHBOR;
+                }
+            }
+        }
+    }
+
     #[inline(always)]
+    /// The whole neighbor table, for fingerprinting.
+    pub(crate) fn neighbors(&self) -> &[u32] {
+        &self.neighbors
+    }
+
     pub(crate) fn neighbor(&self, cell: usize, dir: usize) -> Option<usize> {
-        let n = self.neighbors[cell * 4 + dir];
+        let n = self.neighbors[cell * self.num_dirs + dir];
         if n == NO_NEIGHBOR {
             None
         } else {
@@ -0,0 +1,87 @@
+use crate::config::Config;
+use crate::retry::solve_until_success;
+use crate::{Color, PatternOrigin, Sample};
+
+/// Large-scale layout for a full-resolution run, set as `Config::guide`.
+///
+/// Overlapping WFC only sees a pattern's worth of context, so it can't lay
+/// out regions or biomes larger than that. A guide adds them from a coarse
+/// first pass: every pattern belongs to the region under where it was first
+/// seen in the sample, and cells prefer patterns of the region the layout
+/// shows there.
+#[derive(Clone, Debug)]
+pub struct Guide {
+    /// Coarse layout, stretched over the output.
+    pub layout: Sample,
+    /// Region map in the layout's colors, stretched over the first sample:
+    /// a downscaled copy of it, or a hand-drawn "macro" image.
+    pub regions: Sample,
+    /// Weight multiplier for patterns of the region the layout shows at a
+    /// cell; `1.0` turns the guide off.
+    pub strength: f64,
+}
+
+impl Guide {
+    /// First pass: solve `regions` as a sample at `1/scale` of `config`'s
+    /// output size (rounded up) and guide with the result. The pass keeps
+    /// `config`'s seed, symmetry, wrapping, heuristic and backtracking, with
+    /// the pattern size capped to what a non-wrapping `regions` fits.
+    /// `None` if `max_attempts` seeds all contradicted.
+    #[must_use]
+    pub fn generate(
+        regions: Sample,
+        config: &Config,
+        scale: usize,
+        strength: f64,
+        max_attempts: usize,
+    ) -> Option<Self> {
+        let scale = scale.max(1);
+        let (n, m) = config.pattern_dims();
+        let wrap = config.input_boundary;
+        let fits = |side: usize, len: usize, wraps: bool| if wraps { side } else { side.min(len) };
+        let coarse = Config {
+            pattern_size: fits(n, regions.width, wrap.wraps_x()).max(1),
+            pattern_height: config
+                .pattern_height
+                .map(|_| fits(m, regions.height, wrap.wraps_y()).max(1)),
+            output_width: config.output_width.div_ceil(scale),
+            output_height: config.output_height.div_ceil(scale),
+            input_boundary: wrap,
+            boundary: config.boundary,
+            symmetry: config.symmetry,
+            heuristic: config.heuristic,
+            backtracking: config.backtracking,
+            seed: config.seed,
+            ..Config::default()
+        };
+        let solved = solve_until_success(&regions, &coarse, max_attempts)?;
+        Some(Self {
+            layout: Sample::new(coarse.output_width, coarse.output_height, solved.output),
+            regions,
+            strength,
+        })
+    }
+
+    /// Layout color over cell `(x, y)` of a `width` x `height` output.
+    pub(crate) fn layout_at(&self, x: usize, y: usize, (width, height): (usize, usize)) -> Color {
+        self.layout.get(
+            x * self.layout.width / width.max(1),
+            y * self.layout.height / height.max(1),
+        )
+    }
+
+    /// Region of a pattern first seen at `origin` in a first sample of
+    /// `source_size`; `None` for patterns only found in later samples.
+    pub(crate) fn region_of(
+        &self,
+        origin: PatternOrigin,
+        (width, height): (usize, usize),
+    ) -> Option<Color> {
+        (origin.sample == 0).then(|| {
+            self.regions.get(
+                origin.x * self.regions.width / width.max(1),
+                origin.y * self.regions.height / height.max(1),
+            )
+        })
+    }
+}
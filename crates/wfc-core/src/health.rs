@@ -0,0 +1,52 @@
+use crate::grid::Direction;
+use crate::rules::Rules;
+
+/// Adjacency statistics for spotting samples that will contradict often.
+#[derive(Debug, Clone)]
+pub struct PropagatorHealth {
+    /// `neighbor_counts[pattern][dir]`: compatible patterns in that direction.
+    pub neighbor_counts: Vec<[usize; 4]>,
+    /// Patterns with no compatible neighbor in at least one direction.
+    pub dead_ends: Vec<usize>,
+    /// Mean compatible-neighbor count over all (pattern, direction) pairs.
+    pub average_branching: f64,
+}
+
+impl PropagatorHealth {
+    pub(crate) fn from_rules(rules: &Rules) -> Self {
+        let num_patterns = rules.num_patterns();
+        let mut neighbor_counts = Vec::with_capacity(num_patterns);
+        let mut dead_ends = Vec::new();
+        let mut total = 0usize;
+
+        for p in 0..num_patterns {
+            let mut counts = [0usize; 4];
+            for dir in Direction::ALL {
+                counts[dir as usize] = rules.propagator.count(p, dir as usize);
+            }
+            if counts.contains(&0) {
+                dead_ends.push(p);
+            }
+            total += counts.iter().sum::<usize>();
+            neighbor_counts.push(counts);
+        }
+
+        let average_branching = if num_patterns == 0 {
+            0.0
+        } else {
+            total as f64 / (num_patterns * 4) as f64
+        };
+
+        Self {
+            neighbor_counts,
+            dead_ends,
+            average_branching,
+        }
+    }
+
+    /// True if every pattern has a neighbor in every direction.
+    #[must_use]
+    pub fn is_healthy(&self) -> bool {
+        self.dead_ends.is_empty()
+    }
+}
@@ -0,0 +1,211 @@
+use crate::{Color, Error, Sample, Wfc};
+
+/// 16-bit grayscale terrain. Heights round-trip losslessly through `Color`
+/// (high byte in red, low byte in green), so the solver runs unchanged.
+#[derive(Clone, Debug)]
+pub struct Heightmap {
+    pub width: usize,
+    pub height: usize,
+    pub values: Vec<u16>,
+}
+
+impl Heightmap {
+    pub fn new(width: usize, height: usize, values: Vec<u16>) -> Self {
+        assert_eq!(values.len(), width * height);
+        Self {
+            width,
+            height,
+            values,
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> u16 {
+        self.values[y * self.width + x]
+    }
+
+    #[inline]
+    pub fn encode(h: u16) -> Color {
+        [(h >> 8) as u8, h as u8, 0]
+    }
+
+    #[inline]
+    pub fn decode(c: Color) -> u16 {
+        (c[0] as u16) << 8 | c[1] as u16
+    }
+
+    /// Sample with heights encoded as colors, for pattern extraction.
+    pub fn to_sample(&self) -> Sample {
+        let pixels = self.values.iter().map(|&h| Self::encode(h)).collect();
+        Sample::new(self.width, self.height, pixels)
+    }
+
+    /// Decode a rendered output back into heights.
+    pub fn from_colors(width: usize, height: usize, colors: &[Color]) -> Self {
+        let values = colors.iter().map(|&c| Self::decode(c)).collect();
+        Self::new(width, height, values)
+    }
+
+    /// Heights of a solver's output, decoded per pattern rather than from
+    /// [`Wfc::render`]'s blended colors, which aren't heights. Undecided
+    /// cells take the weighted mean height of their candidates and
+    /// contradicted cells 0, so in-progress outputs preview sensibly.
+    pub fn from_wfc(wfc: &Wfc) -> Self {
+        let config = wfc.config();
+        let (width, height) = (config.output_width, config.output_height);
+        let mut values = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let (sum, total) = wfc.possibilities(x, y).fold(
+                    (0.0, 0.0),
+                    |(sum, total), (_, pattern, weight)| {
+                        let h = f64::from(Self::decode(pattern.get(0, 0)));
+                        (sum + h * weight, total + weight)
+                    },
+                );
+                values.push(if total > 0.0 {
+                    (sum / total).round() as u16
+                } else {
+                    0
+                });
+            }
+        }
+        Self::new(width, height, values)
+    }
+
+    /// Shaded-relief preview lit from the top-left; empty for an empty map.
+    pub fn hillshade(&self) -> Vec<Color> {
+        if self.width == 0 || self.height == 0 {
+            return Vec::new();
+        }
+        let (min, max) = self
+            .values
+            .iter()
+            .fold((u16::MAX, 0), |(lo, hi), &h| (lo.min(h), hi.max(h)));
+        let range = (max.saturating_sub(min)).max(1) as f64;
+        let norm = |x: usize, y: usize| (self.get(x, y) - min) as f64 / range;
+        // Exaggerate relief so small samples still read as terrain
+        let relief = 4.0;
+        let light = [-1.0f64, -1.0, 1.0];
+        let light_len = (3.0f64).sqrt();
+
+        let mut out = Vec::with_capacity(self.values.len());
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let xl = x.saturating_sub(1);
+                let xr = (x + 1).min(self.width - 1);
+                let yu = y.saturating_sub(1);
+                let yd = (y + 1).min(self.height - 1);
+                let dzdx = (norm(xr, y) - norm(xl, y)) * relief;
+                let dzdy = (norm(x, yd) - norm(x, yu)) * relief;
+
+                let normal_len = (dzdx * dzdx + dzdy * dzdy + 1.0).sqrt();
+                let shade = ((-dzdx * light[0] - dzdy * light[1] + light[2])
+                    / (normal_len * light_len))
+                    .clamp(0.0, 1.0);
+
+                let v = (norm(x, y) * 0.4 + shade * 0.6) * 255.0;
+                let v = v.clamp(0.0, 255.0) as u8;
+                out.push([v, v, v]);
+            }
+        }
+        out
+    }
+
+    /// Little-endian u16 values, row-major, no header.
+    pub fn save_raw(&self, path: &std::path::Path) -> Result<(), Error> {
+        let bytes: Vec<u8> = self.values.iter().flat_map(|h| h.to_le_bytes()).collect();
+        std::fs::write(path, bytes).map_err(|e| Error::ImageSave(e.to_string()))
+    }
+
+    #[cfg(feature = "image-io")]
+    pub fn from_image(path: &std::path::Path) -> Result<Self, Error> {
+        let img = image::open(path).map_err(|e| Error::ImageLoad(e.to_string()))?;
+        let luma = img.to_luma16();
+        let width = luma.width() as usize;
+        let height = luma.height() as usize;
+        Ok(Self::new(width, height, luma.into_raw()))
+    }
+
+    #[cfg(feature = "image-io")]
+    fn to_image(&self) -> image::ImageBuffer<image::Luma<u16>, Vec<u16>> {
+        image::ImageBuffer::from_raw(self.width as u32, self.height as u32, self.values.clone())
+            .expect("buffer matches dimensions")
+    }
+
+    /// 16-bit grayscale PNG.
+    #[cfg(feature = "image-io")]
+    pub fn save_png(&self, path: &std::path::Path) -> Result<(), Error> {
+        self.to_image()
+            .save(path)
+            .map_err(|e| Error::ImageSave(e.to_string()))
+    }
+
+    /// 16-bit grayscale PNG file bytes.
+    #[cfg(feature = "image-io")]
+    pub fn encode_png(&self) -> Result<Vec<u8>, Error> {
+        let mut out = std::io::Cursor::new(Vec::new());
+        self.to_image()
+            .write_to(&mut out, image::ImageFormat::Png)
+            .map_err(|e| Error::ImageSave(e.to_string()))?;
+        Ok(out.into_inner())
+    }
+
+    /// 16-bit binary PGM (P5, big-endian samples) bytes.
+    pub fn encode_pgm(&self) -> Vec<u8> {
+        let mut out = format!("P5\n{} {}\n65535\n", self.width, self.height).into_bytes();
+        out.extend(self.values.iter().flat_map(|h| h.to_be_bytes()));
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_encoding_round_trips() {
+        for h in [0u16, 1, 255, 256, 0x1234, u16::MAX] {
+            assert_eq!(Heightmap::decode(Heightmap::encode(h)), h);
+        }
+        let hm = Heightmap::new(2, 2, vec![0, 1000, 40000, u16::MAX]);
+        let sample = hm.to_sample();
+        let back = Heightmap::from_colors(2, 2, &sample.pixels);
+        assert_eq!(back.values, hm.values);
+    }
+
+    #[test]
+    fn pgm_header_and_big_endian_samples() {
+        let pgm = Heightmap::new(2, 1, vec![0x0102, u16::MAX]).encode_pgm();
+        assert_eq!(pgm, b"P5\n2 1\n65535\n\x01\x02\xff\xff");
+    }
+
+    #[test]
+    fn hillshade_of_flat_terrain_is_uniform() {
+        let hm = Heightmap::new(3, 3, vec![500; 9]);
+        let shade = hm.hillshade();
+        assert!(shade.iter().all(|&c| c == shade[0]));
+        assert!(Heightmap::new(0, 4, Vec::new()).hillshade().is_empty());
+    }
+
+    #[test]
+    fn heights_come_from_patterns_not_blended_colors() {
+        let terrain = Heightmap::new(4, 4, (0..16).map(|i| [0, 40000][i % 2]).collect());
+        let config = crate::Config {
+            seed: Some(1),
+            output_width: 6,
+            output_height: 6,
+            ..Default::default()
+        };
+        let mut wfc = Wfc::new(&terrain.to_sample(), config);
+        // Before any collapse every cell averages the two heights
+        let start = Heightmap::from_wfc(&wfc);
+        assert!(start.values.iter().all(|&h| h > 0 && h < 40000));
+        assert_eq!(wfc.run(), crate::RunOutcome::Complete);
+        let done = Heightmap::from_wfc(&wfc);
+        assert!(done.values.iter().all(|&h| h == 0 || h == 40000));
+        assert_eq!(
+            done.values,
+            Heightmap::from_colors(6, 6, &wfc.render()).values
+        );
+    }
+}
@@ -0,0 +1,13 @@
+/// How `step()` picks the next cell to collapse.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Heuristic {
+    /// The undecided cell with the lowest entropy, so the output grows
+    /// outward from wherever it is most constrained.
+    #[default]
+    MinEntropy,
+    /// The first undecided cell in row-major order: the output fills in
+    /// line by line from the top.
+    Scanline,
+    /// Any undecided cell, uniformly.
+    Random,
+}
@@ -0,0 +1,108 @@
+use crate::memory::vec_bytes;
+use crate::rng::SplitMix64;
+use crate::state::State;
+
+/// One observation as made: the cell, the pattern it kept and the RNG
+/// after choosing, so the run continues as it did after a replay.
+struct Observation {
+    cell: usize,
+    chosen: usize,
+    rng: SplitMix64,
+}
+
+/// Recent observations for `Wfc::undo`: the journal of choices, plus a
+/// full state every `interval` observations to replay them from.
+pub(crate) struct History {
+    journal: Vec<Observation>,
+    /// `(journal index, state before that observation)`, oldest first;
+    /// the first one is always at index 0.
+    checkpoints: Vec<(usize, State)>,
+    interval: usize,
+    depth: usize,
+}
+
+impl History {
+    /// Keeps at least `depth` observations undoable; `depth` 0 records
+    /// nothing.
+    pub(crate) fn new(interval: usize, depth: usize) -> Self {
+        Self {
+            journal: Vec::new(),
+            checkpoints: Vec::new(),
+            interval: interval.max(1),
+            depth,
+        }
+    }
+
+    /// Observations that can be undone.
+    pub(crate) fn len(&self) -> usize {
+        self.journal.len()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.journal.clear();
+        self.checkpoints.clear();
+    }
+
+    /// Heap bytes held by checkpoints and the journal.
+    pub(crate) fn heap_bytes(&self) -> usize {
+        self.checkpoints
+            .iter()
+            .map(|(_, state)| state.heap_bytes())
+            .sum::<usize>()
+            + vec_bytes(&self.checkpoints)
+            + vec_bytes(&self.journal)
+    }
+
+    /// Checkpoint `before` if one is due; call ahead of an observation.
+    pub(crate) fn before_observe(&mut self, before: &State) {
+        if self.depth == 0 {
+            return;
+        }
+        let last = self.checkpoints.last().map(|&(at, _)| at);
+        if last.is_none_or(|at| self.journal.len() - at >= self.interval) {
+            self.checkpoints.push((self.journal.len(), before.clone()));
+        }
+    }
+
+    /// Journal the observation just made, dropping the oldest checkpoint
+    /// once the next one alone covers `depth` observations.
+    pub(crate) fn after_observe(&mut self, cell: usize, chosen: usize, rng: &SplitMix64) {
+        if self.depth == 0 {
+            return;
+        }
+        self.journal.push(Observation {
+            cell,
+            chosen,
+            rng: rng.clone(),
+        });
+        if let Some(&(second, _)) = self.checkpoints.get(1)
+            && self.journal.len() - second >= self.depth
+        {
+            self.checkpoints.remove(0);
+            self.journal.drain(..second);
+            for (at, _) in &mut self.checkpoints {
+                *at -= second;
+            }
+        }
+    }
+
+    /// Forget the latest `n` observations (at most [`len`](Self::len)).
+    /// Returns the nearest earlier checkpoint, carrying the RNG to continue
+    /// from, and the observations to replay on top of it as `(cell, chosen)`.
+    pub(crate) fn rewind(&mut self, n: usize) -> Option<(State, Vec<(usize, usize)>)> {
+        let target = self.journal.len() - n.min(self.journal.len());
+        let i = self.checkpoints.iter().rposition(|&(at, _)| at <= target)?;
+        self.checkpoints.truncate(i + 1);
+        self.journal.truncate(target);
+        let (at, checkpoint) = &self.checkpoints[i];
+        let mut state = checkpoint.clone();
+        if let Some(last) = self.journal[*at..].last() {
+            state.rng = last.rng.clone();
+        }
+        let replay = self.journal[*at..]
+            .iter()
+            .map(|o| (o.cell, o.chosen))
+            .collect();
+        Some((state, replay))
+    }
+}
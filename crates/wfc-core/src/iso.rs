@@ -0,0 +1,167 @@
+//! Isometric 2.5D projection of an output grid, for previewing dungeon or
+//! terrain results as raised blocks.
+
+use crate::Color;
+
+/// Screen-space quad in tile units (a tile is 2 wide and 1 tall), with its
+/// shaded color.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct IsoFace {
+    pub points: [[f32; 2]; 4],
+    pub color: Color,
+}
+
+/// Faces in back-to-front order, translated so the scene spans
+/// `0..width` x `0..height`.
+#[derive(Clone, Debug, Default)]
+pub struct IsoScene {
+    pub faces: Vec<IsoFace>,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// Default color to height mapping: luminance in `0..=1`.
+#[must_use]
+pub fn luma_height(color: Color) -> f32 {
+    (0.299 * color[0] as f32 + 0.587 * color[1] as f32 + 0.114 * color[2] as f32) / 255.0
+}
+
+/// Project a `width` x `height` grid with per-cell `heights` (in tile
+/// heights) into top faces plus the two visible side faces of each block.
+#[must_use]
+pub fn project(width: usize, height: usize, colors: &[Color], heights: &[f32]) -> IsoScene {
+    assert_eq!(colors.len(), width * height);
+    assert_eq!(heights.len(), width * height);
+    if width == 0 || height == 0 {
+        return IsoScene::default();
+    }
+    let max_height = heights.iter().copied().fold(0.0f32, f32::max);
+    // Grid point (x, y) at elevation z; x - y spans -height..width
+    let point = |x: usize, y: usize, z: f32| {
+        [
+            x as f32 - y as f32 + height as f32,
+            (x + y) as f32 * 0.5 - z + max_height,
+        ]
+    };
+    let shade = |c: Color, f: f32| c.map(|v| (v as f32 * f) as u8);
+
+    let mut faces = Vec::with_capacity(width * height * 3);
+    // Equal x + y never overlap, so drawing by diagonal is back to front
+    for diagonal in 0..(width + height).saturating_sub(1) {
+        for y in diagonal.saturating_sub(width - 1)..=diagonal.min(height - 1) {
+            let x = diagonal - y;
+            let (color, z) = (colors[y * width + x], heights[y * width + x]);
+            if z > 0.0 {
+                faces.push(IsoFace {
+                    points: [
+                        point(x, y + 1, z),
+                        point(x + 1, y + 1, z),
+                        point(x + 1, y + 1, 0.0),
+                        point(x, y + 1, 0.0),
+                    ],
+                    color: shade(color, 0.75),
+                });
+                faces.push(IsoFace {
+                    points: [
+                        point(x + 1, y, z),
+                        point(x + 1, y + 1, z),
+                        point(x + 1, y + 1, 0.0),
+                        point(x + 1, y, 0.0),
+                    ],
+                    color: shade(color, 0.55),
+                });
+            }
+            faces.push(IsoFace {
+                points: [
+                    point(x, y, z),
+                    point(x + 1, y, z),
+                    point(x + 1, y + 1, z),
+                    point(x, y + 1, z),
+                ],
+                color,
+            });
+        }
+    }
+
+    IsoScene {
+        faces,
+        width: (width + height) as f32,
+        height: (width + height) as f32 * 0.5 + max_height,
+    }
+}
+
+impl IsoScene {
+    /// Rasterize at `scale` pixels per tile unit over `background`, returning
+    /// `(width, height, pixels)`.
+    #[must_use]
+    pub fn rasterize(&self, scale: f32, background: Color) -> (usize, usize, Vec<Color>) {
+        let w = (self.width * scale).ceil() as usize;
+        let h = (self.height * scale).ceil() as usize;
+        let mut pixels = vec![background; w * h];
+        for face in &self.faces {
+            let pts = face.points.map(|[x, y]| [x * scale, y * scale]);
+            let (min_x, max_x, min_y, max_y) = pts.iter().fold(
+                (f32::MAX, f32::MIN, f32::MAX, f32::MIN),
+                |(a, b, c, d), p| (a.min(p[0]), b.max(p[0]), c.min(p[1]), d.max(p[1])),
+            );
+            let x0 = min_x.floor().max(0.0) as usize;
+            let y0 = min_y.floor().max(0.0) as usize;
+            let x1 = (max_x.ceil() as usize).min(w);
+            let y1 = (max_y.ceil() as usize).min(h);
+            for py in y0..y1 {
+                for px in x0..x1 {
+                    if inside_quad(&pts, [px as f32 + 0.5, py as f32 + 0.5]) {
+                        pixels[py * w + px] = face.color;
+                    }
+                }
+            }
+        }
+        (w, h, pixels)
+    }
+}
+
+/// Point in a convex quad of either winding.
+fn inside_quad(pts: &[[f32; 2]; 4], p: [f32; 2]) -> bool {
+    let mut sign = 0.0f32;
+    for i in 0..4 {
+        let (a, b) = (pts[i], pts[(i + 1) % 4]);
+        let cross = (b[0] - a[0]) * (p[1] - a[1]) - (b[1] - a[1]) * (p[0] - a[0]);
+        if cross != 0.0 {
+            if sign != 0.0 && cross.signum() != sign {
+                return false;
+            }
+            sign = cross.signum();
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raised_block_shows_sides_and_rasterizes() {
+        let (floor, wall) = ([200, 200, 200], [100, 50, 0]);
+        let colors = [floor, wall, floor, floor];
+        let scene = project(2, 2, &colors, &[0.0, 1.0, 0.0, 0.0]);
+        // Three flat tops plus a top and two sides for the wall
+        assert_eq!(scene.faces.len(), 6);
+        assert_eq!((scene.width, scene.height), (4.0, 3.0));
+
+        let (w, h, pixels) = scene.rasterize(8.0, [0, 0, 0]);
+        assert_eq!((w, h), (32, 24));
+        assert!(pixels.contains(&wall));
+        assert!(pixels.contains(&[75, 37, 0]));
+        assert!(pixels.contains(&floor));
+    }
+
+    #[test]
+    fn empty_grid_projects_to_nothing() {
+        for (w, h) in [(0, 3), (3, 0), (0, 0)] {
+            let scene = project(w, h, &[], &[]);
+            assert!(scene.faces.is_empty());
+            assert_eq!(scene.rasterize(8.0, [0, 0, 0]).0, 0);
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{Color, Error, Sample, Wfc};
+
+/// Pixel-aligned samples (e.g. visuals plus a collision layer) solved jointly.
+#[derive(Clone, Debug)]
+pub struct LayeredSample {
+    pub width: usize,
+    pub height: usize,
+    pub layers: Vec<Sample>,
+}
+
+impl LayeredSample {
+    pub fn new(layers: Vec<Sample>) -> Result<Self, Error> {
+        let first = layers.first().ok_or(Error::NoLayers)?;
+        let (width, height) = (first.width, first.height);
+        if let Some(bad) = layers
+            .iter()
+            .find(|l| (l.width, l.height) != (width, height))
+        {
+            return Err(Error::LayerSizeMismatch {
+                expected: (width, height),
+                found: (bad.width, bad.height),
+            });
+        }
+        Ok(Self {
+            width,
+            height,
+            layers,
+        })
+    }
+
+    #[cfg(feature = "image-io")]
+    pub fn from_images(paths: &[&std::path::Path]) -> Result<Self, Error> {
+        let layers = paths
+            .iter()
+            .map(|p| Sample::from_image(p))
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::new(layers)
+    }
+
+    /// Collapse each pixel's layer tuple into one key color, so patterns are
+    /// extracted over all layers at once. Keys reuse the first layer's color
+    /// where it is unambiguous, keeping the joint sample recognisable.
+    pub fn joint(&self) -> (Sample, LayerPalette) {
+        let mut keys: HashMap<Vec<Color>, Color> = HashMap::new();
+        let mut used: HashSet<Color> = HashSet::new();
+        let mut pixels = Vec::with_capacity(self.width * self.height);
+
+        for i in 0..self.width * self.height {
+            let tuple: Vec<Color> = self.layers.iter().map(|l| l.pixels[i]).collect();
+            let key = *keys.entry(tuple).or_insert_with_key(|tuple| {
+                let mut key = tuple[0];
+                while !used.insert(key) {
+                    key = next_color(key);
+                }
+                key
+            });
+            pixels.push(key);
+        }
+
+        let lookup = keys.into_iter().map(|(tuple, key)| (key, tuple)).collect();
+        let palette = LayerPalette {
+            lookup,
+            num_layers: self.layers.len(),
+        };
+        (Sample::new(self.width, self.height, pixels), palette)
+    }
+}
+
+/// Maps joint key colors back to per-layer colors.
+#[derive(Clone, Debug)]
+pub struct LayerPalette {
+    lookup: HashMap<Color, Vec<Color>>,
+    num_layers: usize,
+}
+
+impl LayerPalette {
+    #[must_use]
+    pub fn num_layers(&self) -> usize {
+        self.num_layers
+    }
+
+    /// Split a finished joint output into one color buffer per layer.
+    /// Colors that are not a known key keep their rendered color in every
+    /// layer. An undecided cell's blend can happen to equal a key, so split
+    /// a run still in progress with [`split_output`](Self::split_output).
+    #[must_use]
+    pub fn split(&self, colors: &[Color]) -> Vec<Vec<Color>> {
+        (0..self.num_layers)
+            .map(|layer| self.layer(colors, layer))
+            .collect()
+    }
+
+    /// Like [`split`](Self::split) for `wfc`'s current output, looking up
+    /// only collapsed cells; the rest keep their blend in every layer.
+    #[must_use]
+    pub fn split_output(&self, wfc: &Wfc) -> Vec<Vec<Color>> {
+        let colors = wfc.render();
+        let width = wfc.config().output_width;
+        let collapsed: Vec<bool> = (0..colors.len())
+            .map(|i| wfc.is_collapsed(i % width, i / width))
+            .collect();
+        (0..self.num_layers)
+            .map(|layer| self.layer_where(&colors, layer, |i| collapsed[i]))
+            .collect()
+    }
+
+    /// One layer of a finished joint output, e.g. just the collision map;
+    /// like [`split`](Self::split) otherwise. Panics if `layer` is out of
+    /// range.
+    #[must_use]
+    pub fn layer(&self, colors: &[Color], layer: usize) -> Vec<Color> {
+        self.layer_where(colors, layer, |_| true)
+    }
+
+    /// `layer`, looking up only the cells `decided` accepts.
+    fn layer_where(
+        &self,
+        colors: &[Color],
+        layer: usize,
+        decided: impl Fn(usize) -> bool,
+    ) -> Vec<Color> {
+        assert!(layer < self.num_layers, "layer {} out of range", layer);
+        colors
+            .iter()
+            .enumerate()
+            .map(|(i, c)| match self.lookup.get(c) {
+                Some(tuple) if decided(i) => tuple[layer],
+                _ => *c,
+            })
+            .collect()
+    }
+}
+
+/// Step through RGB space to find a free key color.
+fn next_color(c: Color) -> Color {
+    let v = (u32::from(c[0]) << 16 | u32::from(c[1]) << 8 | u32::from(c[2])).wrapping_add(1)
+        & 0xff_ffff;
+    [(v >> 16) as u8, (v >> 8) as u8, v as u8]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn joint_keys_distinguish_tuples_and_split_back() {
+        let a: Color = [10, 10, 10];
+        let b: Color = [200, 0, 0];
+        let solid: Color = [255, 255, 255];
+        let open: Color = [0, 0, 0];
+        // Same visual color `a` carries two different collision values
+        let visual = Sample::new(3, 1, vec![a, a, b]);
+        let collision = Sample::new(3, 1, vec![solid, open, open]);
+
+        let layered = LayeredSample::new(vec![visual.clone(), collision.clone()]).unwrap();
+        let (joint, palette) = layered.joint();
+        assert_ne!(joint.pixels[0], joint.pixels[1]);
+
+        let split = palette.split(&joint.pixels);
+        assert_eq!(split[0], visual.pixels);
+        assert_eq!(split[1], collision.pixels);
+        assert_eq!(palette.layer(&joint.pixels, 1), collision.pixels);
+    }
+
+    #[test]
+    fn undecided_blends_are_not_split_as_keys() {
+        // Evenly weighted, the blend of all three keys is the middle one
+        let visual = Sample::new(3, 1, vec![[0; 3], [100; 3], [200; 3]]);
+        let collision = Sample::new(3, 1, vec![[1; 3], [2; 3], [3; 3]]);
+        let layered = LayeredSample::new(vec![visual, collision]).unwrap();
+        let (joint, palette) = layered.joint();
+        let config = crate::Config {
+            pattern_size: 1,
+            output_width: 4,
+            output_height: 4,
+            ..Default::default()
+        };
+        let wfc = Wfc::new(&joint, config);
+        let blend = wfc.render()[0];
+        assert_eq!(blend, [100; 3]);
+
+        assert_eq!(palette.split(&wfc.render())[1][0], [2; 3]);
+        let split = palette.split_output(&wfc);
+        assert!(split.iter().all(|layer| layer[0] == blend));
+    }
+
+    #[test]
+    fn mismatched_layer_sizes_are_rejected() {
+        let a = Sample::new(2, 1, vec![[0; 3]; 2]);
+        let b = Sample::new(1, 2, vec![[0; 3]; 2]);
+        assert!(LayeredSample::new(vec![a, b]).is_err());
+    }
+}
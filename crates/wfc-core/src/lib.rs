@@ -1,29 +1,88 @@
 //! Wave Function Collapse - overlapping model.
 
 pub(crate) mod backtrack;
+#[cfg(feature = "bevy")]
+pub mod bevy_plugin;
+mod bias;
 pub(crate) mod bitset;
 mod boundary;
+mod cache;
+mod chunked;
 mod config;
+mod connectivity;
 mod constraint;
+mod contradiction;
+mod decision;
+pub mod dual;
+mod edge;
 mod error;
+mod frequency;
+mod graph;
 mod grid;
+mod guide;
+mod health;
+mod heightmap;
+mod heuristic;
+mod history;
+pub mod iso;
+mod layers;
+mod memory;
+mod mirror;
+pub mod montage;
+mod observer;
 mod pattern;
+mod profile;
+mod progress;
+mod queue;
+mod recolor;
 mod retry;
+pub mod rng;
 pub(crate) mod rules;
 mod sample;
+pub mod smooth;
 pub(crate) mod solver;
 pub(crate) mod state;
+mod support;
+mod symmetry;
+pub mod voxel;
+mod weight;
 
+pub use bias::BiasField;
 pub use boundary::Boundary;
+pub use cache::default_cache_dir;
+pub use chunked::ChunkedWfc;
 pub use config::Config;
-pub use constraint::{CellConstraint, ConstraintContext};
+pub use connectivity::Connectivity;
+pub use constraint::{CellConstraint, ConstraintContext, GlobalConstraint};
+pub use contradiction::{Ban, ContradictionInfo};
+pub use decision::{Decision, DecisionLog};
+pub use edge::{EdgeConstraint, Edges};
 pub use error::{Error, RunOutcome, StepOutcome};
+pub use graph::Graph;
 pub use grid::Direction;
-pub use pattern::Pattern;
+pub use guide::Guide;
+pub use health::PropagatorHealth;
+pub use heightmap::Heightmap;
+pub use heuristic::Heuristic;
+pub use layers::{LayerPalette, LayeredSample};
+pub use memory::MemoryUsage;
+pub use mirror::OutputSymmetry;
+pub use observer::{Events, WfcEvent, WfcObserver};
+pub use pattern::{Pattern, PatternOrigin};
+pub use profile::RowProfile;
+pub use progress::{BuildPhase, BuildProgress};
+pub use recolor::{ColorMap, dominant_colors, load_palette, parse_hex, parse_palette};
+pub use retry::{RetryBudget, RetryReport, Solved, solve_until_success};
 pub use rules::Rules;
-pub use sample::{Sample, default_pipe_sample};
-pub use solver::Wfc;
+#[cfg(feature = "url")]
+pub use sample::MAX_DOWNLOAD_BYTES;
+#[cfg(feature = "image-io")]
+pub use sample::MAX_SAMPLE_SIDE;
+pub use sample::{Sample, SampleStats, default_pipe_sample};
+pub use solver::{Wfc, WfcState};
 pub use state::State;
+pub use symmetry::Symmetry;
+pub use weight::{PatternKey, WeightMultiplier};
 
 #[cfg(feature = "parallel")]
 pub use retry::parallel_solve;
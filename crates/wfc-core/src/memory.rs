@@ -0,0 +1,40 @@
+/// Estimated heap bytes held by a solver, by component. Counts allocated
+/// capacity, not just used length, and ignores allocator overhead.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// Possible-pattern bitset, one bit per (cell, pattern).
+    pub wave: usize,
+    /// AC-4 support counts per (cell, pattern, direction); usually the
+    /// largest part.
+    pub compat: usize,
+    /// Compatible-pattern lists and base support counts.
+    pub propagator: usize,
+    /// Patterns, weights and other per-pattern tables.
+    pub patterns: usize,
+    /// Per-cell counts, weight sums and the propagation queue.
+    pub sums: usize,
+    /// Backtracking and undo snapshots, each a copy of the wave, counts
+    /// and sums.
+    pub backtracking: usize,
+    /// Recorded animation frames. The solver records none; front ends that
+    /// keep frames add theirs here.
+    pub frames: usize,
+}
+
+impl MemoryUsage {
+    #[must_use]
+    pub fn total(&self) -> usize {
+        self.wave
+            + self.compat
+            + self.propagator
+            + self.patterns
+            + self.sums
+            + self.backtracking
+            + self.frames
+    }
+}
+
+/// Heap bytes of a `Vec`'s allocation.
+pub(crate) fn vec_bytes<T>(v: &Vec<T>) -> usize {
+    v.capacity() * std::mem::size_of::<T>()
+}
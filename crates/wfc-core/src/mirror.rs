@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+
+use crate::{Boundary, Pattern, Symmetry};
+
+/// Mirror symmetry forced on the output itself, e.g. for emblems and
+/// creatures. Unlike [`Symmetry`], which adds reflected copies of the
+/// sample's patterns, this ties each cell to its mirror image, so works
+/// best when the symmetry also includes the matching flips: patterns whose
+/// reflection was never extracted can't be placed off the mirror axis.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputSymmetry {
+    #[default]
+    None,
+    /// The right half mirrors the left.
+    MirrorX,
+    /// The bottom half mirrors the top.
+    MirrorY,
+    /// Both mirrors: each quadrant reflects its neighbors.
+    FourFold,
+}
+
+impl OutputSymmetry {
+    #[must_use]
+    pub fn mirrors_x(self) -> bool {
+        matches!(self, Self::MirrorX | Self::FourFold)
+    }
+
+    #[must_use]
+    pub fn mirrors_y(self) -> bool {
+        matches!(self, Self::MirrorY | Self::FourFold)
+    }
+}
+
+/// Cells and patterns paired by one mirror of [`OutputSymmetry`], or across
+/// an edge under `Boundary::Mirror`: banning a pattern at a cell bans its
+/// reflection at the mirrored cell. A cell at
+/// `x` covers pixels `x..x + n`, mirrored onto the cell at `width - n - x`;
+/// cells whose mirror falls outside a non-wrapping output are free, as are
+/// cells outside `Config::mask`.
+pub(crate) struct MirrorLink {
+    /// `cells[cell]`: the mirrored cell, or `NONE`.
+    pub(crate) cells: Vec<u32>,
+    /// `patterns[p]`: the reflection of `p`, or `NONE` if no pattern is.
+    pub(crate) patterns: Vec<u32>,
+}
+
+impl MirrorLink {
+    pub(crate) const NONE: u32 = u32::MAX;
+
+    /// Mirror left-right if `horizontal`, else top-bottom, over a
+    /// `width` x `height` output.
+    pub(crate) fn new(
+        patterns: &[Pattern],
+        horizontal: bool,
+        size: (usize, usize),
+        boundary: Boundary,
+        mask: &[bool],
+    ) -> Self {
+        let wraps = if horizontal {
+            boundary.wraps_x()
+        } else {
+            boundary.wraps_y()
+        };
+        Self::build(patterns, horizontal, size, mask, |i, len, side| {
+            if wraps {
+                Some((len as isize - side as isize - i as isize).rem_euclid(len as isize) as usize)
+            } else {
+                (i + side <= len).then(|| len - side - i)
+            }
+        })
+    }
+
+    /// Pairs across the right edge if `horizontal`, else the bottom, under
+    /// `Boundary::Mirror`: a cell at `x` reaching past the edge sees the
+    /// output reflected there, which is what the cell at `2 * width - n - x`
+    /// covers. Cells that fit inside the output are free.
+    pub(crate) fn edge(
+        patterns: &[Pattern],
+        horizontal: bool,
+        size: (usize, usize),
+        mask: &[bool],
+    ) -> Self {
+        Self::build(patterns, horizontal, size, mask, |i, len, side| {
+            if i + side <= len {
+                return None;
+            }
+            (2 * len).checked_sub(side + i).filter(|&m| m < len)
+        })
+    }
+
+    /// Link pairing the cell at `i` along the mirrored axis with the one at
+    /// `mirror(i, len, side)`, `len` the axis length and `side` the pattern
+    /// extent along it.
+    fn build(
+        patterns: &[Pattern],
+        horizontal: bool,
+        (width, height): (usize, usize),
+        mask: &[bool],
+        mirror: impl Fn(usize, usize, usize) -> Option<usize>,
+    ) -> Self {
+        let index: HashMap<&Pattern, usize> =
+            patterns.iter().enumerate().map(|(i, p)| (p, i)).collect();
+        let flip = if horizontal {
+            Symmetry::FLIP_X
+        } else {
+            Symmetry::FLIP_Y
+        };
+        let reflected = patterns
+            .iter()
+            .map(|p| {
+                index
+                    .get(&p.transform(flip))
+                    .map_or(Self::NONE, |&q| q as u32)
+            })
+            .collect();
+
+        let (len, side) = match patterns.first() {
+            Some(p) if horizontal => (width, p.width()),
+            Some(p) => (height, p.height()),
+            None => (0, 0),
+        };
+        let kept = |cell: usize| mask.is_empty() || mask[cell];
+        let mut cells = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let cell = y * width + x;
+                let other = if horizontal {
+                    mirror(x, len, side).map(|mx| y * width + mx)
+                } else {
+                    mirror(y, len, side).map(|my| my * width + x)
+                };
+                cells.push(match other {
+                    Some(other) if kept(cell) && kept(other) => other as u32,
+                    _ => Self::NONE,
+                });
+            }
+        }
+        Self {
+            cells,
+            patterns: reflected,
+        }
+    }
+}
@@ -0,0 +1,147 @@
+//! Grid of outputs composited into one image, for comparing seeds side by
+//! side. Labels use a built-in 3x5 pixel font, so no font files are needed.
+
+use crate::{Color, Sample};
+
+const BACKGROUND: Color = [24, 24, 24];
+/// Fill for cells whose output is missing (a contradicted seed).
+const MISSING: Color = [64, 40, 40];
+const TEXT: Color = [224, 224, 224];
+
+const GLYPH_WIDTH: usize = 3;
+const GLYPH_HEIGHT: usize = 5;
+
+/// One montage cell: the output, or `None` when there is nothing to show,
+/// and a caption drawn under it (empty for none).
+pub type Cell<'a> = (Option<&'a Sample>, &'a str);
+
+/// Lay `cells` out row-major, `columns` per row, each output enlarged
+/// `scale` times with nearest-neighbour sampling. Every output must have the
+/// same size. Captions only take room when at least one is non-empty; the
+/// font covers digits, `-`, `.`, `#` and `x`, and other characters are left
+/// blank.
+#[must_use]
+pub fn compose(cells: &[Cell], columns: usize, scale: usize) -> Sample {
+    let columns = columns.clamp(1, cells.len().max(1));
+    let rows = cells.len().div_ceil(columns);
+    let scale = scale.max(1);
+    let (tile_w, tile_h) = cells
+        .iter()
+        .find_map(|(sample, _)| sample.map(|s| (s.width * scale, s.height * scale)))
+        .unwrap_or((0, 0));
+
+    let gap = scale.max(2);
+    // Captions grow with the tile so they stay legible on large outputs
+    let text_scale = (tile_w / 64).max(1);
+    let caption_h = if cells.iter().any(|(_, label)| !label.is_empty()) {
+        GLYPH_HEIGHT * text_scale + gap
+    } else {
+        0
+    };
+    let cell_w = tile_w + gap;
+    let cell_h = tile_h + caption_h + gap;
+    let width = columns * cell_w + gap;
+    let height = rows * cell_h + gap;
+
+    let mut pixels = vec![BACKGROUND; width * height];
+    for (i, (sample, label)) in cells.iter().enumerate() {
+        let x0 = gap + (i % columns) * cell_w;
+        let y0 = gap + (i / columns) * cell_h;
+        for y in 0..tile_h {
+            let row = &mut pixels[(y0 + y) * width + x0..][..tile_w];
+            match sample {
+                Some(s) => {
+                    assert_eq!(
+                        (s.width * scale, s.height * scale),
+                        (tile_w, tile_h),
+                        "montage outputs must share one size"
+                    );
+                    let src = &s.pixels[(y / scale) * s.width..][..s.width];
+                    for (x, px) in row.iter_mut().enumerate() {
+                        *px = src[x / scale];
+                    }
+                }
+                None => row.fill(MISSING),
+            }
+        }
+        draw_text(
+            &mut pixels,
+            width,
+            (x0, y0 + tile_h + gap / 2),
+            label,
+            text_scale,
+        );
+    }
+    Sample::new(width, height, pixels)
+}
+
+/// Rows of a glyph, top to bottom, three bits each (MSB is the left column).
+fn glyph(c: char) -> Option<[u8; GLYPH_HEIGHT]> {
+    Some(match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b011, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '#' => [0b101, 0b111, 0b101, 0b111, 0b101],
+        'x' => [0b000, 0b101, 0b010, 0b101, 0b000],
+        _ => return None,
+    })
+}
+
+/// Draw `text` with its top-left corner at `origin`, clipped to the image.
+fn draw_text(pixels: &mut [Color], width: usize, origin: (usize, usize), text: &str, scale: usize) {
+    let height = pixels.len() / width;
+    let advance = (GLYPH_WIDTH + 1) * scale;
+    for (i, c) in text.chars().enumerate() {
+        let Some(rows) = glyph(c) else {
+            continue;
+        };
+        let gx = origin.0 + i * advance;
+        for (row, bits) in rows.iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if bits >> (GLYPH_WIDTH - 1 - col) & 1 == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let x = gx + col * scale + dx;
+                        let y = origin.1 + row * scale + dy;
+                        if x < width && y < height {
+                            pixels[y * width + x] = TEXT;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compose_places_cells_row_major() {
+        let red = Sample::new(2, 2, vec![[255, 0, 0]; 4]);
+        let blue = Sample::new(2, 2, vec![[0, 0, 255]; 4]);
+        let cells = [(Some(&red), ""), (Some(&blue), ""), (None, "")];
+        let montage = compose(&cells, 2, 3);
+
+        // Tiles are 6px with a 3px gap: two columns, two rows, no captions
+        assert_eq!((montage.width, montage.height), (21, 21));
+        assert_eq!(montage.get(3, 3), [255, 0, 0]);
+        assert_eq!(montage.get(8, 8), [255, 0, 0]);
+        assert_eq!(montage.get(12, 3), [0, 0, 255]);
+        assert_eq!(montage.get(3, 12), MISSING);
+        assert_eq!(montage.get(12, 12), BACKGROUND);
+        assert_eq!(montage.get(10, 3), BACKGROUND);
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::VecDeque;
+
+use crate::error::StepOutcome;
+use crate::solver::Wfc;
+
+/// Callbacks for following a run as it happens, registered with
+/// [`Wfc::add_observer`](crate::Wfc::add_observer). Cells are `(x, y)`.
+/// Events are delivered at the end of each `step()` or `micro_step()`, in
+/// the order they happened.
+pub trait WfcObserver: Send {
+    /// A step chose `pattern` for `cell`.
+    fn on_observe(&mut self, _cell: (usize, usize), _pattern: usize) {}
+
+    /// `pattern` was ruled out at `cell`, by an observation, propagation,
+    /// a backtrack, a pin or a constraint.
+    fn on_ban(&mut self, _cell: (usize, usize), _pattern: usize) {}
+
+    /// `cell` was left without patterns; backtracking may still recover.
+    fn on_contradiction(&mut self, _cell: (usize, usize)) {}
+
+    /// The wave was replaced by a backtrack, `undo()`, `restore()` or
+    /// `reset()`, so cells may have regained patterns: anything built from
+    /// earlier events is stale and should be re-read from the solver. Later
+    /// bans apply to the new wave.
+    fn on_restore(&mut self) {}
+
+    /// Every cell is decided.
+    fn on_done(&mut self) {}
+}
+
+/// One change made by a step; see [`Wfc::events`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WfcEvent {
+    /// A step chose `pattern` for the cell.
+    Collapsed { x: usize, y: usize, pattern: usize },
+    /// `pattern` was ruled out at the cell.
+    Banned { x: usize, y: usize, pattern: usize },
+    /// The cell was left without patterns; backtracking may still recover.
+    Contradiction { x: usize, y: usize },
+    /// The wave was replaced; see [`WfcObserver::on_restore`].
+    Restored,
+    /// Every cell is decided.
+    Done,
+}
+
+/// Iterator from [`Wfc::events`], stepping the solver as it is consumed.
+pub struct Events<'a> {
+    wfc: &'a mut Wfc,
+    pending: VecDeque<WfcEvent>,
+    finished: bool,
+}
+
+impl<'a> Events<'a> {
+    pub(crate) fn new(wfc: &'a mut Wfc) -> Self {
+        Self {
+            wfc,
+            pending: VecDeque::new(),
+            finished: false,
+        }
+    }
+}
+
+impl Iterator for Events<'_> {
+    type Item = WfcEvent;
+
+    fn next(&mut self) -> Option<WfcEvent> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(event);
+            }
+            if self.finished {
+                return None;
+            }
+            let (outcome, events) = self.wfc.step_events(false);
+            self.finished = outcome != StepOutcome::Progressed;
+            self.pending.extend(events);
+        }
+    }
+}
+
+impl Drop for Events<'_> {
+    /// Stop tracing bans unless observers still want them.
+    fn drop(&mut self) {
+        if !self.wfc.has_observers() {
+            self.wfc.state.ban_trace = None;
+        }
+    }
+}
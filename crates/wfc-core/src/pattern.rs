@@ -1,114 +1,259 @@
 use std::cmp::Ordering;
 use std::hash::{Hash, Hasher};
+use std::ops::{Deref, DerefMut};
 
 use crate::Color;
+use crate::symmetry::Symmetry;
 
 const MAX_INLINE: usize = 16;
 
-/// NxN pattern stored inline (no heap for N<=4).
+/// Where a pattern was first seen: the sample window at `(x, y)` with
+/// `transform` applied. Untransformed occurrences are preferred.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PatternOrigin {
+    /// Index into the samples given to `Wfc::from_samples` (0 for one).
+    pub sample: usize,
+    pub x: usize,
+    pub y: usize,
+    pub transform: Symmetry,
+}
+
+/// NxN (or NxM) pattern, stored inline for up to 16 pixels (4x4) and on
+/// the heap above that.
+///
+/// Pixels are colors by default. Only extraction uses palette indices
+/// (`Pattern<u16>`), which are smaller and faster to hash while counting
+/// windows; the distinct ones are turned back into colors before the
+/// propagator is built, so the rules, the solver and rendering all work
+/// on colors.
 #[derive(Clone, Debug)]
-pub struct Pattern {
-    size: usize,
-    len: usize,
-    pixels: [Color; MAX_INLINE],
+pub struct Pattern<P = Color> {
+    width: usize,
+    height: usize,
+    pixels: Pixels<P>,
 }
 
-impl PartialEq for Pattern {
+/// Row-major pixels of one pattern, exactly `width * height` long.
+#[derive(Clone, Debug)]
+enum Pixels<P> {
+    Inline([P; MAX_INLINE], usize),
+    Heap(Box<[P]>),
+}
+
+impl<P: Copy + Default> Pixels<P> {
+    /// `len` default pixels, to be filled in.
+    fn new(len: usize) -> Self {
+        if len <= MAX_INLINE {
+            Self::Inline([P::default(); MAX_INLINE], len)
+        } else {
+            Self::Heap(vec![P::default(); len].into_boxed_slice())
+        }
+    }
+}
+
+impl<P> Deref for Pixels<P> {
+    type Target = [P];
+
+    #[inline]
+    fn deref(&self) -> &[P] {
+        match self {
+            Self::Inline(buf, len) => &buf[..*len],
+            Self::Heap(buf) => buf,
+        }
+    }
+}
+
+impl<P> DerefMut for Pixels<P> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut [P] {
+        match self {
+            Self::Inline(buf, len) => &mut buf[..*len],
+            Self::Heap(buf) => buf,
+        }
+    }
+}
+
+impl<P: PartialEq> PartialEq for Pattern<P> {
     fn eq(&self, other: &Self) -> bool {
-        self.size == other.size && self.pixels[..self.len] == other.pixels[..other.len]
+        (self.width, self.height) == (other.width, other.height) && *self.pixels == *other.pixels
     }
 }
 
-impl Eq for Pattern {}
+impl<P: Eq> Eq for Pattern<P> {}
 
-impl Hash for Pattern {
+impl<P: Hash> Hash for Pattern<P> {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        self.size.hash(state);
-        self.pixels[..self.len].hash(state);
+        (self.width, self.height).hash(state);
+        self.pixels.hash(state);
     }
 }
 
-impl PartialOrd for Pattern {
+impl<P: Ord> PartialOrd for Pattern<P> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
     }
 }
 
-impl Ord for Pattern {
+impl<P: Ord> Ord for Pattern<P> {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.size
-            .cmp(&other.size)
-            .then_with(|| self.pixels[..self.len].cmp(&other.pixels[..other.len]))
+        (self.width, self.height)
+            .cmp(&(other.width, other.height))
+            .then_with(|| self.pixels.cmp(&other.pixels))
     }
 }
 
-impl Pattern {
-    pub fn new(size: usize, pixels: Vec<Color>) -> Self {
-        let len = size * size;
-        assert_eq!(pixels.len(), len, "pixels length must be size*size");
-        assert!(len <= MAX_INLINE, "pattern size > 4 not supported");
-        let mut buf = [[0u8; 3]; MAX_INLINE];
-        buf[..len].copy_from_slice(&pixels);
+impl<P: Copy + Default + PartialEq> Pattern<P> {
+    pub fn new(size: usize, pixels: Vec<P>) -> Self {
+        Self::new_rect(size, size, pixels)
+    }
+
+    /// `width` x `height` pattern, row-major.
+    pub fn new_rect(width: usize, height: usize, pixels: Vec<P>) -> Self {
+        assert_eq!(
+            pixels.len(),
+            width * height,
+            "pixels length must be width*height"
+        );
+        let mut buf = Pixels::new(pixels.len());
+        buf.copy_from_slice(&pixels);
+        Self::from_buf(width, height, buf)
+    }
+
+    fn from_buf(width: usize, height: usize, pixels: Pixels<P>) -> Self {
+        debug_assert_eq!(pixels.len(), width * height);
         Self {
-            size,
-            len,
-            pixels: buf,
+            width,
+            height,
+            pixels,
         }
     }
 
-    pub(crate) fn from_buf(size: usize, pixels: [Color; MAX_INLINE], len: usize) -> Self {
-        debug_assert_eq!(len, size * size);
-        Self { size, len, pixels }
+    /// The `width` x `height` window at `(x, y)` of a row-major buffer
+    /// `source_width` wide, wrapping at its edges.
+    pub(crate) fn window(
+        source: &[P],
+        source_width: usize,
+        (x, y): (usize, usize),
+        (width, height): (usize, usize),
+    ) -> Self {
+        let source_height = source.len() / source_width;
+        let mut buf = Pixels::new(width * height);
+        for dy in 0..height {
+            let row = ((y + dy) % source_height) * source_width;
+            for dx in 0..width {
+                buf[dy * width + dx] = source[row + (x + dx) % source_width];
+            }
+        }
+        Self::from_buf(width, height, buf)
+    }
+
+    /// Same shape with `f` applied to every pixel.
+    pub(crate) fn map<Q: Copy + Default + PartialEq>(&self, f: impl Fn(P) -> Q) -> Pattern<Q> {
+        let mut buf = Pixels::new(self.pixels.len());
+        for (to, &from) in buf.iter_mut().zip(self.pixels.iter()) {
+            *to = f(from);
+        }
+        Pattern::from_buf(self.width, self.height, buf)
     }
 
+    /// Side length; the width for NxM patterns.
     #[inline]
     pub fn size(&self) -> usize {
-        self.size
+        self.width
+    }
+
+    #[inline]
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    #[inline]
+    pub fn height(&self) -> usize {
+        self.height
     }
 
     #[inline]
-    pub fn get(&self, x: usize, y: usize) -> Color {
-        self.pixels[y * self.size + x]
+    pub fn get(&self, x: usize, y: usize) -> P {
+        self.pixels[y * self.width + x]
     }
 
-    /// Rotate 90 degrees clockwise.
+    /// Rotate 90 degrees clockwise; swaps width and height.
     pub fn rotate(&self) -> Self {
-        let n = self.size;
-        let mut buf = [[0u8; 3]; MAX_INLINE];
-        for y in 0..n {
-            for x in 0..n {
-                buf[x * n + (n - 1 - y)] = self.get(x, y);
+        let (w, h) = (self.width, self.height);
+        let mut buf = Pixels::new(self.pixels.len());
+        for y in 0..h {
+            for x in 0..w {
+                buf[x * h + (h - 1 - y)] = self.get(x, y);
             }
         }
-        Self::from_buf(n, buf, self.len)
+        Self::from_buf(h, w, buf)
     }
 
     /// Reflect horizontally.
     pub fn reflect(&self) -> Self {
-        let n = self.size;
-        let mut buf = [[0u8; 3]; MAX_INLINE];
-        for y in 0..n {
-            for x in 0..n {
-                buf[y * n + (n - 1 - x)] = self.get(x, y);
+        let w = self.width;
+        let mut buf = Pixels::new(self.pixels.len());
+        for y in 0..self.height {
+            for x in 0..w {
+                buf[y * w + (w - 1 - x)] = self.get(x, y);
             }
         }
-        Self::from_buf(n, buf, self.len)
+        Self::from_buf(w, self.height, buf)
     }
 
-    /// All unique symmetry variants (up to 8), sorted.
-    pub fn symmetries(&self) -> Vec<Self> {
-        let mut variants = Vec::with_capacity(8);
-        let mut current = self.clone();
-        for _ in 0..4 {
-            if !variants.contains(&current) {
-                variants.push(current.clone());
+    /// Unique variants paired with the first transform producing each,
+    /// untransformed first.
+    pub(crate) fn transformed_variants(&self, symmetry: Symmetry) -> Vec<(Symmetry, Self)> {
+        const TRANSFORMS: [Symmetry; 7] = [
+            Symmetry::ROTATE_90,
+            Symmetry::ROTATE_270,
+            Symmetry::ROTATE_180,
+            Symmetry::FLIP_Y,
+            Symmetry::ANTI_TRANSPOSE,
+            Symmetry::FLIP_X,
+            Symmetry::TRANSPOSE,
+        ];
+        let mut variants = vec![(Symmetry::NONE, self.clone())];
+        for transform in TRANSFORMS {
+            if symmetry.contains(transform) {
+                let variant = self.transform(transform);
+                if !variants.iter().any(|(_, v)| *v == variant) {
+                    variants.push((transform, variant));
+                }
             }
-            let reflected = current.reflect();
-            if !variants.contains(&reflected) {
-                variants.push(reflected);
-            }
-            current = current.rotate();
         }
+        variants
+    }
+
+    /// Apply one transform from [`Symmetry`]; `NONE` (or a combination)
+    /// returns the pattern unchanged.
+    pub fn transform(&self, transform: Symmetry) -> Self {
+        match transform {
+            Symmetry::ROTATE_90 => self.rotate(),
+            Symmetry::ROTATE_180 => self.rotate().rotate(),
+            Symmetry::ROTATE_270 => self.rotate().rotate().rotate(),
+            Symmetry::FLIP_X => self.reflect(),
+            Symmetry::FLIP_Y => self.reflect().rotate().rotate(),
+            Symmetry::TRANSPOSE => self.rotate().reflect(),
+            Symmetry::ANTI_TRANSPOSE => self.reflect().rotate(),
+            _ => self.clone(),
+        }
+    }
+}
+
+impl<P: Copy + Default + Ord> Pattern<P> {
+    /// All unique symmetry variants (up to 8), sorted.
+    pub fn symmetries(&self) -> Vec<Self> {
+        self.variants(Symmetry::ALL)
+    }
+
+    /// The pattern plus each transform in `symmetry`, deduplicated and sorted.
+    pub fn variants(&self, symmetry: Symmetry) -> Vec<Self> {
+        let mut variants: Vec<Self> = self
+            .transformed_variants(symmetry)
+            .into_iter()
+            .map(|(_, variant)| variant)
+            .collect();
         variants.sort();
         variants
     }
@@ -0,0 +1,67 @@
+use crate::Color;
+
+/// Vertical weight gradient for patterns whose top-left pixel is `color`,
+/// e.g. sky colors weighted up in the top third and down elsewhere.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RowProfile {
+    pub color: Color,
+    /// Weight multipliers at evenly spaced points from the top row to the
+    /// bottom row, linearly interpolated in between.
+    pub curve: Vec<f64>,
+}
+
+impl RowProfile {
+    pub fn new(color: Color, curve: Vec<f64>) -> Self {
+        Self { color, curve }
+    }
+
+    /// Curve from a 1-D mask (any width, one sample per row): each row's mean
+    /// luminance maps black to 0, mid-gray to 1 and white to 2.
+    #[cfg(feature = "image-io")]
+    pub fn from_mask_image(color: Color, path: &std::path::Path) -> Result<Self, crate::Error> {
+        let img = image::open(path)
+            .map_err(|e| crate::Error::ImageLoad(e.to_string()))?
+            .to_luma8();
+        let width = img.width().max(1) as f64;
+        let curve = img
+            .rows()
+            .map(|row| row.map(|p| p.0[0] as f64).sum::<f64>() / width / 127.5)
+            .collect();
+        Ok(Self::new(color, curve))
+    }
+
+    /// Multiplier at row `y` of an output `height` rows tall.
+    #[must_use]
+    pub fn factor(&self, y: usize, height: usize) -> f64 {
+        match self.curve.as_slice() {
+            [] => 1.0,
+            [only] => *only,
+            curve => {
+                let t = if height > 1 {
+                    y as f64 / (height - 1) as f64
+                } else {
+                    0.0
+                };
+                let pos = t * (curve.len() - 1) as f64;
+                let i = (pos.floor() as usize).min(curve.len() - 2);
+                let f = pos - i as f64;
+                curve[i] * (1.0 - f) + curve[i + 1] * f
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn factor_interpolates_between_control_points() {
+        let profile = RowProfile::new([0, 0, 0], vec![4.0, 1.0, 0.0]);
+        assert_eq!(profile.factor(0, 9), 4.0);
+        assert_eq!(profile.factor(2, 9), 2.5);
+        assert_eq!(profile.factor(4, 9), 1.0);
+        assert_eq!(profile.factor(8, 9), 0.0);
+        assert_eq!(RowProfile::new([0, 0, 0], vec![]).factor(3, 9), 1.0);
+    }
+}
@@ -0,0 +1,31 @@
+/// Stage of model construction reported by [`Wfc::with_progress`](crate::Wfc::with_progress).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildPhase {
+    /// Scanning the sample for patterns.
+    Extracting,
+    /// Matching pattern overlaps in every propagation direction.
+    Propagator,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BuildProgress {
+    pub phase: BuildPhase,
+    /// Completed fraction of `phase`, `0.0..=1.0`.
+    pub fraction: f32,
+}
+
+impl BuildProgress {
+    pub(crate) fn new(phase: BuildPhase, done: usize, total: usize) -> Self {
+        Self {
+            phase,
+            fraction: if total == 0 {
+                1.0
+            } else {
+                done as f32 / total as f32
+            },
+        }
+    }
+}
+
+/// Progress callback; returning `false` aborts the build.
+pub(crate) type ProgressFn<'a> = dyn FnMut(BuildProgress) -> bool + 'a;
@@ -0,0 +1,87 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+/// A cell's entropy key when it was queued; stale once the cell has been
+/// narrowed again, which the solver detects by recomputing the key.
+#[derive(Clone, Copy)]
+struct Entry {
+    key: f64,
+    cell: u32,
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    /// Reversed so the max-heap pops the lowest key, then the lowest cell,
+    /// matching a row-major scan.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .key
+            .total_cmp(&self.key)
+            .then_with(|| other.cell.cmp(&self.cell))
+    }
+}
+
+/// Min-entropy priority queue with lazy deletion: cells are pushed again
+/// whenever they narrow instead of being updated in place, and outdated
+/// entries are skipped on pop.
+#[derive(Clone, Default)]
+pub(crate) struct EntropyQueue {
+    heap: BinaryHeap<Entry>,
+}
+
+impl EntropyQueue {
+    pub(crate) fn push(&mut self, cell: usize, key: f64) {
+        self.heap.push(Entry {
+            key,
+            cell: cell as u32,
+        });
+    }
+
+    /// Lowest `(cell, key)`, possibly stale.
+    pub(crate) fn pop(&mut self) -> Option<(usize, f64)> {
+        self.heap.pop().map(|e| (e.cell as usize, e.key))
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.heap.clear();
+    }
+
+    pub(crate) fn heap_bytes(&self) -> usize {
+        self.heap.capacity() * std::mem::size_of::<Entry>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pops_lowest_key_then_lowest_cell() {
+        let mut queue = EntropyQueue::default();
+        queue.push(4, 1.5);
+        queue.push(9, 0.5);
+        queue.push(2, 0.5);
+        queue.push(7, 3.0);
+        assert_eq!(queue.pop(), Some((2, 0.5)));
+        assert_eq!(queue.pop(), Some((9, 0.5)));
+        assert_eq!(queue.pop(), Some((4, 1.5)));
+        assert_eq!(queue.len(), 1);
+    }
+}
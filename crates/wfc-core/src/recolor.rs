@@ -0,0 +1,265 @@
+//! Post-generation recoloring, so one solved structure can ship in several
+//! color schemes without solving again.
+
+use std::collections::HashMap;
+
+use crate::{Color, Error, Sample};
+
+/// Replacement color per sample color. Colors without an entry (e.g. the
+/// contradiction color) pass through unchanged.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ColorMap {
+    /// `(sample color, output color)`, most frequent sample color first.
+    pub entries: Vec<(Color, Color)>,
+}
+
+impl ColorMap {
+    /// Identity map over the sample's colors, most frequent first.
+    #[must_use]
+    pub fn from_sample(sample: &Sample) -> Self {
+        let mut counts: HashMap<Color, usize> = HashMap::new();
+        for &c in &sample.pixels {
+            *counts.entry(c).or_insert(0) += 1;
+        }
+        let mut colors: Vec<(Color, usize)> = counts.into_iter().collect();
+        // Ties broken by color so the order is stable across runs
+        colors.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        Self {
+            entries: colors.into_iter().map(|(c, _)| (c, c)).collect(),
+        }
+    }
+
+    #[must_use]
+    pub fn is_identity(&self) -> bool {
+        self.entries.iter().all(|(from, to)| from == to)
+    }
+
+    /// Map `from` to `to`, adding an entry if `from` has none.
+    pub fn set(&mut self, from: Color, to: Color) {
+        match self.entries.iter_mut().find(|(f, _)| *f == from) {
+            Some(entry) => entry.1 = to,
+            None => self.entries.push((from, to)),
+        }
+    }
+
+    /// Give the sample colors `palette`'s entries in order, cycling when the
+    /// palette is shorter. An empty palette resets to identity.
+    pub fn assign(&mut self, palette: &[Color]) {
+        for (i, (from, to)) in self.entries.iter_mut().enumerate() {
+            *to = if palette.is_empty() {
+                *from
+            } else {
+                palette[i % palette.len()]
+            };
+        }
+    }
+
+    /// Map every sample color to the closest `palette` entry.
+    pub fn match_nearest(&mut self, palette: &[Color]) {
+        for (from, to) in &mut self.entries {
+            *to = nearest_color(*from, palette);
+        }
+    }
+
+    #[must_use]
+    pub fn apply(&self, colors: &[Color]) -> Vec<Color> {
+        let lookup: HashMap<Color, Color> = self.entries.iter().copied().collect();
+        colors
+            .iter()
+            .map(|c| lookup.get(c).copied().unwrap_or(*c))
+            .collect()
+    }
+}
+
+/// Squared "redmean" distance: cheap, and closer to perceived difference
+/// than plain RGB distance.
+fn color_distance(a: Color, b: Color) -> u32 {
+    let mean_r = (a[0] as i32 + b[0] as i32) / 2;
+    let [dr, dg, db] = [0, 1, 2].map(|i| a[i] as i32 - b[i] as i32);
+    ((((512 + mean_r) * dr * dr) >> 8) + 4 * dg * dg + (((767 - mean_r) * db * db) >> 8)) as u32
+}
+
+/// Closest `palette` entry to `color`, or `color` itself if it's empty.
+pub(crate) fn nearest_color(color: Color, palette: &[Color]) -> Color {
+    palette
+        .iter()
+        .copied()
+        .min_by_key(|&c| color_distance(color, c))
+        .unwrap_or(color)
+}
+
+/// Up to `count` representative colors of `pixels` by median cut, most
+/// common first. Large images are subsampled.
+#[must_use]
+pub fn dominant_colors(pixels: &[Color], count: usize) -> Vec<Color> {
+    const MAX_SAMPLES: usize = 1 << 16;
+    let stride = pixels.len().div_ceil(MAX_SAMPLES).max(1);
+    let mut boxes: Vec<Vec<Color>> = vec![pixels.iter().step_by(stride).copied().collect()];
+    boxes.retain(|b| !b.is_empty());
+
+    let range = |b: &[Color], ch: usize| {
+        let (lo, hi) = b
+            .iter()
+            .fold((u8::MAX, 0), |(lo, hi), c| (lo.min(c[ch]), hi.max(c[ch])));
+        hi.saturating_sub(lo)
+    };
+    while boxes.len() < count {
+        // Split the box with the widest channel spread at its median
+        let widest = (0..boxes.len())
+            .flat_map(|i| (0..3).map(move |ch| (i, ch)))
+            .map(|(i, ch)| (range(&boxes[i], ch), i, ch))
+            .max();
+        let Some((spread, i, ch)) = widest else {
+            break;
+        };
+        if spread == 0 {
+            break;
+        }
+        let mut b = boxes.swap_remove(i);
+        b.sort_unstable_by_key(|c| c[ch]);
+        // Keep equal values together so both halves are non-empty
+        let mid = b[b.len() / 2][ch];
+        let split = match b.partition_point(|c| c[ch] < mid) {
+            0 => b.partition_point(|c| c[ch] <= mid),
+            at => at,
+        };
+        let upper = b.split_off(split);
+        boxes.push(b);
+        boxes.push(upper);
+    }
+
+    boxes.sort_by_key(|b| std::cmp::Reverse(b.len()));
+    boxes
+        .iter()
+        .map(|b| {
+            let sum = b.iter().fold([0usize; 3], |mut sum, c| {
+                for ch in 0..3 {
+                    sum[ch] += c[ch] as usize;
+                }
+                sum
+            });
+            sum.map(|s| (s / b.len()) as u8)
+        })
+        .collect()
+}
+
+/// Parse a palette: GIMP `.gpl` rows (`R G B [name]`) or one hex color per
+/// line (`.hex` files, with or without `#`). Blank lines, `#` comments and
+/// GIMP header lines are skipped; a line starting with `#` is a color only
+/// if its first word is exactly `#RRGGBB`.
+pub fn parse_palette(text: &str) -> Result<Vec<Color>, Error> {
+    let mut colors = Vec::new();
+    for line in text.lines().map(str::trim) {
+        let is_header = ["GIMP Palette", "Name:", "Columns:"]
+            .iter()
+            .any(|h| line.starts_with(h));
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let is_comment = line.starts_with('#') && parse_hex(fields[0]).is_none();
+        if line.is_empty() || is_header || is_comment {
+            continue;
+        }
+        let rgb: Option<Vec<u8>> = fields.iter().take(3).map(|f| f.parse().ok()).collect();
+        let color = match rgb {
+            Some(rgb) if rgb.len() == 3 => [rgb[0], rgb[1], rgb[2]],
+            _ => parse_hex(fields[0])
+                .ok_or_else(|| Error::InvalidPalette(format!("bad palette line '{}'", line)))?,
+        };
+        colors.push(color);
+    }
+    if colors.is_empty() {
+        return Err(Error::InvalidPalette("no colors found".to_string()));
+    }
+    Ok(colors)
+}
+
+/// `RRGGBB` or `#RRGGBB`.
+#[must_use]
+pub fn parse_hex(hex: &str) -> Option<Color> {
+    let hex = hex.strip_prefix('#').unwrap_or(hex);
+    if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let rgb = u32::from_str_radix(hex, 16).ok()?;
+    Some([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8])
+}
+
+/// Read a palette file, see [`parse_palette`].
+pub fn load_palette(path: &std::path::Path) -> Result<Vec<Color>, Error> {
+    let text = std::fs::read_to_string(path).map_err(|e| Error::InvalidPalette(e.to_string()))?;
+    parse_palette(&text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_gimp_and_hex_palettes() {
+        let gpl = "GIMP Palette\nName: test\nColumns: 2\n# comment\n255 0 0 Red\n0 128 255\n";
+        assert_eq!(
+            parse_palette(gpl).unwrap(),
+            vec![[255, 0, 0], [0, 128, 255]]
+        );
+        let hex = "ff0000\n#00ff80\n\n";
+        assert_eq!(
+            parse_palette(hex).unwrap(),
+            vec![[255, 0, 0], [0, 255, 128]]
+        );
+        // Comments that happen to be seven characters stay comments
+        let commented = "#colors\n#abcdef sky\n#cafe12x\n# 00ff80\n123456\n";
+        assert_eq!(
+            parse_palette(commented).unwrap(),
+            vec![[0xab, 0xcd, 0xef], [0x12, 0x34, 0x56]]
+        );
+        assert_eq!(parse_hex("#+12345"), None);
+        assert!(parse_palette("GIMP Palette\n").is_err());
+        assert!(parse_palette("not a color").is_err());
+    }
+
+    #[test]
+    fn dominant_colors_find_clusters_and_match_nearest() {
+        let mut pixels = vec![[250, 10, 10]; 60];
+        pixels.extend(vec![[0, 0, 200]; 40]);
+        let palette = dominant_colors(&pixels, 2);
+        // Red is the bigger cluster
+        assert_eq!(palette, vec![[250, 10, 10], [0, 0, 200]]);
+        assert_eq!(dominant_colors(&[[1, 2, 3]; 5], 4), vec![[1, 2, 3]]);
+
+        let sample = Sample::new(2, 1, vec![[255, 0, 0], [0, 0, 128]]);
+        let mut map = ColorMap::from_sample(&sample);
+        map.match_nearest(&palette);
+        assert_eq!(map.apply(&[[255, 0, 0]]), vec![palette[0]]);
+        assert_eq!(map.apply(&[[0, 0, 128]]), vec![palette[1]]);
+    }
+
+    #[test]
+    fn quantize_merges_near_identical_shades() {
+        let pixels = (0..16)
+            .map(|i| match i % 2 {
+                0 => [200 + (i % 3) as u8, 10, 10],
+                _ => [10, 10, 100 + (i % 5) as u8],
+            })
+            .collect();
+        let sample = Sample::new(4, 4, pixels);
+        let quantized = sample.quantize(2);
+        assert_eq!(ColorMap::from_sample(&quantized).entries.len(), 2);
+        assert!(quantized.pixels[0][0] > 150 && quantized.pixels[1][2] > 90);
+        assert_eq!(quantized.quantize(8).pixels, quantized.pixels);
+    }
+
+    #[test]
+    fn assign_maps_most_frequent_color_first() {
+        let (a, b) = ([1, 1, 1], [2, 2, 2]);
+        let sample = Sample::new(3, 1, vec![a, b, b]);
+        let mut map = ColorMap::from_sample(&sample);
+        assert!(map.is_identity());
+        map.assign(&[[9, 9, 9]]);
+        assert_eq!(map.entries, vec![(b, [9, 9, 9]), (a, [9, 9, 9])]);
+        map.set(a, [5, 5, 5]);
+        let purple = [128, 0, 128];
+        assert_eq!(
+            map.apply(&[a, b, purple]),
+            vec![[5, 5, 5], [9, 9, 9], purple]
+        );
+    }
+}
@@ -1,3 +1,131 @@
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::error::RunOutcome;
+use crate::solver::Wfc;
+use crate::{Color, Sample};
+
+/// Output of a successful [`solve_until_success`] run.
+#[derive(Debug, Clone)]
+pub struct Solved {
+    pub output: Vec<Color>,
+    /// Seed of the winning attempt; set it as `Config::seed` to reproduce.
+    pub seed: u64,
+    /// Attempts made, including the successful one.
+    pub attempts: usize,
+    /// Cells collapsed by the winning attempt.
+    pub steps: usize,
+    /// Propagation time of the winning attempt.
+    pub propagation_time: Duration,
+}
+
+/// Attempts and wall time allowed for retrying contradicted runs, each
+/// with the seed after the previous one. Counts the attempt in progress
+/// from creation; see [`Wfc::run_with_retries`].
+#[derive(Debug, Clone)]
+pub struct RetryBudget {
+    pub max_attempts: usize,
+    /// Checked between attempts, so the last one may run past it.
+    pub max_duration: Duration,
+    attempts: usize,
+    started: Instant,
+}
+
+impl RetryBudget {
+    #[must_use]
+    pub fn new(max_attempts: usize, max_duration: Duration) -> Self {
+        Self {
+            max_attempts,
+            max_duration,
+            attempts: 1,
+            started: Instant::now(),
+        }
+    }
+
+    /// Attempts made so far, including the current one.
+    #[must_use]
+    pub fn attempts(&self) -> usize {
+        self.attempts
+    }
+
+    #[must_use]
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// No attempts or time left for another try.
+    #[must_use]
+    pub fn is_spent(&self) -> bool {
+        self.attempts >= self.max_attempts || self.elapsed() >= self.max_duration
+    }
+
+    /// Start counting again from one attempt, keeping the limits.
+    pub fn restart(&mut self) {
+        self.attempts = 1;
+        self.started = Instant::now();
+    }
+
+    /// Start the time limit over, keeping the attempt count, e.g. when a
+    /// paused run resumes.
+    pub fn restart_clock(&mut self) {
+        self.started = Instant::now();
+    }
+
+    /// Reseed `wfc` with its next seed for another attempt, unless the
+    /// budget is spent.
+    pub fn retry(&mut self, wfc: &mut Wfc) -> bool {
+        if self.is_spent() {
+            return false;
+        }
+        self.attempts += 1;
+        wfc.reseed(wfc.seed().wrapping_add(1));
+        true
+    }
+}
+
+/// How [`Wfc::run_with_retries`] went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryReport {
+    /// `Complete` if an attempt succeeded, else the budget ran out.
+    pub outcome: RunOutcome,
+    /// Attempts made, including the last one.
+    pub attempts: usize,
+    /// Wall time across all attempts.
+    pub elapsed: Duration,
+}
+
+/// Retry with fresh seeds until a run completes, up to `max_attempts`;
+/// `None` without building anything when that is 0.
+///
+/// Seeds count up from `config.seed` (or a random base), so the winning
+/// seed alone reproduces the output.
+pub fn solve_until_success(
+    sample: &Sample,
+    config: &Config,
+    max_attempts: usize,
+) -> Option<Solved> {
+    if max_attempts == 0 {
+        return None;
+    }
+    let base_seed = config.seed.unwrap_or_else(rand::random);
+    let mut wfc = Wfc::new(
+        sample,
+        Config {
+            seed: Some(base_seed),
+            ..config.clone()
+        },
+    );
+
+    let report = wfc.run_with_retries(max_attempts, Duration::MAX);
+    (report.outcome == RunOutcome::Complete).then(|| Solved {
+        output: wfc.render(),
+        seed: wfc.seed(),
+        attempts: report.attempts,
+        steps: wfc.steps(),
+        propagation_time: wfc.propagation_time(),
+    })
+}
+
 /// Run `attempts` solvers in parallel with varied seeds, return first success.
 #[cfg(feature = "parallel")]
 pub fn parallel_solve(
@@ -7,9 +135,6 @@ pub fn parallel_solve(
 ) -> Option<Vec<crate::Color>> {
     use rayon::prelude::*;
 
-    use crate::RunOutcome;
-    use crate::solver::Wfc;
-
     let base_seed = config.seed.unwrap_or(0);
 
     (0..attempts).into_par_iter().find_map_any(|i| {
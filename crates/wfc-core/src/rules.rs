@@ -1,366 +1,1261 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::sync::{Arc, OnceLock};
 
 use crate::config::Config;
+use crate::edge::EdgeConstraint;
+use crate::frequency::FrequencyTarget;
+use crate::graph::Graph;
 use crate::grid::{Direction, Grid};
-use crate::{Color, Pattern, Sample};
+use crate::memory::vec_bytes;
+use crate::mirror::MirrorLink;
+use crate::progress::{BuildPhase, BuildProgress, ProgressFn};
+use crate::sample::unused_color;
+use crate::support::{Adjacency, prune_unplaceable};
+use crate::{Boundary, Color, Error, Pattern, PatternOrigin, Sample, Symmetry};
 
 /// Contiguous storage of compatible pattern indices per (pattern, direction).
+#[derive(Clone)]
 pub(crate) struct FlatPropagator {
-    data: Vec<u16>,
-    /// `offsets[pattern * 4 + dir]` = (start, end) into data
-    offsets: Vec<(u32, u32)>,
+    pub(crate) data: Vec<u16>,
+    pub(crate) num_dirs: usize,
+    /// `offsets[pattern * num_dirs + dir]` = (start, end) into data
+    pub(crate) offsets: Vec<(u32, u32)>,
 }
 
 impl FlatPropagator {
     #[inline]
     pub(crate) fn compatible(&self, pattern: usize, dir: usize) -> &[u16] {
-        let idx = pattern * 4 + dir;
+        let idx = pattern * self.num_dirs + dir;
         let (start, end) = self.offsets[idx];
         &self.data[start as usize..end as usize]
     }
 }
 
+/// Compatibility worked out from overlap strips on first use, then kept.
+/// For pattern sets whose full P^2 propagator is slow to build or too large
+/// to hold; lists are shared by every pattern with the same strip.
+#[derive(Clone)]
+pub(crate) struct LazyPropagator {
+    agreement: Agreement,
+    num_dirs: usize,
+    /// Both sides of each opposite-direction pair, as in `build_propagator`.
+    sides: Vec<(StripSet, StripSet)>,
+    /// `route[dir]`: (pair, whether `dir` is the pair's reverse)
+    route: Vec<(usize, bool)>,
+    /// `memo[pair][reversed][strip]`
+    memo: Vec<[Memo; 2]>,
+    /// `counts[pattern * num_dirs + dir]`: list lengths, needed up front for
+    /// the support counts.
+    counts: Vec<u16>,
+}
+
+/// One list per distinct strip, filled on first use.
+type Memo = Vec<OnceLock<Box<[u16]>>>;
+
+impl LazyPropagator {
+    fn compatible(&self, pattern: usize, dir: usize) -> &[u16] {
+        let (pair, reversed) = self.route[dir];
+        let (sources, targets) = &self.sides[pair];
+        let (from, to) = if reversed {
+            (targets, sources)
+        } else {
+            (sources, targets)
+        };
+        let s = from.index[pattern] as usize;
+        self.memo[pair][reversed as usize][s]
+            .get_or_init(|| matches(from.strip(s), to, self.agreement).into())
+    }
+
+    fn heap_bytes(&self) -> usize {
+        let memo: usize = self
+            .memo
+            .iter()
+            .flatten()
+            .map(|lists| {
+                vec_bytes(lists)
+                    + lists
+                        .iter()
+                        .filter_map(OnceLock::get)
+                        .map(|list| std::mem::size_of_val(&**list))
+                        .sum::<usize>()
+            })
+            .sum();
+        self.sides
+            .iter()
+            .map(|(sources, targets)| sources.heap_bytes() + targets.heap_bytes())
+            .sum::<usize>()
+            + vec_bytes(&self.sides)
+            + vec_bytes(&self.route)
+            + vec_bytes(&self.memo)
+            + memo
+            + vec_bytes(&self.counts)
+    }
+}
+
+/// Compatible pattern lists per (pattern, direction): all precomputed, or
+/// filled in as propagation asks for them.
+#[derive(Clone)]
+pub(crate) enum Propagator {
+    Flat(FlatPropagator),
+    Lazy(LazyPropagator),
+}
+
+impl Propagator {
+    #[inline]
+    pub(crate) fn compatible(&self, pattern: usize, dir: usize) -> &[u16] {
+        match self {
+            Self::Flat(flat) => flat.compatible(pattern, dir),
+            Self::Lazy(lazy) => lazy.compatible(pattern, dir),
+        }
+    }
+
+    /// Length of [`compatible`](Self::compatible), without computing a lazy list.
+    pub(crate) fn count(&self, pattern: usize, dir: usize) -> usize {
+        match self {
+            Self::Flat(flat) => flat.compatible(pattern, dir).len(),
+            Self::Lazy(lazy) => lazy.counts[pattern * lazy.num_dirs + dir] as usize,
+        }
+    }
+
+    /// Every list up front, computing what a lazy propagator hasn't yet.
+    pub(crate) fn to_flat(&self, num_patterns: usize) -> FlatPropagator {
+        let lazy = match self {
+            Self::Flat(flat) => return flat.clone(),
+            Self::Lazy(lazy) => lazy,
+        };
+        let mut data = Vec::new();
+        let mut offsets = Vec::with_capacity(num_patterns * lazy.num_dirs);
+        for p in 0..num_patterns {
+            for dir in 0..lazy.num_dirs {
+                let start = data.len() as u32;
+                data.extend_from_slice(lazy.compatible(p, dir));
+                offsets.push((start, data.len() as u32));
+            }
+        }
+        FlatPropagator {
+            data,
+            num_dirs: lazy.num_dirs,
+            offsets,
+        }
+    }
+
+    pub(crate) fn heap_bytes(&self) -> usize {
+        match self {
+            Self::Flat(flat) => vec_bytes(&flat.data) + vec_bytes(&flat.offsets),
+            Self::Lazy(lazy) => lazy.heap_bytes(),
+        }
+    }
+}
+
 pub(crate) const TOP: usize = 0;
 pub(crate) const BOTTOM: usize = 1;
 pub(crate) const LEFT: usize = 2;
 pub(crate) const RIGHT: usize = 3;
 
+/// Sample-derived half of the rules: patterns and their adjacency. Depends
+/// only on the sample and extraction settings, so it can be cached or reused
+/// across output size and boundary changes. Patterns and propagator are
+/// shared, so clones for other solvers are cheap.
+#[derive(Clone)]
+pub(crate) struct Model {
+    pub(crate) patterns: Arc<[Pattern]>,
+    pub(crate) weights: Vec<f64>,
+    pub(crate) edge_mask: Vec<[bool; 4]>,
+    pub(crate) origins: Vec<PatternOrigin>,
+    /// Dimensions of the sample `origins` point into.
+    pub(crate) source_size: (usize, usize),
+    /// Key color of transparent sample pixels, see `Sample::transparent`.
+    pub(crate) transparent: Option<Color>,
+    pub(crate) dir_offsets: Vec<(i32, i32)>,
+    pub(crate) propagator: Arc<Propagator>,
+}
+
+impl Model {
+    pub(crate) fn from_sample(sample: &Sample, config: &Config) -> Self {
+        Self::build(std::slice::from_ref(sample), config, &mut |_| true)
+            .expect("model builds without cancelling")
+    }
+
+    /// Extract from every sample and build the propagator, reporting
+    /// progress. Fails with [`Error::Cancelled`] if `progress` asked to
+    /// stop, or with [`Error::InvalidConfig`] if there are more patterns
+    /// than `Config::MAX_PATTERNS` or none reach `Config::min_pattern_count`.
+    /// `source_size` is the first sample's.
+    pub(crate) fn build(
+        samples: &[Sample],
+        config: &Config,
+        progress: &mut ProgressFn,
+    ) -> Result<Self, Error> {
+        let dir_offsets = Rules::neighbor_offsets(config);
+        let transparent = Rules::shared_transparent(samples);
+        let extracted = Rules::extract_patterns(samples, config, transparent, progress)
+            .ok_or(Error::Cancelled)?;
+        if extracted.patterns.is_empty() {
+            return Err(Error::InvalidConfig(format!(
+                "no pattern occurs at least {} times; lower the minimum pattern count",
+                config.min_pattern_count
+            )));
+        }
+        if extracted.patterns.len() > Config::MAX_PATTERNS {
+            return Err(Error::InvalidConfig(format!(
+                "{} patterns, more than the {} supported; try a smaller pattern size, \
+                 less symmetry or a smaller sample",
+                extracted.patterns.len(),
+                Config::MAX_PATTERNS
+            )));
+        }
+        let propagator = Rules::build_propagator(
+            &extracted.patterns,
+            config.pattern_dims(),
+            &dir_offsets,
+            Agreement {
+                tolerance: config.color_tolerance,
+                wildcard: transparent,
+            },
+            config.lazy_propagator,
+            progress,
+        )
+        .ok_or(Error::Cancelled)?;
+        Ok(Self {
+            patterns: extracted.patterns.into(),
+            weights: extracted.weights,
+            edge_mask: extracted.edge_mask,
+            origins: extracted.origins,
+            source_size: samples.first().map_or((0, 0), |s| (s.width, s.height)),
+            transparent,
+            dir_offsets,
+            propagator: Arc::new(propagator),
+        })
+    }
+
+    /// True if `config` would extract the same model as `other`.
+    pub(crate) fn same_extraction(config: &Config, other: &Config) -> bool {
+        config.pattern_dims() == other.pattern_dims()
+            && config.input_boundary == other.input_boundary
+            && config.symmetry == other.symmetry
+            && config.variant_weight == other.variant_weight
+            && config.edges.uses_sample_edge() == other.edges.uses_sample_edge()
+            && config.diagonal == other.diagonal
+            && config.strict_overlap == other.strict_overlap
+            && config.color_tolerance == other.color_tolerance
+            && config.min_pattern_count == other.min_pattern_count
+            && config.lazy_propagator == other.lazy_propagator
+            && config.importance == other.importance
+            && config.sample_weights == other.sample_weights
+    }
+}
+
+/// Weight tables for cells whose weights depend on where they are.
+pub(crate) struct CellWeights {
+    /// Table of each output cell.
+    pub(crate) class: Vec<u32>,
+    /// `table[class * num_patterns + pattern]`.
+    pub(crate) table: Vec<(f64, f64)>,
+}
+
 /// Immutable rules derived from the sample.
 pub struct Rules {
     pub(crate) config: Config,
     pub(crate) grid: Grid,
-    pub(crate) patterns: Vec<Pattern>,
+    /// `(dx, dy)` per propagation direction; the first 4 (or 8) follow `Direction`.
+    pub(crate) dir_offsets: Vec<(i32, i32)>,
+    /// `opposite[dir]`: direction index pointing back the other way.
+    pub(crate) opposite: Vec<usize>,
+    pub(crate) patterns: Arc<[Pattern]>,
+    /// Extracted weight per pattern, before `Config::weight_multipliers`.
+    pub(crate) base_weights: Vec<f64>,
     /// `(weight, log_weight)` per pattern.
     pub(crate) weight_table: Vec<(f64, f64)>,
-    pub(crate) propagator: FlatPropagator,
-    pub(crate) starting_entropy: f64,
-    /// Base compatibility counts per (pattern, direction).
+    /// Weights with `Config::row_profiles` and `Config::guide` applied;
+    /// `None` when neither is set.
+    pub(crate) cell_weights: Option<CellWeights>,
+    pub(crate) propagator: Arc<Propagator>,
+    /// Entropy of an untouched cell, per [`CellWeights`] class (one entry
+    /// without them); see [`starting_entropy`](Self::starting_entropy).
+    pub(crate) starting_entropy: Vec<f64>,
+    /// Base compatibility counts, `base_compat[pattern * num_dirs + dir]`.
     pub(crate) base_compat: Vec<u16>,
     /// `edge_mask[pattern]`: sample edges where this pattern appeared.
     pub(crate) edge_mask: Vec<[bool; 4]>,
+    pub(crate) origins: Vec<PatternOrigin>,
+    pub(crate) source_size: (usize, usize),
+    pub(crate) transparent: Option<Color>,
     /// Patterns with at least one neighbor in every direction.
     pub(crate) viable: Vec<bool>,
     /// Top-left color per pattern (render cache).
     pub(crate) colors: Vec<Color>,
+    /// One link per mirror of `Config::output_symmetry`, plus one per edge
+    /// axis under `Boundary::Mirror`.
+    pub(crate) mirrors: Vec<MirrorLink>,
+    /// Color limits under `Config::frequency_tolerance`.
+    pub(crate) frequency: Option<FrequencyTarget>,
 }
 
 impl Rules {
     pub fn from_sample(sample: &Sample, config: Config) -> Self {
-        let grid = Grid::new(config.output_width, config.output_height, config.boundary);
-        let extracted = Self::extract_patterns(sample, &config);
-        let propagator = Self::build_propagator(&extracted.patterns, &config);
+        let model = Model::from_sample(sample, &config);
+        Self::from_model(model, config)
+    }
 
-        let patterns = extracted.patterns;
-        let edge_mask = extracted.edge_mask;
+    pub(crate) fn from_model(model: Model, mut config: Config) -> Self {
+        let Model {
+            patterns,
+            weights,
+            edge_mask,
+            origins,
+            source_size,
+            transparent,
+            dir_offsets,
+            propagator,
+        } = model;
 
-        let weight_table: Vec<(f64, f64)> =
-            extracted.weights.iter().map(|&w| (w, w.ln())).collect();
-        let total_weight: f64 = weight_table.iter().map(|(w, _)| w).sum();
-        let sum_wlog: f64 = weight_table.iter().map(|(w, lw)| w * lw).sum();
-        let starting_entropy = total_weight.ln() - sum_wlog / total_weight;
+        let opposite: Vec<usize> = dir_offsets
+            .iter()
+            .map(|&(dx, dy)| {
+                dir_offsets
+                    .iter()
+                    .position(|&o| o == (-dx, -dy))
+                    .expect("offsets are symmetric")
+            })
+            .collect();
+        let num_dirs = dir_offsets.len();
 
+        // A graph's output is one row of its nodes
+        if let Some(nodes) = config.graph.as_ref().map(Graph::len) {
+            (config.output_width, config.output_height) = (nodes, 1);
+        }
+        let mut grid = match &config.graph {
+            Some(graph) => Grid::from_graph(graph, num_dirs),
+            None => Grid::new(
+                config.output_width,
+                config.output_height,
+                config.boundary,
+                &dir_offsets,
+            ),
+        };
+        if !config.mask.is_empty() {
+            grid.mask_out(&config.mask);
+        }
+
+        // base_compat[t * num_dirs + d]: patterns allowing t from direction d,
+        // which by symmetry is the length of t's own list in d
         let num_patterns = patterns.len();
-        let viable = Self::compute_viable(&propagator, num_patterns);
+        let mut base_compat: Vec<u16> = (0..num_patterns * num_dirs)
+            .map(|i| propagator.count(i / num_dirs, i % num_dirs) as u16)
+            .collect();
+        let viable = prune_unplaceable(
+            &mut base_compat,
+            &opposite,
+            |p, dir| propagator.compatible(p, dir),
+            (config.banned_patterns.iter().copied())
+                .filter(|&p| p < num_patterns)
+                .collect(),
+        );
+        // Dead patterns keep their counts of live supporters: they are
+        // banned up front but still lose support as their neighbors do
 
-        // Precompute base_compat[t * 4 + d] considering only viable patterns
-        let mut base_compat = vec![0u16; num_patterns * 4];
-        for p in 0..num_patterns {
-            if !viable[p] {
-                continue;
-            }
-            for dir in Direction::ALL {
-                let opp = dir.opposite() as usize;
-                for &t in propagator.compatible(p, dir as usize) {
-                    if viable[t as usize] {
-                        base_compat[t as usize * 4 + opp] += 1;
-                    }
-                }
+        let colors: Vec<Color> = patterns.iter().map(|p| p.get(0, 0)).collect();
+        let size = (config.output_width, config.output_height);
+        let mut mirrors: Vec<MirrorLink> = [
+            (config.output_symmetry.mirrors_x(), true),
+            (config.output_symmetry.mirrors_y(), false),
+        ]
+        .into_iter()
+        .filter(|&(on, _)| on)
+        .map(|(_, horizontal)| {
+            MirrorLink::new(&patterns, horizontal, size, config.boundary, &config.mask)
+        })
+        .collect();
+        if config.boundary == Boundary::Mirror {
+            for horizontal in [true, false] {
+                mirrors.push(MirrorLink::edge(&patterns, horizontal, size, &config.mask));
             }
         }
 
-        let colors: Vec<Color> = patterns.iter().map(|p| p.get(0, 0)).collect();
-
-        Self {
+        let mut rules = Self {
             config,
             grid,
+            dir_offsets,
+            opposite,
             patterns,
-            weight_table,
+            base_weights: weights,
+            weight_table: Vec::new(),
+            cell_weights: None,
             propagator,
-            starting_entropy,
+            starting_entropy: Vec::new(),
             base_compat,
             edge_mask,
+            origins,
+            source_size,
+            transparent,
             viable,
             colors,
+            mirrors,
+            frequency: None,
+        };
+        rules.build_weights();
+        rules
+    }
+
+    /// Fill the weight tables and starting entropy from `base_weights`,
+    /// weight multipliers, row profiles and the guide, and the color limits
+    /// for `Config::frequency_tolerance` from those weights.
+    fn build_weights(&mut self) {
+        let config = &self.config;
+        self.weight_table = self
+            .base_weights
+            .iter()
+            .enumerate()
+            .map(|(p, &w)| {
+                let factor: f64 = config
+                    .weight_multipliers
+                    .iter()
+                    .filter(|m| m.applies_to(p, self.colors[p]))
+                    .map(|m| m.factor)
+                    .product();
+                let w = w * factor;
+                (w, w.ln())
+            })
+            .collect();
+        self.cell_weights = self.build_cell_weights();
+        let entropy = |weights: &[(f64, f64)]| {
+            let total_weight: f64 = weights.iter().map(|(w, _)| w).sum();
+            let sum_wlog: f64 = weights.iter().map(|(w, lw)| w * lw).sum();
+            total_weight.ln() - sum_wlog / total_weight
+        };
+        self.starting_entropy = match &self.cell_weights {
+            Some(cell_weights) => cell_weights
+                .table
+                .chunks(self.patterns.len().max(1))
+                .map(entropy)
+                .collect(),
+            None => vec![entropy(&self.weight_table)],
+        };
+        self.frequency = self.config.frequency_tolerance.map(|tolerance| {
+            FrequencyTarget::new(&self.colors, &self.weight_table, &self.viable, tolerance)
+        });
+    }
+
+    fn build_cell_weights(&self) -> Option<CellWeights> {
+        let config = &self.config;
+        let guide = config.guide.as_ref();
+        if config.row_profiles.is_empty() && guide.is_none() {
+            return None;
+        }
+        let (width, height) = (self.grid.width, self.grid.height);
+        // Cells on the same row (only if profiles vary by row) under the
+        // same layout color share a table
+        let rows = if config.row_profiles.is_empty() {
+            1
+        } else {
+            height
+        };
+        let mut shades: Vec<Option<Color>> = Vec::new();
+        let mut class = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let shade = match guide {
+                    Some(guide) => {
+                        let color = Some(guide.layout_at(x, y, (width, height)));
+                        shades.iter().position(|&c| c == color).unwrap_or_else(|| {
+                            shades.push(color);
+                            shades.len() - 1
+                        })
+                    }
+                    None => 0,
+                };
+                class.push((y % rows, shade));
+            }
+        }
+        if shades.is_empty() {
+            shades.push(None);
+        }
+        let num_shades = shades.len();
+        let class = class
+            .into_iter()
+            .map(|(row, shade)| (row * num_shades + shade) as u32)
+            .collect();
+
+        let regions: Vec<Option<Color>> = match guide {
+            Some(guide) => self
+                .origins
+                .iter()
+                .map(|&origin| guide.region_of(origin, self.source_size))
+                .collect(),
+            None => Vec::new(),
+        };
+        let mut table = Vec::with_capacity(rows * num_shades * self.weight_table.len());
+        for y in 0..rows {
+            for &shade in &shades {
+                for (p, &(w, _)) in self.weight_table.iter().enumerate() {
+                    let mut factor: f64 = config
+                        .row_profiles
+                        .iter()
+                        .filter(|profile| profile.color == self.colors[p])
+                        .map(|profile| profile.factor(y, height))
+                        .product();
+                    if let Some(guide) = guide
+                        && regions[p] == shade
+                    {
+                        factor *= guide.strength;
+                    }
+                    // Keep weights positive so w*ln(w) stays finite
+                    let w = w * factor.max(1e-9);
+                    table.push((w, w.ln()));
+                }
+            }
+        }
+        Some(CellWeights { class, table })
+    }
+
+    /// Replace `pattern`'s extracted weight; multipliers still apply on top.
+    pub(crate) fn set_weight(&mut self, pattern: usize, weight: f64) {
+        self.base_weights[pattern] = weight;
+        self.build_weights();
+    }
+
+    /// Copy out the sample-derived part, e.g. to rebuild for another output size.
+    pub(crate) fn to_model(&self) -> Model {
+        Model {
+            patterns: self.patterns.clone(),
+            weights: self.base_weights.clone(),
+            edge_mask: self.edge_mask.clone(),
+            origins: self.origins.clone(),
+            source_size: self.source_size,
+            transparent: self.transparent,
+            dir_offsets: self.dir_offsets.clone(),
+            propagator: self.propagator.clone(),
         }
     }
 
+    /// Whether `pattern` may sit on output edge `edge` (`TOP`..`RIGHT`)
+    /// under `Config::edges`; `None` if that edge is unconstrained.
+    pub(crate) fn edge_allows(&self, edge: usize, pattern: usize) -> Option<bool> {
+        Some(match self.config.edges.all()[edge] {
+            EdgeConstraint::None => return None,
+            EdgeConstraint::SampleTop => self.edge_mask[pattern][TOP],
+            EdgeConstraint::SampleBottom => self.edge_mask[pattern][BOTTOM],
+            EdgeConstraint::SampleLeft => self.edge_mask[pattern][LEFT],
+            EdgeConstraint::SampleRight => self.edge_mask[pattern][RIGHT],
+            EdgeConstraint::Color(color) => self.colors[pattern] == *color,
+            EdgeConstraint::Patterns(allowed) => allowed.contains(&pattern),
+        })
+    }
+
     #[inline]
     pub fn num_patterns(&self) -> usize {
         self.patterns.len()
     }
 
+    /// Sample window pattern `p` was taken from.
+    #[inline]
+    pub fn origin(&self, p: usize) -> PatternOrigin {
+        self.origins[p]
+    }
+
+    /// Hash of everything a solver state depends on: patterns, weights,
+    /// support counts and the neighbor table. Only meaningful within one
+    /// process, to tell snapshots of other rules apart.
+    pub(crate) fn fingerprint(&self) -> u64 {
+        let mut h = std::collections::hash_map::DefaultHasher::new();
+        self.patterns.hash(&mut h);
+        let cell_tables = self.cell_weights.iter().flat_map(|w| &w.table);
+        for (w, lw) in self.weight_table.iter().chain(cell_tables) {
+            (w.to_bits(), lw.to_bits()).hash(&mut h);
+        }
+        if let Some(weights) = &self.cell_weights {
+            weights.class.hash(&mut h);
+        }
+        self.dir_offsets.hash(&mut h);
+        self.base_compat.hash(&mut h);
+        self.viable.hash(&mut h);
+        (self.grid.width, self.grid.height).hash(&mut h);
+        self.grid.neighbors().hash(&mut h);
+        h.finish()
+    }
+
+    /// Number of propagation directions (4 unless extra offsets are enabled).
+    #[inline]
+    pub(crate) fn num_dirs(&self) -> usize {
+        self.dir_offsets.len()
+    }
+
+    /// Whether `pattern`, with its first `cols` columns and `rows` rows
+    /// inside the output, repeats the last of each past the edge as
+    /// `Boundary::Clamp` asks. Transparent pixels match anything.
+    pub(crate) fn clamps(&self, pattern: usize, (cols, rows): (usize, usize)) -> bool {
+        let p = &self.patterns[pattern];
+        let same =
+            |a: Color, b: Color| a == b || self.transparent.is_some_and(|t| t == a || t == b);
+        (0..p.height()).all(|y| (cols..p.width()).all(|x| same(p.get(x, y), p.get(cols - 1, y))))
+            && (rows..p.height())
+                .all(|y| (0..p.width()).all(|x| same(p.get(x, y), p.get(x, rows - 1))))
+    }
+
+    /// Whether `cell` is generated (see `Config::mask`).
+    #[inline]
+    pub(crate) fn in_mask(&self, cell: usize) -> bool {
+        self.config.mask.is_empty() || self.config.mask[cell]
+    }
+
     #[inline]
     pub(crate) fn weight(&self, p: usize) -> f64 {
         self.weight_table[p].0
     }
 
-    /// Fixpoint: remove patterns with no viable neighbor in any direction.
-    fn compute_viable(propagator: &FlatPropagator, num_patterns: usize) -> Vec<bool> {
-        let mut viable = vec![true; num_patterns];
-        loop {
-            let mut changed = false;
-            for p in 0..num_patterns {
-                if !viable[p] {
-                    continue;
-                }
-                for dir in Direction::ALL {
-                    let has_viable = propagator
-                        .compatible(p, dir as usize)
-                        .iter()
-                        .any(|&t| viable[t as usize]);
-                    if !has_viable {
-                        viable[p] = false;
-                        changed = true;
-                        break;
-                    }
-                }
-            }
-            if !changed {
-                break;
-            }
+    /// `(weight, log_weight)` of `p` at `cell`, after row profiles and the
+    /// guide.
+    #[inline(always)]
+    pub(crate) fn cell_weight(&self, cell: usize, p: usize) -> (f64, f64) {
+        match &self.cell_weights {
+            Some(w) => w.table[w.class[cell] as usize * self.patterns.len() + p],
+            None => self.weight_table[p],
         }
-        viable
     }
 
-    fn extract_patterns(sample: &Sample, config: &Config) -> ExtractedPatterns {
-        let n = config.pattern_size;
-        let mut pattern_counts: HashMap<Pattern, usize> = HashMap::new();
-        let mut pattern_edges: HashMap<Pattern, [bool; 4]> = HashMap::new();
+    /// Entropy of `cell` with every pattern possible, under its own row
+    /// profile and guide weights.
+    #[inline]
+    pub(crate) fn starting_entropy(&self, cell: usize) -> f64 {
+        let class = self.cell_weights.as_ref().map_or(0, |w| w.class[cell]);
+        self.starting_entropy[class as usize]
+    }
 
-        let x_max = if config.periodic_input {
-            sample.width
-        } else {
-            sample.width.saturating_sub(n - 1)
-        };
-        let y_max = if config.periodic_input {
-            sample.height
-        } else {
-            sample.height.saturating_sub(n - 1)
+    /// `(weight sum, w*ln(w) sum)` over every pattern, per cell.
+    pub(crate) fn starting_sums(&self) -> Vec<(f64, f64)> {
+        let sum = |weights: &[(f64, f64)]| {
+            weights
+                .iter()
+                .fold((0.0, 0.0), |(sum, wlog), &(w, lw)| (sum + w, wlog + w * lw))
         };
+        match &self.cell_weights {
+            Some(cell_weights) => {
+                let classes: Vec<(f64, f64)> = cell_weights
+                    .table
+                    .chunks(self.patterns.len().max(1))
+                    .map(sum)
+                    .collect();
+                cell_weights
+                    .class
+                    .iter()
+                    .map(|&class| classes[class as usize])
+                    .collect()
+            }
+            None => vec![sum(&self.weight_table); self.grid.size()],
+        }
+    }
 
-        for y in 0..y_max {
-            for x in 0..x_max {
-                let mut pixels = Vec::with_capacity(n * n);
-                for dy in 0..n {
-                    for dx in 0..n {
-                        let sx = (x + dx) % sample.width;
-                        let sy = (y + dy) % sample.height;
-                        pixels.push(sample.get(sx, sy));
-                    }
-                }
-                let pattern = Pattern::new(n, pixels);
+    /// Offsets constrained by the propagator: the cardinal directions, the
+    /// diagonals if enabled, then every offset the patterns overlap at
+    /// when `strict_overlap` is set. Indices 0..8 match `Direction`.
+    pub(crate) fn neighbor_offsets(config: &Config) -> Vec<(i32, i32)> {
+        let mut offsets: Vec<(i32, i32)> =
+            Direction::ALL.iter().map(|d| (d.dx(), d.dy())).collect();
 
-                let variants = if config.symmetry {
-                    if config.ground || config.sides {
-                        vec![pattern.clone(), pattern.reflect()]
-                    } else {
-                        pattern.symmetries()
-                    }
-                } else {
-                    vec![pattern]
-                };
+        if config.diagonal || config.strict_overlap {
+            offsets.extend(Direction::DIAGONALS.iter().map(|d| (d.dx(), d.dy())));
+        }
 
-                for variant in variants {
-                    *pattern_counts.entry(variant.clone()).or_insert(0) += 1;
-                    let edges = pattern_edges.entry(variant).or_insert([false; 4]);
-                    if y == 0 {
-                        edges[TOP] = true;
-                    }
-                    if y + n >= sample.height {
-                        edges[BOTTOM] = true;
-                    }
-                    if x == 0 {
-                        edges[LEFT] = true;
-                    }
-                    if x + n >= sample.width {
-                        edges[RIGHT] = true;
+        if config.strict_overlap {
+            let (w, h) = config.pattern_dims();
+            let (w, h) = (w as i32, h as i32);
+            for dy in -(h - 1)..h {
+                for dx in -(w - 1)..w {
+                    if (dx, dy) != (0, 0) && !offsets.contains(&(dx, dy)) {
+                        offsets.push((dx, dy));
                     }
                 }
             }
         }
+        offsets
+    }
+
+    /// One key for every sample's transparent pixels: the first sample's,
+    /// unless another sample has an opaque pixel of that color, which would
+    /// then turn into a wildcard.
+    fn shared_transparent(samples: &[Sample]) -> Option<Color> {
+        let first = samples.iter().find_map(Sample::transparent)?;
+        let opaque: HashSet<Color> = samples
+            .iter()
+            .flat_map(|s| {
+                s.pixels
+                    .iter()
+                    .filter(move |&&c| Some(c) != s.transparent())
+            })
+            .copied()
+            .collect();
+        Some(if opaque.contains(&first) {
+            unused_color(&opaque)
+        } else {
+            first
+        })
+    }
 
-        let mut pairs: Vec<_> = pattern_counts.into_iter().collect();
-        pairs.sort_by(|(a, _), (b, _)| a.cmp(b));
+    /// Accumulate patterns over every sample, each occurrence weighted by
+    /// its sample's `Config::sample_weights` entry.
+    fn extract_patterns(
+        samples: &[Sample],
+        config: &Config,
+        transparent: Option<Color>,
+        progress: &mut ProgressFn,
+    ) -> Option<ExtractedPatterns> {
+        // Every sample's transparent pixels take the shared key
+        let colors: Vec<Vec<Color>> = samples
+            .iter()
+            .map(|sample| match (transparent, sample.transparent()) {
+                (Some(key), Some(own)) if key != own => sample
+                    .pixels
+                    .iter()
+                    .map(|&c| if c == own { key } else { c })
+                    .collect(),
+                _ => sample.pixels.clone(),
+            })
+            .collect();
 
-        let mut patterns = Vec::with_capacity(pairs.len());
-        let mut weights = Vec::with_capacity(pairs.len());
-        let mut edge_mask = Vec::with_capacity(pairs.len());
-        for (pattern, count) in pairs {
-            let edges = pattern_edges.get(&pattern).copied().unwrap_or([false; 4]);
-            edge_mask.push(edges);
+        // Windows of palette indices hash and compare faster than colors
+        // while counting; the distinct patterns go back to colors here, and
+        // everything after extraction sees colors only.
+        let mut found: Vec<(Pattern, Occurrences)> = match index_colors(&colors) {
+            Some((palette, indexed)) => Self::collect_windows(samples, &indexed, config, progress)?
+                .into_iter()
+                .map(|(pattern, seen)| (pattern.map(|i| palette[i as usize]), seen))
+                .collect(),
+            None => Self::collect_windows(samples, &colors, config, progress)?
+                .into_iter()
+                .collect(),
+        };
+        found.retain(|(_, seen)| seen.hits >= config.min_pattern_count);
+        found.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut patterns = Vec::with_capacity(found.len());
+        let mut weights = Vec::with_capacity(found.len());
+        let mut edge_mask = Vec::with_capacity(found.len());
+        let mut origins = Vec::with_capacity(found.len());
+        for (pattern, seen) in found {
+            edge_mask.push(seen.edges);
+            origins.push(seen.origin);
             patterns.push(pattern);
-            weights.push(count as f64);
+            // Masked-out patterns stay for adjacency; keep w*ln(w) finite
+            weights.push(seen.count.max(1e-9));
         }
 
-        ExtractedPatterns {
+        Some(ExtractedPatterns {
             patterns,
             weights,
             edge_mask,
+            origins,
+        })
+    }
+
+    /// Every distinct window of `pixels` (one row-major buffer per sample)
+    /// and its symmetry variants, with where and how often each occurs.
+    fn collect_windows<P: Copy + Default + Eq + Hash>(
+        samples: &[Sample],
+        pixels: &[Vec<P>],
+        config: &Config,
+        progress: &mut ProgressFn,
+    ) -> Option<HashMap<Pattern<P>, Occurrences>> {
+        let (w, h) = config.pattern_dims();
+        let mut found: HashMap<Pattern<P>, Occurrences> = HashMap::new();
+
+        let bounds = |sample: &Sample| {
+            config
+                .input_boundary
+                .window_starts(sample.width, sample.height, w, h)
+        };
+        let total_rows: usize = samples.iter().map(|s| bounds(s).1).sum();
+        let mut rows_done = 0;
+
+        for (index, (sample, source)) in samples.iter().zip(pixels).enumerate() {
+            let (x_max, y_max) = bounds(sample);
+            let sample_weight = config.sample_weights.get(index).copied().unwrap_or(1.0);
+            for y in 0..y_max {
+                if !progress(BuildProgress::new(
+                    BuildPhase::Extracting,
+                    rows_done,
+                    total_rows,
+                )) {
+                    return None;
+                }
+                rows_done += 1;
+                for x in 0..x_max {
+                    let pattern = Pattern::window(source, sample.width, (x, y), (w, h));
+
+                    let variants = pattern.transformed_variants(config.effective_symmetry());
+                    // The importance mask belongs to the first sample
+                    let importance = match index {
+                        0 => config.importance.get(y * sample.width + x).copied(),
+                        _ => None,
+                    }
+                    .unwrap_or(1.0)
+                        * sample_weight;
+
+                    for (transform, variant) in variants {
+                        let origin = PatternOrigin {
+                            sample: index,
+                            x,
+                            y,
+                            transform,
+                        };
+                        let weight = if variant == pattern {
+                            importance
+                        } else {
+                            importance * config.variant_weight
+                        };
+                        let seen = found.entry(variant).or_insert(Occurrences {
+                            count: 0.0,
+                            hits: 0,
+                            edges: [false; 4],
+                            origin,
+                        });
+                        if seen.origin.transform != Symmetry::NONE && transform == Symmetry::NONE {
+                            seen.origin = origin;
+                        }
+                        seen.count += weight;
+                        seen.hits += 1;
+                        if y == 0 {
+                            seen.edges[TOP] = true;
+                        }
+                        if y + h >= sample.height {
+                            seen.edges[BOTTOM] = true;
+                        }
+                        if x == 0 {
+                            seen.edges[LEFT] = true;
+                        }
+                        if x + w >= sample.width {
+                            seen.edges[RIGHT] = true;
+                        }
+                    }
+                }
+            }
         }
+        Some(found)
     }
 
-    fn build_propagator(patterns: &[Pattern], config: &Config) -> FlatPropagator {
-        let n = config.pattern_size;
+    /// Written straight into the flat layout, pattern-major so one pattern's
+    /// lists for every direction sit together during propagation.
+    fn build_propagator(
+        patterns: &[Pattern],
+        (w, h): (usize, usize),
+        dir_offsets: &[(i32, i32)],
+        agreement: Agreement,
+        lazy: bool,
+        progress: &mut ProgressFn,
+    ) -> Option<Propagator> {
         let num_patterns = patterns.len();
+        let num_dirs = dir_offsets.len();
 
-        // Hash overlap strips to find compatible pairs in O(P) instead of O(P^2).
-        // Right: p1 cols [1..n] must match p2 cols [0..n-1], etc.
-        let mut nested_vecs = vec![vec![Vec::<u16>::new(); 4]; num_patterns];
+        // A allows B at (dx, dy) exactly when B allows A at (-dx, -dy), so
+        // only one direction of each opposite pair is compared; the other
+        // is its transpose.
+        let forward: Vec<(usize, usize)> = dir_offsets
+            .iter()
+            .enumerate()
+            .filter_map(|(dir, &(dx, dy))| {
+                let opp = dir_offsets.iter().position(|&o| o == (-dx, -dy))?;
+                (dir < opp).then_some((dir, opp))
+            })
+            .collect();
 
-        // Right: p1 cols [1..n] == p2 cols [0..n-1]
-        Self::fill_compatible_hashed(
-            patterns,
-            n,
-            &mut nested_vecs,
-            Direction::Right as usize,
-            |p, n| Self::hash_cols(p, 1, n, n),
-            |p, n| Self::hash_cols(p, 0, n - 1, n),
-        );
-        // Down: p1 rows [1..n] == p2 rows [0..n-1]
-        Self::fill_compatible_hashed(
-            patterns,
-            n,
-            &mut nested_vecs,
-            Direction::Down as usize,
-            |p, n| Self::hash_rows(p, 1, n, n),
-            |p, n| Self::hash_rows(p, 0, n - 1, n),
-        );
-        // Left: p1 cols [0..n-1] == p2 cols [1..n]
-        Self::fill_compatible_hashed(
-            patterns,
-            n,
-            &mut nested_vecs,
-            Direction::Left as usize,
-            |p, n| Self::hash_cols(p, 0, n - 1, n),
-            |p, n| Self::hash_cols(p, 1, n, n),
-        );
-        // Up: p1 rows [0..n-1] == p2 rows [1..n]
-        Self::fill_compatible_hashed(
-            patterns,
-            n,
-            &mut nested_vecs,
-            Direction::Up as usize,
-            |p, n| Self::hash_rows(p, 0, n - 1, n),
-            |p, n| Self::hash_rows(p, 1, n, n),
-        );
+        // For offset (dx, dy), p1 at the origin and p2 at (dx, dy) must agree
+        // wherever they overlap, e.g. Right: p1 cols [1..w] == p2 cols [0..w-1].
+        // Pack that region per pattern as seen from each side, keeping only
+        // distinct strips: few colors make many patterns share an overlap.
+        let sides: Vec<(StripSet, StripSet)> = forward
+            .iter()
+            .map(|&(dir, _)| {
+                let (dx, dy) = dir_offsets[dir];
+                let (xmin, xmax, ymin, ymax) = Self::overlap_bounds(dx, dy, w, h);
+                // Same region expressed in p2's coordinates
+                let (txmin, txmax) = ((xmin as i32 - dx) as usize, (xmax as i32 - dx) as usize);
+                let (tymin, tymax) = ((ymin as i32 - dy) as usize, (ymax as i32 - dy) as usize);
+                (
+                    StripSet::new(patterns, (xmin..xmax, ymin..ymax)),
+                    StripSet::new(patterns, (txmin..txmax, tymin..tymax)),
+                )
+            })
+            .collect();
 
-        // Flatten into contiguous layout
-        let mut data = Vec::new();
-        let mut offsets = Vec::with_capacity(num_patterns * 4);
-        for dirs in &nested_vecs {
-            for compat in dirs {
-                let start = data.len() as u32;
-                data.extend_from_slice(compat);
-                let end = data.len() as u32;
-                offsets.push((start, end));
+        // Where each direction comes from: (forward pair, is reversed)
+        let mut route = vec![(0, false); num_dirs];
+        for (f, &(dir, opp)) in forward.iter().enumerate() {
+            route[dir] = (f, false);
+            route[opp] = (f, true);
+        }
+        let step = if agreement.is_exact() { 4096 } else { 256 };
+
+        if lazy {
+            // Only list lengths up front, though finding them still compares
+            // every pair of distinct strips. Each match adds to both
+            // directions, so one side is enough.
+            let total: usize = sides.iter().map(|(sources, _)| sources.count()).sum();
+            let mut done = 0;
+            let mut counts = vec![0u16; num_patterns * num_dirs];
+            for (&(dir, opp), (sources, targets)) in forward.iter().zip(&sides) {
+                let mut forward_counts = vec![0; sources.count()];
+                let mut reverse_counts = vec![0; targets.count()];
+                for start in (0..sources.count()).step_by(step) {
+                    if !progress(BuildProgress::new(BuildPhase::Propagator, done, total)) {
+                        return None;
+                    }
+                    let chunk = start..(start + step).min(sources.count());
+                    let found = map_strips(sources, chunk.clone(), |source| {
+                        matching_strips(source, targets, agreement)
+                    });
+                    for (s, found) in chunk.clone().zip(found) {
+                        for t in found.into_iter().map(|t| t as usize) {
+                            forward_counts[s] += targets.members[t].len();
+                            reverse_counts[t] += sources.members[s].len();
+                        }
+                    }
+                    done += chunk.len();
+                }
+                for (set, per_strip, d) in [
+                    (sources, forward_counts, dir),
+                    (targets, reverse_counts, opp),
+                ] {
+                    for (p, &s) in set.index.iter().enumerate() {
+                        counts[p * num_dirs + d] = per_strip[s as usize] as u16;
+                    }
+                }
             }
+            let memo = sides
+                .iter()
+                .map(|(sources, targets)| {
+                    [sources, targets]
+                        .map(|set| (0..set.count()).map(|_| OnceLock::new()).collect())
+                })
+                .collect();
+            return Some(Propagator::Lazy(LazyPropagator {
+                agreement,
+                num_dirs,
+                sides,
+                route,
+                memo,
+                counts,
+            }));
         }
 
-        FlatPropagator { data, offsets }
-    }
+        let total: usize = sides.iter().map(|(sources, _)| sources.count()).sum();
+        let mut done = 0;
 
-    /// Hash-match one direction: candidates by hash, then verify pixels.
-    fn fill_compatible_hashed(
-        patterns: &[Pattern],
-        n: usize,
-        nested: &mut [Vec<Vec<u16>>],
-        dir: usize,
-        source_hash: impl Fn(&Pattern, usize) -> u64,
-        target_hash: impl Fn(&Pattern, usize) -> u64,
-    ) {
-        let mut target_map: HashMap<u64, Vec<u16>> = HashMap::new();
-        for (j, p2) in patterns.iter().enumerate() {
-            target_map
-                .entry(target_hash(p2, n))
-                .or_default()
-                .push(j as u16);
+        // hits[f][s]: patterns whose target strip matches distinct source strip s
+        let mut hits: Vec<Vec<Vec<u16>>> = Vec::with_capacity(forward.len());
+        for (sources, targets) in &sides {
+            let mut lists = Vec::with_capacity(sources.count());
+            for start in (0..sources.count()).step_by(step) {
+                if !progress(BuildProgress::new(BuildPhase::Propagator, done, total)) {
+                    return None;
+                }
+                let chunk = start..(start + step).min(sources.count());
+                done += chunk.len();
+                lists.extend(map_strips(sources, chunk, |source| {
+                    matches(source, targets, agreement)
+                }));
+            }
+            hits.push(lists);
         }
 
-        for (i, p1) in patterns.iter().enumerate() {
-            let h = source_hash(p1, n);
-            if let Some(candidates) = target_map.get(&h) {
-                for &j in candidates {
-                    let p2 = &patterns[j as usize];
-                    if Self::strips_match(p1, p2, dir, n) {
-                        nested[i][dir].push(j);
+        // Transpose each forward relation into its opposite direction, as
+        // (start, end) ranges into one buffer per pair. Walking p1 in order
+        // leaves every reversed list sorted.
+        let reversed: Vec<_> = sides
+            .iter()
+            .zip(&hits)
+            .map(|((sources, _), lists)| {
+                let mut counts = vec![0u32; num_patterns + 1];
+                for &s in &sources.index {
+                    for &j in &lists[s as usize] {
+                        counts[j as usize + 1] += 1;
                     }
                 }
+                for j in 0..num_patterns {
+                    counts[j + 1] += counts[j];
+                }
+                let mut fill = counts.clone();
+                let mut data = vec![0u16; counts[num_patterns] as usize];
+                for (i, &s) in sources.index.iter().enumerate() {
+                    for &j in &lists[s as usize] {
+                        data[fill[j as usize] as usize] = i as u16;
+                        fill[j as usize] += 1;
+                    }
+                }
+                let ranges: Vec<(u32, u32)> = counts.windows(2).map(|w| (w[0], w[1])).collect();
+                (ranges, data)
+            })
+            .collect();
+
+        let mut data = Vec::new();
+        let mut offsets = Vec::with_capacity(num_patterns * num_dirs);
+        for i in 0..num_patterns {
+            for &(f, reverse) in &route {
+                let start = data.len() as u32;
+                if reverse {
+                    let (ranges, list) = &reversed[f];
+                    let (a, b) = ranges[i];
+                    data.extend_from_slice(&list[a as usize..b as usize]);
+                } else {
+                    let s = sides[f].0.index[i];
+                    data.extend_from_slice(&hits[f][s as usize]);
+                }
+                offsets.push((start, data.len() as u32));
             }
         }
+
+        Some(Propagator::Flat(FlatPropagator {
+            data,
+            num_dirs,
+            offsets,
+        }))
     }
 
-    /// Pixel-level overlap strip verification.
-    fn strips_match(p1: &Pattern, p2: &Pattern, dir: usize, n: usize) -> bool {
-        let (dx, dy): (i32, i32) = match dir {
-            0 => (1, 0),  // Right
-            1 => (0, 1),  // Down
-            2 => (-1, 0), // Left
-            _ => (0, -1), // Up
-        };
+    /// Region of p1 (`xmin..xmax`, `ymin..ymax`) covered by p2 placed at (dx, dy).
+    fn overlap_bounds(dx: i32, dy: i32, w: usize, h: usize) -> (usize, usize, usize, usize) {
         let xmin = dx.max(0) as usize;
-        let xmax = (n as i32 + dx.min(0)) as usize;
+        let xmax = (w as i32 + dx.min(0)) as usize;
         let ymin = dy.max(0) as usize;
-        let ymax = (n as i32 + dy.min(0)) as usize;
-
-        for y in ymin..ymax {
-            for x in xmin..xmax {
-                let x2 = (x as i32 - dx) as usize;
-                let y2 = (y as i32 - dy) as usize;
-                if p1.get(x, y) != p2.get(x2, y2) {
-                    return false;
+        let ymax = (h as i32 + dy.min(0)) as usize;
+        (xmin, xmax, ymin, ymax)
+    }
+}
+
+impl Adjacency for Rules {
+    #[inline]
+    fn num_dirs(&self) -> usize {
+        self.dir_offsets.len()
+    }
+
+    #[inline]
+    fn opposite(&self, dir: usize) -> usize {
+        self.opposite[dir]
+    }
+
+    #[inline]
+    fn neighbor(&self, cell: usize, dir: usize) -> Option<usize> {
+        self.grid.neighbor(cell, dir)
+    }
+
+    #[inline]
+    fn compatible(&self, pattern: usize, dir: usize) -> &[u16] {
+        self.propagator.compatible(pattern, dir)
+    }
+}
+
+/// Distinct overlap strips of one side of an offset, with the patterns
+/// having each. A strip is a pattern's pixels in the overlap region as
+/// row-major channel bytes; all strips of a set have the same length.
+#[derive(Clone)]
+struct StripSet {
+    /// Bytes per strip.
+    len: usize,
+    /// The distinct strips back to back, `len` bytes each.
+    bytes: Vec<u8>,
+    /// `index[pattern]`: position of that pattern's strip among the distinct ones
+    index: Vec<u32>,
+    /// `members[s]`: patterns with strip `s`, ascending
+    members: Vec<Vec<u16>>,
+    buckets: HashMap<u64, Vec<u32>>,
+}
+
+impl StripSet {
+    /// Strips of `patterns` over the region `xs` x `ys`.
+    fn new(patterns: &[Pattern], (xs, ys): (Range<usize>, Range<usize>)) -> Self {
+        let mut set = Self {
+            len: 3 * xs.len() * ys.len(),
+            bytes: Vec::new(),
+            index: Vec::new(),
+            members: Vec::new(),
+            buckets: HashMap::new(),
+        };
+        let mut strip = Vec::with_capacity(set.len);
+        for (p, pattern) in patterns.iter().enumerate() {
+            strip.clear();
+            for y in ys.clone() {
+                for x in xs.clone() {
+                    strip.extend_from_slice(&pattern.get(x, y));
                 }
             }
+            let s = match set.find(&strip) {
+                Some(s) => s,
+                None => {
+                    let s = set.count();
+                    set.buckets
+                        .entry(strip_hash(&strip))
+                        .or_default()
+                        .push(s as u32);
+                    set.bytes.extend_from_slice(&strip);
+                    set.members.push(Vec::new());
+                    s
+                }
+            };
+            set.index.push(s as u32);
+            set.members[s].push(p as u16);
         }
-        true
-    }
-
-    /// Hash columns [col_start..col_end) of a pattern (all rows).
-    fn hash_cols(p: &Pattern, col_start: usize, col_end: usize, n: usize) -> u64 {
-        let mut h: u64 = 0xcbf29ce484222325; // FNV offset basis
-        for y in 0..n {
-            for x in col_start..col_end {
-                let c = p.get(x, y);
-                h = h.wrapping_mul(0x100000001b3); // FNV prime
-                h ^= c[0] as u64;
-                h = h.wrapping_mul(0x100000001b3);
-                h ^= c[1] as u64;
-                h = h.wrapping_mul(0x100000001b3);
-                h ^= c[2] as u64;
-            }
-        }
-        h
-    }
-
-    /// Hash rows [row_start..row_end) of a pattern (all columns).
-    fn hash_rows(p: &Pattern, row_start: usize, row_end: usize, n: usize) -> u64 {
-        let mut h: u64 = 0xcbf29ce484222325;
-        for y in row_start..row_end {
-            for x in 0..n {
-                let c = p.get(x, y);
-                h = h.wrapping_mul(0x100000001b3);
-                h ^= c[0] as u64;
-                h = h.wrapping_mul(0x100000001b3);
-                h ^= c[1] as u64;
-                h = h.wrapping_mul(0x100000001b3);
-                h ^= c[2] as u64;
-            }
+        set
+    }
+
+    /// Number of distinct strips.
+    fn count(&self) -> usize {
+        self.members.len()
+    }
+
+    fn strip(&self, s: usize) -> &[u8] {
+        &self.bytes[s * self.len..(s + 1) * self.len]
+    }
+
+    fn heap_bytes(&self) -> usize {
+        let buckets = self.buckets.capacity() * std::mem::size_of::<(u64, Vec<u32>)>()
+            + self.buckets.values().map(vec_bytes).sum::<usize>();
+        vec_bytes(&self.bytes)
+            + vec_bytes(&self.index)
+            + vec_bytes(&self.members)
+            + self.members.iter().map(vec_bytes).sum::<usize>()
+            + buckets
+    }
+
+    fn find(&self, strip: &[u8]) -> Option<usize> {
+        self.buckets
+            .get(&strip_hash(strip))?
+            .iter()
+            .map(|&s| s as usize)
+            .find(|&s| self.strip(s) == strip)
+    }
+}
+
+/// Runs `f` over strips `range` of `set`, across threads with the
+/// `parallel` feature.
+#[cfg(feature = "parallel")]
+fn map_strips<T: Send>(
+    set: &StripSet,
+    range: Range<usize>,
+    f: impl Fn(&[u8]) -> T + Send + Sync,
+) -> Vec<T> {
+    use rayon::prelude::*;
+    range.into_par_iter().map(|s| f(set.strip(s))).collect()
+}
+
+#[cfg(not(feature = "parallel"))]
+fn map_strips<T>(set: &StripSet, range: Range<usize>, f: impl Fn(&[u8]) -> T) -> Vec<T> {
+    range.map(|s| f(set.strip(s))).collect()
+}
+
+/// Distinct strips in `among` that match `key`.
+fn matching_strips(key: &[u8], among: &StripSet, agreement: Agreement) -> Vec<u32> {
+    if agreement.is_exact() {
+        // Exact: at most one
+        return among.find(key).map(|s| s as u32).into_iter().collect();
+    }
+    let agree: fn(&[u8], &[u8], Agreement) -> bool = match agreement.wildcard {
+        Some(_) => strips_agree,
+        None => |a, b, agreement| strips_within(a, b, agreement.tolerance),
+    };
+    (0..among.count() as u32)
+        .filter(|&s| agree(key, among.strip(s as usize), agreement))
+        .collect()
+}
+
+/// Patterns whose strip in `among` matches `key`, ascending.
+fn matches(key: &[u8], among: &StripSet, agreement: Agreement) -> Vec<u16> {
+    let found = matching_strips(key, among, agreement);
+    let mut list: Vec<u16> = found
+        .iter()
+        .flat_map(|&s| among.members[s as usize].iter().copied())
+        .collect();
+    if found.len() > 1 {
+        list.sort_unstable();
+    }
+    list
+}
+
+/// Folds the strip as u64 words, the last one zero-padded; collisions are
+/// filtered by comparing strips.
+fn strip_hash(strip: &[u8]) -> u64 {
+    strip.chunks(8).fold(0, |hash, chunk| {
+        let mut word = [0; 8];
+        word[..chunk.len()].copy_from_slice(chunk);
+        let word = u64::from_le_bytes(word);
+        (hash ^ word)
+            .wrapping_mul(0x0000_0100_0000_01b3)
+            .rotate_left(29)
+    })
+}
+
+/// Every channel within `tolerance`. Branch-free so it vectorizes; cheaper
+/// than bailing at the first differing pixel.
+fn strips_within(a: &[u8], b: &[u8], tolerance: u8) -> bool {
+    a.iter()
+        .zip(b)
+        .fold(0u8, |worst, (a, b)| worst.max(a.abs_diff(*b)))
+        <= tolerance
+}
+
+/// Pixel by pixel, a wildcard on either side matching anything.
+fn strips_agree(a: &[u8], b: &[u8], agreement: Agreement) -> bool {
+    a.chunks_exact(3).zip(b.chunks_exact(3)).all(|(a, b)| {
+        let wild = |p: &[u8]| agreement.wildcard.is_some_and(|w| w == p);
+        wild(a)
+            || wild(b)
+            || a.iter()
+                .zip(b)
+                .all(|(a, b)| a.abs_diff(*b) <= agreement.tolerance)
+    })
+}
+
+/// How overlapping pixels are compared: every channel within `tolerance`,
+/// and `wildcard` pixels (transparent in the sample) agree with anything.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Agreement {
+    tolerance: u8,
+    wildcard: Option<Color>,
+}
+
+impl Agreement {
+    fn is_exact(self) -> bool {
+        self.tolerance == 0 && self.wildcard.is_none()
+    }
+}
+
+/// Palette of `colors` in order of first appearance and each buffer as
+/// indices into it; `None` past `u16` indices.
+fn index_colors(colors: &[Vec<Color>]) -> Option<(Vec<Color>, Vec<Vec<u16>>)> {
+    let mut palette = Vec::new();
+    let mut lookup: HashMap<Color, u16> = HashMap::new();
+    let mut indexed = Vec::with_capacity(colors.len());
+    for pixels in colors {
+        let mut indices = Vec::with_capacity(pixels.len());
+        for &c in pixels {
+            let i = match lookup.get(&c) {
+                Some(&i) => i,
+                None => {
+                    let i = u16::try_from(palette.len()).ok()?;
+                    palette.push(c);
+                    lookup.insert(c, i);
+                    i
+                }
+            };
+            indices.push(i);
         }
-        h
+        indexed.push(indices);
     }
+    Some((palette, indexed))
+}
+
+/// Where and how often one distinct pattern occurs during extraction.
+struct Occurrences {
+    /// Fractional when generated variants are down-weighted
+    count: f64,
+    /// Windows showing the pattern, whatever their weight.
+    hits: usize,
+    /// Sample edges the pattern touched.
+    edges: [bool; 4],
+    origin: PatternOrigin,
 }
 
 struct ExtractedPatterns {
     patterns: Vec<Pattern>,
     weights: Vec<f64>,
     edge_mask: Vec<[bool; 4]>,
+    origins: Vec<PatternOrigin>,
 }
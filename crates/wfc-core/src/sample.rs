@@ -1,12 +1,42 @@
-use crate::Color;
+use std::collections::{HashMap, HashSet};
+
 #[cfg(feature = "image-io")]
 use crate::Error;
+use crate::recolor::{dominant_colors, nearest_color};
+use crate::rules::Rules;
+use crate::{Color, Config};
+
+/// Largest width or height accepted by [`Sample::from_bytes`]. Samples are
+/// rarely more than a few hundred pixels; this only stops decompression bombs.
+#[cfg(feature = "image-io")]
+pub const MAX_SAMPLE_SIDE: u32 = 4096;
+
+/// Largest response body [`Sample::from_url`] will download.
+#[cfg(feature = "url")]
+pub const MAX_DOWNLOAD_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Cheap summary of a sample for the given settings, from [`Sample::analyze`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SampleStats {
+    pub unique_colors: usize,
+    /// Distinct pattern-sized windows seen at least
+    /// `Config::min_pattern_count` times, before symmetry variants.
+    pub unique_windows: usize,
+    /// Extracted pattern count: exact without symmetry; with it an upper
+    /// bound, short only of variants that reach `min_pattern_count` together.
+    pub estimated_patterns: usize,
+    /// Wave bits plus support counts for `estimated_patterns` over the
+    /// output grid, excluding the propagator and backtracking snapshots.
+    pub estimated_wave_bytes: usize,
+}
 
 #[derive(Clone, Debug)]
 pub struct Sample {
     pub width: usize,
     pub height: usize,
     pub pixels: Vec<Color>,
+    /// See [`Sample::transparent`].
+    transparent: Option<Color>,
 }
 
 impl Sample {
@@ -16,9 +46,52 @@ impl Sample {
             width,
             height,
             pixels,
+            transparent: None,
         }
     }
 
+    /// Mark pixels of `color` as transparent (see [`Sample::transparent`]).
+    #[must_use]
+    pub fn with_transparent(mut self, color: Option<Color>) -> Self {
+        self.transparent = color;
+        self
+    }
+
+    /// Color standing in for fully transparent pixels, one no opaque pixel
+    /// uses. Such pixels match anything where patterns overlap and are
+    /// saved transparent. `None` for opaque samples.
+    #[must_use]
+    pub fn transparent(&self) -> Option<Color> {
+        self.transparent
+    }
+
+    /// Build from RGBA pixels. Fully transparent pixels all become one key
+    /// color unused by the opaque ones; partial alpha is dropped.
+    pub fn from_rgba(width: usize, height: usize, rgba: &[[u8; 4]]) -> Self {
+        let opaque: HashSet<Color> = rgba
+            .iter()
+            .filter(|p| p[3] != 0)
+            .map(|&[r, g, b, _]| [r, g, b])
+            .collect();
+        let transparent = rgba
+            .iter()
+            .any(|p| p[3] == 0)
+            .then(|| unused_color(&opaque));
+        let pixels = rgba
+            .iter()
+            .map(|&[r, g, b, a]| match (a, transparent) {
+                (0, Some(key)) => key,
+                _ => [r, g, b],
+            })
+            .collect();
+        Self::new(width, height, pixels).with_transparent(transparent)
+    }
+
+    #[must_use]
+    pub fn is_transparent(&self, x: usize, y: usize) -> bool {
+        self.transparent == Some(self.get(x, y))
+    }
+
     pub fn get(&self, x: usize, y: usize) -> Color {
         self.pixels[y * self.width + x]
     }
@@ -26,19 +99,84 @@ impl Sample {
     #[cfg(feature = "image-io")]
     pub fn from_image(path: &std::path::Path) -> Result<Self, Error> {
         let img = image::open(path).map_err(|e| Error::ImageLoad(e.to_string()))?;
-        let rgb = img.to_rgb8();
-        let width = rgb.width() as usize;
-        let height = rgb.height() as usize;
-        let pixels: Vec<Color> = rgb.pixels().map(|p| [p[0], p[1], p[2]]).collect();
-        Ok(Self {
-            width,
-            height,
-            pixels,
-        })
+        Ok(Self::from_decoded(&img))
     }
 
+    /// Decode an encoded image (PNG, JPEG, ...) held in memory, refusing
+    /// anything larger than [`MAX_SAMPLE_SIDE`] on either side.
+    #[cfg(feature = "image-io")]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = image::ImageReader::new(std::io::Cursor::new(bytes))
+            .with_guessed_format()
+            .map_err(|e| Error::ImageLoad(e.to_string()))?;
+        let mut limits = image::Limits::default();
+        limits.max_image_width = Some(MAX_SAMPLE_SIDE);
+        limits.max_image_height = Some(MAX_SAMPLE_SIDE);
+        reader.limits(limits);
+        let img = reader
+            .decode()
+            .map_err(|e| Error::ImageLoad(e.to_string()))?;
+        Ok(Self::from_decoded(&img))
+    }
+
+    #[cfg(feature = "image-io")]
+    fn from_decoded(img: &image::DynamicImage) -> Self {
+        let rgba = img.to_rgba8();
+        let pixels: Vec<[u8; 4]> = rgba.pixels().map(|p| p.0).collect();
+        Self::from_rgba(rgba.width() as usize, rgba.height() as usize, &pixels)
+    }
+
+    /// Fetch a sample over http(s). The download is capped at
+    /// [`MAX_DOWNLOAD_BYTES`] and decoded with the limits of
+    /// [`Sample::from_bytes`].
+    #[cfg(feature = "url")]
+    pub fn from_url(url: &str) -> Result<Self, Error> {
+        use std::io::Read;
+
+        if !(url.starts_with("http://") || url.starts_with("https://")) {
+            return Err(Error::Fetch(format!("'{}' is not an http(s) URL", url)));
+        }
+        let agent = ureq::AgentBuilder::new()
+            .timeout(std::time::Duration::from_secs(30))
+            .build();
+        let response = agent
+            .get(url)
+            .call()
+            .map_err(|e| Error::Fetch(e.to_string()))?;
+        let declared = response
+            .header("Content-Length")
+            .and_then(|len| len.parse::<u64>().ok());
+        if declared.is_some_and(|len| len > MAX_DOWNLOAD_BYTES) {
+            return Err(Error::Fetch(format!(
+                "{} bytes exceeds the {} byte limit",
+                declared.unwrap_or_default(),
+                MAX_DOWNLOAD_BYTES
+            )));
+        }
+        let mut bytes = Vec::new();
+        response
+            .into_reader()
+            .take(MAX_DOWNLOAD_BYTES + 1)
+            .read_to_end(&mut bytes)
+            .map_err(|e| Error::Fetch(e.to_string()))?;
+        if bytes.len() as u64 > MAX_DOWNLOAD_BYTES {
+            return Err(Error::Fetch(format!(
+                "response exceeds the {} byte limit",
+                MAX_DOWNLOAD_BYTES
+            )));
+        }
+        Self::from_bytes(&bytes)
+    }
+
+    /// Saved as RGBA when [`Sample::transparent`] is set.
     #[cfg(feature = "image-io")]
     pub fn save(&self, path: &std::path::Path) -> Result<(), Error> {
+        if self.transparent.is_some() {
+            return self
+                .rgba_image(None)
+                .save(path)
+                .map_err(|e| Error::ImageSave(e.to_string()));
+        }
         let mut img = image::RgbImage::new(self.width as u32, self.height as u32);
         for y in 0..self.height {
             for x in 0..self.width {
@@ -48,6 +186,233 @@ impl Sample {
         }
         img.save(path).map_err(|e| Error::ImageSave(e.to_string()))
     }
+
+    /// Save as RGBA, transparent where `mask` (row-major, as in
+    /// `Config::mask`) is false.
+    #[cfg(feature = "image-io")]
+    pub fn save_masked(&self, path: &std::path::Path, mask: &[bool]) -> Result<(), Error> {
+        assert_eq!(mask.len(), self.pixels.len());
+        self.rgba_image(Some(mask))
+            .save(path)
+            .map_err(|e| Error::ImageSave(e.to_string()))
+    }
+
+    /// Opaque except for transparent-key pixels and cells `mask` leaves out.
+    #[cfg(feature = "image-io")]
+    fn rgba_image(&self, mask: Option<&[bool]>) -> image::RgbaImage {
+        let raw = self
+            .pixels
+            .iter()
+            .enumerate()
+            .flat_map(|(i, &[r, g, b])| {
+                let keep = mask.is_none_or(|m| m[i]) && self.transparent != Some([r, g, b]);
+                [r, g, b, if keep { 255 } else { 0 }]
+            })
+            .collect();
+        image::RgbaImage::from_raw(self.width as u32, self.height as u32, raw)
+            .expect("buffer matches dimensions")
+    }
+
+    /// Importance mask aligned with this sample, for `Config::importance`:
+    /// luminance mapped to `0.0` (black, ignored) through `1.0` (white).
+    #[cfg(feature = "image-io")]
+    pub fn load_importance(&self, path: &std::path::Path) -> Result<Vec<f64>, Error> {
+        let img = image::open(path)
+            .map_err(|e| Error::ImageLoad(e.to_string()))?
+            .to_luma8();
+        if (img.width() as usize, img.height() as usize) != (self.width, self.height) {
+            return Err(Error::InvalidConfig(format!(
+                "importance mask is {}x{}, sample is {}x{}",
+                img.width(),
+                img.height(),
+                self.width,
+                self.height
+            )));
+        }
+        Ok(img.pixels().map(|p| p.0[0] as f64 / 255.0).collect())
+    }
+
+    /// PNG file bytes, e.g. for writing to stdout.
+    #[cfg(feature = "image-io")]
+    pub fn encode_png(&self) -> Result<Vec<u8>, Error> {
+        let mut out = std::io::Cursor::new(Vec::new());
+        if self.transparent.is_some() {
+            self.rgba_image(None)
+                .write_to(&mut out, image::ImageFormat::Png)
+                .map_err(|e| Error::ImageSave(e.to_string()))?;
+            return Ok(out.into_inner());
+        }
+        let raw = self.pixels.iter().flatten().copied().collect();
+        let img = image::RgbImage::from_raw(self.width as u32, self.height as u32, raw)
+            .expect("buffer matches dimensions");
+        img.write_to(&mut out, image::ImageFormat::Png)
+            .map_err(|e| Error::ImageSave(e.to_string()))?;
+        Ok(out.into_inner())
+    }
+
+    /// Estimate what extraction with `config` would produce by hashing the
+    /// pattern-sized windows, without building patterns or the propagator.
+    #[must_use]
+    pub fn analyze(&self, config: &Config) -> SampleStats {
+        Self::analyze_all(std::slice::from_ref(self), config)
+    }
+
+    /// [`analyze`](Self::analyze) for the samples given to
+    /// `Wfc::from_samples`, counting windows across all of them but none
+    /// crossing between them. Sample weights are ignored.
+    #[must_use]
+    pub fn analyze_all(samples: &[Sample], config: &Config) -> SampleStats {
+        let unique_colors = samples
+            .iter()
+            .flat_map(|s| &s.pixels)
+            .collect::<HashSet<_>>()
+            .len();
+        let (w, h) = config.pattern_dims();
+        let (w, h) = (w.max(1), h.max(1));
+
+        let mut windows: HashMap<u64, usize> = HashMap::new();
+        for sample in samples {
+            let (x_max, y_max) =
+                config
+                    .input_boundary
+                    .window_starts(sample.width, sample.height, w, h);
+            for y in 0..y_max {
+                for x in 0..x_max {
+                    // FNV-1a over the window's colors
+                    let mut hash: u64 = 0xcbf29ce484222325;
+                    for dy in 0..h {
+                        for dx in 0..w {
+                            let c = sample.get((x + dx) % sample.width, (y + dy) % sample.height);
+                            for b in c {
+                                hash ^= b as u64;
+                                hash = hash.wrapping_mul(0x100000001b3);
+                            }
+                        }
+                    }
+                    *windows.entry(hash).or_default() += 1;
+                }
+            }
+        }
+
+        let unique_windows = windows
+            .values()
+            .filter(|&&n| n >= config.min_pattern_count)
+            .count();
+        let variants = 1 + config.effective_symmetry().len();
+        // Can't exceed every possible coloring of a window
+        let possible = u32::try_from(w * h)
+            .ok()
+            .and_then(|e| unique_colors.checked_pow(e))
+            .unwrap_or(usize::MAX);
+        let estimated_patterns = unique_windows.saturating_mul(variants).min(possible);
+        let per_cell = estimated_patterns
+            .saturating_mul(Rules::neighbor_offsets(config).len() * size_of::<u16>())
+            .saturating_add(estimated_patterns.div_ceil(8));
+        SampleStats {
+            unique_colors,
+            unique_windows,
+            estimated_patterns,
+            estimated_wave_bytes: per_cell
+                .saturating_mul(config.output_width)
+                .saturating_mul(config.output_height),
+        }
+    }
+
+    /// Snap every pixel to the nearest of at most `n_colors` median-cut
+    /// colors, so anti-aliased or photographic samples don't split into a
+    /// pattern per slightly different shade.
+    #[must_use]
+    pub fn quantize(&self, n_colors: usize) -> Self {
+        let opaque: Vec<Color> = self
+            .pixels
+            .iter()
+            .copied()
+            .filter(|&c| self.transparent != Some(c))
+            .collect();
+        let palette = dominant_colors(&opaque, n_colors.max(1));
+        let mut snapped: HashMap<Color, Color> = HashMap::new();
+        if let Some(key) = self.transparent {
+            snapped.insert(key, key);
+        }
+        let pixels = self
+            .pixels
+            .iter()
+            .map(|&c| {
+                *snapped
+                    .entry(c)
+                    .or_insert_with(|| nearest_color(c, &palette))
+            })
+            .collect();
+        Self::new(self.width, self.height, pixels).with_transparent(self.transparent)
+    }
+
+    /// Shrink by `factor`, each `factor` x `factor` block (smaller at the
+    /// right and bottom edges) taking its most common color, first seen
+    /// on ties. Colors are never blended, so the palette is kept.
+    #[must_use]
+    pub fn downscale(&self, factor: usize) -> Self {
+        let factor = factor.max(1);
+        let (width, height) = (self.width.div_ceil(factor), self.height.div_ceil(factor));
+        let mut pixels = Vec::with_capacity(width * height);
+        let mut counts: Vec<(Color, usize)> = Vec::new();
+        for by in 0..height {
+            for bx in 0..width {
+                counts.clear();
+                for y in by * factor..((by + 1) * factor).min(self.height) {
+                    for x in bx * factor..((bx + 1) * factor).min(self.width) {
+                        let c = self.get(x, y);
+                        match counts.iter_mut().find(|(seen, _)| *seen == c) {
+                            Some((_, n)) => *n += 1,
+                            None => counts.push((c, 1)),
+                        }
+                    }
+                }
+                let most = counts.iter().map(|&(_, n)| n).max().unwrap_or(0);
+                let (color, _) = counts.iter().find(|&&(_, n)| n == most).unwrap();
+                pixels.push(*color);
+            }
+        }
+        Self::new(width, height, pixels).with_transparent(self.transparent)
+    }
+
+    /// FNV-1a over the dimensions and pixels. The hash itself is stable
+    /// across platforms and versions, so it can pin generated assets (see
+    /// `wfc verify`) built and checked on the same platform.
+    #[must_use]
+    pub fn content_hash(&self) -> u64 {
+        let mut h: u64 = 0xcbf29ce484222325;
+        let mut feed = |bytes: &[u8]| {
+            for &b in bytes {
+                h ^= b as u64;
+                h = h.wrapping_mul(0x100000001b3);
+            }
+        };
+        feed(&(self.width as u64).to_le_bytes());
+        feed(&(self.height as u64).to_le_bytes());
+        for c in &self.pixels {
+            feed(c);
+        }
+        h
+    }
+
+    /// Binary PPM (P6) bytes.
+    pub fn encode_ppm(&self) -> Vec<u8> {
+        let mut out = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        out.extend(self.pixels.iter().flatten());
+        out
+    }
+}
+
+/// Magenta unless `used` holds it; otherwise the nearest color (by XOR)
+/// it doesn't.
+pub(crate) fn unused_color(used: &HashSet<Color>) -> Color {
+    (0..=u32::MAX >> 8)
+        .map(|i| {
+            let [_, r, g, b] = (0xff00ff ^ i).to_be_bytes();
+            [r, g, b]
+        })
+        .find(|c| !used.contains(c))
+        .expect("fewer than 2^24 opaque colors")
 }
 
 pub fn default_pipe_sample() -> Sample {
@@ -0,0 +1,170 @@
+//! Marching-squares smoothing for two-tone outputs (walls/floor).
+
+use std::fmt::Write;
+
+use crate::Color;
+
+/// Contour segment endpoints in cell units (cell `x` spans `x..x+1`).
+pub type Segment = [(f32, f32); 2];
+
+/// Cells darker than the midpoint of the output's luminance range.
+/// For a two-color output this picks out the darker color (usually walls).
+#[must_use]
+pub fn dark_mask(colors: &[Color]) -> Vec<bool> {
+    let luma = |c: &Color| 0.299 * c[0] as f32 + 0.587 * c[1] as f32 + 0.114 * c[2] as f32;
+    let (min, max) = colors
+        .iter()
+        .map(luma)
+        .fold((f32::MAX, f32::MIN), |(lo, hi), l| (lo.min(l), hi.max(l)));
+    let mid = (min + max) * 0.5;
+    colors.iter().map(|c| luma(c) < mid).collect()
+}
+
+/// Contour between inside and outside cells, sampled at cell centers.
+/// Out-of-bounds counts as outside, so every contour closes.
+#[must_use]
+pub fn marching_squares(width: usize, height: usize, inside: &[bool]) -> Vec<Segment> {
+    assert_eq!(inside.len(), width * height);
+    let at = |x: i32, y: i32| {
+        x >= 0
+            && y >= 0
+            && (x as usize) < width
+            && (y as usize) < height
+            && inside[y as usize * width + x as usize]
+    };
+
+    let mut segments = Vec::new();
+    for y in -1..height as i32 {
+        for x in -1..width as i32 {
+            let case = (at(x, y) as u8) << 3
+                | (at(x + 1, y) as u8) << 2
+                | (at(x + 1, y + 1) as u8) << 1
+                | at(x, y + 1) as u8;
+
+            // Square corners are the centers of cells (x, y)..(x+1, y+1)
+            let (cx, cy) = (x as f32 + 0.5, y as f32 + 0.5);
+            let top = (cx + 0.5, cy);
+            let right = (cx + 1.0, cy + 0.5);
+            let bottom = (cx + 0.5, cy + 1.0);
+            let left = (cx, cy + 0.5);
+
+            match case {
+                1 | 14 => segments.push([left, bottom]),
+                2 | 13 => segments.push([bottom, right]),
+                3 | 12 => segments.push([left, right]),
+                4 | 11 => segments.push([top, right]),
+                6 | 9 => segments.push([top, bottom]),
+                7 | 8 => segments.push([left, top]),
+                5 => {
+                    segments.push([left, top]);
+                    segments.push([bottom, right]);
+                }
+                10 => {
+                    segments.push([top, right]);
+                    segments.push([left, bottom]);
+                }
+                _ => {}
+            }
+        }
+    }
+    segments
+}
+
+/// Upscale `inside` by `scale` with the same rounded edges as the contour:
+/// bilinear interpolation between cell centers, thresholded at one half.
+#[must_use]
+pub fn smooth_mask(width: usize, height: usize, inside: &[bool], scale: usize) -> Vec<bool> {
+    assert_eq!(inside.len(), width * height);
+    let value = |x: usize, y: usize| if inside[y * width + x] { 1.0f32 } else { 0.0 };
+    let (out_w, out_h) = (width * scale, height * scale);
+
+    let mut out = Vec::with_capacity(out_w * out_h);
+    for py in 0..out_h {
+        let v = ((py as f32 + 0.5) / scale as f32 - 0.5).clamp(0.0, (height - 1) as f32);
+        let (y0, fy) = (v.floor() as usize, v.fract());
+        let y1 = (y0 + 1).min(height - 1);
+        for px in 0..out_w {
+            let u = ((px as f32 + 0.5) / scale as f32 - 0.5).clamp(0.0, (width - 1) as f32);
+            let (x0, fx) = (u.floor() as usize, u.fract());
+            let x1 = (x0 + 1).min(width - 1);
+
+            let top = value(x0, y0) * (1.0 - fx) + value(x1, y0) * fx;
+            let bottom = value(x0, y1) * (1.0 - fx) + value(x1, y1) * fx;
+            out.push(top * (1.0 - fy) + bottom * fy >= 0.5);
+        }
+    }
+    out
+}
+
+/// SVG of the raw cells as background with the smoothed contour stroked on top.
+#[must_use]
+pub fn contour_svg(
+    width: usize,
+    height: usize,
+    colors: &[Color],
+    segments: &[Segment],
+    scale: f32,
+) -> String {
+    let hex = |c: Color| format!("#{:02x}{:02x}{:02x}", c[0], c[1], c[2]);
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}" shape-rendering="crispEdges">"#,
+        width as f32 * scale,
+        height as f32 * scale,
+        width,
+        height
+    );
+    for y in 0..height {
+        for x in 0..width {
+            let _ = writeln!(
+                svg,
+                r#"<rect x="{}" y="{}" width="1" height="1" fill="{}"/>"#,
+                x,
+                y,
+                hex(colors[y * width + x])
+            );
+        }
+    }
+    let mut d = String::new();
+    for [(x0, y0), (x1, y1)] in segments {
+        let _ = write!(d, "M{} {}L{} {}", x0, y0, x1, y1);
+    }
+    let _ = writeln!(
+        svg,
+        r#"<path d="{}" fill="none" stroke="black" stroke-width="0.1" stroke-linecap="round" shape-rendering="geometricPrecision"/>"#,
+        d
+    );
+    svg.push_str("</svg>\n");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_cell_becomes_closed_diamond() {
+        let inside = [false, false, false, false, true, false, false, false, false];
+        let segments = marching_squares(3, 3, &inside);
+        assert_eq!(segments.len(), 4);
+        // Every endpoint is shared by exactly two segments
+        for [a, b] in &segments {
+            for p in [a, b] {
+                let uses = segments.iter().filter(|[s, e]| s == p || e == p).count();
+                assert_eq!(uses, 2);
+            }
+        }
+    }
+
+    #[test]
+    fn smooth_mask_rounds_corners_but_keeps_solid_regions() {
+        let inside = [true; 4];
+        assert!(smooth_mask(2, 2, &inside, 4).iter().all(|&v| v));
+
+        let corner = [true, false, false, false];
+        let mask = smooth_mask(2, 2, &corner, 4);
+        assert!(mask[0]);
+        assert!(!mask[8 * 8 - 1]);
+    }
+}
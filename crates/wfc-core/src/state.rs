@@ -1,40 +1,101 @@
-use rand::SeedableRng;
-use rand::rngs::SmallRng;
+use std::time::Duration;
 
-use crate::bitset::Bitset;
+use crate::Heuristic;
+use crate::bitset::{Bitset, Candidates, SMALL_MAX, SmallSet};
+use crate::contradiction::BanLog;
+use crate::memory::vec_bytes;
+use crate::mirror::MirrorLink;
+use crate::queue::EntropyQueue;
+use crate::rng::SplitMix64;
 use crate::rules::Rules;
+use crate::support::Supports;
 
+#[derive(Clone)]
 pub struct State {
     pub(crate) wave: Bitset,
-    /// `compat[(cell * num_patterns + pattern) * 4 + dir]`
+    /// `compat[(cell * num_patterns + pattern) * num_dirs + dir]`
     pub(crate) compat: Vec<u16>,
     pub(crate) num_patterns: usize,
+    pub(crate) num_dirs: usize,
     pub(crate) num_possible: Vec<usize>,
+    /// `small[cell]`: the cell's candidates while `num_possible[cell] <=
+    /// SMALL_MAX`; stale above that.
+    pub(crate) small: Vec<SmallSet>,
+    /// Decided cells per color class of `Rules::frequency`; empty without
+    /// one. Cells outside the mask aren't counted.
+    pub(crate) placed: Vec<u32>,
+    /// Sum of `placed`.
+    pub(crate) decided: u32,
     pub(crate) weight_sum: Vec<f64>,
     /// Sum of w*ln(w) per cell, for entropy calculation.
     pub(crate) wlog_sum: Vec<f64>,
     /// (cell, banned_pattern) pairs pending propagation.
     pub(crate) stack: Vec<(usize, usize)>,
+    /// Cells that lost a pattern during the current step, in ban order.
+    pub(crate) dirty_cells: Vec<usize>,
+    dirty: Vec<bool>,
+    /// Cells awaiting observation by entropy; see [`State::flush_queue`].
+    /// Only kept for `Heuristic::MinEntropy`.
+    pub(crate) queue: EntropyQueue,
+    use_queue: bool,
+    /// `Heuristic::Scanline` position: every cell before it is decided.
+    pub(crate) scan: usize,
+    /// `dirty_cells[..queued]` are already in `queue`.
+    queued: usize,
+    /// Per-cell tie-breaking offset added to the entropy, drawn once per run.
+    noise: Vec<f64>,
     pub(crate) contradiction: bool,
     pub(crate) done: bool,
     pub(crate) last_collapsed: Option<(usize, usize)>,
-    pub(crate) rng: SmallRng,
+    /// Cell and pattern of the latest observation.
+    pub(crate) last_observation: Option<(usize, usize)>,
+    /// Latest propagation bans, for [`ContradictionInfo`](crate::ContradictionInfo).
+    pub(crate) ban_log: BanLog,
+    /// Every `(cell, pattern)` banned since the observers were last told;
+    /// `None` without observers.
+    pub(crate) ban_trace: Option<Vec<(usize, usize)>>,
+    /// Observations made, including ones later undone by backtracking.
+    pub(crate) steps: usize,
+    /// Wall time spent in propagation since the last reset.
+    pub(crate) propagation_time: Duration,
+    /// Seed the RNG was created from (drawn from the OS if unset in config).
+    pub(crate) seed: u64,
+    pub(crate) rng: SplitMix64,
 }
 
 impl State {
+    /// Heap bytes of the per-cell counts, sums and propagation queues.
+    /// Heap bytes of a copy: the wave, support counts and sums.
+    pub(crate) fn heap_bytes(&self) -> usize {
+        self.wave.heap_bytes() + vec_bytes(&self.compat) + self.sums_bytes()
+    }
+
+    pub(crate) fn sums_bytes(&self) -> usize {
+        vec_bytes(&self.num_possible)
+            + vec_bytes(&self.small)
+            + vec_bytes(&self.placed)
+            + vec_bytes(&self.weight_sum)
+            + vec_bytes(&self.wlog_sum)
+            + vec_bytes(&self.stack)
+            + vec_bytes(&self.dirty_cells)
+            + vec_bytes(&self.dirty)
+            + vec_bytes(&self.noise)
+            + self.queue.heap_bytes()
+    }
+
     pub fn new(rules: &Rules) -> Self {
         let num_patterns = rules.num_patterns();
         let wave_size = rules.grid.size();
 
-        let total_weight: f64 = rules.weight_table.iter().map(|(w, _)| w).sum();
-        let wlog: f64 = rules.weight_table.iter().map(|(w, lw)| w * lw).sum();
+        let sums = rules.starting_sums();
 
-        let rng = match rules.config.seed {
-            Some(seed) => SmallRng::seed_from_u64(seed),
-            None => SmallRng::from_os_rng(),
-        };
+        let seed = rules.config.seed.unwrap_or_else(rand::random);
+        let mut rng = SplitMix64::new(seed);
+        let amplitude = rules.config.entropy_noise;
+        let noise = (0..wave_size).map(|_| rng.next_f64() * amplitude).collect();
 
-        let block = num_patterns * 4;
+        let num_dirs = rules.num_dirs();
+        let block = num_patterns * num_dirs;
         let mut compat = vec![0u16; wave_size * block];
         for cell in 0..wave_size {
             let start = cell * block;
@@ -45,13 +106,30 @@ impl State {
             wave: Bitset::new(wave_size, num_patterns),
             compat,
             num_patterns,
+            num_dirs,
             num_possible: vec![num_patterns; wave_size],
-            weight_sum: vec![total_weight; wave_size],
-            wlog_sum: vec![wlog; wave_size],
+            small: vec![SmallSet::collect(0..num_patterns); wave_size],
+            placed: vec![0; rules.frequency.as_ref().map_or(0, |f| f.num_colors())],
+            decided: 0,
+            weight_sum: sums.iter().map(|&(sum, _)| sum).collect(),
+            wlog_sum: sums.iter().map(|&(_, wlog)| wlog).collect(),
             stack: Vec::new(),
+            dirty_cells: Vec::new(),
+            dirty: vec![false; wave_size],
+            queue: EntropyQueue::default(),
+            use_queue: rules.config.heuristic == Heuristic::MinEntropy,
+            scan: 0,
+            queued: 0,
+            noise,
             contradiction: false,
             done: false,
             last_collapsed: None,
+            last_observation: None,
+            ban_log: BanLog::default(),
+            ban_trace: None,
+            steps: 0,
+            propagation_time: Duration::ZERO,
+            seed,
             rng,
         };
 
@@ -63,16 +141,126 @@ impl State {
                 }
             }
         }
+        // Cells outside the mask hold one pattern so every heuristic and
+        // count treats them as decided; nothing links to them
+        if let Some(keep) = rules.viable.iter().position(|&v| v) {
+            for cell in (0..wave_size).filter(|&c| !rules.in_mask(c)) {
+                for p in 0..num_patterns {
+                    if p != keep {
+                        state.ban(cell, p, rules);
+                    }
+                }
+            }
+        }
         // Clear stack -- these bans don't need propagation since all
-        // non-viable patterns are removed uniformly
-        state.stack.clear();
+        // non-viable patterns are removed uniformly, unless mirroring
+        // banned their reflections along with them
+        if rules.mirrors.is_empty() {
+            state.stack.clear();
+        }
+        state.clear_dirty();
+        state.restart_observation();
 
         state
     }
 
+    pub(crate) fn clear_dirty(&mut self) {
+        self.flush_queue();
+        for &cell in &self.dirty_cells {
+            self.dirty[cell] = false;
+        }
+        self.dirty_cells.clear();
+        self.queued = 0;
+    }
+
+    pub(crate) fn entropy(&self, cell: usize) -> f64 {
+        let sum = self.weight_sum[cell];
+        if sum <= 0.0 {
+            return 0.0;
+        }
+        sum.ln() - self.wlog_sum[cell] / sum
+    }
+
+    /// Priority of `cell` in `queue`: its entropy plus its tie-breaking noise.
+    #[inline]
+    pub(crate) fn entropy_key(&self, cell: usize) -> f64 {
+        self.entropy(cell) + self.noise[cell]
+    }
+
+    /// Queue the cells narrowed since the last flush. Collapsed cells are
+    /// left out; contradicted ones go in so observation finds them.
+    pub(crate) fn flush_queue(&mut self) {
+        if !self.use_queue {
+            return;
+        }
+        for i in self.queued..self.dirty_cells.len() {
+            let cell = self.dirty_cells[i];
+            if self.num_possible[cell] != 1 {
+                self.queue.push(cell, self.entropy_key(cell));
+            }
+        }
+        self.queued = self.dirty_cells.len();
+        // Stale entries pile up on long runs; start over once they dominate
+        if self.queue.len() > 4 * self.num_possible.len() + 64 {
+            self.restart_observation();
+        }
+    }
+
+    /// Forget where observation was, e.g. after the wave was replaced:
+    /// requeue every undecided cell and rescan from the first.
+    pub(crate) fn restart_observation(&mut self) {
+        self.scan = 0;
+        self.queue.clear();
+        if !self.use_queue {
+            return;
+        }
+        for cell in 0..self.num_possible.len() {
+            if self.num_possible[cell] != 1 {
+                self.queue.push(cell, self.entropy_key(cell));
+            }
+        }
+        self.queued = self.dirty_cells.len();
+    }
+
+    #[cold]
+    fn update_small(&mut self, cell: usize, banned: usize) {
+        if self.num_possible[cell] == SMALL_MAX {
+            self.small[cell] = SmallSet::collect(self.wave.iter_set(cell));
+        } else {
+            self.small[cell].remove(banned);
+        }
+    }
+
+    /// Patterns still possible at `cell`, ascending.
+    #[inline]
+    pub(crate) fn candidates(&self, cell: usize) -> Candidates<'_> {
+        if self.num_possible[cell] <= SMALL_MAX {
+            Candidates::Small(self.small[cell].as_slice().iter())
+        } else {
+            Candidates::Dense(self.wave.iter_set(cell))
+        }
+    }
+
+    /// How many of `allowed` (ascending) are still possible at `cell`.
+    #[inline]
+    pub(crate) fn count_live(&self, cell: usize, allowed: &[u16]) -> usize {
+        if self.num_possible[cell] <= SMALL_MAX {
+            let small = self.small[cell].as_slice();
+            small
+                .iter()
+                .filter(|p| allowed.binary_search(p).is_ok())
+                .count()
+        } else {
+            allowed
+                .iter()
+                .filter(|&&p| self.wave.is_set(cell, p as usize))
+                .count()
+        }
+    }
+
     #[inline]
     pub(crate) fn compat_index(&self, cell: usize, pattern: usize, dir: usize) -> usize {
-        (cell * self.num_patterns + pattern) * 4 + dir
+        (cell * self.num_patterns + pattern) * self.num_dirs + dir
     }
 
     #[inline(always)]
@@ -82,9 +270,72 @@ impl State {
         }
         self.wave.clear(cell, pattern);
         self.num_possible[cell] -= 1;
-        let (w, lw) = rules.weight_table[pattern];
+        if self.num_possible[cell] <= SMALL_MAX {
+            self.update_small(cell, pattern);
+            if self.num_possible[cell] <= 1 && !self.placed.is_empty() {
+                self.count_placed(cell, pattern, rules);
+            }
+        }
+        let (w, lw) = rules.cell_weight(cell, pattern);
         self.weight_sum[cell] -= w;
         self.wlog_sum[cell] -= w * lw;
         self.stack.push((cell, pattern));
+        if let Some(trace) = &mut self.ban_trace {
+            trace.push((cell, pattern));
+        }
+        if !self.dirty[cell] {
+            self.dirty[cell] = true;
+            self.dirty_cells.push(cell);
+        }
+        if !rules.mirrors.is_empty() {
+            self.ban_mirrored(cell, pattern, rules);
+        }
+    }
+
+    /// Count `cell` for its color once decided, or take back `banned`'s
+    /// count if that was its last pattern.
+    #[inline(never)]
+    fn count_placed(&mut self, cell: usize, banned: usize, rules: &Rules) {
+        let Some(target) = &rules.frequency else {
+            return;
+        };
+        if !rules.in_mask(cell) {
+            return;
+        }
+        if let [kept] = self.small[cell].as_slice() {
+            self.placed[target.class[*kept as usize] as usize] += 1;
+            self.decided += 1;
+        } else {
+            self.placed[target.class[banned] as usize] -= 1;
+            self.decided -= 1;
+        }
+    }
+
+    /// Ban the mirror image of a ban under `Config::output_symmetry` or
+    /// `Boundary::Mirror`.
+    #[inline(never)]
+    fn ban_mirrored(&mut self, cell: usize, pattern: usize, rules: &Rules) {
+        for link in &rules.mirrors {
+            let (other, reflected) = (link.cells[cell], link.patterns[pattern]);
+            if other != MirrorLink::NONE && reflected != MirrorLink::NONE {
+                self.ban(other as usize, reflected as usize, rules);
+            }
+        }
+    }
+}
+
+impl Supports<Rules> for State {
+    #[inline(always)]
+    fn withdraw(&mut self, cell: usize, pattern: usize, dir: usize) -> u16 {
+        let ci = self.compat_index(cell, pattern, dir);
+        self.compat[ci] -= 1;
+        self.compat[ci]
+    }
+
+    #[inline(always)]
+    fn unsupported(&mut self, rules: &Rules, cell: usize, pattern: usize, from: usize) -> bool {
+        self.ban(cell, pattern, rules);
+        self.ban_log.push(cell, pattern, Some(from));
+        self.num_possible[cell] == 0
     }
 }
@@ -0,0 +1,79 @@
+/// Cells, directions and compatibility lists a support-counting (AC-4)
+/// solver propagates over; the 2D rules and the voxel rules both are.
+pub(crate) trait Adjacency {
+    fn num_dirs(&self) -> usize;
+    fn opposite(&self, dir: usize) -> usize;
+    fn neighbor(&self, cell: usize, dir: usize) -> Option<usize>;
+    /// Patterns allowed next to `pattern` in `dir`.
+    fn compatible(&self, pattern: usize, dir: usize) -> &[u16];
+}
+
+/// Per-(cell, pattern, direction) support counts and the wave they guard.
+pub(crate) trait Supports<R: Adjacency> {
+    /// Take one supporter from `pattern` at `cell` in `dir`, returning how
+    /// many are left.
+    fn withdraw(&mut self, cell: usize, pattern: usize, dir: usize) -> u16;
+    /// Ban `pattern` at `cell`, which lost its last supporter from `from`.
+    /// Returns whether that left the cell empty.
+    fn unsupported(&mut self, rules: &R, cell: usize, pattern: usize, from: usize) -> bool;
+}
+
+/// Withdraw `banned`'s support from the neighbors of `cell`, banning
+/// whatever runs out. Returns whether a neighbor was left empty, in which
+/// case the rest of the withdrawal is skipped.
+#[inline(always)]
+pub(crate) fn withdraw_support<R: Adjacency, S: Supports<R>>(
+    rules: &R,
+    state: &mut S,
+    cell: usize,
+    banned: usize,
+) -> bool {
+    for dir in 0..rules.num_dirs() {
+        let Some(neighbor) = rules.neighbor(cell, dir) else {
+            continue;
+        };
+        let opp = rules.opposite(dir);
+        for &other in rules.compatible(banned, dir) {
+            let other = other as usize;
+            if state.withdraw(neighbor, other, opp) == 0
+                && state.unsupported(rules, neighbor, other, cell)
+            {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Patterns that can ever be placed. `base_compat[t * num_dirs + d]`
+/// counts the patterns allowing `t` from direction `d`; those in `dead`
+/// and those with none in some direction can't be placed, and retracting
+/// their support may strand others, so this prunes to a fixpoint. Only the
+/// pruned patterns' lists are read, which keeps a lazy propagator lazy.
+pub(crate) fn prune_unplaceable<'a>(
+    base_compat: &mut [u16],
+    opposite: &[usize],
+    compatible: impl Fn(usize, usize) -> &'a [u16],
+    mut dead: Vec<usize>,
+) -> Vec<bool> {
+    let num_dirs = opposite.len();
+    let num_patterns = base_compat.len() / num_dirs;
+    dead.extend(
+        (0..num_patterns).filter(|&p| base_compat[p * num_dirs..(p + 1) * num_dirs].contains(&0)),
+    );
+    let mut viable = vec![true; num_patterns];
+    dead.retain(|&p| std::mem::replace(&mut viable[p], false));
+    while let Some(p) = dead.pop() {
+        for (dir, &opp) in opposite.iter().enumerate() {
+            for &t in compatible(p, dir) {
+                let t = t as usize;
+                base_compat[t * num_dirs + opp] -= 1;
+                if viable[t] && base_compat[t * num_dirs + opp] == 0 {
+                    viable[t] = false;
+                    dead.push(t);
+                }
+            }
+        }
+    }
+    viable
+}
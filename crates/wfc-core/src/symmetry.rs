@@ -0,0 +1,193 @@
+use std::fmt;
+use std::ops::{BitAnd, BitOr};
+use std::str::FromStr;
+
+use crate::Error;
+
+/// Set of transforms applied to each sample pattern to generate variants.
+/// The untransformed pattern is always kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symmetry(u8);
+
+impl Symmetry {
+    pub const NONE: Self = Self(0);
+    /// Rotate 90 degrees clockwise.
+    pub const ROTATE_90: Self = Self(1);
+    pub const ROTATE_180: Self = Self(1 << 1);
+    pub const ROTATE_270: Self = Self(1 << 2);
+    /// Mirror left-right; keeps "up" up.
+    pub const FLIP_X: Self = Self(1 << 3);
+    /// Mirror top-bottom (upside down).
+    pub const FLIP_Y: Self = Self(1 << 4);
+    /// Mirror across the main diagonal.
+    pub const TRANSPOSE: Self = Self(1 << 5);
+    /// Mirror across the anti-diagonal.
+    pub const ANTI_TRANSPOSE: Self = Self(1 << 6);
+
+    pub const ROTATIONS: Self = Self(Self::ROTATE_90.0 | Self::ROTATE_180.0 | Self::ROTATE_270.0);
+    pub const REFLECTIONS: Self =
+        Self(Self::FLIP_X.0 | Self::FLIP_Y.0 | Self::TRANSPOSE.0 | Self::ANTI_TRANSPOSE.0);
+    /// All 8 rotations and reflections.
+    pub const ALL: Self = Self(Self::ROTATIONS.0 | Self::REFLECTIONS.0);
+
+    const NAMES: [(&'static str, Self); 11] = [
+        ("none", Self::NONE),
+        ("all", Self::ALL),
+        ("rotations", Self::ROTATIONS),
+        ("reflections", Self::REFLECTIONS),
+        ("rot90", Self::ROTATE_90),
+        ("rot180", Self::ROTATE_180),
+        ("rot270", Self::ROTATE_270),
+        ("flip-x", Self::FLIP_X),
+        ("flip-y", Self::FLIP_Y),
+        ("transpose", Self::TRANSPOSE),
+        ("anti-transpose", Self::ANTI_TRANSPOSE),
+    ];
+
+    #[must_use]
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    #[must_use]
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Number of transforms in the set.
+    #[must_use]
+    pub fn len(self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    /// Raw bits, stable across versions (used in cache keys).
+    #[must_use]
+    pub fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Inverse of [`bits`](Self::bits); unknown bits are dropped.
+    pub(crate) fn from_bits(bits: u8) -> Self {
+        Self(bits & Self::ALL.0)
+    }
+
+    /// Toggle `other` on or off.
+    pub fn set(&mut self, other: Self, on: bool) {
+        if on {
+            self.0 |= other.0;
+        } else {
+            self.0 &= !other.0;
+        }
+    }
+}
+
+impl Default for Symmetry {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+impl BitOr for Symmetry {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitAnd for Symmetry {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self {
+        Self(self.0 & rhs.0)
+    }
+}
+
+/// Inverse of `FromStr`: a group name when one matches exactly, otherwise
+/// the individual transforms.
+impl fmt::Display for Symmetry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (groups, singles) = Self::NAMES.split_at(4);
+        if let Some((name, _)) = groups.iter().find(|&&(_, sym)| sym == *self) {
+            return f.write_str(name);
+        }
+        let names: Vec<&str> = singles
+            .iter()
+            .filter(|&&(_, sym)| self.contains(sym))
+            .map(|&(name, _)| name)
+            .collect();
+        f.write_str(&names.join(","))
+    }
+}
+
+/// Comma-separated names, e.g. `flip-x,rot180`. Groups: `none`, `all`,
+/// `rotations`, `reflections`.
+impl FromStr for Symmetry {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        s.split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .try_fold(Self::NONE, |acc, name| {
+                Self::NAMES
+                    .iter()
+                    .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                    .map(|&(_, sym)| acc | sym)
+                    .ok_or_else(|| Error::InvalidSymmetry(name.to_string()))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Pattern;
+
+    #[test]
+    fn parses_names_and_groups() {
+        let sym: Symmetry = "flip-x, rot180".parse().unwrap();
+        assert!(sym.contains(Symmetry::FLIP_X | Symmetry::ROTATE_180));
+        assert!(!sym.contains(Symmetry::FLIP_Y));
+        assert_eq!("all".parse::<Symmetry>().unwrap(), Symmetry::ALL);
+        assert_eq!("".parse::<Symmetry>().unwrap(), Symmetry::NONE);
+        assert!("upside-down".parse::<Symmetry>().is_err());
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for sym in [
+            Symmetry::NONE,
+            Symmetry::ALL,
+            Symmetry::ROTATIONS,
+            Symmetry::FLIP_X | Symmetry::ROTATE_180,
+        ] {
+            assert_eq!(sym.to_string().parse::<Symmetry>().unwrap(), sym);
+        }
+        assert_eq!(
+            (Symmetry::FLIP_X | Symmetry::FLIP_Y).to_string(),
+            "flip-x,flip-y"
+        );
+    }
+
+    #[test]
+    fn horizontal_flip_never_turns_patterns_upside_down() {
+        let (a, b, c, d) = ([1, 0, 0], [2, 0, 0], [3, 0, 0], [4, 0, 0]);
+        let pattern = Pattern::new(2, vec![a, b, c, d]);
+        assert_eq!(pattern.variants(Symmetry::ALL).len(), 8);
+        assert_eq!(pattern.variants(Symmetry::NONE), vec![pattern.clone()]);
+
+        let mirrored = pattern.variants(Symmetry::FLIP_X);
+        assert_eq!(mirrored.len(), 2);
+        assert!(mirrored.contains(&Pattern::new(2, vec![b, a, d, c])));
+        // Top row stays on top
+        assert!(
+            mirrored
+                .iter()
+                .all(|p| p.get(0, 1) == c || p.get(0, 1) == d)
+        );
+
+        let upside_down = pattern.variants(Symmetry::FLIP_Y);
+        assert!(upside_down.contains(&Pattern::new(2, vec![c, d, a, b])));
+    }
+}
@@ -0,0 +1,971 @@
+//! Overlapping model in three dimensions, for voxel models. Library only:
+//! samples come from a MagicaVoxel `.vox` file or a stack of PNG slices, and
+//! results are written back as `.vox`.
+//!
+//! Coordinates are `(x, y, z)` with `z` the slice index; in `.vox` files `z`
+//! points up. Symmetry transforms act within each slice (turning the model
+//! about its vertical axis) and never flip it upside down.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::bitset::Bitset;
+use crate::rng::SplitMix64;
+use crate::support::{Adjacency, Supports, prune_unplaceable, withdraw_support};
+use crate::{Boundary, Color, Config, Error, RunOutcome, StepOutcome, Symmetry};
+
+/// Voxel grid with a shared palette. A voxel of `0` is empty; `i > 0` has
+/// color `palette[i - 1]`, as in `.vox` files.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VoxelSample {
+    pub width: usize,
+    pub height: usize,
+    pub depth: usize,
+    /// Up to 255 colors.
+    pub palette: Vec<Color>,
+    /// `voxels[(z * height + y) * width + x]`
+    pub voxels: Vec<u8>,
+}
+
+impl VoxelSample {
+    pub fn new(
+        width: usize,
+        height: usize,
+        depth: usize,
+        palette: Vec<Color>,
+        voxels: Vec<u8>,
+    ) -> Self {
+        assert_eq!(voxels.len(), width * height * depth);
+        assert!(palette.len() <= 255, "palette holds at most 255 colors");
+        Self {
+            width,
+            height,
+            depth,
+            palette,
+            voxels,
+        }
+    }
+
+    #[must_use]
+    pub fn get(&self, x: usize, y: usize, z: usize) -> u8 {
+        self.voxels[(z * self.height + y) * self.width + x]
+    }
+
+    /// Color of a voxel, `None` if empty.
+    #[must_use]
+    pub fn color(&self, x: usize, y: usize, z: usize) -> Option<Color> {
+        match self.get(x, y, z) {
+            0 => None,
+            i => self.palette.get(i as usize - 1).copied(),
+        }
+    }
+
+    /// Stack equally sized images bottom to top, one per slice. Fully
+    /// transparent pixels are empty voxels; at most 255 distinct colors.
+    #[cfg(feature = "image-io")]
+    pub fn from_slices(paths: &[impl AsRef<Path>]) -> Result<Self, Error> {
+        let mut palette: Vec<Color> = Vec::new();
+        let mut voxels = Vec::new();
+        let mut size = None;
+        for path in paths {
+            let path = path.as_ref();
+            let img = image::open(path)
+                .map_err(|e| Error::ImageLoad(format!("{}: {}", path.display(), e)))?
+                .to_rgba8();
+            let dims = (img.width() as usize, img.height() as usize);
+            if *size.get_or_insert(dims) != dims {
+                let expected = size.unwrap_or(dims);
+                return Err(Error::LayerSizeMismatch {
+                    expected,
+                    found: dims,
+                });
+            }
+            for p in img.pixels() {
+                if p[3] == 0 {
+                    voxels.push(0);
+                    continue;
+                }
+                let color = [p[0], p[1], p[2]];
+                let index = match palette.iter().position(|&c| c == color) {
+                    Some(i) => i,
+                    None if palette.len() < 255 => {
+                        palette.push(color);
+                        palette.len() - 1
+                    }
+                    None => {
+                        return Err(Error::ImageLoad(
+                            "slices use more than 255 colors".to_string(),
+                        ));
+                    }
+                };
+                voxels.push(index as u8 + 1);
+            }
+        }
+        let (width, height) = size.ok_or(Error::NoLayers)?;
+        Ok(Self::new(width, height, paths.len(), palette, voxels))
+    }
+
+    pub fn load_vox(path: &Path) -> Result<Self, Error> {
+        let bytes = std::fs::read(path)
+            .map_err(|e| Error::ImageLoad(format!("{}: {}", path.display(), e)))?;
+        Self::from_vox_bytes(&bytes)
+    }
+
+    /// Parse a MagicaVoxel file, keeping its first model. Files without a
+    /// palette chunk (which MagicaVoxel always writes) are rejected.
+    pub fn from_vox_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let invalid = |msg: &str| Error::ImageLoad(format!("vox: {}", msg));
+        let u32_at = |at: usize| -> Result<usize, Error> {
+            bytes
+                .get(at..at + 4)
+                .map(|b| u32::from_le_bytes(b.try_into().unwrap()) as usize)
+                .ok_or_else(|| invalid("truncated"))
+        };
+        if bytes.get(..4) != Some(b"VOX ") {
+            return Err(invalid("missing 'VOX ' header"));
+        }
+
+        let mut size = None;
+        let mut points: Option<&[u8]> = None;
+        let mut rgba: Option<&[u8]> = None;
+        // MAIN holds everything as children, so every chunk can be read in
+        // sequence: skip its header and walk the rest flat
+        let mut at = 8 + 12;
+        while at + 12 <= bytes.len() {
+            let id = &bytes[at..at + 4];
+            let len = u32_at(at + 4)?;
+            let content = bytes
+                .get(at + 12..at + 12 + len)
+                .ok_or_else(|| invalid("truncated chunk"))?;
+            match id {
+                b"SIZE" if size.is_none() => {
+                    size = Some((u32_at(at + 12)?, u32_at(at + 16)?, u32_at(at + 20)?));
+                }
+                b"XYZI" if points.is_none() => {
+                    let count = u32_at(at + 12)?;
+                    points = Some(
+                        content
+                            .get(4..4 + count * 4)
+                            .ok_or_else(|| invalid("truncated voxels"))?,
+                    );
+                }
+                b"RGBA" => rgba = Some(content),
+                _ => {}
+            }
+            at += 12 + len;
+        }
+
+        let (width, height, depth) = size.ok_or_else(|| invalid("no SIZE chunk"))?;
+        if width.max(height).max(depth) > 256 {
+            return Err(invalid("model is larger than 256 per side"));
+        }
+        let points = points.ok_or_else(|| invalid("no XYZI chunk"))?;
+        let rgba = rgba
+            .filter(|c| c.len() >= 1024)
+            .ok_or_else(|| invalid("no palette"))?;
+        // Entry 256 is unused: indices run 1..=255
+        let palette = rgba
+            .chunks_exact(4)
+            .take(255)
+            .map(|c| [c[0], c[1], c[2]])
+            .collect();
+
+        let mut voxels = vec![0; width * height * depth];
+        for p in points.chunks_exact(4) {
+            let (x, y, z) = (p[0] as usize, p[1] as usize, p[2] as usize);
+            if x >= width || y >= height || z >= depth {
+                return Err(invalid("voxel outside the model"));
+            }
+            voxels[(z * height + y) * width + x] = p[3];
+        }
+        Ok(Self::new(width, height, depth, palette, voxels))
+    }
+
+    pub fn save_vox(&self, path: &Path) -> Result<(), Error> {
+        let bytes = self.to_vox_bytes()?;
+        std::fs::write(path, bytes).map_err(|e| Error::ImageSave(e.to_string()))
+    }
+
+    /// Encode as a single-model MagicaVoxel file; each side must be at most 256.
+    pub fn to_vox_bytes(&self) -> Result<Vec<u8>, Error> {
+        if self.width.max(self.height).max(self.depth) > 256 {
+            return Err(Error::ImageSave(format!(
+                "vox models are at most 256 per side, not {}x{}x{}",
+                self.width, self.height, self.depth
+            )));
+        }
+        fn chunk(out: &mut Vec<u8>, id: &[u8; 4], content: &[u8]) {
+            out.extend_from_slice(id);
+            out.extend_from_slice(&(content.len() as u32).to_le_bytes());
+            out.extend_from_slice(&0u32.to_le_bytes());
+            out.extend_from_slice(content);
+        }
+
+        let mut size = Vec::with_capacity(12);
+        for side in [self.width, self.height, self.depth] {
+            size.extend_from_slice(&(side as u32).to_le_bytes());
+        }
+        let mut points = Vec::new();
+        let mut count = 0u32;
+        for z in 0..self.depth {
+            for y in 0..self.height {
+                for x in 0..self.width {
+                    let v = self.get(x, y, z);
+                    if v != 0 {
+                        points.extend_from_slice(&[x as u8, y as u8, z as u8, v]);
+                        count += 1;
+                    }
+                }
+            }
+        }
+        let mut xyzi = count.to_le_bytes().to_vec();
+        xyzi.extend_from_slice(&points);
+        let mut rgba = vec![0u8; 1024];
+        for (entry, color) in rgba.chunks_exact_mut(4).zip(&self.palette) {
+            entry[..3].copy_from_slice(color);
+            entry[3] = 255;
+        }
+
+        let mut children = Vec::new();
+        chunk(&mut children, b"SIZE", &size);
+        chunk(&mut children, b"XYZI", &xyzi);
+        chunk(&mut children, b"RGBA", &rgba);
+        let mut out = b"VOX ".to_vec();
+        out.extend_from_slice(&150u32.to_le_bytes());
+        out.extend_from_slice(b"MAIN");
+        out.extend_from_slice(&0u32.to_le_bytes());
+        out.extend_from_slice(&(children.len() as u32).to_le_bytes());
+        out.extend_from_slice(&children);
+        Ok(out)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct VoxelConfig {
+    /// N in NxNxN pattern extraction.
+    pub pattern_size: usize,
+    pub output_width: usize,
+    pub output_height: usize,
+    pub output_depth: usize,
+    /// Wrap sample scanning around every side.
+    pub periodic_input: bool,
+    /// Wrapping within slices; slices themselves never wrap.
+    pub boundary: Boundary,
+    /// Transforms applied within each slice.
+    pub symmetry: Symmetry,
+    /// RNG seed; `None` draws one from the OS.
+    pub seed: Option<u64>,
+    /// Random offset added to entropies to break ties, as
+    /// `Config::entropy_noise`.
+    pub entropy_noise: f64,
+}
+
+impl Default for VoxelConfig {
+    fn default() -> Self {
+        Self {
+            pattern_size: 2,
+            output_width: 16,
+            output_height: 16,
+            output_depth: 16,
+            periodic_input: true,
+            boundary: Boundary::Fixed,
+            symmetry: Symmetry::ROTATIONS,
+            seed: None,
+            entropy_noise: 1e-6,
+        }
+    }
+}
+
+impl VoxelConfig {
+    /// Largest supported `pattern_size`; N^3 patterns explode quickly.
+    pub const MAX_PATTERN_SIZE: usize = 3;
+
+    pub fn validate(&self, sample: &VoxelSample) -> Result<(), Error> {
+        let invalid = |msg: String| Err(Error::InvalidConfig(msg));
+        let n = self.pattern_size;
+        if !(1..=Self::MAX_PATTERN_SIZE).contains(&n) {
+            return invalid(format!(
+                "voxel pattern size {} is outside 1..={}",
+                n,
+                Self::MAX_PATTERN_SIZE
+            ));
+        }
+        if self.output_width == 0 || self.output_height == 0 || self.output_depth == 0 {
+            return invalid("output size must be non-zero".to_string());
+        }
+        let smallest = sample.width.min(sample.height).min(sample.depth);
+        if smallest == 0 {
+            return invalid("sample is empty".to_string());
+        }
+        if !self.periodic_input && smallest < n {
+            return invalid(format!(
+                "sample {}x{}x{} is smaller than the pattern size {}",
+                sample.width, sample.height, sample.depth, n
+            ));
+        }
+        if self.boundary.extends() {
+            return invalid("voxel boundaries can't mirror or clamp".to_string());
+        }
+        if !(self.entropy_noise >= 0.0 && self.entropy_noise.is_finite()) {
+            return invalid("entropy noise must be non-negative".to_string());
+        }
+        let found = extract(sample, self).0.len();
+        if found > Config::MAX_PATTERNS {
+            return invalid(format!(
+                "{} voxel patterns, more than the {} supported; try a smaller pattern \
+                 size, less symmetry or a smaller sample",
+                found,
+                Config::MAX_PATTERNS
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Where the voxel at `(x, y)` of an NxN slice lands under `transform`,
+/// matching [`Pattern::transform`](crate::Pattern::transform).
+fn transform_xy(transform: Symmetry, x: usize, y: usize, n: usize) -> (usize, usize) {
+    let m = n - 1;
+    match transform {
+        Symmetry::ROTATE_90 => (m - y, x),
+        Symmetry::ROTATE_180 => (m - x, m - y),
+        Symmetry::ROTATE_270 => (y, m - x),
+        Symmetry::FLIP_X => (m - x, y),
+        Symmetry::FLIP_Y => (x, m - y),
+        Symmetry::TRANSPOSE => (y, x),
+        Symmetry::ANTI_TRANSPOSE => (m - y, m - x),
+        _ => (x, y),
+    }
+}
+
+/// Face neighbor of a voxel, in the order [`VoxelWfc`] indexes them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Face {
+    Right = 0,
+    Down = 1,
+    Left = 2,
+    Up = 3,
+    /// Next slice (+z).
+    Forward = 4,
+    /// Previous slice (-z).
+    Back = 5,
+}
+
+impl Face {
+    pub const ALL: [Face; DIRS] = [
+        Face::Right,
+        Face::Down,
+        Face::Left,
+        Face::Up,
+        Face::Forward,
+        Face::Back,
+    ];
+
+    #[must_use]
+    pub fn opposite(self) -> Self {
+        Self::ALL[OPPOSITE[self as usize]]
+    }
+
+    fn offset(self) -> (i32, i32, i32) {
+        match self {
+            Face::Right => (1, 0, 0),
+            Face::Down => (0, 1, 0),
+            Face::Left => (-1, 0, 0),
+            Face::Up => (0, -1, 0),
+            Face::Forward => (0, 0, 1),
+            Face::Back => (0, 0, -1),
+        }
+    }
+}
+
+/// Patterns, their compatibility and the output's neighbor table; fixed
+/// for the life of a [`VoxelWfc`].
+struct VoxelRules {
+    palette: Vec<Color>,
+    /// NxNxN voxels per pattern, `(z * n + y) * n + x`.
+    patterns: Vec<Box<[u8]>>,
+    /// `(weight, log_weight)` per pattern.
+    weights: Vec<(f64, f64)>,
+    /// `propagator[pattern * 6 + dir]`: patterns allowed at that neighbor.
+    propagator: Vec<Vec<u16>>,
+    /// `base_compat[pattern * 6 + dir]`, after pruning unplaceable patterns.
+    base_compat: Vec<u16>,
+    viable: Vec<bool>,
+    /// `neighbors[cell * 6 + dir]`, `u32::MAX` past a fixed edge.
+    neighbors: Vec<u32>,
+}
+
+impl Adjacency for VoxelRules {
+    #[inline]
+    fn num_dirs(&self) -> usize {
+        DIRS
+    }
+
+    #[inline]
+    fn opposite(&self, dir: usize) -> usize {
+        OPPOSITE[dir]
+    }
+
+    #[inline]
+    fn neighbor(&self, cell: usize, dir: usize) -> Option<usize> {
+        let n = self.neighbors[cell * DIRS + dir];
+        (n != u32::MAX).then_some(n as usize)
+    }
+
+    #[inline]
+    fn compatible(&self, pattern: usize, dir: usize) -> &[u16] {
+        &self.propagator[pattern * DIRS + dir]
+    }
+}
+
+/// The wave and its support counts, reset for every run.
+struct VoxelState {
+    num_patterns: usize,
+    wave: Bitset,
+    compat: Vec<u16>,
+    num_possible: Vec<usize>,
+    weight_sum: Vec<f64>,
+    wlog_sum: Vec<f64>,
+    stack: Vec<(usize, usize)>,
+}
+
+impl VoxelState {
+    fn ban(&mut self, cell: usize, pattern: usize, rules: &VoxelRules) {
+        if !self.wave.is_set(cell, pattern) {
+            return;
+        }
+        self.wave.clear(cell, pattern);
+        self.num_possible[cell] -= 1;
+        let (w, lw) = rules.weights[pattern];
+        self.weight_sum[cell] -= w;
+        self.wlog_sum[cell] -= w * lw;
+        self.stack.push((cell, pattern));
+    }
+}
+
+impl Supports<VoxelRules> for VoxelState {
+    #[inline]
+    fn withdraw(&mut self, cell: usize, pattern: usize, dir: usize) -> u16 {
+        let ci = (cell * self.num_patterns + pattern) * DIRS + dir;
+        self.compat[ci] -= 1;
+        self.compat[ci]
+    }
+
+    #[inline]
+    fn unsupported(&mut self, rules: &VoxelRules, cell: usize, pattern: usize, _: usize) -> bool {
+        self.ban(cell, pattern, rules);
+        self.num_possible[cell] == 0
+    }
+}
+
+/// Voxel solver: the overlapping model with support counts over the six
+/// face neighbors. No backtracking or constraints; reseed on contradiction.
+pub struct VoxelWfc {
+    config: VoxelConfig,
+    rules: VoxelRules,
+    state: VoxelState,
+    contradiction: bool,
+    done: bool,
+    seed: u64,
+    rng: SplitMix64,
+}
+
+const DIRS: usize = 6;
+const OPPOSITE: [usize; DIRS] = [2, 3, 0, 1, 5, 4];
+
+impl VoxelWfc {
+    /// Extract NxNxN patterns from `sample` and build the propagator. Check
+    /// `config` with [`VoxelConfig::validate`] first.
+    ///
+    /// # Panics
+    /// If the sample yields more than `Config::MAX_PATTERNS` patterns.
+    #[must_use]
+    pub fn new(sample: &VoxelSample, config: VoxelConfig) -> Self {
+        let (patterns, weights) = extract(sample, &config);
+        assert!(
+            patterns.len() <= Config::MAX_PATTERNS,
+            "{} voxel patterns, more than the {} supported",
+            patterns.len(),
+            Config::MAX_PATTERNS
+        );
+        let n = config.pattern_size;
+        let propagator = build_propagator(&patterns, n);
+
+        let mut base_compat: Vec<u16> = propagator.iter().map(|l| l.len() as u16).collect();
+        let viable = prune_unplaceable(
+            &mut base_compat,
+            &OPPOSITE,
+            |p, dir| &propagator[p * DIRS + dir],
+            Vec::new(),
+        );
+        for (p, _) in viable.iter().enumerate().filter(|(_, v)| !**v) {
+            base_compat[p * DIRS..(p + 1) * DIRS].fill(0);
+        }
+
+        let rules = VoxelRules {
+            palette: sample.palette.clone(),
+            weights: weights.iter().map(|&w| (w, w.ln())).collect(),
+            neighbors: neighbors(&config),
+            patterns,
+            propagator,
+            base_compat,
+            viable,
+        };
+        let mut wfc = Self {
+            state: VoxelState {
+                num_patterns: rules.patterns.len(),
+                wave: Bitset::new(0, 0),
+                compat: Vec::new(),
+                num_possible: Vec::new(),
+                weight_sum: Vec::new(),
+                wlog_sum: Vec::new(),
+                stack: Vec::new(),
+            },
+            rules,
+            contradiction: false,
+            done: false,
+            seed: 0,
+            rng: SplitMix64::new(0),
+            config,
+        };
+        wfc.reset();
+        wfc
+    }
+
+    fn size(&self) -> usize {
+        self.config.output_width * self.config.output_height * self.config.output_depth
+    }
+
+    fn cell(&self, x: usize, y: usize, z: usize) -> usize {
+        (z * self.config.output_height + y) * self.config.output_width + x
+    }
+
+    pub fn reset(&mut self) {
+        let size = self.size();
+        let np = self.rules.patterns.len();
+        let (sum, wlog) = self
+            .rules
+            .weights
+            .iter()
+            .fold((0.0, 0.0), |(s, wl), (w, lw)| (s + w, wl + w * lw));
+        self.seed = self.config.seed.unwrap_or_else(rand::random);
+        self.rng = SplitMix64::new(self.seed);
+        let state = &mut self.state;
+        state.wave = Bitset::new(size, np);
+        state.compat = self.rules.base_compat.repeat(size);
+        state.num_possible = vec![np; size];
+        state.weight_sum = vec![sum; size];
+        state.wlog_sum = vec![wlog; size];
+        state.stack.clear();
+        self.contradiction = false;
+        self.done = false;
+        // Uniform removal needs no propagation
+        for cell in 0..size {
+            for p in 0..np {
+                if !self.rules.viable[p] {
+                    state.ban(cell, p, &self.rules);
+                }
+            }
+        }
+        state.stack.clear();
+        if np == 0 || self.rules.viable.iter().all(|v| !v) {
+            self.contradiction = true;
+        }
+    }
+
+    /// Reset with a new RNG seed, reusing the extracted patterns.
+    pub fn reseed(&mut self, seed: u64) {
+        self.config.seed = Some(seed);
+        self.reset();
+    }
+
+    /// Seed of the current run; pass it back via `VoxelConfig::seed` to reproduce.
+    #[must_use]
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    #[must_use]
+    pub fn config(&self) -> &VoxelConfig {
+        &self.config
+    }
+
+    #[must_use]
+    pub fn num_patterns(&self) -> usize {
+        self.rules.patterns.len()
+    }
+
+    /// Patterns allowed next to `pattern` across `face`.
+    #[must_use]
+    pub fn compatible_patterns(&self, pattern: usize, face: Face) -> &[u16] {
+        self.rules.compatible(pattern, face as usize)
+    }
+
+    #[must_use]
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    #[must_use]
+    pub fn has_contradiction(&self) -> bool {
+        self.contradiction
+    }
+
+    /// Patterns still possible at a cell (1 once collapsed, 0 if contradicted).
+    #[must_use]
+    pub fn remaining_patterns(&self, x: usize, y: usize, z: usize) -> usize {
+        self.state.num_possible[self.cell(x, y, z)]
+    }
+
+    fn observe(&mut self) -> Option<usize> {
+        let state = &self.state;
+        let mut min_entropy = f64::MAX;
+        let mut min_cell = None;
+        for cell in 0..state.num_possible.len() {
+            match state.num_possible[cell] {
+                0 => {
+                    self.contradiction = true;
+                    return None;
+                }
+                1 => continue,
+                _ => {}
+            }
+            let sum = state.weight_sum[cell];
+            let entropy = sum.ln() - state.wlog_sum[cell] / sum
+                + self.rng.next_f64() * self.config.entropy_noise;
+            if entropy < min_entropy {
+                min_entropy = entropy;
+                min_cell = Some(cell);
+            }
+        }
+        min_cell
+    }
+
+    fn collapse(&mut self, cell: usize) {
+        let candidates: Vec<usize> = self.state.wave.iter_set(cell).collect();
+        let mut r = self.rng.next_f64() * self.state.weight_sum[cell];
+        let mut chosen = candidates[0];
+        for &p in &candidates {
+            chosen = p;
+            r -= self.rules.weights[p].0;
+            if r <= 0.0 {
+                break;
+            }
+        }
+        for p in candidates {
+            if p != chosen {
+                self.state.ban(cell, p, &self.rules);
+            }
+        }
+    }
+
+    fn propagate(&mut self) {
+        while let Some((cell, banned)) = self.state.stack.pop() {
+            if withdraw_support(&self.rules, &mut self.state, cell, banned) {
+                self.contradiction = true;
+                return;
+            }
+        }
+    }
+
+    pub fn step(&mut self) -> StepOutcome {
+        if self.done {
+            return StepOutcome::Complete;
+        }
+        if self.contradiction {
+            return StepOutcome::Contradiction;
+        }
+        match self.observe() {
+            None if self.contradiction => StepOutcome::Contradiction,
+            None => {
+                self.done = true;
+                StepOutcome::Complete
+            }
+            Some(cell) => {
+                self.collapse(cell);
+                self.propagate();
+                if self.contradiction {
+                    StepOutcome::Contradiction
+                } else {
+                    StepOutcome::Progressed
+                }
+            }
+        }
+    }
+
+    pub fn run(&mut self) -> RunOutcome {
+        loop {
+            match self.step() {
+                StepOutcome::Progressed => continue,
+                StepOutcome::Complete => return RunOutcome::Complete,
+                StepOutcome::Contradiction => return RunOutcome::Contradiction,
+            }
+        }
+    }
+
+    /// Collapsed cells take their pattern's corner voxel; undecided and
+    /// contradicted cells are left empty.
+    #[must_use]
+    pub fn render(&self) -> VoxelSample {
+        let voxels = (0..self.size())
+            .map(|cell| {
+                if self.state.num_possible[cell] == 1 {
+                    self.rules.patterns[self.state.wave.first_set(cell)][0]
+                } else {
+                    0
+                }
+            })
+            .collect();
+        VoxelSample::new(
+            self.config.output_width,
+            self.config.output_height,
+            self.config.output_depth,
+            self.rules.palette.clone(),
+            voxels,
+        )
+    }
+}
+
+/// Distinct NxNxN windows with their symmetry variants, sorted, and their
+/// occurrence counts.
+fn extract(sample: &VoxelSample, config: &VoxelConfig) -> (Vec<Box<[u8]>>, Vec<f64>) {
+    const TRANSFORMS: [Symmetry; 7] = [
+        Symmetry::ROTATE_90,
+        Symmetry::ROTATE_270,
+        Symmetry::ROTATE_180,
+        Symmetry::FLIP_Y,
+        Symmetry::ANTI_TRANSPOSE,
+        Symmetry::FLIP_X,
+        Symmetry::TRANSPOSE,
+    ];
+    let n = config.pattern_size;
+    let span = |side: usize| {
+        if config.periodic_input {
+            side
+        } else {
+            side.saturating_sub(n - 1)
+        }
+    };
+    let mut counts: HashMap<Box<[u8]>, f64> = HashMap::new();
+    for z in 0..span(sample.depth) {
+        for y in 0..span(sample.height) {
+            for x in 0..span(sample.width) {
+                let mut window = vec![0; n * n * n];
+                for dz in 0..n {
+                    for dy in 0..n {
+                        for dx in 0..n {
+                            window[(dz * n + dy) * n + dx] = sample.get(
+                                (x + dx) % sample.width,
+                                (y + dy) % sample.height,
+                                (z + dz) % sample.depth,
+                            );
+                        }
+                    }
+                }
+
+                let mut variants = vec![window.clone().into_boxed_slice()];
+                for transform in TRANSFORMS {
+                    if !config.symmetry.contains(transform) {
+                        continue;
+                    }
+                    let mut variant = vec![0; n * n * n];
+                    for dz in 0..n {
+                        for dy in 0..n {
+                            for dx in 0..n {
+                                let (tx, ty) = transform_xy(transform, dx, dy, n);
+                                variant[(dz * n + ty) * n + tx] = window[(dz * n + dy) * n + dx];
+                            }
+                        }
+                    }
+                    let variant = variant.into_boxed_slice();
+                    if !variants.contains(&variant) {
+                        variants.push(variant);
+                    }
+                }
+                for variant in variants {
+                    *counts.entry(variant).or_insert(0.0) += 1.0;
+                }
+            }
+        }
+    }
+    let mut pairs: Vec<_> = counts.into_iter().collect();
+    pairs.sort_by(|(a, _), (b, _)| a.cmp(b));
+    pairs.into_iter().unzip()
+}
+
+/// For each face direction, group patterns by the overlap they present to a
+/// neighbor there, then look each pattern's own side up: linear in the
+/// pattern count instead of comparing every pair.
+fn build_propagator(patterns: &[Box<[u8]>], n: usize) -> Vec<Vec<u16>> {
+    let overlap = |p: &[u8], (dx, dy, dz): (i32, i32, i32), shift: bool| -> Vec<u8> {
+        let range = |d: i32| {
+            let (lo, hi) = (d.max(0), n as i32 + d.min(0));
+            if shift { lo - d..hi - d } else { lo..hi }
+        };
+        let mut out = Vec::with_capacity(n * n * n);
+        for z in range(dz) {
+            for y in range(dy) {
+                for x in range(dx) {
+                    out.push(p[((z as usize) * n + y as usize) * n + x as usize]);
+                }
+            }
+        }
+        out
+    };
+
+    let mut propagator = vec![Vec::new(); patterns.len() * DIRS];
+    for (dir, face) in Face::ALL.into_iter().enumerate() {
+        let offset = face.offset();
+        let mut by_overlap: HashMap<Vec<u8>, Vec<u16>> = HashMap::new();
+        for (q, pattern) in patterns.iter().enumerate() {
+            by_overlap
+                .entry(overlap(pattern, offset, true))
+                .or_default()
+                .push(q as u16);
+        }
+        for (p, pattern) in patterns.iter().enumerate() {
+            if let Some(list) = by_overlap.get(&overlap(pattern, offset, false)) {
+                propagator[p * DIRS + dir] = list.clone();
+            }
+        }
+    }
+    propagator
+}
+
+fn neighbors(config: &VoxelConfig) -> Vec<u32> {
+    let (w, h, d) = (
+        config.output_width as i32,
+        config.output_height as i32,
+        config.output_depth as i32,
+    );
+    let resolve = |v: i32, side: i32, wraps: bool| {
+        if (0..side).contains(&v) {
+            Some(v)
+        } else if wraps {
+            Some(v.rem_euclid(side))
+        } else {
+            None
+        }
+    };
+    let mut neighbors = Vec::with_capacity((w * h * d) as usize * DIRS);
+    for z in 0..d {
+        for y in 0..h {
+            for x in 0..w {
+                for face in Face::ALL {
+                    let (dx, dy, dz) = face.offset();
+                    let cell = (
+                        resolve(x + dx, w, config.boundary.wraps_x()),
+                        resolve(y + dy, h, config.boundary.wraps_y()),
+                        resolve(z + dz, d, false),
+                    );
+                    neighbors.push(match cell {
+                        (Some(x), Some(y), Some(z)) => ((z * h + y) * w + x) as u32,
+                        _ => u32::MAX,
+                    });
+                }
+            }
+        }
+    }
+    neighbors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two solid layers of stone, two empty, repeating upward.
+    fn strata() -> VoxelSample {
+        let voxels = (0..4 * 4 * 4)
+            .map(|i| if i / 16 < 2 { 1 } else { 0 })
+            .collect();
+        VoxelSample::new(4, 4, 4, vec![[120, 110, 100]], voxels)
+    }
+
+    #[test]
+    fn vox_round_trips() {
+        let mut sample = strata();
+        sample.voxels[5] = 0;
+        let bytes = sample.to_vox_bytes().unwrap();
+        let mut parsed = VoxelSample::from_vox_bytes(&bytes).unwrap();
+        assert_eq!(parsed.palette.len(), 255);
+        assert_eq!(parsed.palette[0], sample.palette[0]);
+        parsed.palette.truncate(1);
+        assert_eq!(parsed, sample);
+        assert!(VoxelSample::from_vox_bytes(b"PNG ").is_err());
+    }
+
+    #[test]
+    fn output_keeps_sample_layering() {
+        let sample = strata();
+        let config = VoxelConfig {
+            output_width: 6,
+            output_height: 5,
+            output_depth: 8,
+            seed: Some(3),
+            ..Default::default()
+        };
+        assert!(config.validate(&sample).is_ok());
+        let mut wfc = VoxelWfc::new(&sample, config);
+        assert_eq!(wfc.run(), RunOutcome::Complete);
+
+        let out = wfc.render();
+        let mut filled = 0;
+        for z in 0..8 {
+            let layer = out.get(0, 0, z);
+            filled += usize::from(layer != 0);
+            for y in 0..5 {
+                for x in 0..6 {
+                    assert_eq!(out.get(x, y, z), layer);
+                }
+            }
+        }
+        assert!((2..=6).contains(&filled));
+    }
+
+    #[test]
+    fn validate_caps_pattern_count() {
+        let mut rng = SplitMix64::new(7);
+        let voxels = (0..40 * 40 * 40)
+            .map(|_| rng.below(255) as u8 + 1)
+            .collect();
+        let noise = VoxelSample::new(40, 40, 40, vec![[0, 0, 0]; 255], voxels);
+        let config = VoxelConfig {
+            pattern_size: 3,
+            ..Default::default()
+        };
+        assert!(config.validate(&noise).is_err());
+
+        let wfc = VoxelWfc::new(&strata(), VoxelConfig::default());
+        for p in 0..wfc.num_patterns() {
+            for &q in wfc.compatible_patterns(p, Face::Forward) {
+                assert!(
+                    wfc.compatible_patterns(q as usize, Face::Back)
+                        .contains(&(p as u16))
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn transforms_match_2d_patterns() {
+        let pixels: Vec<Color> = (0..9).map(|i| [i, 0, 0]).collect();
+        let pattern = crate::Pattern::new(3, pixels);
+        for transform in [
+            Symmetry::ROTATE_90,
+            Symmetry::ROTATE_180,
+            Symmetry::ROTATE_270,
+            Symmetry::FLIP_X,
+            Symmetry::FLIP_Y,
+            Symmetry::TRANSPOSE,
+            Symmetry::ANTI_TRANSPOSE,
+        ] {
+            let expected = pattern.transform(transform);
+            for y in 0..3 {
+                for x in 0..3 {
+                    let (tx, ty) = transform_xy(transform, x, y, 3);
+                    assert_eq!(expected.get(tx, ty), pattern.get(x, y));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{Color, Error, parse_hex};
+
+/// Which patterns a [`WeightMultiplier`] scales.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PatternKey {
+    /// Every pattern whose top-left pixel is this color.
+    Color(Color),
+    /// One pattern, by index into `Wfc::patterns`.
+    Index(usize),
+}
+
+/// Scales the extracted weight of some patterns, e.g. `2.0` to make a rare
+/// feature twice as common without editing the sample.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WeightMultiplier {
+    pub key: PatternKey,
+    pub factor: f64,
+}
+
+impl WeightMultiplier {
+    pub fn new(key: PatternKey, factor: f64) -> Self {
+        Self { key, factor }
+    }
+
+    #[must_use]
+    pub fn applies_to(&self, pattern: usize, color: Color) -> bool {
+        match self.key {
+            PatternKey::Color(c) => c == color,
+            PatternKey::Index(i) => i == pattern,
+        }
+    }
+}
+
+/// `RRGGBB=FACTOR` by color or `pINDEX=FACTOR` by pattern index.
+impl FromStr for WeightMultiplier {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let invalid = Error::InvalidConfig;
+        let (key, factor) = s
+            .split_once('=')
+            .ok_or_else(|| invalid("expected KEY=FACTOR".to_string()))?;
+        let key = match key.trim().strip_prefix('p') {
+            Some(index) => PatternKey::Index(
+                index
+                    .parse()
+                    .map_err(|_| invalid(format!("invalid pattern index '{}'", index)))?,
+            ),
+            None => PatternKey::Color(
+                parse_hex(key.trim())
+                    .ok_or_else(|| invalid(format!("invalid color '{}', expected RRGGBB", key)))?,
+            ),
+        };
+        let factor = factor
+            .trim()
+            .parse()
+            .map_err(|_| invalid(format!("invalid factor '{}'", factor)))?;
+        Ok(Self::new(key, factor))
+    }
+}
+
+impl fmt::Display for WeightMultiplier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.key {
+            PatternKey::Color([r, g, b]) => write!(f, "{:02x}{:02x}{:02x}", r, g, b)?,
+            PatternKey::Index(i) => write!(f, "p{}", i)?,
+        }
+        write!(f, "={}", self.factor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_color_and_index_keys() {
+        let by_color: WeightMultiplier = "ff8000=2.5".parse().unwrap();
+        assert_eq!(by_color.key, PatternKey::Color([255, 128, 0]));
+        assert_eq!(by_color.factor, 2.5);
+        assert!(by_color.applies_to(7, [255, 128, 0]));
+
+        let by_index: WeightMultiplier = "p12=0.5".parse().unwrap();
+        assert_eq!(by_index.key, PatternKey::Index(12));
+        assert!(!by_index.applies_to(11, [0, 0, 0]));
+        assert_eq!(by_index.to_string(), "p12=0.5");
+
+        assert!("p12".parse::<WeightMultiplier>().is_err());
+        assert!("zz0000=1".parse::<WeightMultiplier>().is_err());
+    }
+}
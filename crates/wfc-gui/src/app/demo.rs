@@ -0,0 +1,109 @@
+use std::path::PathBuf;
+
+use eframe::egui;
+use wfc_core::{Sample, default_pipe_sample};
+
+use super::App;
+
+impl App {
+    /// Start cycling through `samples/` (or the built-in sample), each with
+    /// surprise settings, run to completion and held for `demo.pause`.
+    pub fn start_demo(&mut self) {
+        let mut samples: Vec<PathBuf> = std::fs::read_dir("samples")
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "png"))
+            .collect();
+        samples.sort();
+        self.demo.samples = samples;
+        self.demo.next = 0;
+        self.demo.active = true;
+        self.camera.zoom = 0.0;
+        self.camera.pan_offset = egui::Vec2::ZERO;
+        self.demo_next();
+    }
+
+    pub fn stop_demo(&mut self) {
+        self.demo.active = false;
+        self.playback.running = false;
+    }
+
+    /// Load the next sample and rebuild it with randomized settings.
+    fn demo_next(&mut self) {
+        self.demo.finished_for = 0.0;
+        // Skip unreadable files, but give up after a full lap
+        let mut sample = None;
+        for _ in 0..self.demo.samples.len() {
+            let path = &self.demo.samples[self.demo.next % self.demo.samples.len()];
+            self.demo.next += 1;
+            if let Ok(loaded) = Sample::from_image(path) {
+                sample = Some((loaded, Some(path.clone())));
+                break;
+            }
+        }
+        let (sample, path) = sample.unwrap_or_else(|| (default_pipe_sample(), None));
+        self.set_sample(sample, path);
+
+        let mut config = self.config().clone();
+        config.importance.clear();
+        config.randomize(&self.sample, None);
+        // About three seconds per output at 60 fps
+        let cells = config.output_width * config.output_height;
+        self.playback.steps_per_frame = (cells / 180).max(1);
+        self.playback.auto_restart = true;
+        self.messages.error = None;
+        self.messages.success = None;
+        self.build(config, true, false);
+    }
+
+    /// Drive the demo each frame: keep it running, move on after the pause,
+    /// skip samples too large for the build limits. Esc stops it.
+    pub fn update_demo(&mut self, ctx: &egui::Context) {
+        if !self.demo.active {
+            return;
+        }
+        if ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+            self.stop_demo();
+            return;
+        }
+        if self.pending_rebuild.take().is_some() {
+            self.model_stale = true;
+            self.demo_next();
+        }
+        if self.build_job.is_none() {
+            if self.wfc.is_done() && !self.wfc.has_contradiction() {
+                self.demo.finished_for += ctx.input(|i| i.stable_dt);
+                if self.demo.finished_for >= self.demo.pause {
+                    self.demo_next();
+                }
+            } else if self.wfc.has_contradiction() && self.playback.retries.is_spent() {
+                self.demo_next();
+            } else {
+                self.play();
+            }
+        }
+
+        let name = self
+            .sample_path
+            .as_ref()
+            .and_then(|path| path.file_stem())
+            .map_or("built-in".into(), |stem| stem.to_string_lossy());
+        let config = self.config();
+        egui::Area::new(egui::Id::new("demo_caption"))
+            .anchor(egui::Align2::LEFT_BOTTOM, [12.0, -12.0])
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.label(format!(
+                        "{}  ·  N={}  ·  {}  ·  seed {}",
+                        name,
+                        config.pattern_size,
+                        config.symmetry,
+                        self.wfc.seed()
+                    ));
+                    ui.weak("Esc to stop the demo");
+                });
+            });
+        ctx.request_repaint();
+    }
+}
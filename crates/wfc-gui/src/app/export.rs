@@ -1,12 +1,31 @@
+use std::collections::HashMap;
+
 use eframe::egui;
 use gif::{Encoder, Frame, Repeat};
 
-use wfc_core::{Error, Sample};
+use wfc_core::{Error, Heightmap, Sample, dual, smooth};
 
-use super::App;
+use super::{App, Milestones, ora};
 
 impl App {
+    /// Refuse to bake placeholder colors into a "final" image unless allowed.
+    fn check_complete(&mut self) -> bool {
+        let contradicted = self.wfc.contradicted_cells();
+        let uncollapsed = self.wfc.uncollapsed_cells();
+        if self.export.allow_incomplete || (contradicted == 0 && uncollapsed == 0) {
+            return true;
+        }
+        self.messages.error = Some(format!(
+            "Not saved: {} contradicted and {} uncollapsed cells (enable \"Allow incomplete\")",
+            contradicted, uncollapsed
+        ));
+        false
+    }
+
     pub fn save_output(&mut self) {
+        if !self.check_complete() {
+            return;
+        }
         let Some(path) = rfd::FileDialog::new()
             .add_filter("PNG", &["png"])
             .set_file_name("output.png")
@@ -15,29 +34,25 @@ impl App {
             return;
         };
 
-        let colors = self.wfc.render();
         let w = self.wfc.config().output_width;
         let h = self.wfc.config().output_height;
 
-        let result = if self.export.export_scale == 1 {
-            Sample::new(w, h, colors).save(&path)
+        let result = if self.heightmap {
+            Heightmap::from_wfc(&self.wfc).save_png(&path)
         } else {
-            let mut img = image::RgbImage::new(w as u32, h as u32);
-            for y in 0..h {
-                for x in 0..w {
-                    let c = colors[y * w + x];
-                    img.put_pixel(x as u32, y as u32, image::Rgb(c));
-                }
-            }
-            let scaled = image::imageops::resize(
-                &img,
-                w as u32 * self.export.export_scale,
-                h as u32 * self.export.export_scale,
-                image::imageops::FilterType::Nearest,
-            );
-            scaled
-                .save(&path)
-                .map_err(|e| Error::ImageSave(e.to_string()))
+            // Layer 0 goes to `path`, extra layers to `<stem>.layerN.png`
+            self.layer_colors()
+                .into_iter()
+                .enumerate()
+                .try_for_each(|(i, colors)| {
+                    let layer_path = if i == 0 {
+                        path.clone()
+                    } else {
+                        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                        path.with_file_name(format!("{}.layer{}.png", stem, i))
+                    };
+                    self.save_scaled(&layer_path, w, h, colors)
+                })
         };
 
         match result {
@@ -46,6 +61,158 @@ impl App {
         }
     }
 
+    fn save_scaled(
+        &self,
+        path: &std::path::Path,
+        w: usize,
+        h: usize,
+        colors: Vec<[u8; 3]>,
+    ) -> Result<(), Error> {
+        let entropy = self.export.bake_entropy.then(|| self.entropy_levels());
+        if self.export.export_scale == 1 && entropy.is_none() {
+            return Sample::new(w, h, colors)
+                .with_transparent(self.wfc.transparent_color())
+                .save(path);
+        }
+        compose(
+            w,
+            h,
+            &colors,
+            entropy.as_deref(),
+            self.export.bake_grid,
+            self.export.export_scale as usize,
+        )
+        .save(path)
+        .map_err(|e| Error::ImageSave(e.to_string()))
+    }
+
+    /// Isometric preview as a PNG, eight pixels per tile unit times the
+    /// export scale.
+    pub fn save_isometric(&mut self) {
+        if !self.check_complete() {
+            return;
+        }
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("PNG", &["png"])
+            .set_file_name("output-iso.png")
+            .save_file()
+        else {
+            return;
+        };
+
+        let colors = self.display_colors();
+        let scale = 8.0 * self.export.export_scale as f32;
+        let (w, h, pixels) = self.iso_scene(&colors).rasterize(scale, [0, 0, 0]);
+        match Sample::new(w, h, pixels).save(&path) {
+            Ok(_) => self.messages.success = Some("Isometric view saved".to_string()),
+            Err(e) => self.messages.error = Some(format!("Failed to save: {}", e)),
+        }
+    }
+
+    /// Pick a map pixel-aligned with the sample and save it rendered from the
+    /// output's patterns.
+    pub fn save_companion(&mut self) {
+        if !self.check_complete() {
+            return;
+        }
+        let Some(source) = rfd::FileDialog::new()
+            .add_filter("Images", &["png", "jpg", "jpeg", "bmp"])
+            .set_title("Companion map aligned with the sample")
+            .pick_file()
+        else {
+            return;
+        };
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("PNG", &["png"])
+            .set_file_name("output-companion.png")
+            .save_file()
+        else {
+            return;
+        };
+
+        let config = self.wfc.config();
+        let (w, h) = (config.output_width, config.output_height);
+        let result = Sample::from_image(&source)
+            .and_then(|map| self.wfc.render_companion(&map))
+            .and_then(|colors| Sample::new(w, h, colors).save(&path));
+        match result {
+            Ok(_) => self.messages.success = Some("Companion map saved".to_string()),
+            Err(e) => self.messages.error = Some(format!("Failed to save companion: {}", e)),
+        }
+    }
+
+    /// Dual grid output drawn as the transition tiles between its corners.
+    pub fn save_tiles(&mut self) {
+        if !self.check_complete() {
+            return;
+        }
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("PNG", &["png"])
+            .set_file_name("output-tiles.png")
+            .save_file()
+        else {
+            return;
+        };
+
+        let colors = self.display_colors();
+        let config = self.wfc.config();
+        let (w, h, boundary) = (config.output_width, config.output_height, config.boundary);
+        let scale = (self.export.export_scale as usize).max(2);
+        let (tiles_w, tiles_h) = dual::tile_dims(w, h, boundary);
+        let pixels = dual::render_tiles(w, h, &colors, boundary, scale);
+
+        match Sample::new(tiles_w * scale, tiles_h * scale, pixels).save(&path) {
+            Ok(_) => self.messages.success = Some("Tiles saved".to_string()),
+            Err(e) => self.messages.error = Some(format!("Failed to save: {}", e)),
+        }
+    }
+
+    /// Marching-squares smoothed PNG plus an SVG of the contours beside it.
+    pub fn save_smoothed(&mut self) {
+        if !self.check_complete() {
+            return;
+        }
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("PNG", &["png"])
+            .set_file_name("output-smooth.png")
+            .save_file()
+        else {
+            return;
+        };
+
+        let colors = self.display_colors();
+        let w = self.wfc.config().output_width;
+        let h = self.wfc.config().output_height;
+        let scale = (self.export.export_scale as usize).max(2);
+
+        let dark = smooth::dark_mask(&colors);
+        let pick = |want: bool| {
+            dark.iter()
+                .position(|&d| d == want)
+                .map_or([0, 0, 0], |i| colors[i])
+        };
+        let (dark_color, light_color) = (pick(true), pick(false));
+        let pixels = smooth::smooth_mask(w, h, &dark, scale)
+            .into_iter()
+            .map(|d| if d { dark_color } else { light_color })
+            .collect();
+
+        let segments = smooth::marching_squares(w, h, &dark);
+        let svg = smooth::contour_svg(w, h, &colors, &segments, scale as f32);
+
+        let result = Sample::new(w * scale, h * scale, pixels)
+            .save(&path)
+            .and_then(|_| {
+                std::fs::write(path.with_extension("svg"), svg)
+                    .map_err(|e| Error::ImageSave(e.to_string()))
+            });
+
+        match result {
+            Ok(_) => self.messages.success = Some("Smoothed output saved".to_string()),
+            Err(e) => self.messages.error = Some(format!("Failed to save: {}", e)),
+        }
+    }
+
     pub fn start_save_gif(&mut self) {
         if self.export.gif_frames.is_empty() {
             self.messages.error = Some("No frames to save".to_string());
@@ -82,6 +249,21 @@ impl App {
         }
     }
 
+    /// Frames written to the GIF; boomerang adds the inner frames reversed.
+    fn gif_frame_count(&self) -> usize {
+        let n = self.export.gif_frames.len();
+        if self.export.boomerang && n > 2 {
+            2 * n - 2
+        } else {
+            n
+        }
+    }
+
+    fn gif_frame_index(&self, i: usize) -> usize {
+        let n = self.export.gif_frames.len();
+        if i < n { i } else { 2 * n - 2 - i }
+    }
+
     pub fn process_gif_saving(&mut self, ctx: &egui::Context) {
         if self.export.gif_save_cancel {
             self.messages.error = Some("GIF save cancelled".to_string());
@@ -91,12 +273,12 @@ impl App {
             return;
         }
 
-        let Some(encoder) = &mut self.export.gif_encoder else {
+        if self.export.gif_encoder.is_none() {
             return;
-        };
+        }
 
         let idx = self.export.gif_save_progress;
-        if idx >= self.export.gif_frames.len() {
+        if idx >= self.gif_frame_count() {
             self.export.saving_gif = false;
             self.export.gif_encoder = None;
             if let Some(path) = &self.export.gif_save_path {
@@ -106,29 +288,14 @@ impl App {
             return;
         }
 
-        let w = self.wfc.config().output_width as u32;
-        let h = self.wfc.config().output_height as u32;
-        let scaled_w = (w * self.export.export_scale) as u16;
-        let scaled_h = (h * self.export.export_scale) as u16;
-
-        let scaled_frame = if self.export.export_scale == 1 {
-            self.export.gif_frames[idx].clone()
-        } else {
-            let img =
-                image::RgbaImage::from_raw(w, h, self.export.gif_frames[idx].clone()).unwrap();
-            image::imageops::resize(
-                &img,
-                scaled_w as u32,
-                scaled_h as u32,
-                image::imageops::FilterType::Nearest,
-            )
-            .into_raw()
-        };
-
-        let mut scaled_frame_mut = scaled_frame;
-        let mut frame = Frame::from_rgba_speed(scaled_w, scaled_h, &mut scaled_frame_mut, 10);
+        let img = self.compose_frame(self.gif_frame_index(idx));
+        let mut frame =
+            Frame::from_rgb_speed(img.width() as u16, img.height() as u16, img.as_raw(), 10);
         frame.delay = self.export.gif_frame_delay;
 
+        let Some(encoder) = &mut self.export.gif_encoder else {
+            return;
+        };
         if let Err(e) = encoder.write_frame(&frame) {
             self.messages.error = Some(format!("Failed to write frame: {}", e));
             self.export.saving_gif = false;
@@ -140,6 +307,190 @@ impl App {
         }
     }
 
+    /// Recorded frame `source` at export scale, with baked overlays.
+    fn compose_frame(&self, source: usize) -> image::RgbImage {
+        let w = self.wfc.config().output_width;
+        let h = self.wfc.config().output_height;
+        let colors: Vec<[u8; 3]> = self.export.gif_frames[source]
+            .chunks_exact(4)
+            .map(|p| [p[0], p[1], p[2]])
+            .collect();
+        let entropy = self
+            .export
+            .bake_entropy
+            .then(|| self.export.gif_entropy[source].as_slice());
+        let scale = self.export.export_scale as usize;
+        compose(w, h, &colors, entropy, self.export.bake_grid, scale)
+    }
+
+    /// The GIF's frames packed row by row into one PNG, plus a JSON beside it
+    /// (Aseprite's array format) giving each frame's rect and duration.
+    pub fn save_sprite_sheet(&mut self) {
+        if self.export.gif_frames.is_empty() {
+            self.messages.error = Some("No frames to save".to_string());
+            return;
+        }
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("PNG", &["png"])
+            .set_file_name("wfc-sheet.png")
+            .save_file()
+        else {
+            return;
+        };
+
+        let count = self.gif_frame_count();
+        let columns = self.export.sheet_columns.clamp(1, count);
+        let rows = count.div_ceil(columns);
+        let scale = self.export.export_scale.max(1);
+        let fw = self.wfc.config().output_width as u32 * scale;
+        let fh = self.wfc.config().output_height as u32 * scale;
+        let duration = self.export.gif_frame_delay as u32 * 10;
+
+        let mut sheet = image::RgbImage::new(fw * columns as u32, fh * rows as u32);
+        let mut frames = Vec::with_capacity(count);
+        for i in 0..count {
+            let x = (i % columns) as u32 * fw;
+            let y = (i / columns) as u32 * fh;
+            let frame = self.compose_frame(self.gif_frame_index(i));
+            image::imageops::replace(&mut sheet, &frame, x as i64, y as i64);
+            frames.push(format!(
+                "    {{\"filename\": \"frame{}\", \
+                 \"frame\": {{\"x\": {}, \"y\": {}, \"w\": {}, \"h\": {}}}, \"duration\": {}}}",
+                i, x, y, fw, fh, duration
+            ));
+        }
+        let image_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let json = format!(
+            "{{\n  \"frames\": [\n{}\n  ],\n  \
+             \"meta\": {{\"image\": {}, \"size\": {{\"w\": {}, \"h\": {}}}, \"scale\": \"1\"}}\n}}\n",
+            frames.join(",\n"),
+            json_string(&image_name),
+            sheet.width(),
+            sheet.height()
+        );
+
+        let result = sheet.save(&path).map_err(|e| e.to_string()).and_then(|_| {
+            std::fs::write(path.with_extension("json"), json).map_err(|e| e.to_string())
+        });
+        match result {
+            Ok(_) => {
+                self.messages.success = Some(format!("Sprite sheet saved to {}", path.display()))
+            }
+            Err(e) => self.messages.error = Some(format!("Failed to save sprite sheet: {}", e)),
+        }
+    }
+
+    /// Recorded frames, or the current output if none, as an Aseprite file
+    /// at one pixel per cell with the output palette.
+    pub fn save_aseprite(&mut self) {
+        let frames: Vec<Vec<[u8; 3]>> = if self.export.gif_frames.is_empty() {
+            if !self.check_complete() {
+                return;
+            }
+            vec![self.display_colors()]
+        } else {
+            (0..self.gif_frame_count())
+                .map(|i| {
+                    self.export.gif_frames[self.gif_frame_index(i)]
+                        .chunks_exact(4)
+                        .map(|p| [p[0], p[1], p[2]])
+                        .collect()
+                })
+                .collect()
+        };
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("Aseprite", &["aseprite", "ase"])
+            .set_file_name("output.aseprite")
+            .save_file()
+        else {
+            return;
+        };
+
+        let config = self.wfc.config();
+        let palette: Vec<[u8; 3]> = self.color_map.entries.iter().map(|&(_, to)| to).collect();
+        let duration = self.export.gif_frame_delay * 10;
+        let bytes = encode_aseprite(
+            config.output_width,
+            config.output_height,
+            &frames,
+            duration,
+            &palette,
+        );
+        match std::fs::write(&path, bytes) {
+            Ok(_) => self.messages.success = Some(format!("Saved {}", path.display())),
+            Err(e) => self.messages.error = Some(format!("Failed to save: {}", e)),
+        }
+    }
+
+    /// Percentage of decided cells in recorded frame `i`.
+    fn decided_percent(&self, i: usize) -> usize {
+        let levels = &self.export.gif_entropy[i];
+        levels.iter().filter(|&&l| l == 0).count() * 100 / levels.len().max(1)
+    }
+
+    /// Recorded frames picked by `export.milestones`, ascending and always
+    /// ending with the last frame.
+    fn milestone_frames(&self) -> Vec<usize> {
+        let n = self.export.gif_frames.len();
+        let mut picked: Vec<usize> = match self.export.milestones {
+            Milestones::EveryFrames(k) => (0..n).step_by(k.max(1)).collect(),
+            Milestones::EveryPercent(step) => {
+                let step = step.max(1);
+                let mut next = 0;
+                (0..n)
+                    .filter(|&i| {
+                        let percent = self.decided_percent(i);
+                        let keep = percent >= next;
+                        if keep {
+                            next = (percent / step + 1) * step;
+                        }
+                        keep
+                    })
+                    .collect()
+            }
+        };
+        if n > 0 && picked.last() != Some(&(n - 1)) {
+            picked.push(n - 1);
+        }
+        picked
+    }
+
+    /// Milestone frames as layers of an OpenRaster file, oldest at the
+    /// bottom, so stages can be composited in Krita or GIMP.
+    pub fn save_openraster(&mut self) {
+        if self.export.gif_frames.is_empty() {
+            self.messages.error = Some("No frames to save".to_string());
+            return;
+        }
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("OpenRaster", &["ora"])
+            .set_file_name("wfc-stages.ora")
+            .save_file()
+        else {
+            return;
+        };
+
+        let layers: Vec<(String, image::RgbImage)> = self
+            .milestone_frames()
+            .into_iter()
+            .map(|i| {
+                let name = format!("Frame {} ({}% decided)", i, self.decided_percent(i));
+                (name, self.compose_frame(i))
+            })
+            .collect();
+        let count = layers.len();
+        let result = ora::encode(&layers)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| std::fs::write(&path, bytes).map_err(|e| e.to_string()));
+        match result {
+            Ok(_) => {
+                self.messages.success =
+                    Some(format!("Saved {} layers to {}", count, path.display()))
+            }
+            Err(e) => self.messages.error = Some(format!("Failed to save: {}", e)),
+        }
+    }
+
     pub fn show_gif_saving_modal(&mut self, ctx: &egui::Context) {
         egui::Window::new("Saving GIF")
             .collapsible(false)
@@ -151,11 +502,11 @@ impl App {
                     ui.label(format!(
                         "Processing frame {} of {}...",
                         self.export.gif_save_progress,
-                        self.export.gif_frames.len()
+                        self.gif_frame_count()
                     ));
 
                     let progress =
-                        self.export.gif_save_progress as f32 / self.export.gif_frames.len() as f32;
+                        self.export.gif_save_progress as f32 / self.gif_frame_count() as f32;
                     ui.add(egui::ProgressBar::new(progress).show_percentage());
 
                     ui.add_space(10.0);
@@ -168,6 +519,53 @@ impl App {
         ctx.request_repaint();
     }
 
+    /// Ask the backend for a screenshot; it arrives as an event next frame.
+    pub fn request_screenshot(&mut self, ctx: &egui::Context) {
+        self.export.screenshot_pending = true;
+        ctx.send_viewport_cmd(egui::ViewportCommand::Screenshot(Default::default()));
+    }
+
+    /// Crop a delivered screenshot to the canvas and save it with overlays,
+    /// zoom and pan exactly as shown.
+    pub fn handle_screenshot(&mut self, ctx: &egui::Context) {
+        if !self.export.screenshot_pending {
+            return;
+        }
+        let Some(image) = ctx.input(|i| {
+            i.events.iter().find_map(|e| match e {
+                egui::Event::Screenshot { image, .. } => Some(image.clone()),
+                _ => None,
+            })
+        }) else {
+            return;
+        };
+        self.export.screenshot_pending = false;
+
+        let Some(rect) = self.camera.canvas_rect else {
+            return;
+        };
+        let view = image.region(&rect, Some(ctx.pixels_per_point()));
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("PNG", &["png"])
+            .set_file_name("view.png")
+            .save_file()
+        else {
+            return;
+        };
+
+        let [w, h] = view.size;
+        let pixels = view.pixels.iter().flat_map(|c| c.to_array()).collect();
+        let result = image::RgbaImage::from_raw(w as u32, h as u32, pixels)
+            .expect("buffer matches dimensions")
+            .save(&path);
+        match result {
+            Ok(_) => {
+                self.messages.success = Some(format!("Screenshot saved to {}", path.display()))
+            }
+            Err(e) => self.messages.error = Some(format!("Failed to save screenshot: {}", e)),
+        }
+    }
+
     pub fn scale_ui(&mut self, ui: &mut egui::Ui) {
         ui.horizontal(|ui| {
             ui.label("Scale:");
@@ -178,3 +576,201 @@ impl App {
         });
     }
 }
+
+const GRID_COLOR: image::Rgb<u8> = image::Rgb([64, 64, 64]);
+
+/// Aseprite file with one background layer and an uncompressed cel per
+/// frame, following aseprite's `docs/ase-file-specs.md`. The palette starts
+/// with `palette`, then any other colors in order of appearance; the sprite
+/// is indexed when that fits in 256 entries and RGBA otherwise, as blended
+/// mid-collapse frames often need.
+fn encode_aseprite(
+    w: usize,
+    h: usize,
+    frames: &[Vec<[u8; 3]>],
+    duration_ms: u16,
+    palette: &[[u8; 3]],
+) -> Vec<u8> {
+    fn u16le(out: &mut Vec<u8>, v: u16) {
+        out.extend_from_slice(&v.to_le_bytes());
+    }
+    fn u32le(out: &mut Vec<u8>, v: u32) {
+        out.extend_from_slice(&v.to_le_bytes());
+    }
+    fn chunk(out: &mut Vec<u8>, kind: u16, data: &[u8]) {
+        u32le(out, data.len() as u32 + 6);
+        u16le(out, kind);
+        out.extend_from_slice(data);
+    }
+
+    let mut colors: Vec<[u8; 3]> = Vec::new();
+    let mut index: HashMap<[u8; 3], usize> = HashMap::new();
+    for &c in palette.iter().chain(frames.iter().flatten()) {
+        index.entry(c).or_insert_with(|| {
+            colors.push(c);
+            colors.len() - 1
+        });
+    }
+    let indexed = colors.len() <= 256;
+    let entries = if indexed {
+        colors.len()
+    } else {
+        palette.len().min(256)
+    };
+
+    let mut out = Vec::new();
+    // Header; the file size at offset 0 is patched in at the end
+    u32le(&mut out, 0);
+    u16le(&mut out, 0xA5E0);
+    u16le(&mut out, frames.len() as u16);
+    u16le(&mut out, w as u16);
+    u16le(&mut out, h as u16);
+    u16le(&mut out, if indexed { 8 } else { 32 });
+    u32le(&mut out, 1); // layer opacity is valid
+    u16le(&mut out, duration_ms);
+    out.extend_from_slice(&[0; 8]);
+    out.push(0); // transparent index; unused by a background layer
+    out.extend_from_slice(&[0; 3]);
+    u16le(&mut out, entries as u16);
+    out.extend_from_slice(&[1, 1]); // square pixels
+    out.extend_from_slice(&[0; 4]); // grid origin
+    u16le(&mut out, 16);
+    u16le(&mut out, 16);
+    out.extend_from_slice(&[0; 84]);
+
+    for (f, frame) in frames.iter().enumerate() {
+        let mut chunks = Vec::new();
+        let mut count = 0;
+        if f == 0 {
+            let mut data = Vec::new();
+            u32le(&mut data, entries as u32);
+            u32le(&mut data, 0);
+            u32le(&mut data, entries.saturating_sub(1) as u32);
+            data.extend_from_slice(&[0; 8]);
+            for &[r, g, b] in &colors[..entries] {
+                u16le(&mut data, 0);
+                data.extend_from_slice(&[r, g, b, 255]);
+            }
+            chunk(&mut chunks, 0x2019, &data);
+
+            let mut data = Vec::new();
+            u16le(&mut data, 1 | 2 | 8); // visible, editable, background
+            u16le(&mut data, 0); // image layer
+            u16le(&mut data, 0); // child level
+            u32le(&mut data, 0); // default width and height, ignored
+            u16le(&mut data, 0); // normal blending
+            data.extend_from_slice(&[255, 0, 0, 0]);
+            let name = b"WFC";
+            u16le(&mut data, name.len() as u16);
+            data.extend_from_slice(name);
+            chunk(&mut chunks, 0x2004, &data);
+            count += 2;
+        }
+
+        let mut data = Vec::new();
+        u16le(&mut data, 0); // layer
+        u32le(&mut data, 0); // x, y
+        data.push(255);
+        u16le(&mut data, 0); // raw cel
+        u16le(&mut data, 0); // z-index
+        data.extend_from_slice(&[0; 5]);
+        u16le(&mut data, w as u16);
+        u16le(&mut data, h as u16);
+        for c in frame {
+            if indexed {
+                data.push(index[c] as u8);
+            } else {
+                data.extend_from_slice(&[c[0], c[1], c[2], 255]);
+            }
+        }
+        chunk(&mut chunks, 0x2005, &data);
+        count += 1;
+
+        u32le(&mut out, chunks.len() as u32 + 16);
+        u16le(&mut out, 0xF1FA);
+        u16le(&mut out, count);
+        u16le(&mut out, duration_ms);
+        out.extend_from_slice(&[0; 2]);
+        u32le(&mut out, count as u32);
+        out.extend_from_slice(&chunks);
+    }
+
+    let size = out.len() as u32;
+    out[..4].copy_from_slice(&size.to_le_bytes());
+    out
+}
+
+/// Quoted JSON string with quotes, backslashes and control characters escaped.
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Blue (nearly decided) to red (wide open) for normalized entropy `t`.
+pub fn heat_color(t: f32) -> [u8; 3] {
+    let t = t.clamp(0.0, 1.0);
+    [(255.0 * t) as u8, 64, (255.0 * (1.0 - t)) as u8]
+}
+
+fn blend(base: [u8; 3], over: [u8; 3], alpha: f32) -> [u8; 3] {
+    std::array::from_fn(|i| (base[i] as f32 * (1.0 - alpha) + over[i] as f32 * alpha) as u8)
+}
+
+/// Software compositor for exports: nearest-neighbor upscale, then the
+/// entropy heatmap (`0` = collapsed, see `App::entropy_levels`) and grid lines
+/// drawn at export resolution. Grid lines need a scale of at least 2.
+fn compose(
+    w: usize,
+    h: usize,
+    colors: &[[u8; 3]],
+    entropy: Option<&[u8]>,
+    grid: bool,
+    scale: usize,
+) -> image::RgbImage {
+    let scale = scale.max(1);
+    let grid = grid && scale >= 2;
+    let (out_w, out_h) = ((w * scale) as u32, (h * scale) as u32);
+    let mut img = image::RgbImage::new(out_w, out_h);
+
+    for y in 0..h {
+        for x in 0..w {
+            let i = y * w + x;
+            let mut c = colors[i];
+            if let Some(levels) = entropy
+                && levels[i] > 0
+            {
+                c = blend(c, heat_color(levels[i] as f32 / 255.0), 0.6);
+            }
+            for py in 0..scale {
+                for px in 0..scale {
+                    let pixel = if grid && (px == 0 || py == 0) {
+                        GRID_COLOR
+                    } else {
+                        image::Rgb(c)
+                    };
+                    img.put_pixel((x * scale + px) as u32, (y * scale + py) as u32, pixel);
+                }
+            }
+        }
+    }
+
+    // Close the grid along the right and bottom edges
+    if grid {
+        for y in 0..out_h {
+            img.put_pixel(out_w - 1, y, GRID_COLOR);
+        }
+        for x in 0..out_w {
+            img.put_pixel(x, out_h - 1, GRID_COLOR);
+        }
+    }
+    img
+}
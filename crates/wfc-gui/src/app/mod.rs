@@ -1,17 +1,67 @@
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
+use std::time::Duration;
 
-use eframe::egui::Vec2;
+use eframe::egui::{Rect, Vec2};
 use gif::Encoder;
 
-use wfc_core::{Config, Sample, Wfc, default_pipe_sample};
+use wfc_core::{
+    BuildProgress, ColorMap, Config, Error, Heightmap, LayerPalette, LayeredSample, MemoryUsage,
+    PropagatorHealth, RetryBudget, Sample, SampleStats, StepOutcome, Wfc, WfcState,
+    default_pipe_sample, dominant_colors, iso, iso::IsoScene, load_palette,
+};
 
+pub mod demo;
 pub mod export;
+pub mod ora;
+pub mod timeline;
 pub mod ui;
 
+/// Estimated model size above which a rebuild waits for confirmation.
+pub struct BuildLimits {
+    pub max_patterns: usize,
+    pub max_wave_mb: usize,
+}
+
+impl Default for BuildLimits {
+    fn default() -> Self {
+        Self {
+            max_patterns: 2000,
+            max_wave_mb: 512,
+        }
+    }
+}
+
+impl BuildLimits {
+    pub fn exceeded_by(&self, stats: &SampleStats) -> bool {
+        stats.estimated_patterns > self.max_patterns
+            || stats.estimated_wave_bytes > self.max_wave_mb << 20
+    }
+}
+
+/// Pattern extraction running on a worker thread.
+pub struct BuildJob {
+    pub progress: Arc<Mutex<Option<BuildProgress>>>,
+    pub cancel: Arc<AtomicBool>,
+    result: mpsc::Receiver<Result<Wfc, Error>>,
+    /// Re-extraction for a new sample; cancelling leaves the model stale.
+    full: bool,
+}
+
+/// Rebuild held back by `BuildLimits` until the user confirms.
+pub struct PendingRebuild {
+    pub config: Config,
+    /// Re-extract even if the config alone would allow reuse (new sample).
+    pub full: bool,
+}
+
 pub struct CameraState {
     pub zoom: f32,
     pub pan_offset: Vec2,
     pub cell_size: f32,
+    /// Screen area of the canvas last frame, for view screenshots.
+    pub canvas_rect: Option<Rect>,
 }
 
 impl Default for CameraState {
@@ -20,12 +70,35 @@ impl Default for CameraState {
             zoom: 1.0,
             pan_offset: Vec2::ZERO,
             cell_size: 16.0,
+            canvas_rect: None,
+        }
+    }
+}
+
+/// Isometric 2.5D preview of the output.
+pub struct IsoState {
+    pub enabled: bool,
+    /// Block height, in tile heights, of a color mapped to 1.
+    pub max_height: f32,
+    /// Per-color height in `0..=1`, keyed by displayed color; colors not yet
+    /// listed default to their luminance.
+    pub heights: Vec<([u8; 3], f32)>,
+}
+
+impl Default for IsoState {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_height: 2.0,
+            heights: Vec::new(),
         }
     }
 }
 
 pub struct ExportState {
     pub gif_frames: Vec<Vec<u8>>,
+    /// Per-frame `App::entropy_levels`, parallel to `gif_frames`.
+    pub gif_entropy: Vec<Vec<u8>>,
     pub gif_frame_delay: u16,
     pub export_scale: u32,
     pub saving_gif: bool,
@@ -33,12 +106,37 @@ pub struct ExportState {
     pub gif_save_cancel: bool,
     pub gif_save_path: Option<PathBuf>,
     pub gif_encoder: Option<Encoder<std::fs::File>>,
+    /// Save PNGs even with contradicted or uncollapsed cells.
+    pub allow_incomplete: bool,
+    /// Append the frames in reverse so the GIF plays forward then back.
+    pub boomerang: bool,
+    /// Frames per row in sprite sheet exports.
+    pub sheet_columns: usize,
+    /// Recorded frames that become layers in OpenRaster exports.
+    pub milestones: Milestones,
+    /// A viewport screenshot was requested and hasn't arrived yet.
+    pub screenshot_pending: bool,
+    /// Draw grid lines into PNG/GIF exports (needs scale 2 or more).
+    pub bake_grid: bool,
+    /// Tint uncollapsed cells by entropy in PNG/GIF exports.
+    pub bake_entropy: bool,
+}
+
+/// Which recorded frames are kept as construction stages.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Milestones {
+    /// Every Kth frame.
+    EveryFrames(usize),
+    /// The first frame at or past each multiple of this percentage of
+    /// decided cells.
+    EveryPercent(usize),
 }
 
 impl Default for ExportState {
     fn default() -> Self {
         Self {
             gif_frames: Vec::new(),
+            gif_entropy: Vec::new(),
             gif_frame_delay: 5,
             export_scale: 1,
             saving_gif: false,
@@ -46,14 +144,93 @@ impl Default for ExportState {
             gif_save_cancel: false,
             gif_save_path: None,
             gif_encoder: None,
+            allow_incomplete: false,
+            boomerang: false,
+            sheet_columns: 8,
+            milestones: Milestones::EveryPercent(25),
+            screenshot_pending: false,
+            bake_grid: false,
+            bake_entropy: false,
         }
     }
 }
 
+/// Attract mode: cycle through the bundled samples with random settings.
+pub struct DemoState {
+    pub active: bool,
+    /// Seconds to show a finished output before moving on.
+    pub pause: f32,
+    /// Images found in `samples/` when the demo started; empty means the
+    /// built-in pipe sample.
+    pub samples: Vec<PathBuf>,
+    /// Index into `samples` of the one to show next.
+    pub next: usize,
+    /// Seconds the current output has been finished.
+    pub finished_for: f32,
+}
+
+impl Default for DemoState {
+    fn default() -> Self {
+        Self {
+            active: false,
+            pause: 3.0,
+            samples: Vec::new(),
+            next: 0,
+            finished_for: 0.0,
+        }
+    }
+}
+
+/// Image being partly regenerated: cells outside `region` keep its pixels.
+pub struct InpaintState {
+    /// Loaded image, output-sized; `None` when not inpainting.
+    pub image: Option<Sample>,
+    /// Cells to regenerate, row-major over the output.
+    pub region: Vec<bool>,
+    /// Primary-drag on the canvas paints `region`, secondary-drag erases.
+    pub painting: bool,
+    /// Brush radius in cells; 0 drags out rectangles instead.
+    pub brush: usize,
+}
+
+impl Default for InpaintState {
+    fn default() -> Self {
+        Self {
+            image: None,
+            region: Vec::new(),
+            painting: false,
+            brush: 2,
+        }
+    }
+}
+
+/// What review playback does on reaching the end of the recording.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReviewMode {
+    /// Stop at the last (or, in reverse, first) frame.
+    #[default]
+    Once,
+    /// Jump back to the other end.
+    Loop,
+    /// Bounce between the ends, like a boomerang GIF.
+    PingPong,
+}
+
 pub struct PlaybackState {
     pub running: bool,
     pub steps_per_frame: usize,
     pub auto_restart: bool,
+    /// Seeds tried since the last manual reset, including the current one,
+    /// and how many more auto-restart may try.
+    pub retries: RetryBudget,
+    /// Recorded frame shown on the canvas instead of the live wave.
+    pub review_frame: Option<usize>,
+    pub review_playing: bool,
+    /// Play recorded frames backwards; flipped at each end in ping-pong mode.
+    pub review_reverse: bool,
+    pub review_mode: ReviewMode,
+    /// Seconds since the review frame last advanced.
+    pub review_elapsed: f32,
 }
 
 impl Default for PlaybackState {
@@ -62,6 +239,12 @@ impl Default for PlaybackState {
             running: false,
             steps_per_frame: 1,
             auto_restart: true,
+            retries: RetryBudget::new(100, Duration::from_secs(30)),
+            review_frame: None,
+            review_playing: false,
+            review_reverse: false,
+            review_mode: ReviewMode::Once,
+            review_elapsed: 0.0,
         }
     }
 }
@@ -74,12 +257,67 @@ pub struct Messages {
 
 pub struct App {
     pub wfc: Wfc,
+    /// Adjacency stats for the current rules, refreshed on rebuild.
+    pub health: PropagatorHealth,
+    /// Quick analysis of the solver sample under the latest requested settings.
+    pub sample_stats: SampleStats,
+    pub limits: BuildLimits,
+    pub pending_rebuild: Option<PendingRebuild>,
+    pub build_job: Option<BuildJob>,
+    /// The sample changed but its rebuild was cancelled; the next rebuild
+    /// must re-extract.
+    pub model_stale: bool,
     pub sample: Sample,
+    /// Output recoloring for the sample's colors; identity until edited.
+    pub color_map: ColorMap,
+    /// Palette extracted from a reference image and the map it suggests,
+    /// shown on the canvas until applied or discarded.
+    pub palette_transfer: Option<(Vec<[u8; 3]>, ColorMap)>,
     pub sample_path: Option<PathBuf>,
+    /// Text of the "Load URL" field.
+    pub sample_url: String,
+    /// Text of the seed field while it is being edited.
+    pub seed_text: String,
+    /// Sample is a 16-bit heightmap: preview as hillshade, export as 16-bit.
+    pub heightmap: bool,
+    /// Pixel-aligned layers solved jointly with `sample` (e.g. collision).
+    pub extra_layers: Vec<Sample>,
+    /// Maps joint output back to layers; set while `extra_layers` is non-empty.
+    pub layer_palette: Option<LayerPalette>,
+    /// Layer shown on the canvas (0 = `sample`).
+    pub shown_layer: usize,
+    /// More images to train on alongside `sample`; unused with extra layers.
+    pub extra_samples: Vec<Sample>,
+    /// Color count for "Quantize".
+    pub quantize_colors: usize,
+    /// Solver state before each manual step, newest last.
+    pub undo: Vec<WfcState>,
+    /// Primary-drag on the canvas selects an area to regenerate.
+    pub region_tool: bool,
+    /// Anchor and current cell of the area being dragged out.
+    pub region_drag: Option<((usize, usize), (usize, usize))>,
+    pub inpaint: InpaintState,
+    /// Width/height changes crop or extend the current result instead of
+    /// starting over.
+    pub keep_on_resize: bool,
     pub show_grid: bool,
+    /// Draw the remaining pattern count on uncollapsed cells.
+    pub show_counts: bool,
+    /// Tint uncollapsed cells by normalized entropy.
+    pub show_entropy: bool,
+    /// Outline cells narrowed by the latest propagation, fading out.
+    pub show_propagation: bool,
+    /// Per-cell outline intensity in `0..=1`; empty until the first step.
+    pub propagation_flash: Vec<f32>,
+    /// Hatch contradicted cells on the canvas.
+    pub hatch_contradictions: bool,
+    /// List a hovered cell's remaining patterns in a tooltip.
+    pub inspect_cells: bool,
     pub camera: CameraState,
+    pub iso: IsoState,
     pub export: ExportState,
     pub playback: PlaybackState,
+    pub demo: DemoState,
     pub messages: Messages,
 }
 
@@ -87,16 +325,47 @@ impl Default for App {
     fn default() -> Self {
         let sample = default_pipe_sample();
         let config = Config::default();
+        let sample_stats = sample.analyze(&config);
         let wfc = Wfc::new(&sample, config);
+        let health = wfc.propagator_health();
 
         let mut app = Self {
             wfc,
+            health,
+            sample_stats,
+            color_map: ColorMap::from_sample(&sample),
+            palette_transfer: None,
+            limits: BuildLimits::default(),
+            pending_rebuild: None,
+            build_job: None,
+            model_stale: false,
             sample,
             sample_path: None,
+            sample_url: String::new(),
+            seed_text: String::new(),
+            heightmap: false,
+            extra_layers: Vec::new(),
+            layer_palette: None,
+            shown_layer: 0,
+            extra_samples: Vec::new(),
+            quantize_colors: 8,
+            undo: Vec::new(),
+            region_tool: false,
+            region_drag: None,
+            inpaint: InpaintState::default(),
+            keep_on_resize: true,
             show_grid: false,
+            show_counts: false,
+            show_entropy: false,
+            show_propagation: false,
+            propagation_flash: Vec::new(),
+            hatch_contradictions: true,
+            inspect_cells: false,
             camera: CameraState::default(),
+            iso: IsoState::default(),
             export: ExportState::default(),
             playback: PlaybackState::default(),
+            demo: DemoState::default(),
             messages: Messages::default(),
         };
         app.capture_frame();
@@ -113,38 +382,626 @@ impl App {
         self.wfc.config()
     }
 
+    /// Apply a config change; output-only settings skip pattern extraction.
     pub fn rebuild_with_config(&mut self, config: Config) {
-        self.wfc = Wfc::new(&self.sample, config);
-        self.playback.running = false;
-        self.export.gif_frames.clear();
-        self.capture_frame();
+        self.build(config, false, false);
     }
 
+    /// Full rebuild; call after the sample or its layers change.
     pub fn rebuild(&mut self) {
-        self.rebuild_with_config(self.wfc.config().clone());
+        let mut config = self.wfc.config().clone();
+        // A mask only fits the sample it was drawn for
+        if config.importance.len() != self.sample.width * self.sample.height {
+            config.importance.clear();
+        }
+        config.sample_weights.truncate(1 + self.extra_samples.len());
+        self.build(config, true, false);
+    }
+
+    /// Run a rebuild held back by the limits.
+    pub fn confirm_rebuild(&mut self) {
+        if let Some(pending) = self.pending_rebuild.take() {
+            self.build(pending.config, pending.full, true);
+        }
+    }
+
+    /// Drop a held-back rebuild and keep the current model.
+    pub fn cancel_rebuild(&mut self) {
+        let Some(pending) = self.pending_rebuild.take() else {
+            return;
+        };
+        self.model_stale |= pending.full;
+        self.sample_stats = Sample::analyze_all(&self.solver_samples(), self.wfc.config());
+        self.messages.error = Some("Rebuild cancelled; model too large".to_string());
+    }
+
+    fn build(&mut self, config: Config, full: bool, confirmed: bool) {
+        let samples = self.solver_samples();
+        self.sample_stats = Sample::analyze_all(&samples, &config);
+        if !confirmed && self.limits.exceeded_by(&self.sample_stats) {
+            self.playback.running = false;
+            self.pending_rebuild = Some(PendingRebuild { config, full });
+            return;
+        }
+        if full || self.model_stale || self.wfc.needs_extraction(&config) {
+            self.start_build_job(samples, config, full);
+        } else {
+            self.wfc.reconfigure_samples(&samples, config);
+            self.after_rebuild();
+        }
+    }
+
+    /// Extract on a worker thread so large samples don't freeze the window.
+    /// Quick builds finish within the wait and never show progress.
+    fn start_build_job(&mut self, samples: Vec<Sample>, config: Config, full: bool) {
+        self.cancel_build_job();
+        let progress = Arc::new(Mutex::new(None));
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = mpsc::channel();
+        {
+            let progress = Arc::clone(&progress);
+            let cancel = Arc::clone(&cancel);
+            std::thread::spawn(move || {
+                let result = Wfc::from_samples_with_progress(&samples, config, |p| {
+                    *progress.lock().unwrap() = Some(p);
+                    !cancel.load(Ordering::Relaxed)
+                });
+                let _ = tx.send(result);
+            });
+        }
+        self.playback.running = false;
+        self.build_job = Some(BuildJob {
+            progress,
+            cancel,
+            result: rx,
+            full,
+        });
+        self.poll_build_job(Duration::from_millis(100));
+    }
+
+    /// Apply the worker's result once it arrives, waiting up to `timeout`.
+    pub fn poll_build_job(&mut self, timeout: Duration) {
+        let Some(job) = &self.build_job else {
+            return;
+        };
+        let result = match job.result.recv_timeout(timeout) {
+            Ok(result) => result.map_err(|e| e.to_string()),
+            Err(mpsc::RecvTimeoutError::Timeout) => return,
+            Err(mpsc::RecvTimeoutError::Disconnected) => Err("build thread crashed".to_string()),
+        };
+        let full = job.full;
+        self.build_job = None;
+        match result {
+            Ok(wfc) => {
+                self.wfc = wfc;
+                self.model_stale = false;
+                self.after_rebuild();
+            }
+            Err(e) => {
+                self.model_stale |= full;
+                self.sample_stats = Sample::analyze_all(&self.solver_samples(), self.wfc.config());
+                self.messages.error = Some(format!("Rebuild stopped: {}", e));
+            }
+        }
+    }
+
+    /// Ask the worker to stop; its result is discarded.
+    pub fn cancel_build_job(&mut self) {
+        if let Some(job) = self.build_job.take() {
+            job.cancel.store(true, Ordering::Relaxed);
+            self.model_stale |= job.full;
+        }
+    }
+
+    /// Crop or extend the output, keeping collapsed cells.
+    pub fn resize_output(&mut self, width: usize, height: usize) {
+        self.wfc.resize(width, height);
+        self.sample_stats = Sample::analyze_all(&self.solver_samples(), self.wfc.config());
+        self.after_rebuild();
+    }
+
+    fn after_rebuild(&mut self) {
+        self.pin_inpaint_image();
+        self.health = self.wfc.propagator_health();
+        self.undo.clear();
+        self.playback.running = false;
+        self.playback.retries.restart();
+        self.clear_frames();
+        self.propagation_flash.clear();
+        self.go_live();
+        self.capture_frame();
     }
 
     pub fn reset(&mut self) {
         self.wfc.reset();
+        self.restart();
+    }
+
+    /// Start over from `seed`, e.g. to regenerate a shared result.
+    pub fn reset_with_seed(&mut self, seed: u64) {
+        self.wfc.reseed(seed);
+        self.restart();
+    }
+
+    fn restart(&mut self) {
+        self.undo.clear();
         self.playback.running = false;
-        self.export.gif_frames.clear();
+        self.playback.retries.restart();
+        self.clear_frames();
+        self.propagation_flash.clear();
+        self.go_live();
+        self.capture_frame();
+    }
+
+    /// Step once, lighting up the cells the step's propagation narrowed.
+    pub fn step_once(&mut self) -> StepOutcome {
+        let outcome = self.wfc.step();
+        self.flash_changed();
+        outcome
+    }
+
+    /// Remember the current state so [`App::undo_step`] can return to it.
+    /// Each snapshot is a full copy of the wave and support counts, so the
+    /// oldest are dropped past 256 MiB, keeping at least the newest.
+    pub fn push_undo(&mut self) {
+        const MAX_UNDO_BYTES: usize = 256 << 20;
+        let saved = self.wfc.snapshot();
+        let mut held: usize = self.undo.iter().map(WfcState::heap_bytes).sum();
+        while !self.undo.is_empty() && held + saved.heap_bytes() > MAX_UNDO_BYTES {
+            held -= self.undo.remove(0).heap_bytes();
+        }
+        self.undo.push(saved);
+    }
+
+    /// Go back to the state before the latest manual step.
+    pub fn undo_step(&mut self) {
+        let Some(saved) = self.undo.pop() else {
+            return;
+        };
+        match self.wfc.restore(&saved) {
+            Ok(()) => {
+                self.playback.running = false;
+                self.propagation_flash.clear();
+                self.capture_frame();
+            }
+            Err(e) => self.messages.error = Some(format!("Undo failed: {}", e)),
+        }
+    }
+
+    /// Reopen the cells between two corners (inclusive) and resume
+    /// playback to fill them in again; undoable like a step.
+    pub fn regenerate_region(&mut self, a: (usize, usize), b: (usize, usize)) {
+        self.push_undo();
+        self.wfc.uncollapse_region(
+            a.0.min(b.0),
+            a.1.min(b.1),
+            a.0.max(b.0) + 1,
+            a.1.max(b.1) + 1,
+        );
+        self.propagation_flash.clear();
         self.capture_frame();
+        self.play();
+    }
+
+    /// Load an image to inpaint, resizing the output to match; the whole
+    /// image is kept until an area is painted.
+    pub fn open_inpaint_dialog(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("Images", &["png", "jpg", "jpeg", "bmp", "gif"])
+            .pick_file()
+        else {
+            return;
+        };
+        let image = match Sample::from_image(&path) {
+            Ok(image) => image,
+            Err(e) => {
+                self.messages.error = Some(format!("Failed to load image: {}", e));
+                return;
+            }
+        };
+        let config = Config {
+            output_width: image.width,
+            output_height: image.height,
+            mask: Vec::new(),
+            ..self.config().clone()
+        };
+        self.inpaint.region = vec![false; image.width * image.height];
+        self.inpaint.image = Some(image);
+        self.inpaint.painting = true;
+        self.region_tool = false;
+        self.messages.error = None;
+        self.rebuild_with_config(config);
+    }
+
+    /// Pin the cells outside the painted area to the inpainted image and
+    /// start over; drops the image once the output no longer fits it.
+    fn pin_inpaint_image(&mut self) {
+        let Some(image) = &self.inpaint.image else {
+            return;
+        };
+        let config = self.wfc.config();
+        if (image.width, image.height) != (config.output_width, config.output_height) {
+            self.inpaint = InpaintState::default();
+            self.messages.error = Some("Output resized; inpainting image dropped".to_string());
+            return;
+        }
+        self.wfc.clear_pins();
+        self.wfc.reset();
+        match self.wfc.inpaint(image, &self.inpaint.region) {
+            Ok(0) => {}
+            Ok(open) => {
+                self.messages.error = Some(format!(
+                    "{} cells of the image match no pattern and are left open",
+                    open
+                ));
+            }
+            Err(e) => self.messages.error = Some(format!("Inpainting failed: {}", e)),
+        }
+    }
+
+    /// Start or resume playback. The retry time limit counts from here
+    /// rather than from the rebuild or reset, which may be long past.
+    pub fn play(&mut self) {
+        if !self.playback.running {
+            self.playback.retries.restart_clock();
+        }
+        self.playback.running = true;
+    }
+
+    /// Regenerate the painted area of the inpainted image.
+    pub fn run_inpaint(&mut self) {
+        self.pin_inpaint_image();
+        self.restart();
+        self.play();
+    }
+
+    /// Stop inpainting and start over without the image.
+    pub fn clear_inpaint(&mut self) {
+        self.inpaint = InpaintState::default();
+        self.wfc.clear_pins();
+        self.reset();
+    }
+
+    /// Process a single ban (or an unpropagated collapse) for debugging.
+    pub fn micro_step(&mut self) -> StepOutcome {
+        let outcome = self.wfc.micro_step();
+        self.flash_changed();
+        outcome
+    }
+
+    fn flash_changed(&mut self) {
+        let w = self.wfc.config().output_width;
+        let h = self.wfc.config().output_height;
+        self.propagation_flash.resize(w * h, 0.0);
+        for (x, y) in self.wfc.changed_cells() {
+            self.propagation_flash[y * w + x] = 1.0;
+        }
+    }
+
+    /// Fade the propagation outlines; true while any are still visible.
+    pub fn fade_propagation(&mut self, dt: f32) -> bool {
+        let mut visible = false;
+        for f in &mut self.propagation_flash {
+            *f = (*f - dt * 2.0).max(0.0);
+            visible |= *f > 0.0;
+        }
+        visible
+    }
+
+    /// Retry with the next seed after a contradiction, keeping the result
+    /// reproducible; stops playback once the retry budget is spent.
+    pub fn restart_with_next_seed(&mut self) {
+        if !self.playback.retries.retry(&mut self.wfc) {
+            self.playback.running = false;
+            self.messages.error = Some(format!(
+                "Gave up after {} attempts ({:.1}s)",
+                self.playback.retries.attempts(),
+                self.playback.retries.elapsed().as_secs_f32()
+            ));
+            return;
+        }
+        self.undo.clear();
+        self.propagation_flash.clear();
+        self.capture_frame();
+    }
+
+    /// Rebuild with randomized settings to explore what suits a new sample.
+    pub fn surprise(&mut self) {
+        let mut config = self.config().clone();
+        config.randomize(&self.sample, None);
+        self.messages.error = None;
+        self.messages.success = Some(format!(
+            "Surprise: N={}, symmetry {}, {:?} input, {:?}{}",
+            config.pattern_size,
+            config.symmetry,
+            config.input_boundary,
+            config.boundary,
+            if config.edges.is_ground() {
+                ", ground"
+            } else {
+                ""
+            },
+        ));
+        self.rebuild_with_config(config);
     }
 
     pub fn load_sample(&mut self, path: PathBuf) {
         match Sample::from_image(&path) {
             Ok(sample) => {
-                self.sample = sample;
-                self.sample_path = Some(path);
+                self.set_sample(sample, Some(path));
                 self.messages.error = None;
                 self.messages.success = Some("Sample loaded successfully".to_string());
-                self.export.gif_frames.clear();
                 self.rebuild();
             }
             Err(e) => self.messages.error = Some(format!("Failed to load: {}", e)),
         }
     }
 
+    /// Fetch the sample from `sample_url`. Blocks the UI until the
+    /// download finishes or times out.
+    #[cfg(feature = "url")]
+    pub fn load_url(&mut self) {
+        let url = self.sample_url.trim().to_string();
+        match Sample::from_url(&url) {
+            Ok(sample) => {
+                self.set_sample(sample, None);
+                self.messages.error = None;
+                self.messages.success = Some(format!("Sample fetched from {}", url));
+                self.rebuild();
+            }
+            Err(e) => self.messages.error = Some(format!("Failed to fetch: {}", e)),
+        }
+    }
+
+    /// Replace the sample with a plain image, dropping what belonged to the
+    /// old one. The caller rebuilds.
+    fn set_sample(&mut self, sample: Sample, path: Option<PathBuf>) {
+        self.sample = sample;
+        self.color_map = ColorMap::from_sample(&self.sample);
+        self.palette_transfer = None;
+        self.sample_path = path;
+        self.heightmap = false;
+        self.extra_layers.clear();
+        self.extra_samples.clear();
+        self.clear_frames();
+    }
+
+    pub fn load_heightmap(&mut self, path: PathBuf) {
+        match Heightmap::from_image(&path) {
+            Ok(hm) => {
+                self.sample = hm.to_sample();
+                self.color_map = ColorMap::default();
+                self.palette_transfer = None;
+                self.sample_path = Some(path);
+                self.heightmap = true;
+                self.extra_layers.clear();
+                self.extra_samples.clear();
+                self.messages.error = None;
+                self.messages.success = Some("Heightmap loaded successfully".to_string());
+                self.clear_frames();
+                self.rebuild();
+            }
+            Err(e) => self.messages.error = Some(format!("Failed to load: {}", e)),
+        }
+    }
+
+    pub fn open_heightmap_dialog(&mut self) {
+        if let Some(path) = rfd::FileDialog::new()
+            .add_filter("Images", &["png", "tif", "tiff"])
+            .set_directory("samples")
+            .pick_file()
+        {
+            self.load_heightmap(path);
+        }
+    }
+
+    /// Reduce the sample to `quantize_colors` colors in place, merging
+    /// near-identical shades before extraction.
+    pub fn quantize_sample(&mut self) {
+        self.sample = self.sample.quantize(self.quantize_colors);
+        self.color_map = ColorMap::from_sample(&self.sample);
+        self.palette_transfer = None;
+        self.clear_frames();
+        self.rebuild();
+    }
+
+    /// Sample fed to the solver: the joint sample when extra layers are loaded.
+    fn solver_sample(&mut self) -> Sample {
+        if self.extra_layers.is_empty() {
+            self.layer_palette = None;
+            return self.sample.clone();
+        }
+        let mut layers = vec![self.sample.clone()];
+        layers.extend(self.extra_layers.iter().cloned());
+        match LayeredSample::new(layers) {
+            Ok(layered) => {
+                let (joint, palette) = layered.joint();
+                self.layer_palette = Some(palette);
+                joint
+            }
+            Err(e) => {
+                self.messages.error = Some(format!("Layers dropped: {}", e));
+                self.extra_layers.clear();
+                self.layer_palette = None;
+                self.sample.clone()
+            }
+        }
+    }
+
+    /// Every sample fed to the solver: the solver sample, then the extra
+    /// samples unless layers are loaded.
+    fn solver_samples(&mut self) -> Vec<Sample> {
+        let mut samples = vec![self.solver_sample()];
+        if self.extra_layers.is_empty() {
+            samples.extend(self.extra_samples.iter().cloned());
+        }
+        samples
+    }
+
+    pub fn add_sample(&mut self, path: PathBuf) {
+        match Sample::from_image(&path) {
+            Ok(sample) => {
+                self.extra_samples.push(sample);
+                self.messages.error = None;
+                self.messages.success = Some("Sample added".to_string());
+                self.rebuild();
+            }
+            Err(e) => self.messages.error = Some(format!("Failed to load: {}", e)),
+        }
+    }
+
+    pub fn open_add_sample_dialog(&mut self) {
+        if let Some(path) = rfd::FileDialog::new()
+            .add_filter("Images", &["png", "jpg", "jpeg", "bmp", "gif"])
+            .set_directory("samples")
+            .pick_file()
+        {
+            self.add_sample(path);
+        }
+    }
+
+    pub fn clear_samples(&mut self) {
+        self.extra_samples.clear();
+        self.rebuild();
+    }
+
+    pub fn add_layer(&mut self, path: PathBuf) {
+        match Sample::from_image(&path) {
+            Ok(layer) if (layer.width, layer.height) != (self.sample.width, self.sample.height) => {
+                self.messages.error = Some(format!(
+                    "Layer is {}x{}, sample is {}x{}",
+                    layer.width, layer.height, self.sample.width, self.sample.height
+                ));
+            }
+            Ok(layer) => {
+                self.extra_layers.push(layer);
+                self.messages.error = None;
+                self.messages.success = Some("Layer added".to_string());
+                self.rebuild();
+            }
+            Err(e) => self.messages.error = Some(format!("Failed to load: {}", e)),
+        }
+    }
+
+    pub fn open_layer_dialog(&mut self) {
+        if let Some(path) = rfd::FileDialog::new()
+            .add_filter("Images", &["png", "jpg", "jpeg", "bmp", "gif"])
+            .set_directory("samples")
+            .pick_file()
+        {
+            self.add_layer(path);
+        }
+    }
+
+    pub fn clear_layers(&mut self) {
+        self.extra_layers.clear();
+        self.shown_layer = 0;
+        self.rebuild();
+    }
+
+    /// Rendered output split per layer (a single entry without extra layers),
+    /// with `color_map` applied to the first.
+    pub fn layer_colors(&self) -> Vec<Vec<[u8; 3]>> {
+        let mut layers = match &self.layer_palette {
+            Some(palette) => palette.split_output(&self.wfc),
+            None => vec![self.wfc.render()],
+        };
+        let map = self
+            .palette_transfer
+            .as_ref()
+            .map_or(&self.color_map, |(_, map)| map);
+        if !map.is_identity() {
+            layers[0] = map.apply(&layers[0]);
+        }
+        layers
+    }
+
+    /// Preview the sample colors matched to a reference image's dominant colors.
+    pub fn open_palette_image_dialog(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("Images", &["png", "jpg", "jpeg", "bmp", "gif"])
+            .pick_file()
+        else {
+            return;
+        };
+        match Sample::from_image(&path) {
+            Ok(reference) => {
+                let palette = dominant_colors(&reference.pixels, self.color_map.entries.len());
+                let mut map = self.color_map.clone();
+                map.match_nearest(&palette);
+                self.palette_transfer = Some((palette, map));
+            }
+            Err(e) => self.messages.error = Some(format!("Failed to load: {}", e)),
+        }
+    }
+
+    pub fn open_importance_dialog(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("Images", &["png", "jpg", "jpeg", "bmp", "gif"])
+            .set_directory("samples")
+            .pick_file()
+        else {
+            return;
+        };
+        match self.sample.load_importance(&path) {
+            Ok(importance) => {
+                let config = Config {
+                    importance,
+                    ..self.config().clone()
+                };
+                self.messages.error = None;
+                self.rebuild_with_config(config);
+            }
+            Err(e) => self.messages.error = Some(format!("Failed to load mask: {}", e)),
+        }
+    }
+
+    /// Assign colors from a palette file to the sample colors.
+    pub fn open_palette_dialog(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("Palettes", &["gpl", "hex", "txt"])
+            .pick_file()
+        else {
+            return;
+        };
+        match load_palette(&path) {
+            Ok(palette) => {
+                self.color_map.assign(&palette);
+                self.messages.success = Some(format!("Applied {} palette colors", palette.len()));
+            }
+            Err(e) => self.messages.error = Some(format!("Failed to load palette: {}", e)),
+        }
+    }
+
+    /// Output colors as displayed: the shown layer, hillshaded in heightmap mode.
+    pub fn display_colors(&self) -> Vec<[u8; 3]> {
+        if self.heightmap {
+            return Heightmap::from_wfc(&self.wfc).hillshade();
+        }
+        let mut layers = self.layer_colors();
+        layers.swap_remove(self.shown_layer.min(layers.len() - 1))
+    }
+
+    /// Isometric projection of `colors`, listing any new colors in the height
+    /// table at their luminance.
+    pub fn iso_scene(&mut self, colors: &[[u8; 3]]) -> IsoScene {
+        let heights = colors
+            .iter()
+            .map(|&color| {
+                let entry = match self.iso.heights.iter().position(|(c, _)| *c == color) {
+                    Some(i) => i,
+                    None => {
+                        self.iso.heights.push((color, iso::luma_height(color)));
+                        self.iso.heights.len() - 1
+                    }
+                };
+                self.iso.heights[entry].1 * self.iso.max_height
+            })
+            .collect::<Vec<_>>();
+        let config = self.wfc.config();
+        iso::project(config.output_width, config.output_height, colors, &heights)
+    }
+
     pub fn open_file_dialog(&mut self) {
         if let Some(path) = rfd::FileDialog::new()
             .add_filter("Images", &["png", "jpg", "jpeg", "bmp", "gif"])
@@ -155,8 +1012,48 @@ impl App {
         }
     }
 
+    /// Solver footprint plus undo snapshots and the frames recorded for
+    /// GIF export.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let frames = self
+            .export
+            .gif_frames
+            .iter()
+            .chain(&self.export.gif_entropy)
+            .map(Vec::capacity)
+            .sum();
+        let solver = self.wfc.memory_usage();
+        MemoryUsage {
+            backtracking: solver.backtracking
+                + self.undo.iter().map(WfcState::heap_bytes).sum::<usize>(),
+            frames,
+            ..solver
+        }
+    }
+
+    pub fn clear_frames(&mut self) {
+        self.export.gif_frames.clear();
+        self.export.gif_entropy.clear();
+    }
+
+    /// Normalized entropy per cell as `1..=255`, with `0` for decided cells.
+    pub fn entropy_levels(&self) -> Vec<u8> {
+        self.wfc
+            .possibility_counts()
+            .into_iter()
+            .zip(self.wfc.entropy_grid())
+            .map(|(count, entropy)| {
+                if count > 1 {
+                    (entropy * 254.0) as u8 + 1
+                } else {
+                    0
+                }
+            })
+            .collect()
+    }
+
     pub fn capture_frame(&mut self) {
-        let colors = self.wfc.render();
+        let colors = self.display_colors();
         let config = self.wfc.config();
         let w = config.output_width;
         let h = config.output_height;
@@ -170,5 +1067,7 @@ impl App {
         }
 
         self.export.gif_frames.push(frame_data);
+        let entropy = self.entropy_levels();
+        self.export.gif_entropy.push(entropy);
     }
 }
@@ -0,0 +1,128 @@
+//! OpenRaster (`.ora`) writer: a ZIP of PNG layers plus `stack.xml`, opened
+//! as a layered image by Krita, GIMP and MyPaint.
+
+use std::io::Cursor;
+
+use image::{ImageFormat, RgbImage, imageops};
+
+/// Layers bottom to top, all the same size. The topmost doubles as the
+/// merged image and thumbnail.
+pub fn encode(layers: &[(String, RgbImage)]) -> Result<Vec<u8>, image::ImageError> {
+    let top = &layers.last().expect("at least one layer").1;
+    let (w, h) = top.dimensions();
+
+    let mut zip = ZipWriter::default();
+    // Must come first and uncompressed so the type sniffs without unzipping
+    zip.add("mimetype", b"image/openraster");
+
+    let mut stack = format!(
+        "<?xml version='1.0' encoding='UTF-8'?>\n<image version=\"0.0.3\" w=\"{}\" h=\"{}\">\n <stack>\n",
+        w, h
+    );
+    // stack.xml lists the topmost layer first
+    for (i, (name, image)) in layers.iter().enumerate().rev() {
+        let src = format!("data/layer{}.png", i);
+        stack.push_str(&format!(
+            "  <layer name=\"{}\" src=\"{}\" x=\"0\" y=\"0\" opacity=\"1.0\" visibility=\"visible\"/>\n",
+            xml_escape(name),
+            src
+        ));
+        zip.add(&src, &png(image)?);
+    }
+    stack.push_str(" </stack>\n</image>\n");
+    zip.add("stack.xml", stack.as_bytes());
+
+    zip.add("mergedimage.png", &png(top)?);
+    let scale = 256.0 / w.max(h) as f32;
+    let thumbnail = if scale < 1.0 {
+        let (tw, th) = ((w as f32 * scale).max(1.0), (h as f32 * scale).max(1.0));
+        imageops::thumbnail(top, tw as u32, th as u32)
+    } else {
+        top.clone()
+    };
+    zip.add("Thumbnails/thumbnail.png", &png(&thumbnail)?);
+    Ok(zip.finish())
+}
+
+fn png(image: &RgbImage) -> Result<Vec<u8>, image::ImageError> {
+    let mut bytes = Vec::new();
+    image.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)?;
+    Ok(bytes)
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Minimal ZIP archive of stored (uncompressed) entries; the PNGs inside
+/// are already compressed.
+#[derive(Default)]
+struct ZipWriter {
+    out: Vec<u8>,
+    /// Central directory records, written after the entries.
+    central: Vec<u8>,
+    entries: u16,
+}
+
+impl ZipWriter {
+    /// DOS date for 1980-01-01, the earliest a ZIP can record.
+    const DATE: u16 = (1 << 5) | 1;
+
+    fn add(&mut self, name: &str, data: &[u8]) {
+        let crc = crc32fast::hash(data);
+        let offset = self.out.len() as u32;
+        let fields = |buf: &mut Vec<u8>| {
+            put16(buf, 20); // version needed: 2.0
+            put16(buf, 0); // flags
+            put16(buf, 0); // stored
+            put16(buf, 0); // time
+            put16(buf, Self::DATE);
+            put32(buf, crc);
+            put32(buf, data.len() as u32);
+            put32(buf, data.len() as u32);
+            put16(buf, name.len() as u16);
+            put16(buf, 0); // extra field length
+        };
+
+        put32(&mut self.out, 0x0403_4b50);
+        fields(&mut self.out);
+        self.out.extend_from_slice(name.as_bytes());
+        self.out.extend_from_slice(data);
+
+        put32(&mut self.central, 0x0201_4b50);
+        put16(&mut self.central, 20); // version made by
+        fields(&mut self.central);
+        put16(&mut self.central, 0); // comment length
+        put16(&mut self.central, 0); // disk number
+        put16(&mut self.central, 0); // internal attributes
+        put32(&mut self.central, 0); // external attributes
+        put32(&mut self.central, offset);
+        self.central.extend_from_slice(name.as_bytes());
+        self.entries += 1;
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let offset = self.out.len() as u32;
+        self.out.extend_from_slice(&self.central);
+        put32(&mut self.out, 0x0605_4b50);
+        put16(&mut self.out, 0); // this disk
+        put16(&mut self.out, 0); // disk with the directory
+        put16(&mut self.out, self.entries);
+        put16(&mut self.out, self.entries);
+        put32(&mut self.out, self.central.len() as u32);
+        put32(&mut self.out, offset);
+        put16(&mut self.out, 0); // comment length
+        self.out
+    }
+}
+
+fn put16(out: &mut Vec<u8>, v: u16) {
+    out.extend_from_slice(&v.to_le_bytes());
+}
+
+fn put32(out: &mut Vec<u8>, v: u32) {
+    out.extend_from_slice(&v.to_le_bytes());
+}
@@ -0,0 +1,150 @@
+use eframe::egui;
+
+use super::{App, ReviewMode};
+
+impl App {
+    /// Colors of the recorded frame under review, or `None` when live.
+    pub fn review_colors(&self) -> Option<Vec<[u8; 3]>> {
+        let frame = self.export.gif_frames.get(self.playback.review_frame?)?;
+        Some(frame.chunks_exact(4).map(|p| [p[0], p[1], p[2]]).collect())
+    }
+
+    /// Advance review playback at the GIF frame delay; true while playing.
+    pub fn advance_review(&mut self, dt: f32) -> bool {
+        let Some(mut frame) = self.playback.review_frame else {
+            return false;
+        };
+        if !self.playback.review_playing {
+            return false;
+        }
+
+        let last = self.export.gif_frames.len().saturating_sub(1);
+        let delay = self.export.gif_frame_delay.max(1) as f32 / 100.0;
+        self.playback.review_elapsed += dt;
+        while self.playback.review_elapsed >= delay {
+            self.playback.review_elapsed -= delay;
+            let at_end = if self.playback.review_reverse {
+                frame == 0
+            } else {
+                frame >= last
+            };
+            if at_end {
+                match self.playback.review_mode {
+                    ReviewMode::Once => {
+                        self.playback.review_playing = false;
+                        break;
+                    }
+                    ReviewMode::Loop => {
+                        frame = if self.playback.review_reverse {
+                            last
+                        } else {
+                            0
+                        };
+                        continue;
+                    }
+                    ReviewMode::PingPong => {
+                        self.playback.review_reverse = !self.playback.review_reverse;
+                        if last == 0 {
+                            continue;
+                        }
+                    }
+                }
+            }
+            if self.playback.review_reverse {
+                frame -= 1;
+            } else {
+                frame += 1;
+            }
+        }
+        self.playback.review_frame = Some(frame);
+        true
+    }
+
+    pub fn go_live(&mut self) {
+        self.playback.review_frame = None;
+        self.playback.review_playing = false;
+    }
+
+    /// Scrubber under the canvas for the frames recorded for GIF export.
+    pub fn show_timeline(&mut self, ctx: &egui::Context) {
+        let count = self.export.gif_frames.len();
+        if count == 0 || self.demo.active {
+            self.go_live();
+            return;
+        }
+
+        egui::TopBottomPanel::bottom("timeline").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                let reviewing = self.playback.review_frame.is_some();
+                let play_label = if self.playback.review_playing {
+                    "⏸"
+                } else if self.playback.review_reverse {
+                    "◀"
+                } else {
+                    "▶"
+                };
+                if ui
+                    .button(play_label)
+                    .on_hover_text("Replay recorded frames")
+                    .clicked()
+                {
+                    // Restart from the far end when play would stop immediately
+                    let start = if self.playback.review_reverse {
+                        count - 1
+                    } else {
+                        0
+                    };
+                    let at_end = self.playback.review_frame.is_none_or(|f| {
+                        if self.playback.review_reverse {
+                            f == 0
+                        } else {
+                            f + 1 >= count
+                        }
+                    });
+                    if !self.playback.review_playing && at_end {
+                        self.playback.review_frame = Some(start);
+                    }
+                    self.playback.review_playing = !self.playback.review_playing;
+                    self.playback.review_elapsed = 0.0;
+                }
+                ui.toggle_value(&mut self.playback.review_reverse, "⇄")
+                    .on_hover_text("Play in reverse");
+                egui::ComboBox::from_id_salt("review_mode")
+                    .width(80.0)
+                    .selected_text(match self.playback.review_mode {
+                        ReviewMode::Once => "Once",
+                        ReviewMode::Loop => "Loop",
+                        ReviewMode::PingPong => "Ping-pong",
+                    })
+                    .show_ui(ui, |ui| {
+                        let mode = &mut self.playback.review_mode;
+                        ui.selectable_value(mode, ReviewMode::Once, "Once");
+                        ui.selectable_value(mode, ReviewMode::Loop, "Loop");
+                        ui.selectable_value(mode, ReviewMode::PingPong, "Ping-pong");
+                    });
+
+                let mut frame = self.playback.review_frame.unwrap_or(count - 1);
+                ui.spacing_mut().slider_width = (ui.available_width() - 140.0).max(100.0);
+                if ui
+                    .add(egui::Slider::new(&mut frame, 0..=count - 1).text("frame"))
+                    .changed()
+                {
+                    self.playback.review_frame = Some(frame);
+                    self.playback.review_playing = false;
+                }
+
+                if ui
+                    .add_enabled(reviewing, egui::Button::new("Live"))
+                    .on_hover_text("Back to the current solver state")
+                    .clicked()
+                {
+                    self.go_live();
+                }
+            });
+        });
+
+        if self.advance_review(ctx.input(|i| i.stable_dt)) {
+            ctx.request_repaint();
+        }
+    }
+}
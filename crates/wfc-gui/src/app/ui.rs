@@ -1,8 +1,14 @@
+use std::time::Duration;
+
 use eframe::egui::{self, Color32, Pos2, Rect, Stroke, Vec2};
 
-use wfc_core::{Boundary, StepOutcome};
+use wfc_core::{
+    Boundary, BuildPhase, Config, EdgeConstraint, Edges, Heightmap, Heuristic, OutputSymmetry,
+    RowProfile, Sample, StepOutcome, Symmetry, default_cache_dir,
+};
 
-use super::App;
+use super::export::heat_color;
+use super::{App, Milestones};
 
 fn config_slider(
     ui: &mut egui::Ui,
@@ -20,6 +26,115 @@ fn config_slider(
     changed
 }
 
+/// Kind picker for one output edge plus the color or pattern list it
+/// takes. `color` seeds a newly picked color constraint.
+fn edge_editor(
+    ui: &mut egui::Ui,
+    label: &str,
+    edge: &mut EdgeConstraint,
+    color: [u8; 3],
+    num_patterns: usize,
+) -> bool {
+    let kinds = [
+        (EdgeConstraint::None, "None"),
+        (EdgeConstraint::SampleTop, "Sample top"),
+        (EdgeConstraint::SampleBottom, "Sample bottom"),
+        (EdgeConstraint::SampleLeft, "Sample left"),
+        (EdgeConstraint::SampleRight, "Sample right"),
+        (EdgeConstraint::Color(color), "Color"),
+        (EdgeConstraint::Patterns(vec![0]), "Patterns"),
+    ];
+    let kind_of = |edge: &EdgeConstraint| {
+        kinds
+            .iter()
+            .position(|(k, _)| std::mem::discriminant(k) == std::mem::discriminant(edge))
+            .unwrap_or(0)
+    };
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        let current = kind_of(edge);
+        egui::ComboBox::from_label(label)
+            .selected_text(kinds[current].1)
+            .show_ui(ui, |ui| {
+                for (i, (kind, name)) in kinds.iter().enumerate() {
+                    if ui.selectable_label(i == current, *name).clicked() && i != current {
+                        *edge = kind.clone();
+                        changed = true;
+                    }
+                }
+            });
+        match edge {
+            EdgeConstraint::Color(color) => {
+                changed |= ui.color_edit_button_srgb(color).changed();
+            }
+            EdgeConstraint::Patterns(patterns) => {
+                let mut remove = None;
+                for (i, p) in patterns.iter_mut().enumerate() {
+                    changed |= ui
+                        .add(egui::DragValue::new(p).range(0..=num_patterns.saturating_sub(1)))
+                        .changed();
+                    if ui.small_button("x").clicked() {
+                        remove = Some(i);
+                    }
+                }
+                if let Some(i) = remove {
+                    patterns.remove(i);
+                    changed = true;
+                }
+                if ui.small_button("+").clicked() {
+                    patterns.push(0);
+                    changed = true;
+                }
+            }
+            _ => {}
+        }
+    });
+    changed
+}
+
+/// Picker for the output boundary or the sample's input wrapping; only
+/// the `output` one offers mirroring and clamping.
+fn boundary_combo(ui: &mut egui::Ui, label: &str, boundary: &mut Boundary, output: bool) -> bool {
+    let mut choices = vec![
+        (Boundary::Fixed, "Fixed"),
+        (Boundary::PeriodicX, "Periodic X"),
+        (Boundary::PeriodicY, "Periodic Y"),
+        (Boundary::Periodic, "Periodic"),
+    ];
+    if output {
+        choices.extend([(Boundary::Mirror, "Mirror"), (Boundary::Clamp, "Clamp")]);
+    }
+    let selected = choices
+        .iter()
+        .find(|&&(b, _)| b == *boundary)
+        .map_or("Fixed", |&(_, name)| name);
+    let mut changed = false;
+    egui::ComboBox::from_label(label)
+        .selected_text(selected)
+        .show_ui(ui, |ui| {
+            for (b, name) in choices {
+                changed |= ui.selectable_value(boundary, b, name).changed();
+            }
+        });
+    changed
+}
+
+/// Bytes in the largest unit that keeps the number at least 1.
+fn format_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
 impl eframe::App for App {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         if self.export.saving_gif {
@@ -27,17 +142,31 @@ impl eframe::App for App {
             self.show_gif_saving_modal(ctx);
             return;
         }
+        self.update_demo(ctx);
+        if self.pending_rebuild.is_some() {
+            self.show_rebuild_confirm(ctx);
+            return;
+        }
+        if self.build_job.is_some() {
+            self.poll_build_job(std::time::Duration::ZERO);
+            self.show_build_progress(ctx);
+            return;
+        }
 
+        // Kiosk-style: the demo shows only the canvas
         egui::SidePanel::left("controls")
             .min_width(200.0)
-            .show(ctx, |ui| {
+            .show_animated(ctx, !self.demo.active, |ui| {
                 ui.heading("WFC Controls");
                 ui.separator();
 
                 ui.horizontal(|ui| {
                     ui.label("Status:");
                     if self.wfc.has_contradiction() {
-                        ui.colored_label(Color32::RED, "Contradiction!");
+                        let label = ui.colored_label(Color32::RED, "Contradiction!");
+                        if let Some(info) = self.wfc.contradiction_info() {
+                            label.on_hover_text(info.to_string());
+                        }
                     } else if self.wfc.is_done() {
                         ui.colored_label(Color32::GREEN, "Done");
                     } else if self.playback.running {
@@ -52,6 +181,70 @@ impl eframe::App for App {
                     ui.label(self.wfc.num_patterns().to_string());
                 });
 
+                ui.horizontal(|ui| {
+                    ui.label("Avg neighbors:");
+                    ui.label(format!("{:.1}", self.health.average_branching));
+                });
+                if !self.health.is_healthy() {
+                    ui.colored_label(
+                        Color32::YELLOW,
+                        format!(
+                            "{} pattern(s) have no neighbor in some direction",
+                            self.health.dead_ends.len()
+                        ),
+                    )
+                    .on_hover_text(
+                        "These patterns are never placed; samples with many of them \
+                         tend to contradict. Try periodic input or a smaller pattern size.",
+                    );
+                }
+
+                ui.horizontal(|ui| {
+                    ui.label("Propagation:");
+                    ui.label(format!("{:.1?}", self.wfc.propagation_time()));
+                });
+                let memory = self.memory_usage();
+                ui.horizontal(|ui| {
+                    ui.label("Memory:");
+                    ui.label(format_bytes(memory.total())).on_hover_text(format!(
+                        "wave {}\nsupport counts {}\npropagator {}\npatterns {}\n\
+                         sums {}\nbacktracking {}\nrecorded frames {}",
+                        format_bytes(memory.wave),
+                        format_bytes(memory.compat),
+                        format_bytes(memory.propagator),
+                        format_bytes(memory.patterns),
+                        format_bytes(memory.sums),
+                        format_bytes(memory.backtracking),
+                        format_bytes(memory.frames),
+                    ));
+                });
+                if self.wfc.pending_bans() > 0 {
+                    ui.label(format!("{} ban(s) pending", self.wfc.pending_bans()));
+                }
+
+                ui.horizontal(|ui| {
+                    ui.label("Seed:");
+                    // A text field rather than a DragValue, which goes through
+                    // f64 and rounds seeds above 2^53.
+                    let field = ui
+                        .add(egui::TextEdit::singleline(&mut self.seed_text).desired_width(150.0))
+                        .on_hover_text("Same seed and settings give the same output");
+                    if field.lost_focus() {
+                        match self.seed_text.trim().parse::<u64>() {
+                            Ok(seed) if seed != self.wfc.seed() => self.reset_with_seed(seed),
+                            Ok(_) => {}
+                            Err(_) => {
+                                self.messages.error =
+                                    Some(format!("Invalid seed: {:?}", self.seed_text.trim()));
+                            }
+                        }
+                    }
+                    if !field.has_focus() {
+                        self.seed_text = self.wfc.seed().to_string();
+                    }
+                    ui.label(format!("(attempt {})", self.playback.retries.attempts()));
+                });
+
                 if let Some(err) = &self.messages.error {
                     ui.colored_label(Color32::RED, err);
                 }
@@ -62,8 +255,137 @@ impl eframe::App for App {
                 ui.separator();
                 ui.heading("Sample");
 
-                if ui.button("Load Image...").clicked() {
-                    self.open_file_dialog();
+                ui.horizontal(|ui| {
+                    if ui.button("Load Image...").clicked() {
+                        self.open_file_dialog();
+                    }
+                    if ui.button("Load Heightmap...").clicked() {
+                        self.open_heightmap_dialog();
+                    }
+                });
+                #[cfg(feature = "url")]
+                ui.horizontal(|ui| {
+                    let field = ui.add(
+                        egui::TextEdit::singleline(&mut self.sample_url)
+                            .hint_text("https://...")
+                            .desired_width(180.0),
+                    );
+                    let entered =
+                        field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    if (ui.button("Load URL").clicked() || entered)
+                        && !self.sample_url.trim().is_empty()
+                    {
+                        self.load_url();
+                    }
+                });
+                ui.horizontal(|ui| {
+                    if ui
+                        .button("▶ Demo")
+                        .on_hover_text("Cycle through samples/ with random settings")
+                        .clicked()
+                    {
+                        self.start_demo();
+                    }
+                    ui.label("Pause:");
+                    ui.add(
+                        egui::Slider::new(&mut self.demo.pause, 0.5..=15.0)
+                            .suffix(" s")
+                            .logarithmic(true),
+                    );
+                });
+
+                if !self.heightmap {
+                    ui.horizontal(|ui| {
+                        if ui.button("Add Layer...").clicked() {
+                            self.open_layer_dialog();
+                        }
+                        if !self.extra_layers.is_empty() && ui.button("Clear Layers").clicked() {
+                            self.clear_layers();
+                        }
+                    });
+                }
+                if !self.heightmap && self.extra_layers.is_empty() {
+                    ui.horizontal(|ui| {
+                        if ui
+                            .button("Add Sample...")
+                            .on_hover_text("Train on another image alongside this one")
+                            .clicked()
+                        {
+                            self.open_add_sample_dialog();
+                        }
+                        if !self.extra_samples.is_empty() && ui.button("Clear Samples").clicked() {
+                            self.clear_samples();
+                        }
+                    });
+                    let mut weights = self.config().sample_weights.clone();
+                    weights.resize(1 + self.extra_samples.len(), 1.0);
+                    let mut changed = false;
+                    if !self.extra_samples.is_empty() {
+                        for (i, weight) in weights.iter_mut().enumerate() {
+                            let size = match i {
+                                0 => (self.sample.width, self.sample.height),
+                                _ => {
+                                    let extra = &self.extra_samples[i - 1];
+                                    (extra.width, extra.height)
+                                }
+                            };
+                            ui.horizontal(|ui| {
+                                ui.label(format!("Sample {} ({}x{}) weight:", i, size.0, size.1));
+                                changed |= ui
+                                    .add(egui::DragValue::new(weight).range(0.0..=10.0).speed(0.05))
+                                    .changed();
+                            });
+                        }
+                    }
+                    if changed {
+                        let config = wfc_core::Config {
+                            sample_weights: weights,
+                            ..self.config().clone()
+                        };
+                        self.rebuild_with_config(config);
+                    }
+                }
+                if !self.extra_layers.is_empty() {
+                    ui.horizontal(|ui| {
+                        ui.label("Show layer:");
+                        for layer in 0..=self.extra_layers.len() {
+                            ui.selectable_value(&mut self.shown_layer, layer, layer.to_string());
+                        }
+                    });
+                }
+
+                ui.horizontal(|ui| {
+                    if ui
+                        .button("Importance Mask...")
+                        .on_hover_text("Grayscale image weighting where patterns are learned from")
+                        .clicked()
+                    {
+                        self.open_importance_dialog();
+                    }
+                    if !self.config().importance.is_empty() && ui.button("Clear Mask").clicked() {
+                        let config = wfc_core::Config {
+                            importance: Vec::new(),
+                            ..self.config().clone()
+                        };
+                        self.rebuild_with_config(config);
+                    }
+                });
+
+                if !self.heightmap {
+                    ui.horizontal(|ui| {
+                        if ui
+                            .button("Quantize")
+                            .on_hover_text("Merge near-identical colors in the sample")
+                            .clicked()
+                        {
+                            self.quantize_sample();
+                        }
+                        ui.add(
+                            egui::DragValue::new(&mut self.quantize_colors)
+                                .range(1..=64)
+                                .suffix(" colors"),
+                        );
+                    });
                 }
 
                 if let Some(path) = &self.sample_path {
@@ -75,17 +397,77 @@ impl eframe::App for App {
                     ui.label("(default pipes)");
                 }
 
-                ui.label(format!("{}x{}", self.sample.width, self.sample.height));
+                ui.label(format!(
+                    "{}x{}, {} colors",
+                    self.sample.width, self.sample.height, self.sample_stats.unique_colors
+                ));
+                ui.label(format!(
+                    "~{} patterns ({} unique windows), ~{} MB wave",
+                    self.sample_stats.estimated_patterns,
+                    self.sample_stats.unique_windows,
+                    self.sample_stats.estimated_wave_bytes.div_ceil(1 << 20)
+                ))
+                .on_hover_text("Estimate for the current pattern size, symmetry and output size");
+                let stats = self.sample_stats;
+                if stats.estimated_patterns > Config::MAX_PATTERNS {
+                    // Windows alone over the limit always fail; with symmetry
+                    // the estimate is only an upper bound
+                    let verdict = if stats.unique_windows > Config::MAX_PATTERNS {
+                        "will"
+                    } else {
+                        "may"
+                    };
+                    ui.colored_label(
+                        Color32::RED,
+                        format!(
+                            "Over {} patterns: the build {} fail",
+                            Config::MAX_PATTERNS,
+                            verdict
+                        ),
+                    )
+                    .on_hover_text("Try a smaller pattern size, less symmetry, or a smaller sample");
+                } else if self.limits.exceeded_by(&self.sample_stats) {
+                    ui.colored_label(
+                        Color32::YELLOW,
+                        "Very large model: building and solving will be slow",
+                    )
+                    .on_hover_text(
+                        "Try a smaller pattern size, less symmetry, or a smaller sample",
+                    );
+                }
+                ui.collapsing("Build limits", |ui| {
+                    ui.label("Ask before building a model larger than:");
+                    ui.add(
+                        egui::Slider::new(&mut self.limits.max_patterns, 100..=50_000)
+                            .logarithmic(true)
+                            .text("patterns"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut self.limits.max_wave_mb, 16..=16_384)
+                            .logarithmic(true)
+                            .text("MB"),
+                    );
+                });
                 let sample_size = 80.0;
                 let (response, painter) =
                     ui.allocate_painter(Vec2::new(sample_size, sample_size), egui::Sense::hover());
                 let rect = response.rect;
                 let px_w = sample_size / self.sample.width as f32;
                 let px_h = sample_size / self.sample.height as f32;
+                let sample_colors = if self.heightmap {
+                    Heightmap::from_colors(
+                        self.sample.width,
+                        self.sample.height,
+                        &self.sample.pixels,
+                    )
+                    .hillshade()
+                } else {
+                    self.sample.pixels.clone()
+                };
 
                 for y in 0..self.sample.height {
                     for x in 0..self.sample.width {
-                        let color = self.sample.get(x, y);
+                        let color = sample_colors[y * self.sample.width + x];
                         let pos = rect.min + Vec2::new(x as f32 * px_w, y as f32 * px_h);
                         painter.rect_filled(
                             Rect::from_min_size(pos, Vec2::new(px_w, px_h)),
@@ -101,51 +483,274 @@ impl eframe::App for App {
                 let mut config = self.wfc.config().clone();
                 let mut changed = false;
 
-                changed |= config_slider(ui, "Pattern size:", &mut config.pattern_size, 2..=4);
-                changed |= config_slider(ui, "Width:", &mut config.output_width, 8..=128);
-                changed |= config_slider(ui, "Height:", &mut config.output_height, 8..=128);
-                changed |= ui.checkbox(&mut config.symmetry, "Symmetry").changed();
-
-                let boundary_label = match config.boundary {
-                    Boundary::Fixed => "Fixed",
-                    Boundary::PeriodicX => "Periodic X",
-                    Boundary::PeriodicY => "Periodic Y",
-                    Boundary::Periodic => "Periodic",
-                };
-                egui::ComboBox::from_label("Boundary")
-                    .selected_text(boundary_label)
-                    .show_ui(ui, |ui| {
-                        changed |= ui
-                            .selectable_value(&mut config.boundary, Boundary::Fixed, "Fixed")
-                            .changed();
+                ui.add_enabled_ui(!config.dual_grid, |ui| {
+                    changed |= config_slider(
+                        ui,
+                        "Pattern size:",
+                        &mut config.pattern_size,
+                        2..=Config::MAX_PATTERN_SIZE,
+                    );
+                });
+                if ui
+                    .checkbox(&mut config.dual_grid, "Dual grid (corner tiles)")
+                    .on_hover_text(
+                        "Sample pixels are terrain at tile corners; export draws the \
+                         transition tiles between them",
+                    )
+                    .changed()
+                {
+                    if config.dual_grid {
+                        config.pattern_size = 2;
+                    }
+                    changed = true;
+                }
+                let mut resized = config_slider(ui, "Width:", &mut config.output_width, 8..=128);
+                resized |= config_slider(ui, "Height:", &mut config.output_height, 8..=128);
+                ui.checkbox(&mut self.keep_on_resize, "Keep content on resize")
+                    .on_hover_text("Crop or extend the current result instead of restarting");
+                ui.collapsing("Symmetry", |ui| {
+                    ui.horizontal(|ui| {
+                        if ui.button("All").clicked() {
+                            config.symmetry = Symmetry::ALL;
+                            changed = true;
+                        }
+                        if ui.button("None").clicked() {
+                            config.symmetry = Symmetry::NONE;
+                            changed = true;
+                        }
+                    });
+                    for (label, transform) in [
+                        ("Rotate 90°", Symmetry::ROTATE_90),
+                        ("Rotate 180°", Symmetry::ROTATE_180),
+                        ("Rotate 270°", Symmetry::ROTATE_270),
+                        ("Flip horizontal", Symmetry::FLIP_X),
+                        ("Flip vertical", Symmetry::FLIP_Y),
+                        ("Transpose", Symmetry::TRANSPOSE),
+                        ("Anti-transpose", Symmetry::ANTI_TRANSPOSE),
+                    ] {
+                        let mut on = config.symmetry.contains(transform);
+                        if ui.checkbox(&mut on, label).changed() {
+                            config.symmetry.set(transform, on);
+                            changed = true;
+                        }
+                    }
+                    ui.horizontal(|ui| {
+                        ui.label("Variant weight:");
                         changed |= ui
-                            .selectable_value(
-                                &mut config.boundary,
-                                Boundary::PeriodicX,
-                                "Periodic X",
+                            .add(egui::Slider::new(&mut config.variant_weight, 0.05..=1.0))
+                            .on_hover_text(
+                                "Weight of generated variants vs. literal sample patterns",
                             )
                             .changed();
+                    });
+                });
+
+                changed |= boundary_combo(ui, "Boundary", &mut config.boundary, true);
+                changed |= boundary_combo(ui, "Input wrap", &mut config.input_boundary, false);
+
+                let mirrors = [
+                    (OutputSymmetry::None, "None"),
+                    (OutputSymmetry::MirrorX, "Left-right"),
+                    (OutputSymmetry::MirrorY, "Top-bottom"),
+                    (OutputSymmetry::FourFold, "Four-fold"),
+                ];
+                egui::ComboBox::from_label("Mirror output")
+                    .selected_text(
+                        mirrors
+                            .iter()
+                            .find(|&&(m, _)| m == config.output_symmetry)
+                            .map_or("None", |&(_, name)| name),
+                    )
+                    .show_ui(ui, |ui| {
+                        for (m, name) in mirrors {
+                            changed |= ui
+                                .selectable_value(&mut config.output_symmetry, m, name)
+                                .changed();
+                        }
+                    });
+
+                egui::ComboBox::from_label("Heuristic")
+                    .selected_text(match config.heuristic {
+                        Heuristic::MinEntropy => "Min entropy",
+                        Heuristic::Scanline => "Scanline",
+                        Heuristic::Random => "Random",
+                    })
+                    .show_ui(ui, |ui| {
+                        for (heuristic, name) in [
+                            (Heuristic::MinEntropy, "Min entropy"),
+                            (Heuristic::Scanline, "Scanline"),
+                            (Heuristic::Random, "Random"),
+                        ] {
+                            changed |= ui
+                                .selectable_value(&mut config.heuristic, heuristic, name)
+                                .changed();
+                        }
+                    });
+                if config.heuristic == Heuristic::MinEntropy {
+                    changed |= ui
+                        .add(
+                            egui::Slider::new(&mut config.entropy_noise, 0.0..=1e-2)
+                                .logarithmic(true)
+                                .smallest_positive(1e-9)
+                                .text("Tie noise"),
+                        )
+                        .on_hover_text("Random entropy offset; 0 breaks ties by position")
+                        .changed();
+                }
+
+                let mut ground = config.edges.is_ground();
+                if ui
+                    .checkbox(&mut ground, "Ground (preserve verticality)")
+                    .changed()
+                {
+                    let preset = if ground { Edges::ground() } else { Edges::default() };
+                    config.edges.top = preset.top;
+                    config.edges.bottom = preset.bottom;
+                    changed = true;
+                }
+                let mut sides = config.edges.left == EdgeConstraint::SampleLeft
+                    && config.edges.right == EdgeConstraint::SampleRight;
+                if ui
+                    .checkbox(&mut sides, "Sides (preserve horizontality)")
+                    .changed()
+                {
+                    let preset = if sides { Edges::sides() } else { Edges::default() };
+                    config.edges.left = preset.left;
+                    config.edges.right = preset.right;
+                    changed = true;
+                }
+                ui.collapsing("Edges", |ui| {
+                    ui.label("Patterns allowed on each side of the output");
+                    let color = self.sample.pixels[0];
+                    let np = self.wfc.num_patterns();
+                    let edges = &mut config.edges;
+                    changed |= edge_editor(ui, "Top", &mut edges.top, color, np);
+                    changed |= edge_editor(ui, "Bottom", &mut edges.bottom, color, np);
+                    changed |= edge_editor(ui, "Left", &mut edges.left, color, np);
+                    changed |= edge_editor(ui, "Right", &mut edges.right, color, np);
+                });
+                if config.edges.top != EdgeConstraint::None
+                    || config.edges.bottom != EdgeConstraint::None
+                {
+                    ui.horizontal(|ui| {
+                        ui.label("Ground boost:");
                         changed |= ui
-                            .selectable_value(
-                                &mut config.boundary,
-                                Boundary::PeriodicY,
-                                "Periodic Y",
-                            )
+                            .add(egui::Slider::new(&mut config.ground_boost, 1.0..=10.0))
+                            .on_hover_text("Favor edge patterns near their edge, not just on it")
                             .changed();
+                    });
+                }
+
+                ui.collapsing("Row profiles", |ui| {
+                    ui.label("Weight by row, top to bottom, for one color");
+                    let mut remove = None;
+                    for (i, profile) in config.row_profiles.iter_mut().enumerate() {
+                        ui.horizontal(|ui| {
+                            changed |= ui.color_edit_button_srgb(&mut profile.color).changed();
+                            for point in &mut profile.curve {
+                                changed |= ui
+                                    .add(egui::Slider::new(point, 0.0..=4.0).vertical())
+                                    .changed();
+                            }
+                            if ui.small_button("x").clicked() {
+                                remove = Some(i);
+                            }
+                        });
+                    }
+                    if let Some(i) = remove {
+                        config.row_profiles.remove(i);
+                        changed = true;
+                    }
+                    if ui.button("Add Profile").clicked() {
+                        let color = self.sample.pixels[0];
+                        config
+                            .row_profiles
+                            .push(RowProfile::new(color, vec![1.0; 5]));
+                        changed = true;
+                    }
+                });
+                ui.collapsing("Direction weights", |ui| {
+                    ui.label("Favor continuing the color of a collapsed neighbor");
+                    for (label, weight) in ["Right:", "Down:", "Left:", "Up:"]
+                        .into_iter()
+                        .zip(&mut config.direction_weights)
+                    {
+                        ui.horizontal(|ui| {
+                            ui.label(label);
+                            changed |= ui
+                                .add(egui::Slider::new(weight, 0.1..=10.0).logarithmic(true))
+                                .changed();
+                        });
+                    }
+                });
+                let mut hold = config.frequency_tolerance.is_some();
+                if ui
+                    .checkbox(&mut hold, "Hold color shares")
+                    .on_hover_text("Keep each color near its share of the sample")
+                    .changed()
+                {
+                    config.frequency_tolerance = hold.then_some(0.05);
+                    changed = true;
+                }
+                if let Some(tolerance) = &mut config.frequency_tolerance {
+                    ui.horizontal(|ui| {
+                        ui.label("Tolerance:");
                         changed |= ui
-                            .selectable_value(&mut config.boundary, Boundary::Periodic, "Periodic")
+                            .add(egui::Slider::new(tolerance, 0.0..=0.5))
                             .changed();
                     });
-
+                }
                 changed |= ui
-                    .checkbox(&mut config.ground, "Ground (preserve verticality)")
+                    .checkbox(&mut config.diagonal, "Diagonal adjacency")
                     .changed();
                 changed |= ui
-                    .checkbox(&mut config.sides, "Sides (preserve horizontality)")
+                    .checkbox(&mut config.strict_overlap, "Strict overlap")
+                    .on_hover_text("Constrain every overlapping offset; slower to build")
+                    .changed();
+                ui.horizontal(|ui| {
+                    ui.label("Color tolerance:");
+                    changed |= ui
+                        .add(egui::Slider::new(&mut config.color_tolerance, 0..=64))
+                        .on_hover_text(
+                            "Overlapping pixels agree if each channel differs by at most this; \
+                             for noisy samples",
+                        )
+                        .changed();
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Min pattern count:");
+                    changed |= ui
+                        .add(egui::Slider::new(&mut config.min_pattern_count, 1..=10))
+                        .on_hover_text(
+                            "Drop patterns seen fewer times in the sample; prunes noise",
+                        )
+                        .changed();
+                });
+                changed |= ui
+                    .checkbox(&mut config.lazy_propagator, "Lazy propagator")
+                    .on_hover_text(
+                        "Compute compatibility on first use; less memory for large, \
+                         high-tolerance samples",
+                    )
                     .changed();
+                // Off by default: the GUI shouldn't write to disk unasked
+                let mut cache = config.model_cache.is_some();
+                if ui
+                    .checkbox(&mut cache, "Cache models on disk")
+                    .on_hover_text(format!(
+                        "Reuse extracted models across sessions, stored in {}",
+                        default_cache_dir().display()
+                    ))
+                    .changed()
+                {
+                    config.model_cache = cache.then(default_cache_dir);
+                    changed = true;
+                }
 
-                if changed {
+                if changed || (resized && !self.keep_on_resize) {
                     self.rebuild_with_config(config);
+                } else if resized {
+                    self.resize_output(config.output_width, config.output_height);
                 }
 
                 ui.separator();
@@ -171,36 +776,178 @@ impl eframe::App for App {
                     if ui.button(button_label).clicked() {
                         if is_finished {
                             self.reset();
-                            self.playback.running = true;
+                            self.play();
+                        } else if self.playback.running {
+                            self.playback.running = false;
                         } else {
-                            self.playback.running = !self.playback.running;
+                            self.play();
                         }
                     }
                     if ui.button("⏭ Step").clicked() {
-                        let _ = self.wfc.step();
+                        self.push_undo();
+                        let _ = self.step_once();
+                        self.capture_frame();
+                    }
+                    if ui
+                        .button("⏵ Micro")
+                        .on_hover_text("Propagate a single ban; collapse without propagating")
+                        .clicked()
+                    {
+                        self.push_undo();
+                        let _ = self.micro_step();
                         self.capture_frame();
                     }
+                    if ui
+                        .add_enabled(!self.undo.is_empty(), egui::Button::new("↶ Undo"))
+                        .on_hover_text("Return to the state before the last step")
+                        .clicked()
+                    {
+                        self.undo_step();
+                    }
                 });
 
                 ui.horizontal(|ui| {
                     if ui.button("🎲 New").clicked() {
-                        self.rebuild();
+                        let config = wfc_core::Config {
+                            seed: None,
+                            ..self.config().clone()
+                        };
+                        self.rebuild_with_config(config);
+                    }
+                    if ui
+                        .button("✨ Surprise me")
+                        .on_hover_text(
+                            "Random pattern size, symmetry, periodicity, ground and heuristic",
+                        )
+                        .clicked()
+                    {
+                        self.surprise();
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    if ui
+                        .toggle_value(&mut self.region_tool, "⬚ Regenerate area")
+                        .on_hover_text("Drag a rectangle on the canvas to generate it again")
+                        .changed()
+                    {
+                        self.region_drag = None;
+                        self.inpaint.painting = false;
+                    }
+                    let mut halo = self.config().region_halo;
+                    ui.label("Halo:");
+                    if ui
+                        .add(egui::Slider::new(&mut halo, 0..=8))
+                        .on_hover_text("Cells reopened around the selection so its border can adapt")
+                        .changed()
+                    {
+                        self.wfc.set_region_halo(halo);
                     }
                 });
 
+                ui.horizontal(|ui| {
+                    if ui
+                        .button("Inpaint Image...")
+                        .on_hover_text(
+                            "Load an image to keep, then paint over the parts to generate anew",
+                        )
+                        .clicked()
+                    {
+                        self.open_inpaint_dialog();
+                    }
+                    if self.inpaint.image.is_some() {
+                        if ui
+                            .toggle_value(&mut self.inpaint.painting, "🖌 Paint")
+                            .on_hover_text("Drag to mark cells, right-drag to unmark")
+                            .changed()
+                        {
+                            self.region_tool = false;
+                            self.region_drag = None;
+                        }
+                        if ui.button("Inpaint").clicked() {
+                            self.run_inpaint();
+                        }
+                        if ui.button("Clear").clicked() {
+                            self.clear_inpaint();
+                        }
+                    }
+                });
+                if self.inpaint.image.is_some() {
+                    ui.horizontal(|ui| {
+                        ui.label("Brush:");
+                        ui.add(egui::Slider::new(&mut self.inpaint.brush, 0..=16))
+                            .on_hover_text("Radius in cells; 0 drags out rectangles");
+                    });
+                }
+
                 ui.checkbox(
                     &mut self.playback.auto_restart,
                     "Auto-restart on contradiction",
                 );
+                ui.add_enabled_ui(self.playback.auto_restart, |ui| {
+                    ui.horizontal(|ui| {
+                        let retries = &mut self.playback.retries;
+                        ui.label("Give up after");
+                        ui.add(egui::DragValue::new(&mut retries.max_attempts).range(1..=10_000))
+                            .on_hover_text("Attempts since the last reset, including the first");
+                        ui.label("attempts or");
+                        let mut secs = retries.max_duration.as_secs();
+                        if ui
+                            .add(egui::DragValue::new(&mut secs).range(1..=3600).suffix(" s"))
+                            .changed()
+                        {
+                            retries.max_duration = Duration::from_secs(secs);
+                        }
+                    });
+                });
 
                 ui.separator();
                 ui.heading("Export");
+                ui.horizontal(|ui| {
+                    ui.label("Bake in:");
+                    ui.checkbox(&mut self.export.bake_grid, "Grid")
+                        .on_hover_text("Grid lines at export scale (scale 2+)");
+                    ui.checkbox(&mut self.export.bake_entropy, "Entropy")
+                        .on_hover_text("Entropy heatmap over uncollapsed cells");
+                });
 
                 ui.label(egui::RichText::new("Image (PNG)").strong());
                 self.scale_ui(ui);
-                if ui.button("💾 Save PNG").clicked() {
-                    self.save_output();
+                ui.horizontal(|ui| {
+                    if ui.button("💾 Save PNG").clicked() {
+                        self.save_output();
+                    }
+                    if ui
+                        .button("〰 Save Smoothed")
+                        .on_hover_text("Marching-squares contours of dark vs light cells")
+                        .clicked()
+                    {
+                        self.save_smoothed();
+                    }
+                });
+                if ui
+                    .button("🗺 Save Companion...")
+                    .on_hover_text("Render a map aligned with the sample (normals, emissive) from the same patterns")
+                    .clicked()
+                {
+                    self.save_companion();
+                }
+                if self.iso.enabled && ui.button("🧊 Save Isometric").clicked() {
+                    self.save_isometric();
+                }
+                if self.wfc.config().dual_grid && ui.button("◩ Save Tiles").clicked() {
+                    self.save_tiles();
                 }
+                if ui
+                    .button("📷 Screenshot View")
+                    .on_hover_text("Canvas as shown, with zoom, pan and overlays")
+                    .clicked()
+                {
+                    self.request_screenshot(ctx);
+                }
+
+                ui.checkbox(&mut self.export.allow_incomplete, "Allow incomplete")
+                    .on_hover_text("Save even if contradicted or uncollapsed cells remain");
 
                 ui.add_space(8.0);
 
@@ -219,14 +966,176 @@ impl eframe::App for App {
                 if !self.export.gif_frames.is_empty() {
                     ui.label(format!("{} frames recorded", self.export.gif_frames.len()));
                 }
+                ui.checkbox(&mut self.export.boomerang, "Boomerang")
+                    .on_hover_text("Play forward then back; preview with ping-pong review");
                 if ui.button("🎞 Save GIF").clicked() {
                     self.start_save_gif();
                 }
+                ui.horizontal(|ui| {
+                    ui.label("Sheet columns:");
+                    ui.add(egui::Slider::new(&mut self.export.sheet_columns, 1..=32));
+                });
+                if ui
+                    .button("🧩 Save Sprite Sheet")
+                    .on_hover_text("All frames in one PNG, with frame rects and delays in a JSON")
+                    .clicked()
+                {
+                    self.save_sprite_sheet();
+                }
+                if ui
+                    .button("🖌 Save Aseprite")
+                    .on_hover_text(
+                        "Recorded frames (or the current output) at one pixel per cell, \
+                         with the output palette",
+                    )
+                    .clicked()
+                {
+                    self.save_aseprite();
+                }
+                ui.horizontal(|ui| {
+                    ui.label("Stages:");
+                    let mut by_percent = matches!(self.export.milestones, Milestones::EveryPercent(_));
+                    egui::ComboBox::from_id_salt("milestones")
+                        .selected_text(if by_percent { "every % decided" } else { "every N frames" })
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut by_percent, true, "every % decided");
+                            ui.selectable_value(&mut by_percent, false, "every N frames");
+                        });
+                    self.export.milestones = match (self.export.milestones, by_percent) {
+                        (Milestones::EveryFrames(_), true) => Milestones::EveryPercent(25),
+                        (Milestones::EveryPercent(_), false) => Milestones::EveryFrames(10),
+                        (kept, _) => kept,
+                    };
+                    match &mut self.export.milestones {
+                        Milestones::EveryPercent(step) => {
+                            ui.add(egui::DragValue::new(step).range(1..=100).suffix("%"))
+                        }
+                        Milestones::EveryFrames(k) => {
+                            ui.add(egui::DragValue::new(k).range(1..=1000))
+                        }
+                    };
+                });
+                if ui
+                    .button("🗂 Save OpenRaster")
+                    .on_hover_text("Construction stages as layers of an .ora for Krita or GIMP")
+                    .clicked()
+                {
+                    self.save_openraster();
+                }
 
                 ui.separator();
                 ui.heading("Visualization");
 
                 ui.checkbox(&mut self.show_grid, "Show grid lines");
+                ui.checkbox(&mut self.show_entropy, "Show entropy heatmap");
+                ui.checkbox(&mut self.show_propagation, "Show propagation")
+                    .on_hover_text("Outline cells narrowed by the latest step");
+                ui.checkbox(&mut self.show_counts, "Show remaining counts")
+                    .on_hover_text("Patterns left per uncollapsed cell; visible when zoomed in");
+                ui.checkbox(&mut self.inspect_cells, "Inspect cells")
+                    .on_hover_text("Hover a cell to list its remaining patterns and weights");
+
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.hatch_contradictions, "Hatch contradictions");
+                    let mut color = self.config().contradiction_color;
+                    if ui.color_edit_button_srgb(&mut color).changed() {
+                        self.wfc.set_contradiction_color(color);
+                    }
+                });
+
+                ui.checkbox(&mut self.iso.enabled, "Isometric view")
+                    .on_hover_text("Preview as 2.5D blocks raised by color");
+                if self.iso.enabled {
+                    ui.collapsing("Heights", |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("Max height:");
+                            ui.add(egui::Slider::new(&mut self.iso.max_height, 0.0..=8.0));
+                        });
+                        egui::Grid::new("iso_heights").show(ui, |ui| {
+                            for (color, height) in &mut self.iso.heights {
+                                let (rect, _) =
+                                    ui.allocate_exact_size(Vec2::splat(16.0), egui::Sense::hover());
+                                ui.painter().rect_filled(
+                                    rect,
+                                    2.0,
+                                    Color32::from_rgb(color[0], color[1], color[2]),
+                                );
+                                ui.add(egui::Slider::new(height, 0.0..=1.0));
+                                ui.end_row();
+                            }
+                        });
+                        if ui.button("Reset").clicked() {
+                            self.iso.heights.clear();
+                        }
+                    });
+                }
+
+                if !self.heightmap {
+                    ui.collapsing("Recolor", |ui| {
+                        ui.label("Output color per sample color");
+                        egui::Grid::new("color_map").show(ui, |ui| {
+                            for (from, to) in &mut self.color_map.entries {
+                                let (rect, _) =
+                                    ui.allocate_exact_size(Vec2::splat(16.0), egui::Sense::hover());
+                                ui.painter().rect_filled(
+                                    rect,
+                                    2.0,
+                                    Color32::from_rgb(from[0], from[1], from[2]),
+                                );
+                                ui.label("→");
+                                ui.color_edit_button_srgb(to);
+                                ui.end_row();
+                            }
+                        });
+                        ui.horizontal(|ui| {
+                            if ui.button("Load Palette...").clicked() {
+                                self.open_palette_dialog();
+                            }
+                            if ui
+                                .button("Match Image...")
+                                .on_hover_text("Nearest colors from another image's palette")
+                                .clicked()
+                            {
+                                self.open_palette_image_dialog();
+                            }
+                            if ui.button("Reset").clicked() {
+                                self.color_map.assign(&[]);
+                            }
+                        });
+
+                        let mut decision = None;
+                        if let Some((palette, map)) = &self.palette_transfer {
+                            ui.label("Extracted palette (previewed on canvas):");
+                            ui.horizontal_wrapped(|ui| {
+                                for c in palette {
+                                    let (rect, _) = ui.allocate_exact_size(
+                                        Vec2::splat(16.0),
+                                        egui::Sense::hover(),
+                                    );
+                                    ui.painter().rect_filled(
+                                        rect,
+                                        2.0,
+                                        Color32::from_rgb(c[0], c[1], c[2]),
+                                    );
+                                }
+                            });
+                            ui.horizontal(|ui| {
+                                if ui.button("Apply").clicked() {
+                                    decision = Some(Some(map.clone()));
+                                }
+                                if ui.button("Discard").clicked() {
+                                    decision = Some(None);
+                                }
+                            });
+                        }
+                        if let Some(applied) = decision {
+                            if let Some(map) = applied {
+                                self.color_map = map;
+                            }
+                            self.palette_transfer = None;
+                        }
+                    });
+                }
 
                 ui.horizontal(|ui| {
                     ui.label("Zoom:");
@@ -246,18 +1155,20 @@ impl eframe::App for App {
                 });
             });
 
+        self.handle_screenshot(ctx);
+        self.show_timeline(ctx);
+
         egui::CentralPanel::default().show(ctx, |ui| {
             if self.playback.running && !self.wfc.is_done() {
                 if self.wfc.has_contradiction() {
                     if self.playback.auto_restart {
-                        self.wfc.reset();
-                        self.capture_frame();
+                        self.restart_with_next_seed();
                     } else {
                         self.playback.running = false;
                     }
                 } else {
                     for _ in 0..self.playback.steps_per_frame {
-                        if self.wfc.step() != StepOutcome::Progressed {
+                        if self.step_once() != StepOutcome::Progressed {
                             break;
                         }
                         self.capture_frame();
@@ -272,6 +1183,7 @@ impl eframe::App for App {
 
             let (response, painter) =
                 ui.allocate_painter(available_size, egui::Sense::click_and_drag());
+            self.camera.canvas_rect = Some(response.rect);
 
             // Calculate current actual_zoom for input handling
             let current_actual_zoom = if self.camera.zoom <= 0.0 {
@@ -340,18 +1252,104 @@ impl eframe::App for App {
                 + Vec2::new(offset_x.max(0.0), offset_y.max(0.0))
                 + self.camera.pan_offset;
 
+            // Overlays describe the live wave, so hide them while reviewing
+            let review = self.review_colors();
+            let live = review.is_none();
+            let colors = review.unwrap_or_else(|| self.display_colors());
+            if self.fade_propagation(ctx.input(|i| i.stable_dt)) && self.show_propagation {
+                ctx.request_repaint();
+            }
+            if self.iso.enabled {
+                self.paint_iso(&painter, response.rect, &colors);
+                return;
+            }
+            let counts = self.wfc.possibility_counts();
+            let entropy = self.show_entropy.then(|| self.wfc.entropy_grid());
             for y in 0..output_height {
                 for x in 0..output_width {
                     let pos =
                         canvas_origin + Vec2::new(x as f32 * actual_zoom, y as f32 * actual_zoom);
                     let cell_rect = Rect::from_min_size(pos, Vec2::splat(actual_zoom));
 
-                    let color = self.wfc.get_color(x, y);
+                    let color = colors[y * output_width + x];
                     let base = Color32::from_rgb(color[0], color[1], color[2]);
 
                     painter.rect_filled(cell_rect, 0.0, base);
 
-                    if let Some((lx, ly)) = self.wfc.last_collapsed()
+                    if live
+                        && let Some(entropy) = &entropy
+                        && counts[y * output_width + x] > 1
+                    {
+                        let [r, g, b] = heat_color(entropy[y * output_width + x] as f32);
+                        painter.rect_filled(
+                            cell_rect,
+                            0.0,
+                            Color32::from_rgba_unmultiplied(r, g, b, 150),
+                        );
+                    }
+
+                    if live && self.hatch_contradictions && self.wfc.is_contradicted(x, y) {
+                        let stroke = Stroke::new(1.0, Color32::BLACK);
+                        for i in 1..4 {
+                            let t = i as f32 * 0.25 * actual_zoom;
+                            painter.line_segment(
+                                [
+                                    cell_rect.min + Vec2::new(t, 0.0),
+                                    cell_rect.min + Vec2::new(0.0, t),
+                                ],
+                                stroke,
+                            );
+                            painter.line_segment(
+                                [
+                                    cell_rect.max - Vec2::new(t, 0.0),
+                                    cell_rect.max - Vec2::new(0.0, t),
+                                ],
+                                stroke,
+                            );
+                        }
+                        painter
+                            .line_segment([cell_rect.left_bottom(), cell_rect.right_top()], stroke);
+                    }
+
+                    let flash = self
+                        .propagation_flash
+                        .get(y * output_width + x)
+                        .copied()
+                        .unwrap_or(0.0);
+                    if live && self.show_propagation && flash > 0.0 {
+                        painter.rect_stroke(
+                            cell_rect.shrink(1.0),
+                            0.0,
+                            Stroke::new(
+                                2.0,
+                                Color32::from_rgba_unmultiplied(255, 200, 0, (flash * 255.0) as u8),
+                            ),
+                            egui::StrokeKind::Inside,
+                        );
+                    }
+
+                    // Skip labels too small to read
+                    let remaining = counts[y * output_width + x];
+                    if live && self.show_counts && remaining > 1 && actual_zoom >= 14.0 {
+                        let luma = 0.299 * color[0] as f32
+                            + 0.587 * color[1] as f32
+                            + 0.114 * color[2] as f32;
+                        let text_color = if luma > 128.0 {
+                            Color32::BLACK
+                        } else {
+                            Color32::WHITE
+                        };
+                        painter.text(
+                            cell_rect.center(),
+                            egui::Align2::CENTER_CENTER,
+                            remaining.to_string(),
+                            egui::FontId::monospace(actual_zoom * 0.4),
+                            text_color,
+                        );
+                    }
+
+                    if live
+                        && let Some((lx, ly)) = self.wfc.last_collapsed()
                         && x == lx
                         && y == ly
                     {
@@ -388,6 +1386,152 @@ impl eframe::App for App {
                     );
                 }
             }
+
+            // Ring the emptied cell and link it to the neighbors that emptied it
+            if live
+                && self.wfc.has_contradiction()
+                && let Some(info) = self.wfc.contradiction_info()
+            {
+                let center = |(x, y): (usize, usize)| {
+                    canvas_origin + Vec2::new(x as f32 + 0.5, y as f32 + 0.5) * actual_zoom
+                };
+                let stroke = Stroke::new(2.0, Color32::from_rgb(255, 0, 255));
+                for cause in info.bans.iter().filter_map(|ban| ban.cause) {
+                    painter.line_segment([center(cause), center(info.cell)], stroke);
+                }
+                painter.circle_stroke(center(info.cell), actual_zoom.max(6.0), stroke);
+            }
+
+            if self.inspect_cells
+                && live
+                && self.region_drag.is_none()
+                && let Some(pos) = response.hover_pos()
+            {
+                let rel = (pos - canvas_origin) / actual_zoom;
+                if rel.x >= 0.0 && rel.y >= 0.0 {
+                    let (x, y) = (rel.x as usize, rel.y as usize);
+                    if x < output_width && y < output_height {
+                        response.clone().on_hover_ui_at_pointer(|ui| {
+                            self.possibilities_tooltip(ui, x, y);
+                        });
+                    }
+                }
+            }
+
+            if self.region_tool && live {
+                let cell_at = |pos: Pos2| {
+                    let rel = (pos - canvas_origin) / actual_zoom;
+                    (
+                        (rel.x.max(0.0) as usize).min(output_width - 1),
+                        (rel.y.max(0.0) as usize).min(output_height - 1),
+                    )
+                };
+                if response.drag_started_by(egui::PointerButton::Primary)
+                    && let Some(pos) = response.interact_pointer_pos()
+                {
+                    let cell = cell_at(pos);
+                    self.region_drag = Some((cell, cell));
+                }
+                if response.dragged_by(egui::PointerButton::Primary)
+                    && let (Some(pos), Some((_, end))) =
+                        (response.interact_pointer_pos(), &mut self.region_drag)
+                {
+                    *end = cell_at(pos);
+                }
+                if let Some((a, b)) = self.region_drag {
+                    let min = Vec2::new(a.0.min(b.0) as f32, a.1.min(b.1) as f32);
+                    let max = Vec2::new(a.0.max(b.0) as f32 + 1.0, a.1.max(b.1) as f32 + 1.0);
+                    painter.rect_stroke(
+                        Rect::from_min_max(
+                            canvas_origin + min * actual_zoom,
+                            canvas_origin + max * actual_zoom,
+                        ),
+                        0.0,
+                        Stroke::new(2.0, Color32::WHITE),
+                        egui::StrokeKind::Middle,
+                    );
+                    if response.drag_stopped_by(egui::PointerButton::Primary) {
+                        self.region_drag = None;
+                        self.regenerate_region(a, b);
+                    }
+                }
+            }
+
+            if self.inpaint.region.len() == output_width * output_height {
+                let cell_at = |pos: Pos2| {
+                    let rel = (pos - canvas_origin) / actual_zoom;
+                    (
+                        (rel.x.max(0.0) as usize).min(output_width - 1),
+                        (rel.y.max(0.0) as usize).min(output_height - 1),
+                    )
+                };
+                if self.inpaint.painting && live {
+                    let button = [egui::PointerButton::Primary, egui::PointerButton::Secondary]
+                        .into_iter()
+                        .find(|&b| response.dragged_by(b) || response.drag_stopped_by(b));
+                    let mark = button == Some(egui::PointerButton::Primary);
+                    if let (Some(button), Some(pos)) = (button, response.interact_pointer_pos())
+                        && response.dragged_by(button)
+                    {
+                        let cell = cell_at(pos);
+                        let r = self.inpaint.brush as isize;
+                        if r == 0 {
+                            let start = self.region_drag.map_or(cell, |(a, _)| a);
+                            self.region_drag = Some((start, cell));
+                        }
+                        for dy in -r..=r {
+                            for dx in -r..=r {
+                                let (x, y) = (cell.0 as isize + dx, cell.1 as isize + dy);
+                                if r > 0
+                                    && dx * dx + dy * dy <= r * r
+                                    && (0..output_width as isize).contains(&x)
+                                    && (0..output_height as isize).contains(&y)
+                                {
+                                    self.inpaint.region[y as usize * output_width + x as usize] =
+                                        mark;
+                                }
+                            }
+                        }
+                    }
+                    if let Some(button) = button
+                        && response.drag_stopped_by(button)
+                        && let Some((a, b)) = self.region_drag.take()
+                    {
+                        for y in a.1.min(b.1)..=a.1.max(b.1) {
+                            for x in a.0.min(b.0)..=a.0.max(b.0) {
+                                self.inpaint.region[y * output_width + x] = mark;
+                            }
+                        }
+                    }
+                    if let Some((a, b)) = self.region_drag {
+                        let min = Vec2::new(a.0.min(b.0) as f32, a.1.min(b.1) as f32);
+                        let max = Vec2::new(a.0.max(b.0) as f32 + 1.0, a.1.max(b.1) as f32 + 1.0);
+                        painter.rect_stroke(
+                            Rect::from_min_max(
+                                canvas_origin + min * actual_zoom,
+                                canvas_origin + max * actual_zoom,
+                            ),
+                            0.0,
+                            Stroke::new(2.0, Color32::WHITE),
+                            egui::StrokeKind::Middle,
+                        );
+                    }
+                }
+                // Tint the area to regenerate while painting it
+                if self.inpaint.painting {
+                    for (i, _) in self.inpaint.region.iter().enumerate().filter(|&(_, &r)| r) {
+                        let (x, y) = (i % output_width, i / output_width);
+                        painter.rect_filled(
+                            Rect::from_min_size(
+                                canvas_origin + Vec2::new(x as f32, y as f32) * actual_zoom,
+                                Vec2::splat(actual_zoom),
+                            ),
+                            0.0,
+                            Color32::from_rgba_unmultiplied(255, 255, 255, 60),
+                        );
+                    }
+                }
+            }
         });
 
         if self.playback.running {
@@ -397,3 +1541,124 @@ impl eframe::App for App {
         }
     }
 }
+
+impl App {
+    /// The patterns still possible at a cell, most likely first.
+    fn possibilities_tooltip(&self, ui: &mut egui::Ui, x: usize, y: usize) {
+        const SHOWN: usize = 8;
+        let mut possible: Vec<(usize, [u8; 3], f64)> = self
+            .wfc
+            .possibilities(x, y)
+            .map(|(p, pattern, weight)| (p, pattern.get(0, 0), weight))
+            .collect();
+        let total: f64 = possible.iter().map(|&(_, _, w)| w).sum();
+        possible.sort_by(|a, b| b.2.total_cmp(&a.2));
+
+        ui.label(format!("({}, {}): {} patterns", x, y, possible.len()));
+        for &(p, [r, g, b], weight) in possible.iter().take(SHOWN) {
+            ui.horizontal(|ui| {
+                let (rect, _) = ui.allocate_exact_size(Vec2::splat(12.0), egui::Sense::hover());
+                ui.painter()
+                    .rect_filled(rect, 2.0, Color32::from_rgb(r, g, b));
+                ui.label(format!("p{}  {:.1}%", p, 100.0 * weight / total));
+            });
+        }
+        if possible.len() > SHOWN {
+            ui.weak(format!("and {} more", possible.len() - SHOWN));
+        }
+    }
+
+    /// Isometric preview fitted (or zoomed) into `rect`, following the
+    /// canvas camera's zoom and pan.
+    fn paint_iso(&mut self, painter: &egui::Painter, rect: Rect, colors: &[[u8; 3]]) {
+        let scene = self.iso_scene(colors);
+        // A tile is two units wide, so one unit is half a cell at 100%
+        let scale = if self.camera.zoom <= 0.0 {
+            (rect.width() / scene.width).min(rect.height() / scene.height) * 0.95
+        } else {
+            self.camera.zoom * self.camera.cell_size * 0.5
+        };
+        let size = Vec2::new(scene.width, scene.height) * scale;
+        let origin =
+            rect.min + ((rect.size() - size) * 0.5).max(Vec2::ZERO) + self.camera.pan_offset;
+        for face in &scene.faces {
+            let points = face
+                .points
+                .iter()
+                .map(|&[x, y]| origin + Vec2::new(x, y) * scale)
+                .collect();
+            let [r, g, b] = face.color;
+            painter.add(egui::Shape::convex_polygon(
+                points,
+                Color32::from_rgb(r, g, b),
+                Stroke::NONE,
+            ));
+        }
+    }
+
+    fn show_build_progress(&mut self, ctx: &egui::Context) {
+        let Some(job) = &self.build_job else {
+            return;
+        };
+        let progress = *job.progress.lock().unwrap();
+        egui::Window::new("Building model")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.vertical_centered(|ui| {
+                    ui.add_space(10.0);
+                    let (label, fraction) = match progress {
+                        Some(p) => (
+                            match p.phase {
+                                BuildPhase::Extracting => "Extracting patterns...",
+                                BuildPhase::Propagator => "Matching neighbors...",
+                            },
+                            p.fraction,
+                        ),
+                        None => ("Starting...", 0.0),
+                    };
+                    ui.label(label);
+                    ui.add(egui::ProgressBar::new(fraction).show_percentage());
+                    ui.add_space(10.0);
+                    if ui.button("Cancel").clicked() {
+                        self.cancel_build_job();
+                        self.sample_stats =
+                            Sample::analyze_all(&self.solver_samples(), self.wfc.config());
+                        self.messages.error = Some("Rebuild cancelled".to_string());
+                    }
+                    ui.add_space(10.0);
+                });
+            });
+        ctx.request_repaint();
+    }
+
+    fn show_rebuild_confirm(&mut self, ctx: &egui::Context) {
+        let stats = self.sample_stats;
+        egui::Window::new("Build large model?")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.vertical_centered(|ui| {
+                    ui.add_space(10.0);
+                    ui.label(format!(
+                        "About {} patterns and {} MB of wave state.",
+                        stats.estimated_patterns,
+                        stats.estimated_wave_bytes.div_ceil(1 << 20)
+                    ));
+                    ui.label("Building may take a long time and freeze the app.");
+                    ui.add_space(10.0);
+                    ui.horizontal(|ui| {
+                        if ui.button("Build anyway").clicked() {
+                            self.confirm_rebuild();
+                        }
+                        if ui.button("Cancel").clicked() {
+                            self.cancel_rebuild();
+                        }
+                    });
+                    ui.add_space(10.0);
+                });
+            });
+    }
+}
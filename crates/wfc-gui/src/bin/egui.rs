@@ -8,6 +8,13 @@ fn main() -> eframe::Result<()> {
     eframe::run_native(
         "WFC - Wave Function Collapse",
         options,
-        Box::new(|_| Ok(Box::new(App::new()))),
+        Box::new(|_| {
+            let mut app = App::new();
+            // `--demo` starts in attract mode, e.g. for a kiosk display
+            if std::env::args().any(|arg| arg == "--demo") {
+                app.start_demo();
+            }
+            Ok(Box::new(app))
+        }),
     )
 }
@@ -0,0 +1,181 @@
+use std::io;
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
+
+use clap::Parser;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::DefaultTerminal;
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color as TermColor, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::Paragraph;
+use wfc_core::{Config, Sample, StepOutcome, Wfc, default_pipe_sample};
+
+/// Terminal viewer for Wave Function Collapse
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    /// Input sample image (default: built-in pipes)
+    #[arg(short, long)]
+    input: Option<PathBuf>,
+
+    /// Output width in cells
+    #[arg(short = 'W', long, default_value_t = 48)]
+    width: usize,
+
+    /// Output height in cells
+    #[arg(short = 'H', long, default_value_t = 32)]
+    height: usize,
+
+    /// Pattern size NxN
+    #[arg(short, long, default_value_t = 3)]
+    pattern_size: usize,
+
+    /// RNG seed for deterministic output
+    #[arg(short, long)]
+    seed: Option<u64>,
+}
+
+struct Viewer {
+    wfc: Wfc,
+    running: bool,
+    steps_per_frame: usize,
+}
+
+impl Viewer {
+    fn status(&self) -> &'static str {
+        if self.wfc.has_contradiction() {
+            "contradiction"
+        } else if self.wfc.is_done() {
+            "done"
+        } else if self.running {
+            "running"
+        } else {
+            "paused"
+        }
+    }
+
+    fn tick(&mut self) {
+        if !self.running {
+            return;
+        }
+        for _ in 0..self.steps_per_frame {
+            if self.wfc.step() != StepOutcome::Progressed {
+                self.running = false;
+                break;
+            }
+        }
+    }
+
+    /// Two cells per character: upper half-block with fg = top, bg = bottom.
+    fn grid_lines(&self) -> Vec<Line<'static>> {
+        let config = self.wfc.config();
+        let (w, h) = (config.output_width, config.output_height);
+        let rgb = |c: [u8; 3]| TermColor::Rgb(c[0], c[1], c[2]);
+
+        (0..h.div_ceil(2))
+            .map(|row| {
+                let spans: Vec<Span> = (0..w)
+                    .map(|x| {
+                        let top = self.wfc.get_color(x, row * 2);
+                        let style = if row * 2 + 1 < h {
+                            Style::new()
+                                .fg(rgb(top))
+                                .bg(rgb(self.wfc.get_color(x, row * 2 + 1)))
+                        } else {
+                            Style::new().fg(rgb(top))
+                        };
+                        Span::styled("▀", style)
+                    })
+                    .collect();
+                Line::from(spans)
+            })
+            .collect()
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        loop {
+            terminal.draw(|frame| {
+                let [grid, status] =
+                    Layout::vertical([Constraint::Min(1), Constraint::Length(1)])
+                        .areas(frame.area());
+                frame.render_widget(Paragraph::new(self.grid_lines()), grid);
+                let text = format!(
+                    " {} | seed {} | speed {} | space run/pause  s step  r reset  n new  +/- speed  q quit",
+                    self.status(),
+                    self.wfc.seed(),
+                    self.steps_per_frame
+                );
+                frame.render_widget(Paragraph::new(text), status);
+            })?;
+
+            let timeout = if self.running {
+                Duration::from_millis(16)
+            } else {
+                Duration::from_millis(250)
+            };
+            if event::poll(timeout)?
+                && let Event::Key(key) = event::read()?
+                && key.kind == KeyEventKind::Press
+            {
+                match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                    KeyCode::Char(' ') => self.running = !self.running,
+                    KeyCode::Char('s') => {
+                        self.running = false;
+                        let _ = self.wfc.step();
+                    }
+                    KeyCode::Char('r') => {
+                        self.wfc.reset();
+                        self.running = false;
+                    }
+                    KeyCode::Char('n') => {
+                        let next = self.wfc.seed().wrapping_add(1);
+                        self.wfc.reseed(next);
+                        self.running = true;
+                    }
+                    KeyCode::Char('+') | KeyCode::Char('=') => {
+                        self.steps_per_frame = (self.steps_per_frame * 2).min(1024);
+                    }
+                    KeyCode::Char('-') => {
+                        self.steps_per_frame = (self.steps_per_frame / 2).max(1);
+                    }
+                    _ => {}
+                }
+            }
+
+            self.tick();
+        }
+    }
+}
+
+fn main() -> io::Result<()> {
+    let cli = Cli::parse();
+
+    let sample = match &cli.input {
+        Some(path) => Sample::from_image(path).unwrap_or_else(|e| {
+            eprintln!("Error loading sample '{}': {}", path.display(), e);
+            process::exit(1);
+        }),
+        None => default_pipe_sample(),
+    };
+    let config = Config {
+        pattern_size: cli.pattern_size,
+        output_width: cli.width,
+        output_height: cli.height,
+        seed: cli.seed,
+        ..Default::default()
+    };
+
+    let mut viewer = Viewer {
+        wfc: Wfc::new(&sample, config),
+        running: false,
+        steps_per_frame: 1,
+    };
+
+    let mut terminal = ratatui::init();
+    let result = viewer.run(&mut terminal);
+    ratatui::restore();
+    result
+}